@@ -0,0 +1,41 @@
+use std::path::Path;
+use std::process::Command;
+
+/// Sets `GIT_HASH` for `/version`: the `GIT_HASH` environment variable if set, e.g. when building
+/// outside a git checkout, else the checked-out commit, else `unknown`.
+fn main() {
+    println!("cargo:rerun-if-env-changed=GIT_HASH");
+    let hash = std::env::var("GIT_HASH")
+        .ok()
+        .filter(|hash| !hash.is_empty())
+        .or_else(git_hash)
+        .unwrap_or_else(|| String::from("unknown"));
+    println!("cargo:rustc-env=GIT_HASH={}", hash);
+}
+
+fn git_hash() -> Option<String> {
+    // Rebuild when HEAD moves, whether it is detached or on a branch that gets new commits.
+    let head = Path::new(".git/HEAD");
+    if head.exists() {
+        println!("cargo:rerun-if-changed=.git/HEAD");
+        if let Some(branch) = std::fs::read_to_string(head)
+            .ok()
+            .and_then(|head| head.strip_prefix("ref: ").map(|b| b.trim().to_owned()))
+        {
+            // A branch only in `.git/packed-refs` has no file of its own until it moves.
+            if Path::new(".git").join(&branch).exists() {
+                println!("cargo:rerun-if-changed=.git/{}", branch);
+            }
+        }
+    }
+
+    let output = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let hash = String::from_utf8(output.stdout).ok()?;
+    Some(hash.trim().to_owned())
+}
@@ -0,0 +1,125 @@
+-- Initial ugs_db schema, matching the tables used by the original C# MetadataServer.
+
+CREATE DATABASE IF NOT EXISTS ugs_db;
+
+CREATE TABLE IF NOT EXISTS ugs_db.Projects (
+    Id BIGINT NOT NULL AUTO_INCREMENT,
+    Name VARCHAR(512) NOT NULL,
+    PRIMARY KEY (Id),
+    UNIQUE KEY Name (Name)
+);
+
+CREATE TABLE IF NOT EXISTS ugs_db.Users (
+    Id BIGINT NOT NULL AUTO_INCREMENT,
+    Name VARCHAR(128) NOT NULL,
+    PRIMARY KEY (Id),
+    UNIQUE KEY Name (Name)
+);
+
+CREATE TABLE IF NOT EXISTS ugs_db.Badges (
+    Id BIGINT NOT NULL AUTO_INCREMENT,
+    ChangeNumber INT NOT NULL,
+    BuildType VARCHAR(128) NOT NULL,
+    Result VARCHAR(16) NOT NULL,
+    Url VARCHAR(1024) NOT NULL,
+    ArchivePath VARCHAR(1024) NOT NULL DEFAULT '',
+    ProjectId BIGINT NOT NULL,
+    PRIMARY KEY (Id),
+    KEY ProjectId_ChangeNumber (ProjectId, ChangeNumber)
+);
+
+CREATE TABLE IF NOT EXISTS ugs_db.Comments (
+    Id BIGINT NOT NULL AUTO_INCREMENT,
+    ChangeNumber INT NOT NULL,
+    UserName VARCHAR(128) NOT NULL,
+    Text TEXT NOT NULL,
+    Project VARCHAR(512) NOT NULL,
+    ProjectId BIGINT NOT NULL,
+    PRIMARY KEY (Id),
+    KEY ProjectId_ChangeNumber (ProjectId, ChangeNumber)
+);
+
+CREATE TABLE IF NOT EXISTS ugs_db.UserVotes (
+    Id BIGINT NOT NULL AUTO_INCREMENT,
+    Changelist INT NOT NULL,
+    UserName VARCHAR(128) NOT NULL,
+    Verdict VARCHAR(32) NOT NULL,
+    Project VARCHAR(512) NOT NULL,
+    ProjectId BIGINT NOT NULL,
+    PRIMARY KEY (Id),
+    KEY ProjectId_Changelist (ProjectId, Changelist)
+);
+
+CREATE TABLE IF NOT EXISTS ugs_db.Issues (
+    Id BIGINT NOT NULL AUTO_INCREMENT,
+    Project VARCHAR(512) NOT NULL,
+    Summary VARCHAR(200) NOT NULL,
+    OwnerId BIGINT NULL,
+    NominatedById BIGINT NULL,
+    CreatedAt DATETIME NOT NULL,
+    AcknowledgedAt DATETIME NULL,
+    FixChange INT NOT NULL DEFAULT 0,
+    ResolvedAt DATETIME NULL,
+    PRIMARY KEY (Id),
+    KEY ResolvedAt (ResolvedAt)
+);
+
+CREATE TABLE IF NOT EXISTS ugs_db.IssueWatchers (
+    IssueId BIGINT NOT NULL,
+    UserId BIGINT NOT NULL,
+    PRIMARY KEY (IssueId, UserId)
+);
+
+CREATE TABLE IF NOT EXISTS ugs_db.IssueBuilds (
+    Id BIGINT NOT NULL AUTO_INCREMENT,
+    IssueId BIGINT NOT NULL,
+    Stream VARCHAR(512) NOT NULL,
+    `Change` INT NOT NULL,
+    JobName VARCHAR(1024) NOT NULL,
+    JobUrl VARCHAR(1024) NOT NULL,
+    JobStepName VARCHAR(1024) NOT NULL,
+    JobStepUrl VARCHAR(1024) NOT NULL,
+    ErrorUrl VARCHAR(1024) NOT NULL,
+    Outcome INT NOT NULL,
+    PRIMARY KEY (Id),
+    KEY IssueId (IssueId)
+);
+
+CREATE TABLE IF NOT EXISTS ugs_db.IssueDiagnostics (
+    Id BIGINT NOT NULL AUTO_INCREMENT,
+    IssueId BIGINT NOT NULL,
+    BuildId BIGINT NULL,
+    Message VARCHAR(1000) NOT NULL,
+    Url VARCHAR(1024) NOT NULL,
+    PRIMARY KEY (Id),
+    KEY IssueId (IssueId)
+);
+
+CREATE TABLE IF NOT EXISTS ugs_db.Errors (
+    Id BIGINT NOT NULL AUTO_INCREMENT,
+    Type VARCHAR(32) NOT NULL,
+    Text TEXT NOT NULL,
+    UserName VARCHAR(128) NOT NULL,
+    Project VARCHAR(512) NULL,
+    Timestamp DATETIME NOT NULL,
+    Version VARCHAR(64) NOT NULL,
+    IpAddress VARCHAR(64) NOT NULL,
+    ProjectId BIGINT NULL,
+    PRIMARY KEY (Id),
+    KEY Timestamp (Timestamp)
+);
+
+CREATE TABLE IF NOT EXISTS ugs_db.Telemetry_v2 (
+    Id BIGINT NOT NULL AUTO_INCREMENT,
+    Action VARCHAR(128) NOT NULL,
+    Result VARCHAR(128) NOT NULL,
+    UserName VARCHAR(128) NOT NULL,
+    Project VARCHAR(512) NOT NULL,
+    Timestamp DATETIME NOT NULL,
+    Duration FLOAT NOT NULL,
+    Version VARCHAR(64) NOT NULL,
+    IpAddress VARCHAR(64) NOT NULL,
+    ProjectId BIGINT NOT NULL,
+    PRIMARY KEY (Id),
+    KEY Timestamp (Timestamp)
+);
@@ -0,0 +1,6 @@
+-- UserVotes had no timestamp, so retention policies could not age out events. Rows that predate
+-- this migration are stamped with the time it runs.
+
+ALTER TABLE ugs_db.UserVotes
+    ADD COLUMN CreatedAt DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    ADD KEY CreatedAt (CreatedAt);
@@ -0,0 +1,11 @@
+-- Projects.Stream holds the first two fragments of Name, e.g. //UE5/Main, so the polling queries
+-- find the projects of a request by index rather than with LIKE '%//UE5/Main%' over every row.
+-- It is filled in by the server, which also fills it in for existing projects at startup.
+
+ALTER TABLE ugs_db.Projects
+    ADD COLUMN Stream VARCHAR(512) NULL,
+    ADD KEY Stream (Stream);
+
+ALTER TABLE ugs_db.Badges ADD KEY ProjectId_Id (ProjectId, Id);
+ALTER TABLE ugs_db.Comments ADD KEY ProjectId_Id (ProjectId, Id);
+ALTER TABLE ugs_db.UserVotes ADD KEY ProjectId_Id (ProjectId, Id);
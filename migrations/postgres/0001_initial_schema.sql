@@ -0,0 +1,112 @@
+-- Initial schema for the PostgreSQL backend, mirroring migrations/mysql/0001_initial_schema.sql.
+-- Identifiers are quoted so they keep the PascalCase names the models are mapped to.
+
+CREATE TABLE IF NOT EXISTS "Projects" (
+    "Id" BIGSERIAL PRIMARY KEY,
+    "Name" VARCHAR(512) NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS "Users" (
+    "Id" BIGSERIAL PRIMARY KEY,
+    "Name" VARCHAR(128) NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS "Badges" (
+    "Id" BIGSERIAL PRIMARY KEY,
+    "ChangeNumber" INTEGER NOT NULL,
+    "BuildType" VARCHAR(128) NOT NULL,
+    "Result" VARCHAR(16) NOT NULL,
+    "Url" VARCHAR(1024) NOT NULL,
+    "ArchivePath" VARCHAR(1024) NOT NULL DEFAULT '',
+    "ProjectId" BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS "Badges_ProjectId_ChangeNumber" ON "Badges" ("ProjectId", "ChangeNumber");
+
+CREATE TABLE IF NOT EXISTS "Comments" (
+    "Id" BIGSERIAL PRIMARY KEY,
+    "ChangeNumber" INTEGER NOT NULL,
+    "UserName" VARCHAR(128) NOT NULL,
+    "Text" TEXT NOT NULL,
+    "Project" VARCHAR(512) NOT NULL,
+    "ProjectId" BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS "Comments_ProjectId_ChangeNumber" ON "Comments" ("ProjectId", "ChangeNumber");
+
+CREATE TABLE IF NOT EXISTS "UserVotes" (
+    "Id" BIGSERIAL PRIMARY KEY,
+    "Changelist" INTEGER NOT NULL,
+    "UserName" VARCHAR(128) NOT NULL,
+    "Verdict" VARCHAR(32) NOT NULL,
+    "Project" VARCHAR(512) NOT NULL,
+    "ProjectId" BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS "UserVotes_ProjectId_Changelist" ON "UserVotes" ("ProjectId", "Changelist");
+
+CREATE TABLE IF NOT EXISTS "Issues" (
+    "Id" BIGSERIAL PRIMARY KEY,
+    "Project" VARCHAR(512) NOT NULL,
+    "Summary" VARCHAR(200) NOT NULL,
+    "OwnerId" BIGINT NULL,
+    "NominatedById" BIGINT NULL,
+    "CreatedAt" TIMESTAMPTZ NOT NULL,
+    "AcknowledgedAt" TIMESTAMPTZ NULL,
+    "FixChange" INTEGER NOT NULL DEFAULT 0,
+    "ResolvedAt" TIMESTAMPTZ NULL
+);
+CREATE INDEX IF NOT EXISTS "Issues_ResolvedAt" ON "Issues" ("ResolvedAt");
+
+CREATE TABLE IF NOT EXISTS "IssueWatchers" (
+    "IssueId" BIGINT NOT NULL,
+    "UserId" BIGINT NOT NULL,
+    PRIMARY KEY ("IssueId", "UserId")
+);
+
+CREATE TABLE IF NOT EXISTS "IssueBuilds" (
+    "Id" BIGSERIAL PRIMARY KEY,
+    "IssueId" BIGINT NOT NULL,
+    "Stream" VARCHAR(512) NOT NULL,
+    "Change" INTEGER NOT NULL,
+    "JobName" VARCHAR(1024) NOT NULL,
+    "JobUrl" VARCHAR(1024) NOT NULL,
+    "JobStepName" VARCHAR(1024) NOT NULL,
+    "JobStepUrl" VARCHAR(1024) NOT NULL,
+    "ErrorUrl" VARCHAR(1024) NOT NULL,
+    "Outcome" INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS "IssueBuilds_IssueId" ON "IssueBuilds" ("IssueId");
+
+CREATE TABLE IF NOT EXISTS "IssueDiagnostics" (
+    "Id" BIGSERIAL PRIMARY KEY,
+    "IssueId" BIGINT NOT NULL,
+    "BuildId" BIGINT NULL,
+    "Message" VARCHAR(1000) NOT NULL,
+    "Url" VARCHAR(1024) NOT NULL
+);
+CREATE INDEX IF NOT EXISTS "IssueDiagnostics_IssueId" ON "IssueDiagnostics" ("IssueId");
+
+CREATE TABLE IF NOT EXISTS "Errors" (
+    "Id" BIGSERIAL PRIMARY KEY,
+    "Type" VARCHAR(32) NOT NULL,
+    "Text" TEXT NOT NULL,
+    "UserName" VARCHAR(128) NOT NULL,
+    "Project" VARCHAR(512) NULL,
+    "Timestamp" TIMESTAMPTZ NOT NULL,
+    "Version" VARCHAR(64) NOT NULL,
+    "IpAddress" VARCHAR(64) NOT NULL,
+    "ProjectId" BIGINT NULL
+);
+CREATE INDEX IF NOT EXISTS "Errors_Timestamp" ON "Errors" ("Timestamp");
+
+CREATE TABLE IF NOT EXISTS "Telemetry_v2" (
+    "Id" BIGSERIAL PRIMARY KEY,
+    "Action" VARCHAR(128) NOT NULL,
+    "Result" VARCHAR(128) NOT NULL,
+    "UserName" VARCHAR(128) NOT NULL,
+    "Project" VARCHAR(512) NOT NULL,
+    "Timestamp" TIMESTAMPTZ NOT NULL,
+    "Duration" REAL NOT NULL,
+    "Version" VARCHAR(64) NOT NULL,
+    "IpAddress" VARCHAR(64) NOT NULL,
+    "ProjectId" BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS "Telemetry_v2_Timestamp" ON "Telemetry_v2" ("Timestamp");
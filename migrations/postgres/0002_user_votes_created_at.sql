@@ -0,0 +1,5 @@
+-- UserVotes had no timestamp, so retention policies could not age out events. Rows that predate
+-- this migration are stamped with the time it runs.
+
+ALTER TABLE "UserVotes" ADD COLUMN "CreatedAt" TIMESTAMPTZ NOT NULL DEFAULT NOW();
+CREATE INDEX IF NOT EXISTS "UserVotes_CreatedAt" ON "UserVotes" ("CreatedAt");
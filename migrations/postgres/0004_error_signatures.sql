@@ -0,0 +1,6 @@
+-- Errors.Signature identifies the crash of a report by its normalized call stack, so that
+-- reports can be grouped into crash buckets. It is filled in by the server, which also fills it
+-- in for existing reports at startup.
+
+ALTER TABLE "Errors" ADD COLUMN "Signature" VARCHAR(16) NULL;
+CREATE INDEX IF NOT EXISTS "Errors_Signature" ON "Errors" ("Signature");
@@ -0,0 +1,111 @@
+-- Initial schema for the SQLite backend, mirroring migrations/mysql/0001_initial_schema.sql.
+
+CREATE TABLE IF NOT EXISTS Projects (
+    Id INTEGER PRIMARY KEY AUTOINCREMENT,
+    Name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS Users (
+    Id INTEGER PRIMARY KEY AUTOINCREMENT,
+    Name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS Badges (
+    Id INTEGER PRIMARY KEY AUTOINCREMENT,
+    ChangeNumber INTEGER NOT NULL,
+    BuildType TEXT NOT NULL,
+    Result TEXT NOT NULL,
+    Url TEXT NOT NULL,
+    ArchivePath TEXT NOT NULL DEFAULT '',
+    ProjectId INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS Badges_ProjectId_ChangeNumber ON Badges (ProjectId, ChangeNumber);
+
+CREATE TABLE IF NOT EXISTS Comments (
+    Id INTEGER PRIMARY KEY AUTOINCREMENT,
+    ChangeNumber INTEGER NOT NULL,
+    UserName TEXT NOT NULL,
+    Text TEXT NOT NULL,
+    Project TEXT NOT NULL,
+    ProjectId INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS Comments_ProjectId_ChangeNumber ON Comments (ProjectId, ChangeNumber);
+
+CREATE TABLE IF NOT EXISTS UserVotes (
+    Id INTEGER PRIMARY KEY AUTOINCREMENT,
+    Changelist INTEGER NOT NULL,
+    UserName TEXT NOT NULL,
+    Verdict TEXT NOT NULL,
+    Project TEXT NOT NULL,
+    ProjectId INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS UserVotes_ProjectId_Changelist ON UserVotes (ProjectId, Changelist);
+
+CREATE TABLE IF NOT EXISTS Issues (
+    Id INTEGER PRIMARY KEY AUTOINCREMENT,
+    Project TEXT NOT NULL,
+    Summary TEXT NOT NULL,
+    OwnerId INTEGER NULL,
+    NominatedById INTEGER NULL,
+    CreatedAt DATETIME NOT NULL,
+    AcknowledgedAt DATETIME NULL,
+    FixChange INTEGER NOT NULL DEFAULT 0,
+    ResolvedAt DATETIME NULL
+);
+CREATE INDEX IF NOT EXISTS Issues_ResolvedAt ON Issues (ResolvedAt);
+
+CREATE TABLE IF NOT EXISTS IssueWatchers (
+    IssueId INTEGER NOT NULL,
+    UserId INTEGER NOT NULL,
+    PRIMARY KEY (IssueId, UserId)
+);
+
+CREATE TABLE IF NOT EXISTS IssueBuilds (
+    Id INTEGER PRIMARY KEY AUTOINCREMENT,
+    IssueId INTEGER NOT NULL,
+    Stream TEXT NOT NULL,
+    "Change" INTEGER NOT NULL,
+    JobName TEXT NOT NULL,
+    JobUrl TEXT NOT NULL,
+    JobStepName TEXT NOT NULL,
+    JobStepUrl TEXT NOT NULL,
+    ErrorUrl TEXT NOT NULL,
+    Outcome INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS IssueBuilds_IssueId ON IssueBuilds (IssueId);
+
+CREATE TABLE IF NOT EXISTS IssueDiagnostics (
+    Id INTEGER PRIMARY KEY AUTOINCREMENT,
+    IssueId INTEGER NOT NULL,
+    BuildId INTEGER NULL,
+    Message TEXT NOT NULL,
+    Url TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS IssueDiagnostics_IssueId ON IssueDiagnostics (IssueId);
+
+CREATE TABLE IF NOT EXISTS Errors (
+    Id INTEGER PRIMARY KEY AUTOINCREMENT,
+    Type TEXT NOT NULL,
+    Text TEXT NOT NULL,
+    UserName TEXT NOT NULL,
+    Project TEXT NULL,
+    Timestamp DATETIME NOT NULL,
+    Version TEXT NOT NULL,
+    IpAddress TEXT NOT NULL,
+    ProjectId INTEGER NULL
+);
+CREATE INDEX IF NOT EXISTS Errors_Timestamp ON Errors (Timestamp);
+
+CREATE TABLE IF NOT EXISTS Telemetry_v2 (
+    Id INTEGER PRIMARY KEY AUTOINCREMENT,
+    Action TEXT NOT NULL,
+    Result TEXT NOT NULL,
+    UserName TEXT NOT NULL,
+    Project TEXT NOT NULL,
+    Timestamp DATETIME NOT NULL,
+    Duration REAL NOT NULL,
+    Version TEXT NOT NULL,
+    IpAddress TEXT NOT NULL,
+    ProjectId INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS Telemetry_v2_Timestamp ON Telemetry_v2 (Timestamp);
@@ -0,0 +1,6 @@
+-- UserVotes had no timestamp, so retention policies could not age out events. Rows that predate
+-- this migration are stamped with the time it runs.
+
+ALTER TABLE UserVotes ADD COLUMN CreatedAt DATETIME NULL;
+UPDATE UserVotes SET CreatedAt = datetime('now');
+CREATE INDEX IF NOT EXISTS UserVotes_CreatedAt ON UserVotes (CreatedAt);
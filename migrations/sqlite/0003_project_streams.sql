@@ -0,0 +1,10 @@
+-- Projects.Stream holds the first two fragments of Name, e.g. //UE5/Main, so the polling queries
+-- find the projects of a request by index rather than with LIKE '%//UE5/Main%' over every row.
+-- It is filled in by the server, which also fills it in for existing projects at startup.
+
+ALTER TABLE Projects ADD COLUMN Stream TEXT NULL;
+CREATE INDEX IF NOT EXISTS Projects_Stream ON Projects (Stream);
+
+CREATE INDEX IF NOT EXISTS Badges_ProjectId_Id ON Badges (ProjectId, Id);
+CREATE INDEX IF NOT EXISTS Comments_ProjectId_Id ON Comments (ProjectId, Id);
+CREATE INDEX IF NOT EXISTS UserVotes_ProjectId_Id ON UserVotes (ProjectId, Id);
@@ -0,0 +1,78 @@
+//! Timing telemetry of the UGS clients as Prometheus histograms, served by `GET /metrics/clients`
+//! apart from the metrics of the server itself. They are aggregated in memory as posts come in,
+//! so they start over when the server restarts, as Prometheus expects of counters.
+
+use crate::metrics::{self, LabelValues};
+use crate::models;
+use lazy_static::lazy_static;
+use prometheus::{HistogramOpts, HistogramVec, Registry};
+
+/// Upper bounds, in seconds, of the duration buckets: from quick actions such as opening a
+/// workspace up to full syncs and builds taking hours.
+pub const DURATION_BUCKETS: [f64; 14] = [
+    0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1200.0, 1800.0, 3600.0, 7200.0,
+];
+
+/// Distinct values each label of the histograms takes at most, before the rest are counted as
+/// `metrics::OTHER_LABEL`.
+pub const MAX_ACTIONS: usize = 50;
+pub const MAX_RESULTS: usize = 20;
+pub const MAX_PROJECTS: usize = 100;
+pub const MAX_VERSIONS: usize = 50;
+
+struct ClientMetrics {
+    registry: Registry,
+    /// By `action`, `result`, `project` and client `version`.
+    durations: HistogramVec,
+    actions: LabelValues,
+    results: LabelValues,
+    projects: LabelValues,
+    versions: LabelValues,
+}
+
+impl ClientMetrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        let durations = HistogramVec::new(
+            HistogramOpts::new(
+                "ugs_client_duration_seconds",
+                "Durations UGS clients posted as timing telemetry.",
+            )
+            .buckets(DURATION_BUCKETS.to_vec()),
+            &["action", "result", "project", "version"],
+        )
+        .unwrap();
+        registry.register(Box::new(durations.clone())).unwrap();
+        ClientMetrics {
+            registry,
+            durations,
+            actions: LabelValues::new(MAX_ACTIONS),
+            results: LabelValues::new(MAX_RESULTS),
+            projects: LabelValues::new(MAX_PROJECTS),
+            versions: LabelValues::new(MAX_VERSIONS),
+        }
+    }
+}
+
+lazy_static! {
+    static ref CLIENT_METRICS: ClientMetrics = ClientMetrics::new();
+}
+
+/// Adds the duration of `post` to its histogram. Called once per post the server accepted.
+pub fn observe(post: &models::TelemetryPost) {
+    let metrics = &*CLIENT_METRICS;
+    metrics
+        .durations
+        .with_label_values(&[
+            metrics.actions.get(&post.data.action),
+            metrics.results.get(&post.data.result),
+            metrics.projects.get(&post.data.project),
+            metrics.versions.get(&post.version),
+        ])
+        .observe(f64::from(post.data.duration));
+}
+
+/// Every client metric in the Prometheus text format.
+pub fn render() -> String {
+    metrics::encode(&CLIENT_METRICS.registry.gather())
+}
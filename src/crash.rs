@@ -0,0 +1,149 @@
+//! Grouping of crash reports. The call stack in `TelemetryErrorData.text` is normalized, so that
+//! reports of the same crash from different builds, machines and users share a signature, which
+//! the stores keep in `Errors.Signature` and group by.
+
+use crate::models;
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use regex::Regex;
+use rocket_db_pools::sqlx::FromRow;
+use std::collections::{BTreeSet, HashMap};
+
+/// How many normalized lines from the top of a stack make up its signature. Deeper frames are
+/// mostly the UI or thread plumbing the crash was reached through.
+pub const SIGNATURE_LINES: usize = 10;
+
+lazy_static! {
+    /// `System.IO.IOException` in `System.IO.IOException: The process cannot access the file...`,
+    /// also after the `--->` of an inner exception. The message is dropped, as it often names
+    /// files, users or numbers.
+    static ref EXCEPTION: Regex =
+        Regex::new(r#"^(?:--->\s*)?([\w.`+]+(?:Exception|Error))\b"#).unwrap();
+    /// ` in D:\Build\Source\File.cs:line 123` after a .NET frame.
+    static ref SOURCE_LOCATION: Regex = Regex::new(r#"\s+in\s+\S.*:line\s+\d+\s*$"#).unwrap();
+    /// Windows, UNC and Unix paths of at least one directory.
+    static ref PATH: Regex =
+        Regex::new(r#"(?:[A-Za-z]:|\\\\[^\\\s]+)?[\\/](?:[^\\/\s:()\[\]']+[\\/])+[^\\/\s:()\[\]']*"#)
+            .unwrap();
+    /// `0x00007ff6a1b2c3d4`, `+0x1a` and bare addresses such as `00007FF6A1B2C3D4`.
+    static ref ADDRESS: Regex =
+        Regex::new(r#"\+?0x[0-9A-Fa-f]+\b|\b[0-9A-Fa-f]{8,}\b"#).unwrap();
+    /// `:line 123`, `:123` and `:123:4` left behind once the path before them is gone.
+    static ref LINE_NUMBER: Regex = Regex::new(r#":line\s+\d+|:\d+(?::\d+)?\b"#).unwrap();
+    static ref NUMBER: Regex = Regex::new(r#"\b\d+\b"#).unwrap();
+    static ref EMPTY_BRACKETS: Regex = Regex::new(r#"\[\s*\]"#).unwrap();
+    static ref SPACE: Regex = Regex::new(r#"\s+"#).unwrap();
+}
+
+/// The lines of the call stack `text` without addresses, paths, line numbers and exception
+/// messages, e.g. `UnrealGameSync.WorkspaceControl.UpdateBuildSteps()` for
+/// `   at UnrealGameSync.WorkspaceControl.UpdateBuildSteps() in D:\...\WorkspaceControl.cs:line 1234`.
+pub fn normalize(text: &str) -> Vec<String> {
+    text.lines().filter_map(normalize_line).collect()
+}
+
+fn normalize_line(line: &str) -> Option<String> {
+    let line = line.trim();
+    // .NET separates inner exceptions and rethrows with `--- End of ... ---`.
+    if line.is_empty() || line.starts_with("--- End of") {
+        return None;
+    }
+    if let Some(captures) = EXCEPTION.captures(line) {
+        return Some(captures[1].to_string());
+    }
+    let line = line.strip_prefix("at ").unwrap_or(line);
+    let line = SOURCE_LOCATION.replace(line, "");
+    let line = PATH.replace_all(&line, "");
+    let line = ADDRESS.replace_all(&line, "");
+    let line = LINE_NUMBER.replace_all(&line, "");
+    let line = NUMBER.replace_all(&line, "#");
+    let line = EMPTY_BRACKETS.replace_all(&line, "");
+    let line = SPACE.replace_all(&line, " ");
+    let line = line.trim();
+    match line.is_empty() {
+        true => None,
+        false => Some(line.to_string()),
+    }
+}
+
+/// Signature of the crash with the call stack `text`: 16 hex digits hashing its first
+/// `SIGNATURE_LINES` normalized lines.
+pub fn signature(text: &str) -> String {
+    let lines = normalize(text);
+    let top = &lines[..lines.len().min(SIGNATURE_LINES)];
+    format!("{:016x}", fnv1a(top.join("\n").as_bytes()))
+}
+
+/// 64-bit FNV-1a. Signatures are stored, so unlike `DefaultHasher` the hash must never change
+/// between builds.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Counts of the reports of one signature, as the stores query them for `buckets`.
+#[derive(Debug, Clone, FromRow)]
+#[sqlx(rename_all = "PascalCase")]
+pub struct CrashCounts {
+    pub signature: String,
+    pub count: i64,
+    pub user_count: i64,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub last_error_id: i64,
+}
+
+/// A distinct version, user and project that reported the crash with `signature`.
+#[derive(Debug, Clone, FromRow)]
+#[sqlx(rename_all = "PascalCase")]
+pub struct CrashReporter {
+    pub signature: String,
+    pub version: String,
+    pub user_name: String,
+    pub project: Option<String>,
+}
+
+/// Puts together the `CrashBucket`s of `counts`, in the same order. `texts` are the call stacks
+/// of the `last_error_id`s.
+pub fn buckets(
+    counts: Vec<CrashCounts>,
+    reporters: Vec<CrashReporter>,
+    texts: Vec<(i64, String)>,
+) -> Vec<models::CrashBucket> {
+    let mut reporters_by_signature: HashMap<String, Vec<CrashReporter>> = HashMap::new();
+    for reporter in reporters {
+        reporters_by_signature
+            .entry(reporter.signature.clone())
+            .or_default()
+            .push(reporter);
+    }
+    let texts: HashMap<i64, String> = texts.into_iter().collect();
+    counts
+        .into_iter()
+        .map(|counts| {
+            let reporters = reporters_by_signature
+                .remove(&counts.signature)
+                .unwrap_or_default();
+            let distinct = |field: fn(&CrashReporter) -> Option<&String>| {
+                let values: BTreeSet<&String> = reporters.iter().filter_map(field).collect();
+                values.into_iter().cloned().collect()
+            };
+            models::CrashBucket {
+                frames: texts
+                    .get(&counts.last_error_id)
+                    .map(|text| normalize(text))
+                    .unwrap_or_default(),
+                versions: distinct(|reporter| Some(&reporter.version)),
+                users: distinct(|reporter| Some(&reporter.user_name)),
+                projects: distinct(|reporter| reporter.project.as_ref()),
+                signature: counts.signature,
+                count: counts.count,
+                user_count: counts.user_count,
+                first_seen: counts.first_seen,
+                last_seen: counts.last_seen,
+                last_error_id: counts.last_error_id,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,322 @@
+//! Background check of the crash report rate of every client version and project, which alerts
+//! the configured webhooks when a rate jumps well above its baseline, e.g. after a new UGS build
+//! was rolled out.
+
+use crate::models;
+use crate::sql::{Result, SharedStore};
+use crate::webhook;
+use chrono::{DateTime, Utc};
+use rocket::fairing::AdHoc;
+use rocket::figment::Figment;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::time::Duration;
+
+/// The `crash_alerts` section of the Rocket config. Nothing is checked without `webhooks`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CrashAlertConfig {
+    /// Seconds between checks. `0` disables them.
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+    /// Seconds of the latest reports, whose rate is checked.
+    #[serde(default = "default_window")]
+    pub window: u64,
+    /// Seconds before `window` over which the baseline rate is measured.
+    #[serde(default = "default_baseline")]
+    pub baseline: u64,
+    /// How many times its baseline rate the rate of a version or project must exceed to alert.
+    #[serde(default = "default_threshold")]
+    pub threshold: f64,
+    /// Reports within `window` below which there is no alert, however low the baseline. A new
+    /// version has no baseline at all.
+    #[serde(default = "default_min_reports")]
+    pub min_reports: i64,
+    /// Seconds before the same version or project is alerted about again.
+    #[serde(default = "default_cooldown")]
+    pub cooldown: u64,
+    /// Seconds to wait for a webhook to answer.
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    #[serde(default)]
+    pub webhooks: Vec<String>,
+}
+
+impl Default for CrashAlertConfig {
+    fn default() -> Self {
+        CrashAlertConfig {
+            interval: default_interval(),
+            window: default_window(),
+            baseline: default_baseline(),
+            threshold: default_threshold(),
+            min_reports: default_min_reports(),
+            cooldown: default_cooldown(),
+            timeout: default_timeout(),
+            webhooks: Vec::new(),
+        }
+    }
+}
+
+impl CrashAlertConfig {
+    pub fn from_figment(figment: &Figment) -> std::result::Result<Self, String> {
+        let config = match figment.extract_inner::<CrashAlertConfig>("crash_alerts") {
+            Ok(config) => config,
+            Err(e) if e.missing() => CrashAlertConfig::default(),
+            Err(e) => return Err(e.to_string()),
+        };
+
+        if config.window == 0 || config.baseline == 0 {
+            return Err(String::from("`window` and `baseline` must be at least 1."));
+        }
+        if config.threshold.is_nan() || config.threshold < 1.0 {
+            return Err(String::from("`threshold` must be at least 1."));
+        }
+        for url in &config.webhooks {
+            if let Err(e) = webhook::validate(url) {
+                return Err(format!("webhook `{}`: {}", url, e));
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// What the reports of a `CrashSpike` have in common.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub enum SpikeScope {
+    Version,
+    Project,
+}
+
+/// A client version or project whose crash rate is above `threshold` times its baseline.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "PascalCase")]
+pub struct CrashSpike {
+    pub scope: SpikeScope,
+    /// The version, or the project.
+    pub name: String,
+    /// Reports within `window`.
+    pub count: i64,
+    /// Reports within `baseline`.
+    pub baseline_count: i64,
+    /// Reports an hour within `window`.
+    pub rate: f64,
+    /// Reports an hour within `baseline`.
+    pub baseline_rate: f64,
+}
+
+impl fmt::Display for CrashSpike {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let scope = match self.scope {
+            SpikeScope::Version => "UGS version",
+            SpikeScope::Project => "project",
+        };
+        write!(
+            f,
+            "Crash spike in {} {}: {} reports, {:.1} an hour against {:.1} an hour before.",
+            scope, self.name, self.count, self.rate, self.baseline_rate
+        )
+    }
+}
+
+// Public Functions:
+
+/// The spikes among the `recent` counts, which cover `config.window`, against the `baseline`
+/// counts, which cover `config.baseline`. Ordered by scope and name.
+pub fn find_spikes(
+    recent: &[models::ErrorCount],
+    baseline: &[models::ErrorCount],
+    config: &CrashAlertConfig,
+) -> Vec<CrashSpike> {
+    let baseline_counts = totals(baseline);
+    totals(recent)
+        .into_iter()
+        .filter_map(|((scope, name), count)| {
+            let baseline_count = baseline_counts
+                .get(&(scope, name.clone()))
+                .copied()
+                .unwrap_or(0);
+            let rate = hourly_rate(count, config.window);
+            let baseline_rate = hourly_rate(baseline_count, config.baseline);
+            let spiking = count >= config.min_reports && rate > config.threshold * baseline_rate;
+            spiking.then_some(CrashSpike {
+                scope,
+                name,
+                count,
+                baseline_count,
+                rate,
+                baseline_rate,
+            })
+        })
+        .collect()
+}
+
+/// Counts the reports of the `window` ending at `now` and of the `baseline` before it, and
+/// returns the spikes.
+pub async fn check(
+    store: &SharedStore,
+    config: &CrashAlertConfig,
+    now: DateTime<Utc>,
+) -> Result<Vec<CrashSpike>> {
+    let window_start = now - seconds(config.window);
+    let baseline_start = window_start - seconds(config.baseline);
+    let recent = store.get_error_counts(window_start, now).await?;
+    let baseline = store.get_error_counts(baseline_start, window_start).await?;
+    Ok(find_spikes(&recent, &baseline, config))
+}
+
+/// Sends spikes to the webhooks, at most once every `cooldown` for the same version or project.
+pub struct Alerter {
+    config: CrashAlertConfig,
+    /// When each version or project still cooling down was last alerted about.
+    pub last_alerted: HashMap<(SpikeScope, String), DateTime<Utc>>,
+}
+
+impl Alerter {
+    pub fn new(config: CrashAlertConfig) -> Self {
+        Alerter {
+            config,
+            last_alerted: HashMap::new(),
+        }
+    }
+
+    /// Posts every spike not alerted about within `cooldown` of `now` to every webhook, and
+    /// returns how many spikes that was. A webhook that fails is logged, not retried.
+    pub async fn alert(&mut self, spikes: &[CrashSpike], now: DateTime<Utc>) -> usize {
+        let cooldown = seconds(self.config.cooldown);
+        self.last_alerted.retain(|_, last| now - *last < cooldown);
+        let mut alerted = 0;
+        for spike in spikes {
+            let key = (spike.scope, spike.name.clone());
+            if self.last_alerted.contains_key(&key) {
+                continue;
+            }
+            log::warn!("{}", spike);
+            let alert = Alert {
+                text: spike.to_string(),
+                spike,
+            };
+            for url in &self.config.webhooks {
+                let timeout = Duration::from_secs(self.config.timeout);
+                if let Err(e) = webhook::post(url, &alert, timeout).await {
+                    log::error!("Could not send a crash alert to `{}`: {}", url, e);
+                }
+            }
+            self.last_alerted.insert(key, now);
+            alerted += 1;
+        }
+        alerted
+    }
+}
+
+/// Fairing that validates the `crash_alerts` config and, once the server is up, checks for crash
+/// spikes every `interval` seconds. Must be attached after `sql::stage()`.
+pub fn stage() -> AdHoc {
+    AdHoc::try_on_ignite("Crash Alerts", |rocket| async {
+        let config = match CrashAlertConfig::from_figment(rocket.figment()) {
+            Ok(config) => config,
+            Err(e) => {
+                log::error!("Invalid `crash_alerts` config: {}", e);
+                return Err(rocket);
+            }
+        };
+        if config.interval == 0 || config.webhooks.is_empty() {
+            return Ok(rocket);
+        }
+
+        Ok(
+            rocket.attach(AdHoc::on_liftoff("Crash Alerts Job", |rocket| {
+                let store = rocket.state::<SharedStore>().cloned();
+                Box::pin(async move {
+                    match store {
+                        Some(store) => {
+                            tokio::spawn(run_periodically(store, config));
+                        }
+                        None => log::error!("Crash alerts are disabled: no metadata store."),
+                    }
+                })
+            })),
+        )
+    })
+}
+
+// Private Functions:
+
+/// Body of a webhook request. `text` is the field Slack and Microsoft Teams incoming webhooks
+/// show; other receivers can read the fields of the spike.
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+struct Alert<'a> {
+    text: String,
+    #[serde(flatten)]
+    spike: &'a CrashSpike,
+}
+
+fn default_interval() -> u64 {
+    5 * 60
+}
+
+fn default_window() -> u64 {
+    60 * 60
+}
+
+fn default_baseline() -> u64 {
+    7 * 24 * 60 * 60
+}
+
+fn default_threshold() -> f64 {
+    3.0
+}
+
+fn default_min_reports() -> i64 {
+    10
+}
+
+fn default_cooldown() -> u64 {
+    6 * 60 * 60
+}
+
+fn default_timeout() -> u64 {
+    10
+}
+
+fn seconds(seconds: u64) -> chrono::Duration {
+    chrono::Duration::seconds(seconds as i64)
+}
+
+fn hourly_rate(count: i64, seconds: u64) -> f64 {
+    count as f64 * 3600.0 / seconds as f64
+}
+
+/// Report counts by version, and by project for the reports that name one.
+fn totals(counts: &[models::ErrorCount]) -> BTreeMap<(SpikeScope, String), i64> {
+    let mut totals = BTreeMap::new();
+    for count in counts {
+        *totals
+            .entry((SpikeScope::Version, count.version.clone()))
+            .or_default() += count.count;
+        if let Some(project) = &count.project {
+            *totals
+                .entry((SpikeScope::Project, project.clone()))
+                .or_default() += count.count;
+        }
+    }
+    totals
+}
+
+async fn run_periodically(store: SharedStore, config: CrashAlertConfig) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval));
+    let mut alerter = Alerter::new(config.clone());
+    loop {
+        interval.tick().await;
+        let now = Utc::now();
+        match check(&store, &config, now).await {
+            Ok(spikes) => {
+                alerter.alert(&spikes, now).await;
+            }
+            Err(e) => log::error!("Failed to check for crash spikes: {}", e),
+        }
+    }
+}
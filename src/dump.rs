@@ -0,0 +1,366 @@
+//! Portable dumps of the whole metadata database: one gzip-compressed JSON Lines file per table,
+//! with each line holding the `models` type the web APIs use for that table. Issues carry their
+//! builds, diagnostics and watchers along, so the ids those refer to can be remapped on import.
+
+use crate::models;
+use crate::sql::SharedStore;
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rocket::serde::json;
+use rocket::serde::{Deserialize, Serialize};
+use rocket_db_pools::sqlx;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const PAGE_SIZE: u32 = 1000;
+
+/// A table of a dump, in the order `import` replays them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpTable {
+    Projects,
+    Users,
+    Badges,
+    Comments,
+    UserVotes,
+    Issues,
+    Errors,
+    Telemetry,
+}
+
+impl DumpTable {
+    pub const ALL: [DumpTable; 8] = [
+        DumpTable::Projects,
+        DumpTable::Users,
+        DumpTable::Badges,
+        DumpTable::Comments,
+        DumpTable::UserVotes,
+        DumpTable::Issues,
+        DumpTable::Errors,
+        DumpTable::Telemetry,
+    ];
+
+    pub fn file_name(self) -> &'static str {
+        match self {
+            DumpTable::Projects => "projects.jsonl.gz",
+            DumpTable::Users => "users.jsonl.gz",
+            DumpTable::Badges => "badges.jsonl.gz",
+            DumpTable::Comments => "comments.jsonl.gz",
+            DumpTable::UserVotes => "user_votes.jsonl.gz",
+            DumpTable::Issues => "issues.jsonl.gz",
+            DumpTable::Errors => "errors.jsonl.gz",
+            DumpTable::Telemetry => "telemetry.jsonl.gz",
+        }
+    }
+}
+
+/// One row of a `DumpTable` as the stores read and write it. Issue builds, diagnostics and
+/// watchers go through the regular `MetadataStore` methods instead.
+pub enum DumpRow {
+    Project {
+        id: i64,
+        name: String,
+    },
+    User {
+        id: i64,
+        name: String,
+    },
+    Badge(models::BuildData),
+    Comment(models::CommentData),
+    Event {
+        event: models::EventData,
+        created_at: DateTime<Utc>,
+    },
+    Issue(models::IssueData),
+    Error(models::TelemetryErrorData),
+    Telemetry {
+        id: i64,
+        data: models::TelemetryTimingData,
+        version: String,
+        ip_address: String,
+    },
+}
+
+impl DumpRow {
+    /// Id of the row in the store it was exported from.
+    pub fn id(&self) -> i64 {
+        match self {
+            DumpRow::Project { id, .. } | DumpRow::User { id, .. } => *id,
+            DumpRow::Badge(build) => build.id,
+            DumpRow::Comment(comment) => comment.id,
+            DumpRow::Event { event, .. } => event.id,
+            DumpRow::Issue(issue) => issue.id,
+            DumpRow::Error(error) => error.id,
+            DumpRow::Telemetry { id, .. } => *id,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DumpError {
+    Io(PathBuf, io::Error),
+    Json(PathBuf, usize, json::serde_json::Error),
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for DumpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DumpError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            DumpError::Json(path, line, e) => write!(f, "{}:{}: {}", path.display(), line, e),
+            DumpError::Database(e) => write!(f, "database error: {}", e),
+        }
+    }
+}
+
+impl From<sqlx::Error> for DumpError {
+    fn from(e: sqlx::Error) -> Self {
+        DumpError::Database(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, DumpError>;
+
+// Public Functions:
+
+/// Writes every table of `store` to its file in `dir`, creating the directory if needed, and
+/// returns how many rows each table had.
+pub async fn export(store: &SharedStore, dir: &Path) -> Result<Vec<(DumpTable, u64)>> {
+    std::fs::create_dir_all(dir).map_err(|e| DumpError::Io(dir.to_path_buf(), e))?;
+
+    let mut report = Vec::with_capacity(DumpTable::ALL.len());
+    for table in DumpTable::ALL {
+        let path = dir.join(table.file_name());
+        let io_error = |e| DumpError::Io(path.clone(), e);
+        let file = File::create(&path).map_err(io_error)?;
+        let mut writer = BufWriter::new(GzEncoder::new(file, Compression::default()));
+
+        let mut rows = 0;
+        let mut after_id = 0;
+        loop {
+            let page = store.export_rows(table, after_id, PAGE_SIZE).await?;
+            let is_last_page = page.len() < PAGE_SIZE as usize;
+            after_id = page.last().map_or(after_id, DumpRow::id);
+            for row in page {
+                let record = to_record(store, row).await?;
+                json::serde_json::to_writer(&mut writer, &record)
+                    .map_err(|e| DumpError::Json(path.clone(), rows as usize + 1, e))?;
+                writer.write_all(b"\n").map_err(io_error)?;
+                rows += 1;
+            }
+            if is_last_page {
+                break;
+            }
+        }
+
+        let encoder = writer.into_inner().map_err(|e| io_error(e.into_error()))?;
+        encoder.finish().map_err(io_error)?;
+        report.push((table, rows));
+    }
+    Ok(report)
+}
+
+/// Adds every row of the dump in `dir` to `store` under new ids, and returns how many rows each
+/// table had. Tables without a file are skipped. Diagnostics that refer to a build missing from
+/// the dump lose that reference.
+pub async fn import(store: &SharedStore, dir: &Path) -> Result<Vec<(DumpTable, u64)>> {
+    let mut issue_build_ids = HashMap::new();
+    let mut report = Vec::with_capacity(DumpTable::ALL.len());
+    for table in DumpTable::ALL {
+        let path = dir.join(table.file_name());
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(DumpError::Io(path, e)),
+        };
+
+        let mut rows = 0;
+        for (index, line) in BufReader::new(GzDecoder::new(file)).lines().enumerate() {
+            let line = line.map_err(|e| DumpError::Io(path.clone(), e))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record =
+                from_line(table, &line).map_err(|e| DumpError::Json(path.clone(), index + 1, e))?;
+            import_record(store, record, &mut issue_build_ids).await?;
+            rows += 1;
+        }
+        report.push((table, rows));
+    }
+    Ok(report)
+}
+
+// Private Functions:
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "PascalCase")]
+struct NamedRecord {
+    id: i64,
+    name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "PascalCase")]
+struct EventRecord {
+    #[serde(flatten)]
+    event: models::EventData,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "PascalCase")]
+struct IssueRecord {
+    #[serde(flatten)]
+    issue: models::IssueData,
+    builds: Vec<Exported<models::IssueBuildData>>,
+    diagnostics: Vec<models::IssueDiagnosticData>,
+    watchers: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "PascalCase")]
+struct TelemetryRecord {
+    #[serde(flatten)]
+    data: models::TelemetryTimingData,
+    version: String,
+    ip_address: String,
+}
+
+/// Reads back the `Id` that the models skip when deserializing, for rows other rows refer to.
+/// `T` writes the `Id` itself.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Exported<T> {
+    #[serde(rename = "Id", skip_serializing)]
+    id: i64,
+    #[serde(flatten)]
+    data: T,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde", untagged)]
+enum Record {
+    Named(NamedRecord),
+    Build(models::BuildData),
+    Comment(models::CommentData),
+    Event(EventRecord),
+    Issue(IssueRecord),
+    Error(models::TelemetryErrorData),
+    Telemetry(TelemetryRecord),
+}
+
+async fn to_record(store: &SharedStore, row: DumpRow) -> Result<Record> {
+    Ok(match row {
+        DumpRow::Project { id, name } | DumpRow::User { id, name } => {
+            Record::Named(NamedRecord { id, name })
+        }
+        DumpRow::Badge(build) => Record::Build(build),
+        DumpRow::Comment(comment) => Record::Comment(comment),
+        DumpRow::Event { event, created_at } => Record::Event(EventRecord { event, created_at }),
+        DumpRow::Issue(issue) => Record::Issue(IssueRecord {
+            builds: store
+                .get_builds_by_issue(issue.id)
+                .await?
+                .into_iter()
+                .map(|build| Exported {
+                    id: build.id,
+                    data: build,
+                })
+                .collect(),
+            diagnostics: store.get_diagnostics(issue.id).await?,
+            watchers: store.get_watchers(issue.id).await?,
+            issue,
+        }),
+        DumpRow::Error(error) => Record::Error(error),
+        DumpRow::Telemetry {
+            data,
+            version,
+            ip_address,
+            ..
+        } => Record::Telemetry(TelemetryRecord {
+            data,
+            version,
+            ip_address,
+        }),
+    })
+}
+
+/// A line of a dump, ready to be added to a store.
+enum Imported {
+    Row(DumpRow),
+    Issue(IssueRecord),
+}
+
+fn from_line(table: DumpTable, line: &str) -> json::serde_json::Result<Imported> {
+    Ok(Imported::Row(match table {
+        DumpTable::Projects => {
+            let record: NamedRecord = json::from_str(line)?;
+            DumpRow::Project {
+                id: record.id,
+                name: record.name,
+            }
+        }
+        DumpTable::Users => {
+            let record: NamedRecord = json::from_str(line)?;
+            DumpRow::User {
+                id: record.id,
+                name: record.name,
+            }
+        }
+        DumpTable::Badges => DumpRow::Badge(json::from_str(line)?),
+        DumpTable::Comments => DumpRow::Comment(json::from_str(line)?),
+        DumpTable::UserVotes => {
+            let record: EventRecord = json::from_str(line)?;
+            DumpRow::Event {
+                event: record.event,
+                created_at: record.created_at,
+            }
+        }
+        DumpTable::Issues => return Ok(Imported::Issue(json::from_str(line)?)),
+        DumpTable::Errors => DumpRow::Error(json::from_str(line)?),
+        DumpTable::Telemetry => {
+            let record: TelemetryRecord = json::from_str(line)?;
+            DumpRow::Telemetry {
+                id: 0,
+                data: record.data,
+                version: record.version,
+                ip_address: record.ip_address,
+            }
+        }
+    }))
+}
+
+async fn import_record(
+    store: &SharedStore,
+    imported: Imported,
+    issue_build_ids: &mut HashMap<i64, i64>,
+) -> Result<()> {
+    let record = match imported {
+        Imported::Row(row) => {
+            store.import_row(&row).await?;
+            return Ok(());
+        }
+        Imported::Issue(record) => record,
+    };
+
+    let issue_id = store.import_row(&DumpRow::Issue(record.issue)).await?;
+    for build in &record.builds {
+        let build_id = store.add_build(issue_id, &build.data).await?;
+        issue_build_ids.insert(build.id, build_id);
+    }
+    for mut diagnostic in record.diagnostics {
+        diagnostic.build_id = diagnostic
+            .build_id
+            .and_then(|build_id| issue_build_ids.get(&build_id).copied());
+        store.add_diagnostic(issue_id, &diagnostic).await?;
+    }
+    for watcher in &record.watchers {
+        store.add_watcher(issue_id, watcher).await?;
+    }
+    Ok(())
+}
@@ -1,18 +1,57 @@
+mod client_metrics;
+mod crash;
+mod crash_alerts;
+mod dump;
+mod metrics;
 mod models;
+mod retention;
+mod spool;
 mod sql;
+mod telemetry_queue;
+mod telemetry_stats;
 mod web_apis;
+mod webhook;
 
+#[cfg(test)]
+mod tests;
+
+use rocket::{Build, Ignite, Rocket};
+use sql::lazy_pool::LazyPool;
 use rocket_db_pools::sqlx;
 use rocket_db_pools::Database;
+use std::path::PathBuf;
+
+#[derive(Database)]
+#[database("ugsdb")]
+pub struct UGSDatabase(LazyPool<sqlx::MySql>);
+
+#[derive(Database)]
+#[database("ugsdb")]
+pub struct SqliteDatabase(LazyPool<sqlx::Sqlite>);
 
 #[derive(Database)]
 #[database("ugsdb")]
-pub struct UGSDatabase(sqlx::MySqlPool);
+pub struct PgDatabase(LazyPool<sqlx::Postgres>);
 
-#[rocket::launch]
-fn ugs_metadata_server() -> _ {
-    rocket::build()
-        .attach(UGSDatabase::init())
+fn ugs_metadata_server() -> Rocket<Build> {
+    mount_apis(
+        rocket::build()
+            .attach(sql::stage())
+            .attach(metrics::stage())
+            .attach(retention::stage())
+            .attach(crash_alerts::stage())
+            .attach(spool::stage())
+            .attach(telemetry_queue::stage()),
+    )
+}
+
+/// Mounts every web API under `/api`, and the health probes and metrics at the root. The routes
+/// expect a managed `SharedStore`.
+fn mount_apis(rocket: Rocket<Build>) -> Rocket<Build> {
+    rocket
+        .mount("/", web_apis::health_api::routes())
+        .mount("/", web_apis::metrics_api::routes())
+        .mount("/api", web_apis::admin_api::routes())
         .mount("/api", web_apis::build_api::routes())
         .mount("/api", web_apis::comment_api::routes())
         .mount("/api", web_apis::error_api::routes())
@@ -23,3 +62,108 @@ fn ugs_metadata_server() -> _ {
         .mount("/api", web_apis::telemetry_api::routes())
         .mount("/api", web_apis::user_api::routes())
 }
+
+/// Applies any pending schema migrations and exits, without serving requests.
+async fn migrate() {
+    // Migrations run while igniting when `auto_migrate` is enabled.
+    let figment = rocket::Config::figment().merge(("databases.ugsdb.auto_migrate", true));
+    match rocket::custom(figment).attach(sql::stage()).ignite().await {
+        Ok(_) => log::info!("Database schema is up to date."),
+        Err(e) => {
+            eprintln!("Failed to migrate: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Brings up the configured metadata store without serving requests, exiting if that fails.
+async fn ignite_store() -> (Rocket<Ignite>, sql::SharedStore) {
+    let rocket = match rocket::build().attach(sql::stage()).ignite().await {
+        Ok(rocket) => rocket,
+        Err(e) => {
+            eprintln!("Failed to start: {e}");
+            std::process::exit(1);
+        }
+    };
+    let store = rocket
+        .state::<sql::SharedStore>()
+        .expect("sql::stage() manages a SharedStore")
+        .clone();
+    (rocket, store)
+}
+
+/// Applies the `retention` policies once, prints how many rows each one deleted and exits.
+async fn prune() {
+    let (rocket, store) = ignite_store().await;
+    let config = match retention::RetentionConfig::from_figment(rocket.figment()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid `retention` config: {e}");
+            std::process::exit(1);
+        }
+    };
+    if config.policies.is_empty() {
+        println!("No retention policies are configured.");
+        return;
+    }
+
+    match retention::run(&store, &config).await {
+        Ok(report) => {
+            for pruned in report {
+                println!("Pruned {} rows: {}.", pruned.rows, pruned.policy);
+            }
+        }
+        Err(e) => {
+            eprintln!("Failed to apply retention policies: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Dumps the whole database to `dir`, or adds a dump from `dir` to it, see `dump`.
+async fn transfer(command: &str, dir: Option<String>) {
+    let dir = match dir {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            eprintln!("Usage: ugs-metadata-server {command} <directory>");
+            std::process::exit(2);
+        }
+    };
+    let (_rocket, store) = ignite_store().await;
+    let result = if command == "export" {
+        dump::export(&store, &dir).await
+    } else {
+        dump::import(&store, &dir).await
+    };
+    match result {
+        Ok(report) => {
+            for (table, rows) in report {
+                println!("{}: {} rows", table.file_name(), rows);
+            }
+        }
+        Err(e) => {
+            eprintln!("Failed to {command} {}: {e}", dir.display());
+            std::process::exit(1);
+        }
+    }
+}
+
+#[rocket::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = args.first().map(String::as_str);
+    match command {
+        None | Some("serve") => {
+            let _ = ugs_metadata_server().launch().await;
+        }
+        Some("migrate") => migrate().await,
+        Some("prune") => prune().await,
+        Some(command @ ("export" | "import")) => transfer(command, args.get(1).cloned()).await,
+        Some(command) => {
+            eprintln!(
+                "Unknown command `{command}`. Expected one of: serve, migrate, prune, export, import."
+            );
+            std::process::exit(2);
+        }
+    }
+}
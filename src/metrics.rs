@@ -0,0 +1,200 @@
+//! Prometheus metrics of the server itself, served by `GET /metrics`: requests per route, queries
+//! and errors per store function, and what was stored per project. Gauges of the pool, spool and
+//! telemetry queue are read when scraped, see `web_apis::metrics_api`.
+
+use lazy_static::lazy_static;
+use prometheus::proto::MetricFamily;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use rocket::fairing::AdHoc;
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Every metric updated as requests come in, registered in one `Registry`.
+pub struct Metrics {
+    registry: Registry,
+    /// By `method`, `route` and `status`.
+    pub http_requests: IntCounterVec,
+    /// By `method` and `route`.
+    pub http_request_duration: HistogramVec,
+    /// By `MetadataStore` function, which is named after the `sql_connector` one it calls.
+    pub db_query_duration: HistogramVec,
+    pub db_query_errors: IntCounterVec,
+    /// Badges, comments, events, timing telemetry and error reports stored, by `kind` and `project`.
+    pub ingested: IntCounterVec,
+    ingested_projects: LabelValues,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        let http_requests = IntCounterVec::new(
+            Opts::new("ugs_http_requests_total", "HTTP requests answered."),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "ugs_http_request_duration_seconds",
+                "Time to answer an HTTP request.",
+            ),
+            &["method", "route"],
+        )
+        .unwrap();
+        let db_query_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "ugs_db_query_duration_seconds",
+                "Time a metadata store function took, including waiting for a connection.",
+            ),
+            &["function"],
+        )
+        .unwrap();
+        let db_query_errors = IntCounterVec::new(
+            Opts::new(
+                "ugs_db_query_errors_total",
+                "Metadata store function calls that failed.",
+            ),
+            &["function"],
+        )
+        .unwrap();
+        let ingested = IntCounterVec::new(
+            Opts::new("ugs_ingested_total", "Posts stored in the database."),
+            &["kind", "project"],
+        )
+        .unwrap();
+
+        registry.register(Box::new(http_requests.clone())).unwrap();
+        registry
+            .register(Box::new(http_request_duration.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(db_query_duration.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(db_query_errors.clone()))
+            .unwrap();
+        registry.register(Box::new(ingested.clone())).unwrap();
+        Metrics {
+            registry,
+            http_requests,
+            http_request_duration,
+            db_query_duration,
+            db_query_errors,
+            ingested,
+            ingested_projects: LabelValues::new(MAX_INGESTED_PROJECTS),
+        }
+    }
+}
+
+lazy_static! {
+    pub static ref METRICS: Metrics = Metrics::new();
+}
+
+/// Label of the requests no route matched, so that probing random paths adds no series.
+pub const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Distinct `project` labels of `ugs_ingested_total` at most.
+pub const MAX_INGESTED_PROJECTS: usize = 100;
+
+/// Label value the series of a `LabelValues` label share once it has taken as many values as it
+/// may.
+pub const OTHER_LABEL: &str = "other";
+
+/// The values a label taken from request bodies has had, up to `max`, so that clients can't add
+/// series without bound.
+pub struct LabelValues {
+    max: usize,
+    values: Mutex<HashSet<String>>,
+}
+
+impl LabelValues {
+    pub fn new(max: usize) -> Self {
+        LabelValues {
+            max,
+            values: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// `value` if the label has had it before or may still take a new value, `OTHER_LABEL`
+    /// otherwise.
+    pub fn get<'a>(&self, value: &'a str) -> &'a str {
+        let mut values = self.values.lock().unwrap();
+        if values.contains(value) || (values.len() < self.max && values.insert(String::from(value)))
+        {
+            value
+        } else {
+            OTHER_LABEL
+        }
+    }
+}
+
+/// The Prometheus text format of every metric in `METRICS`, followed by those of `snapshot`.
+pub fn render(snapshot: &Registry) -> String {
+    let mut families = METRICS.registry.gather();
+    families.extend(snapshot.gather());
+    encode(&families)
+}
+
+/// `families` in the Prometheus text format.
+pub fn encode(families: &[MetricFamily]) -> String {
+    let mut text = Vec::new();
+    TextEncoder::new()
+        .encode(families, &mut text)
+        .expect("metrics encode as text");
+    String::from_utf8(text).expect("metrics are UTF-8")
+}
+
+/// Counts `count` posts of `kind` stored for `project`, or for `OTHER_LABEL` once
+/// `MAX_INGESTED_PROJECTS` projects have been counted.
+pub fn ingested(kind: &str, project: &str, count: u64) {
+    METRICS
+        .ingested
+        .with_label_values(&[kind, METRICS.ingested_projects.get(project)])
+        .inc_by(count);
+}
+
+/// Fairing that counts and times every request by the route that answered it.
+pub fn stage() -> AdHoc {
+    AdHoc::on_ignite("Metrics", |rocket| async {
+        rocket
+            .attach(AdHoc::on_request("Request Start", |request, _| {
+                Box::pin(async move {
+                    request.local_cache(|| RequestStart(Instant::now()));
+                })
+            }))
+            .attach(AdHoc::on_response(
+                "Request Metrics",
+                |request, response| {
+                    Box::pin(async move {
+                        let elapsed = request
+                            .local_cache(|| RequestStart(Instant::now()))
+                            .0
+                            .elapsed();
+                        let route = request
+                            .route()
+                            .map_or(UNMATCHED_ROUTE, |route| route.uri.origin.path().as_str());
+                        let method = request.method().as_str();
+                        METRICS
+                            .http_requests
+                            .with_label_values(&[
+                                method,
+                                route,
+                                response.status().code.to_string().as_str(),
+                            ])
+                            .inc();
+                        METRICS
+                            .http_request_duration
+                            .with_label_values(&[method, route])
+                            .observe(elapsed.as_secs_f64());
+                    })
+                },
+            ))
+    })
+}
+
+// Private Functions:
+
+/// When the request came in, cached by the `Request Start` fairing.
+struct RequestStart(Instant);
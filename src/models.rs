@@ -1,15 +1,16 @@
 use chrono::serde::{ts_seconds, ts_seconds_option};
 use rocket::serde::{Deserialize, Serialize};
+use rocket::FromFormField;
 use serde_repr::{Serialize_repr, Deserialize_repr};
+use rocket_db_pools::sqlx::database::{HasArguments, HasValueRef};
 use rocket_db_pools::sqlx::encode::IsNull;
 use rocket_db_pools::sqlx::error::BoxDynError;
-use rocket_db_pools::sqlx::{Decode, Encode, FromRow, MySql, Type};
-use sqlx::mysql::{MySqlTypeInfo, MySqlValueRef};
+use rocket_db_pools::sqlx::{Database, Decode, Encode, FromRow, Type};
 use std::fmt;
 
 type DateTime = chrono::DateTime<chrono::Utc>;
 
-#[derive(Debug, PartialEq, Eq, Serialize_repr, Deserialize_repr)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr)]
 #[repr(i32)]
 pub enum BuildResult {
     Starting = 0,
@@ -25,24 +26,33 @@ impl fmt::Display for BuildResult {
     }
 }
 
-impl Type<MySql> for BuildResult {
-    fn type_info() -> MySqlTypeInfo {
-        <str as Type<MySql>>::type_info()
+impl<DB: Database> Type<DB> for BuildResult
+where
+    str: Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <str as Type<DB>>::type_info()
     }
 
-    fn compatible(ty: &MySqlTypeInfo) -> bool {
-        <str as Type<MySql>>::compatible(ty)
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <str as Type<DB>>::compatible(ty)
     }
 }
 
-impl Encode<'_, MySql> for BuildResult {
-    fn encode_by_ref(&self, buf: &mut Vec<u8>) -> IsNull {
-        <&str as Encode<MySql>>::encode(&*(self.to_string()), buf)
+impl<'q, DB: Database> Encode<'q, DB> for BuildResult
+where
+    String: Encode<'q, DB>,
+{
+    fn encode_by_ref(&self, buf: &mut <DB as HasArguments<'q>>::ArgumentBuffer) -> IsNull {
+        <String as Encode<DB>>::encode(self.to_string(), buf)
     }
 }
 
-impl Decode<'_, MySql> for BuildResult {
-    fn decode(value: MySqlValueRef<'_>) -> Result<Self, BoxDynError> {
+impl<'r, DB: Database> Decode<'r, DB> for BuildResult
+where
+    &'r str: Decode<'r, DB>,
+{
+    fn decode(value: <DB as HasValueRef<'r>>::ValueRef) -> Result<Self, BoxDynError> {
         let map_op = |from_str: &str| -> Self {
             match from_str {
                 "Starting" => Self::Starting,
@@ -53,11 +63,11 @@ impl Decode<'_, MySql> for BuildResult {
                 &_ => unimplemented!(),
             }
         };
-        <&str as Decode<MySql>>::decode(value).map(map_op)
+        <&str as Decode<DB>>::decode(value).map(map_op)
     }
 }
 
-#[derive(Serialize, Deserialize, FromRow)]
+#[derive(Serialize, Deserialize, FromRow, Clone)]
 #[sqlx(rename_all = "PascalCase")]
 #[serde(rename_all = "PascalCase")]
 pub struct BuildData {
@@ -71,7 +81,7 @@ pub struct BuildData {
     pub archive_path: String,
 }
 
-#[derive(Serialize, Deserialize, FromRow)]
+#[derive(Serialize, Deserialize, FromRow, Clone)]
 #[sqlx(rename_all = "PascalCase")]
 #[serde(rename_all = "PascalCase")]
 pub struct CommentData {
@@ -83,7 +93,7 @@ pub struct CommentData {
     pub project: String,
 }
 
-#[derive(Debug, PartialEq, Eq, Serialize_repr, Deserialize_repr)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr)]
 #[repr(i32)]
 pub enum EventType {
     Syncing = 0,
@@ -110,24 +120,33 @@ impl fmt::Display for EventType {
     }
 }
 
-impl Type<MySql> for EventType {
-    fn type_info() -> MySqlTypeInfo {
-        <str as Type<MySql>>::type_info()
+impl<DB: Database> Type<DB> for EventType
+where
+    str: Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <str as Type<DB>>::type_info()
     }
 
-    fn compatible(ty: &MySqlTypeInfo) -> bool {
-        <str as Type<MySql>>::compatible(ty)
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <str as Type<DB>>::compatible(ty)
     }
 }
 
-impl Encode<'_, MySql> for EventType {
-    fn encode_by_ref(&self, buf: &mut Vec<u8>) -> IsNull {
-        <&str as Encode<MySql>>::encode(&*(self.to_string()), buf)
+impl<'q, DB: Database> Encode<'q, DB> for EventType
+where
+    String: Encode<'q, DB>,
+{
+    fn encode_by_ref(&self, buf: &mut <DB as HasArguments<'q>>::ArgumentBuffer) -> IsNull {
+        <String as Encode<DB>>::encode(self.to_string(), buf)
     }
 }
 
-impl Decode<'_, MySql> for EventType {
-    fn decode(value: MySqlValueRef<'_>) -> Result<Self, BoxDynError> {
+impl<'r, DB: Database> Decode<'r, DB> for EventType
+where
+    &'r str: Decode<'r, DB>,
+{
+    fn decode(value: <DB as HasValueRef<'r>>::ValueRef) -> Result<Self, BoxDynError> {
         let map_op = |from_str: &str| -> Self {
             match from_str {
                 "Syncing" => Self::Syncing,
@@ -143,11 +162,11 @@ impl Decode<'_, MySql> for EventType {
                 &_ => unimplemented!(),
             }
         };
-        <&str as Decode<MySql>>::decode(value).map(map_op)
+        <&str as Decode<DB>>::decode(value).map(map_op)
     }
 }
 
-#[derive(Serialize, Deserialize, FromRow)]
+#[derive(Serialize, Deserialize, FromRow, Clone)]
 #[sqlx(rename_all = "PascalCase")]
 #[serde(rename_all = "PascalCase")]
 pub struct EventData {
@@ -175,24 +194,33 @@ impl fmt::Display for ReviewVerdict {
     }
 }
 
-impl Type<MySql> for ReviewVerdict {
-    fn type_info() -> MySqlTypeInfo {
-        <str as Type<MySql>>::type_info()
+impl<DB: Database> Type<DB> for ReviewVerdict
+where
+    str: Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <str as Type<DB>>::type_info()
     }
 
-    fn compatible(ty: &MySqlTypeInfo) -> bool {
-        <str as Type<MySql>>::compatible(ty)
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <str as Type<DB>>::compatible(ty)
     }
 }
 
-impl Encode<'_, MySql> for ReviewVerdict {
-    fn encode_by_ref(&self, buf: &mut Vec<u8>) -> IsNull {
-        <&str as Encode<MySql>>::encode(&*(self.to_string()), buf)
+impl<'q, DB: Database> Encode<'q, DB> for ReviewVerdict
+where
+    String: Encode<'q, DB>,
+{
+    fn encode_by_ref(&self, buf: &mut <DB as HasArguments<'q>>::ArgumentBuffer) -> IsNull {
+        <String as Encode<DB>>::encode(self.to_string(), buf)
     }
 }
 
-impl Decode<'_, MySql> for ReviewVerdict {
-    fn decode(value: MySqlValueRef<'_>) -> Result<Self, BoxDynError> {
+impl<'r, DB: Database> Decode<'r, DB> for ReviewVerdict
+where
+    &'r str: Decode<'r, DB>,
+{
+    fn decode(value: <DB as HasValueRef<'r>>::ValueRef) -> Result<Self, BoxDynError> {
         let map_op = |from_str: &str| -> Self {
             match from_str {
                 "Unknown" => Self::Unknown,
@@ -202,7 +230,7 @@ impl Decode<'_, MySql> for ReviewVerdict {
                 &_ => unimplemented!(),
             }
         };
-        <&str as Decode<MySql>>::decode(value).map(map_op)
+        <&str as Decode<DB>>::decode(value).map(map_op)
     }
 }
 
@@ -227,7 +255,7 @@ pub struct IssueWatcherData {
     pub user_name: String,
 }
 
-#[derive(Serialize, Deserialize, FromRow)]
+#[derive(Serialize, Deserialize, FromRow, Clone)]
 #[sqlx(rename_all = "PascalCase")]
 #[serde(rename_all = "PascalCase")]
 pub struct IssueBuildData {
@@ -250,7 +278,7 @@ pub struct IssueBuildUpdateData {
     pub outcome: i32,
 }
 
-#[derive(Serialize, Deserialize, FromRow)]
+#[derive(Serialize, Deserialize, FromRow, Clone)]
 #[sqlx(rename_all = "PascalCase")]
 #[serde(rename_all = "PascalCase")]
 pub struct IssueDiagnosticData {
@@ -293,7 +321,7 @@ pub struct IssueUpdateData {
     pub resolved: Option<bool>,
 }
 
-#[derive(Serialize)]
+#[derive(Debug, PartialEq, Eq, Serialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct LatestData {
     pub last_event_id: i64,
@@ -301,7 +329,7 @@ pub struct LatestData {
     pub last_build_id: i64,
 }
 
-#[derive(Debug, PartialEq, Eq, Serialize_repr, Deserialize_repr)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr, FromFormField)]
 #[repr(i32)]
 pub enum TelemetryErrorType {
     Crash = 0,
@@ -313,35 +341,44 @@ impl fmt::Display for TelemetryErrorType {
     }
 }
 
-impl Type<MySql> for TelemetryErrorType {
-    fn type_info() -> MySqlTypeInfo {
-        <str as Type<MySql>>::type_info()
+impl<DB: Database> Type<DB> for TelemetryErrorType
+where
+    str: Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <str as Type<DB>>::type_info()
     }
 
-    fn compatible(ty: &MySqlTypeInfo) -> bool {
-        <str as Type<MySql>>::compatible(ty)
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <str as Type<DB>>::compatible(ty)
     }
 }
 
-impl Encode<'_, MySql> for TelemetryErrorType {
-    fn encode_by_ref(&self, buf: &mut Vec<u8>) -> IsNull {
-        <&str as Encode<MySql>>::encode(&*(self.to_string()), buf)
+impl<'q, DB: Database> Encode<'q, DB> for TelemetryErrorType
+where
+    String: Encode<'q, DB>,
+{
+    fn encode_by_ref(&self, buf: &mut <DB as HasArguments<'q>>::ArgumentBuffer) -> IsNull {
+        <String as Encode<DB>>::encode(self.to_string(), buf)
     }
 }
 
-impl Decode<'_, MySql> for TelemetryErrorType {
-    fn decode(value: MySqlValueRef<'_>) -> Result<Self, BoxDynError> {
+impl<'r, DB: Database> Decode<'r, DB> for TelemetryErrorType
+where
+    &'r str: Decode<'r, DB>,
+{
+    fn decode(value: <DB as HasValueRef<'r>>::ValueRef) -> Result<Self, BoxDynError> {
         let map_op = |from_str: &str| -> Self {
             match from_str {
                 "Crash" => Self::Crash,
                 &_ => unimplemented!(),
             }
         };
-        <&str as Decode<MySql>>::decode(value).map(map_op)
+        <&str as Decode<DB>>::decode(value).map(map_op)
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
 #[sqlx(rename_all = "PascalCase")]
 #[serde(rename_all = "PascalCase")]
 pub struct TelemetryErrorData {
@@ -358,7 +395,56 @@ pub struct TelemetryErrorData {
     pub ip_address: String,
 }
 
-#[derive(Debug, Deserialize, FromRow)]
+/// Which error reports to return, newest first. Every filter that is set must match.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ErrorQuery {
+    /// Matched by stream, like the `project` parameter of the other GET APIs.
+    pub project: Option<String>,
+    pub user_name: Option<String>,
+    pub version: Option<String>,
+    pub error_type: Option<TelemetryErrorType>,
+    /// Reports from this time on.
+    pub from: Option<DateTime>,
+    /// Reports from before this time.
+    pub to: Option<DateTime>,
+    /// Crash signature, as computed by `crash::signature`.
+    pub signature: Option<String>,
+    /// Reports with a lower id, i.e. the ones after the last report of the previous page.
+    pub before_id: Option<i64>,
+    pub records: i32,
+}
+
+/// Number of error reports from one client version and project.
+#[derive(Debug, Clone, PartialEq, Eq, FromRow)]
+#[sqlx(rename_all = "PascalCase")]
+pub struct ErrorCount {
+    pub version: String,
+    pub project: Option<String>,
+    pub count: i64,
+}
+
+/// Error reports sharing a crash signature. Counts, times and lists cover the reports matching
+/// the `ErrorQuery` the bucket was returned for.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct CrashBucket {
+    pub signature: String,
+    /// Normalized call stack of the latest report.
+    pub frames: Vec<String>,
+    pub count: i64,
+    /// Distinct users that reported the crash.
+    pub user_count: i64,
+    #[serde(with = "ts_seconds")]
+    pub first_seen: DateTime,
+    #[serde(with = "ts_seconds")]
+    pub last_seen: DateTime,
+    pub last_error_id: i64,
+    pub versions: Vec<String>,
+    pub users: Vec<String>,
+    pub projects: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
 #[sqlx(rename_all = "PascalCase")]
 #[serde(rename_all = "PascalCase")]
 pub struct TelemetryTimingData {
@@ -370,3 +456,80 @@ pub struct TelemetryTimingData {
     pub timestamp: DateTime,
     pub duration: f32,
 }
+
+/// Which timing telemetry to aggregate, and how to group it. Every filter that is set must match.
+/// Samples are always grouped by `Action`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TimingQuery {
+    pub action: Option<String>,
+    /// Matched by stream, like the `project` parameter of the other GET APIs.
+    pub project: Option<String>,
+    pub version: Option<String>,
+    /// Samples from this time on.
+    pub from: Option<DateTime>,
+    /// Samples from before this time.
+    pub to: Option<DateTime>,
+    pub by_project: bool,
+    pub by_version: bool,
+    /// Seconds since the Unix epoch of the time buckets to group by.
+    pub bucket: Option<i64>,
+}
+
+/// The number of timing samples of one group of a `TimingQuery` with one `Result`. `Project`,
+/// `Version` and `Start` are only set when grouped by.
+#[derive(Debug, Clone, PartialEq, FromRow)]
+#[sqlx(rename_all = "PascalCase")]
+pub struct TimingCount {
+    pub action: String,
+    pub project: Option<String>,
+    pub version: Option<String>,
+    /// Start of the time bucket, in seconds since the Unix epoch.
+    pub start: Option<i64>,
+    pub result: String,
+    pub count: i64,
+}
+
+/// The duration of one timing sample, with its group of a `TimingQuery` as in `TimingCount`.
+#[derive(Debug, Clone, PartialEq, FromRow)]
+#[sqlx(rename_all = "PascalCase")]
+pub struct TimingSample {
+    pub action: String,
+    pub project: Option<String>,
+    pub version: Option<String>,
+    pub start: Option<i64>,
+    pub duration: f32,
+}
+
+/// Stats of the timing samples of one action, and of one project, version and time bucket when
+/// grouped by them. Durations are the nearest-rank percentiles, in the unit clients post, of the
+/// samples `get_timing_samples` returned for the group. They are left out if it returned none.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct TimingStats {
+    pub action: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Start of the time bucket.
+    #[serde(with = "ts_seconds_option", skip_serializing_if = "Option::is_none")]
+    pub start: Option<DateTime>,
+    pub count: i64,
+    pub successes: i64,
+    pub success_rate: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p50: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p90: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p99: Option<f32>,
+}
+
+/// A `TelemetryTimingData` post with the query parameters it came with.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct TelemetryPost {
+    pub data: TelemetryTimingData,
+    pub version: String,
+    pub ip_address: String,
+}
@@ -0,0 +1,203 @@
+use crate::sql::{Result, SharedStore};
+use chrono::{DateTime, Utc};
+use rocket::fairing::AdHoc;
+use rocket::figment::Figment;
+use rocket::serde::Deserialize;
+use rocket::tokio;
+use std::fmt;
+use std::time::Duration;
+
+/// Table a `RetentionPolicy` prunes, named as in the schema.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub enum RetentionTable {
+    #[serde(rename = "Telemetry_v2")]
+    Telemetry,
+    UserVotes,
+    Errors,
+}
+
+/// Deletes the rows of `table` older than `days`. `project` limits it to the projects the GET APIs
+/// would match for that `project` parameter, and `verdict` to `UserVotes` rows with that event type
+/// name, e.g. `Syncing`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct RetentionPolicy {
+    pub table: RetentionTable,
+    pub days: u32,
+    #[serde(default)]
+    pub project: Option<String>,
+    #[serde(default)]
+    pub verdict: Option<String>,
+}
+
+impl fmt::Display for RetentionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let table = match self.table {
+            RetentionTable::Telemetry => "Telemetry_v2",
+            RetentionTable::UserVotes => "UserVotes",
+            RetentionTable::Errors => "Errors",
+        };
+        write!(f, "{} older than {} days", table, self.days)?;
+        if let Some(verdict) = &self.verdict {
+            write!(f, " with verdict {}", verdict)?;
+        }
+        if let Some(project) = &self.project {
+            write!(f, " in {}", project)?;
+        }
+        Ok(())
+    }
+}
+
+/// The `retention` section of the Rocket config.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct RetentionConfig {
+    /// Seconds between background runs. `0` only prunes on demand, with `ugs-metadata-server prune`.
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+    /// Rows deleted per statement, so no single statement holds its locks for long.
+    #[serde(default = "default_batch_size")]
+    pub batch_size: u32,
+    /// Milliseconds to wait between batches, giving other writers a turn.
+    #[serde(default = "default_batch_pause")]
+    pub batch_pause: u64,
+    #[serde(default)]
+    pub policies: Vec<RetentionPolicy>,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        RetentionConfig {
+            interval: default_interval(),
+            batch_size: default_batch_size(),
+            batch_pause: default_batch_pause(),
+            policies: Vec::new(),
+        }
+    }
+}
+
+impl RetentionConfig {
+    pub fn from_figment(figment: &Figment) -> std::result::Result<Self, String> {
+        let config = match figment.extract_inner::<RetentionConfig>("retention") {
+            Ok(config) => config,
+            Err(e) if e.missing() => RetentionConfig::default(),
+            Err(e) => return Err(e.to_string()),
+        };
+
+        if config.batch_size == 0 {
+            return Err(String::from("`batch_size` must be at least 1."));
+        }
+        if let Some(policy) = config
+            .policies
+            .iter()
+            .find(|policy| policy.verdict.is_some() && policy.table != RetentionTable::UserVotes)
+        {
+            return Err(format!("`{}`: only UserVotes rows have a verdict.", policy));
+        }
+        Ok(config)
+    }
+}
+
+/// Number of rows one policy deleted.
+#[derive(Debug)]
+pub struct Pruned {
+    pub policy: RetentionPolicy,
+    pub rows: u64,
+}
+
+// Public Functions:
+
+/// Applies every policy once, batch by batch, and reports how many rows each one deleted.
+pub async fn run(store: &SharedStore, config: &RetentionConfig) -> Result<Vec<Pruned>> {
+    let mut report = Vec::with_capacity(config.policies.len());
+    for policy in &config.policies {
+        let rows = prune(store, config, policy, cutoff(policy.days)).await?;
+        report.push(Pruned {
+            policy: policy.clone(),
+            rows,
+        });
+    }
+    Ok(report)
+}
+
+/// Fairing that validates the `retention` config and, once the server is up, applies the policies
+/// every `interval` seconds. Must be attached after `sql::stage()`.
+pub fn stage() -> AdHoc {
+    AdHoc::try_on_ignite("Data Retention", |rocket| async {
+        let config = match RetentionConfig::from_figment(rocket.figment()) {
+            Ok(config) => config,
+            Err(e) => {
+                log::error!("Invalid `retention` config: {}", e);
+                return Err(rocket);
+            }
+        };
+        if config.interval == 0 || config.policies.is_empty() {
+            return Ok(rocket);
+        }
+
+        Ok(
+            rocket.attach(AdHoc::on_liftoff("Data Retention Job", |rocket| {
+                let store = rocket.state::<SharedStore>().cloned();
+                Box::pin(async move {
+                    match store {
+                        Some(store) => {
+                            tokio::spawn(run_periodically(store, config));
+                        }
+                        None => log::error!("Data retention is disabled: no metadata store."),
+                    }
+                })
+            })),
+        )
+    })
+}
+
+// Private Functions:
+
+fn default_interval() -> u64 {
+    60 * 60
+}
+
+fn default_batch_size() -> u32 {
+    1000
+}
+
+fn default_batch_pause() -> u64 {
+    100
+}
+
+fn cutoff(days: u32) -> DateTime<Utc> {
+    Utc::now() - chrono::Duration::days(days.into())
+}
+
+async fn prune(
+    store: &SharedStore,
+    config: &RetentionConfig,
+    policy: &RetentionPolicy,
+    cutoff: DateTime<Utc>,
+) -> Result<u64> {
+    let mut rows = 0;
+    loop {
+        let deleted = store.prune(policy, cutoff, config.batch_size).await?;
+        rows += deleted;
+        if deleted < u64::from(config.batch_size) {
+            return Ok(rows);
+        }
+        tokio::time::sleep(Duration::from_millis(config.batch_pause)).await;
+    }
+}
+
+async fn run_periodically(store: SharedStore, config: RetentionConfig) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval));
+    loop {
+        interval.tick().await;
+        match run(&store, &config).await {
+            Ok(report) => {
+                for pruned in report.iter().filter(|pruned| pruned.rows > 0) {
+                    log::info!("Pruned {} rows: {}.", pruned.rows, pruned.policy);
+                }
+            }
+            Err(e) => log::error!("Failed to apply retention policies: {}", e),
+        }
+    }
+}
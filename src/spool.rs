@@ -0,0 +1,466 @@
+//! Disk-backed spool for the POSTs of build badges, comments, events and telemetry that arrive
+//! while the database can't be reached. Each one is kept as a JSON file named after its sequence
+//! number in `spool.dir`, and replayed in that order through the store once the database is back.
+
+use crate::models;
+use crate::sql::availability::is_unavailable;
+use crate::sql::{Result, SharedStore};
+use chrono::{DateTime, Utc};
+use rocket::fairing::AdHoc;
+use rocket::figment::Figment;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::json;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio::{self, fs, io::AsyncWriteExt};
+use rocket_db_pools::sqlx;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Directory under `spool.dir` that posts the database rejected are moved to, so that they do not
+/// hold up the ones after them.
+const REJECTED_DIR: &str = "rejected";
+
+/// The `spool` section of the Rocket config. Spooling is disabled unless `dir` is set.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SpoolConfig {
+    pub dir: PathBuf,
+    /// Seconds between attempts to replay the spooled posts.
+    #[serde(default = "default_replay_interval")]
+    pub replay_interval: u64,
+    /// Posts the spool holds at most; once full, posts fail as if there were no spool.
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize,
+}
+
+impl SpoolConfig {
+    pub fn from_figment(figment: &Figment) -> std::result::Result<Option<Self>, String> {
+        let config = match figment.extract_inner::<SpoolConfig>("spool") {
+            Ok(config) => config,
+            Err(e) if e.missing() => return Ok(None),
+            Err(e) => return Err(e.to_string()),
+        };
+
+        if config.replay_interval == 0 {
+            return Err(String::from("`replay_interval` must be at least 1."));
+        }
+        Ok(Some(config))
+    }
+}
+
+/// A POST that can be spooled, with what its `MetadataStore` method takes.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", tag = "Kind", content = "Data")]
+pub enum SpooledPost {
+    Build(models::BuildData),
+    Comment(models::CommentData),
+    Event(models::EventData),
+    /// Replayed in one transaction, like the `/event/batch` post it came from.
+    EventBatch(Vec<models::EventData>),
+    Telemetry(models::TelemetryPost),
+    /// Replayed in one transaction, like the `/telemetry/batch` post it came from.
+    TelemetryBatch(Vec<models::TelemetryPost>),
+    #[serde(rename_all = "PascalCase")]
+    Error {
+        data: models::TelemetryErrorData,
+        version: String,
+        ip_address: String,
+    },
+}
+
+impl SpooledPost {
+    pub async fn post(&self, store: &SharedStore) -> Result<()> {
+        match self {
+            SpooledPost::Build(build) => store.post_build(build).await,
+            SpooledPost::Comment(comment) => store.post_comment(comment).await,
+            SpooledPost::Event(event) => store.post_event(event).await,
+            SpooledPost::EventBatch(events) => store.post_event_batch(events).await,
+            SpooledPost::Telemetry(telemetry) => {
+                store
+                    .post_telemetry_data(&telemetry.data, &telemetry.version, &telemetry.ip_address)
+                    .await
+            }
+            SpooledPost::TelemetryBatch(batch) => store.post_telemetry_batch(batch).await,
+            SpooledPost::Error {
+                data,
+                version,
+                ip_address,
+            } => store.post_error_data(data, version, ip_address).await,
+        }
+    }
+}
+
+/// One file of the spool.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "PascalCase")]
+pub struct SpoolEntry {
+    pub id: u64,
+    pub spooled_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub post: SpooledPost,
+}
+
+/// Why `submit` failed.
+#[derive(Debug)]
+pub enum SubmitError {
+    /// The store failed, or the database could not be reached and there is no spool.
+    Database(sqlx::Error),
+    /// The database could not be reached and the spool holds `max_entries` posts already.
+    SpoolFull,
+    /// The database could not be reached and the post could not be written to the spool.
+    Spool(io::Error),
+}
+
+impl fmt::Display for SubmitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SubmitError::Database(e) => write!(f, "{}", e),
+            SubmitError::SpoolFull => f.write_str("the spool is full"),
+            SubmitError::Spool(e) => write!(f, "could not spool the post: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SubmitError {}
+
+/// Whether a post went to the database or to the spool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Submitted {
+    Stored,
+    Spooled,
+}
+
+/// What one replay of the spool did.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "PascalCase")]
+pub struct ReplayReport {
+    pub replayed: usize,
+    pub rejected: usize,
+    pub remaining: usize,
+}
+
+struct State {
+    next_id: u64,
+    /// Ids of the spooled posts, oldest first.
+    pending: Vec<u64>,
+}
+
+pub struct Spool {
+    config: SpoolConfig,
+    state: Mutex<State>,
+    /// Held while writing a post, so that posts are spooled in the order of their ids.
+    appending: tokio::sync::Mutex<()>,
+    /// Held while replaying, so that posts are replayed once each and in order.
+    replaying: tokio::sync::Mutex<()>,
+}
+
+pub type SharedSpool = Arc<Spool>;
+
+impl Spool {
+    /// Opens the spool in `config.dir`, creating it if needed, with the posts left from before.
+    /// Ids carry on after the highest one pending or rejected, so that a new post never takes the
+    /// name of a rejected one.
+    pub fn open(config: SpoolConfig) -> io::Result<Spool> {
+        let rejected_dir = config.dir.join(REJECTED_DIR);
+        std::fs::create_dir_all(&rejected_dir)?;
+        let mut pending = entry_ids(&config.dir)?;
+        pending.sort_unstable();
+        let last_rejected = entry_ids(&rejected_dir)?.into_iter().max();
+        let next_id = pending
+            .last()
+            .copied()
+            .max(last_rejected)
+            .map_or(1, |id| id + 1);
+        Ok(Spool {
+            config,
+            state: Mutex::new(State { next_id, pending }),
+            appending: tokio::sync::Mutex::new(()),
+            replaying: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    /// Number of posts waiting to be replayed.
+    pub fn depth(&self) -> usize {
+        self.state.lock().unwrap().pending.len()
+    }
+
+    /// Writes `post` to the database, or spools it if the database can't be reached. Posts are
+    /// also spooled while older ones are, so that they are not stored ahead of them.
+    pub async fn submit(
+        &self,
+        store: &SharedStore,
+        post: SpooledPost,
+    ) -> std::result::Result<Submitted, SubmitError> {
+        if self.depth() == 0 {
+            match post.post(store).await {
+                Err(e) if is_unavailable(&e) => {}
+                result => {
+                    return result
+                        .map(|()| Submitted::Stored)
+                        .map_err(SubmitError::Database)
+                }
+            }
+        }
+        match self.append(post).await {
+            Ok(true) => Ok(Submitted::Spooled),
+            Ok(false) => Err(SubmitError::SpoolFull),
+            Err(e) => {
+                log::error!("Could not spool a post: {}", e);
+                Err(SubmitError::Spool(e))
+            }
+        }
+    }
+
+    /// Up to `limit` of the oldest spooled posts.
+    pub async fn entries(&self, limit: usize) -> io::Result<Vec<SpoolEntry>> {
+        let ids: Vec<u64> = self
+            .state
+            .lock()
+            .unwrap()
+            .pending
+            .iter()
+            .take(limit)
+            .copied()
+            .collect();
+        let mut entries = Vec::with_capacity(ids.len());
+        for id in ids {
+            match self.read(id).await {
+                Ok(entry) => entries.push(entry),
+                // Replayed since the ids were taken.
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Posts the spooled posts to `store` in order, until they are all stored or the database
+    /// can't be reached. Posts that fail for another reason are moved to `rejected/`.
+    pub async fn replay(&self, store: &SharedStore) -> ReplayReport {
+        let _replaying = self.replaying.lock().await;
+        let mut report = ReplayReport::default();
+        while let Some(id) = self.oldest() {
+            let result = match self.read(id).await {
+                Ok(entry) => match entry.post.post(store).await {
+                    Err(e) if is_unavailable(&e) => break,
+                    result => result.map_err(|e| e.to_string()),
+                },
+                Err(e) => Err(e.to_string()),
+            };
+            let done = match result {
+                Ok(()) => fs::remove_file(self.path(id)).await.map(|()| {
+                    report.replayed += 1;
+                }),
+                Err(e) => {
+                    log::error!(
+                        "Spooled post {} was rejected, moving it to `{}`: {}",
+                        id,
+                        REJECTED_DIR,
+                        e
+                    );
+                    let rejected = self.config.dir.join(REJECTED_DIR).join(file_name(id));
+                    move_aside(&self.path(id), &rejected).await.map(|()| {
+                        report.rejected += 1;
+                    })
+                }
+            };
+            if let Err(e) = done {
+                log::error!("Could not remove spooled post {}: {}", id, e);
+                break;
+            }
+            self.state.lock().unwrap().pending.remove(0);
+        }
+        report.remaining = self.depth();
+        if report.replayed > 0 || report.rejected > 0 {
+            log::info!(
+                "Replayed {} spooled posts, rejected {}, {} remaining.",
+                report.replayed,
+                report.rejected,
+                report.remaining
+            );
+        }
+        report
+    }
+
+    /// Writes `post` to a file of its own without trying the database first, and returns `false`
+    /// if the spool is full.
+    pub async fn append(&self, post: SpooledPost) -> io::Result<bool> {
+        let _appending = self.appending.lock().await;
+        let id = {
+            let mut state = self.state.lock().unwrap();
+            if state.pending.len() >= self.config.max_entries {
+                log::warn!("The spool is full, refusing posts until it is replayed.");
+                return Ok(false);
+            }
+            state.next_id += 1;
+            state.next_id - 1
+        };
+        let entry = SpoolEntry {
+            id,
+            spooled_at: Utc::now(),
+            post,
+        };
+        let contents = json::to_string(&entry).map_err(io::Error::other)?;
+
+        // Written under another name first, so that replay never reads a partial file.
+        let path = self.path(id);
+        let partial = path.with_extension("partial");
+        let mut file = fs::File::create(&partial).await?;
+        file.write_all(contents.as_bytes()).await?;
+        file.sync_all().await?;
+        fs::rename(&partial, &path).await?;
+
+        let mut state = self.state.lock().unwrap();
+        state.pending.push(id);
+        if state.pending.len() == 1 {
+            log::warn!("Spooling posts until the database is back.");
+        }
+        Ok(true)
+    }
+
+    fn oldest(&self) -> Option<u64> {
+        self.state.lock().unwrap().pending.first().copied()
+    }
+
+    async fn read(&self, id: u64) -> io::Result<SpoolEntry> {
+        let contents = fs::read(self.path(id)).await?;
+        json::from_slice(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn path(&self, id: u64) -> PathBuf {
+        self.config.dir.join(file_name(id))
+    }
+}
+
+/// Request guard for the spool, if one is configured. An `Option<&State<SharedSpool>>` guard would
+/// stop Rocket from launching without one.
+pub struct Spooler(pub Option<SharedSpool>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Spooler {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(Spooler(request.rocket().state::<SharedSpool>().cloned()))
+    }
+}
+
+impl Spooler {
+    /// Whether there is a spool with posts waiting in it, which new posts have to go after.
+    pub fn is_spooling(&self) -> bool {
+        self.0.as_ref().is_some_and(|spool| spool.depth() > 0)
+    }
+
+    /// `Spool::submit` with the spool, or a plain post to `store` without one.
+    pub async fn submit(
+        &self,
+        store: &SharedStore,
+        post: SpooledPost,
+    ) -> std::result::Result<Submitted, SubmitError> {
+        match &self.0 {
+            Some(spool) => spool.submit(store, post).await,
+            None => post
+                .post(store)
+                .await
+                .map(|()| Submitted::Stored)
+                .map_err(SubmitError::Database),
+        }
+    }
+}
+
+/// Fairing that opens the spool configured in `spool` and, once the server is up, replays it every
+/// `replay_interval` seconds. Must be attached after `sql::stage()`.
+pub fn stage() -> AdHoc {
+    AdHoc::try_on_ignite("Post Spool", |rocket| async {
+        let config = match SpoolConfig::from_figment(rocket.figment()) {
+            Ok(Some(config)) => config,
+            Ok(None) => return Ok(rocket),
+            Err(e) => {
+                log::error!("Invalid `spool` config: {}", e);
+                return Err(rocket);
+            }
+        };
+        let interval = Duration::from_secs(config.replay_interval);
+        let spool = match Spool::open(config) {
+            Ok(spool) => Arc::new(spool),
+            Err(e) => {
+                log::error!("Could not open the spool: {}", e);
+                return Err(rocket);
+            }
+        };
+        if spool.depth() > 0 {
+            log::info!("{} posts are spooled from before.", spool.depth());
+        }
+
+        let replayed = spool.clone();
+        Ok(rocket
+            .manage(spool)
+            .attach(AdHoc::on_liftoff("Post Spool Replay", move |rocket| {
+                let store = rocket.state::<SharedStore>().cloned();
+                Box::pin(async move {
+                    match store {
+                        Some(store) => {
+                            tokio::spawn(replay_periodically(store, replayed, interval));
+                        }
+                        None => log::error!("Spool replay is disabled: no metadata store."),
+                    }
+                })
+            })))
+    })
+}
+
+// Private Functions:
+
+fn default_replay_interval() -> u64 {
+    5
+}
+
+fn default_max_entries() -> usize {
+    100_000
+}
+
+fn file_name(id: u64) -> String {
+    format!("{:020}.json", id)
+}
+
+fn entry_id(path: &Path) -> Option<u64> {
+    if path.extension()? != "json" {
+        return None;
+    }
+    path.file_stem()?.to_str()?.parse().ok()
+}
+
+/// Ids of the spool files in `dir`.
+fn entry_ids(dir: &Path) -> io::Result<Vec<u64>> {
+    let mut ids = Vec::new();
+    for file in std::fs::read_dir(dir)? {
+        if let Some(id) = entry_id(&file?.path()) {
+            ids.push(id);
+        }
+    }
+    Ok(ids)
+}
+
+/// Renames `from` to `to`, failing rather than replacing a file already at `to`.
+async fn move_aside(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::metadata(to).await.is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("`{}` already exists", to.display()),
+        ));
+    }
+    fs::rename(from, to).await
+}
+
+async fn replay_periodically(store: SharedStore, spool: SharedSpool, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        if spool.depth() > 0 {
+            spool.replay(&store).await;
+        }
+    }
+}
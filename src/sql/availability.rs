@@ -0,0 +1,130 @@
+use crate::sql::Result;
+use rocket::serde::Deserialize;
+use rocket::tokio::time;
+use rocket_db_pools::sqlx::{self, Connection};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Whether `error` means the database could not be reached, rather than that a query failed.
+pub fn is_unavailable(error: &sqlx::Error) -> bool {
+    matches!(
+        error,
+        sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed
+            | sqlx::Error::WorkerCrashed
+    )
+}
+
+/// `databases.ugsdb.startup_retry`: how long to wait for the database to come up at launch before
+/// giving up, doubling the delay after every failed attempt.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct StartupRetry {
+    /// Connection attempts before giving up, including the first.
+    #[serde(default = "StartupRetry::default_attempts")]
+    pub attempts: u32,
+    /// Seconds to wait after the first failed attempt.
+    #[serde(default = "StartupRetry::default_initial_delay")]
+    pub initial_delay: u64,
+    /// Seconds the delay doubles up to.
+    #[serde(default = "StartupRetry::default_max_delay")]
+    pub max_delay: u64,
+}
+
+impl StartupRetry {
+    fn default_attempts() -> u32 {
+        8
+    }
+
+    fn default_initial_delay() -> u64 {
+        1
+    }
+
+    fn default_max_delay() -> u64 {
+        30
+    }
+
+    /// How long to wait after failed attempt `attempt`, counting from 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u64
+            .checked_shl(attempt.saturating_sub(1))
+            .unwrap_or(u64::MAX);
+        Duration::from_secs(
+            self.initial_delay
+                .saturating_mul(factor)
+                .min(self.max_delay),
+        )
+    }
+
+    /// Connects to `url` until it succeeds or the attempts run out, logging every failure. Only
+    /// errors that mean the database is unreachable are retried.
+    pub async fn wait_for<C: Connection>(&self, url: &str) -> Result<()> {
+        let mut attempt = 1;
+        loop {
+            let error = match C::connect(url).await {
+                Ok(connection) => return connection.close().await,
+                Err(e) => e,
+            };
+            if attempt >= self.attempts || !is_unavailable(&error) {
+                return Err(error);
+            }
+            let delay = self.delay(attempt);
+            log::warn!(
+                "Could not connect to the database (attempt {} of {}), retrying in {} seconds: {}",
+                attempt,
+                self.attempts,
+                delay.as_secs(),
+                error
+            );
+            time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+impl Default for StartupRetry {
+    fn default() -> Self {
+        StartupRetry {
+            attempts: StartupRetry::default_attempts(),
+            initial_delay: StartupRetry::default_initial_delay(),
+            max_delay: StartupRetry::default_max_delay(),
+        }
+    }
+}
+
+/// Whether the database of a store could be reached the last time it was used, so that an outage
+/// and the recovery from it are logged once each instead of on every request.
+#[derive(Default)]
+pub struct Availability {
+    unavailable_since: Mutex<Option<Instant>>,
+}
+
+impl Availability {
+    /// Takes note of the outcome of acquiring a connection, and passes it through.
+    pub fn record<T>(&self, result: Result<T>) -> Result<T> {
+        let mut unavailable_since = self.unavailable_since.lock().unwrap();
+        match &result {
+            Ok(_) => {
+                if let Some(since) = unavailable_since.take() {
+                    log::info!(
+                        "Database connection recovered after {} seconds.",
+                        since.elapsed().as_secs()
+                    );
+                }
+            }
+            Err(e) if is_unavailable(e) => {
+                if unavailable_since.is_none() {
+                    log::error!(
+                        "Database is unavailable, answering 503 until it is back: {}",
+                        e
+                    );
+                    *unavailable_since = Some(Instant::now());
+                }
+            }
+            Err(_) => {}
+        }
+        result
+    }
+}
@@ -0,0 +1,108 @@
+use lru::LruCache;
+use rocket::serde::Serialize;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Project name → id and normalized user name → id mappings, shared by every request to a store so
+/// that posting a build, comment or event does not take an INSERT and a SELECT to resolve them.
+///
+/// Rows of `Projects` and `Users` are never updated or deleted by the server, so entries only go
+/// stale if those tables are edited by hand; `invalidate` is for that.
+pub struct IdCache {
+    projects: Option<Mutex<LruCache<String, i64>>>,
+    users: Option<Mutex<LruCache<String, i64>>>,
+    capacity: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Snapshot of an `IdCache`, as returned by `GET /api/admin/cache`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "PascalCase")]
+pub struct IdCacheStats {
+    /// Entries kept per mapping; `0` means caching is disabled.
+    pub capacity: usize,
+    pub projects: usize,
+    pub users: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl IdCache {
+    pub const DEFAULT_CAPACITY: usize = 10_000;
+
+    /// Keeps up to `capacity` of the most recently used projects, and as many users. A `capacity`
+    /// of `0` disables the cache.
+    pub fn new(capacity: usize) -> Self {
+        let map =
+            || NonZeroUsize::new(capacity).map(|capacity| Mutex::new(LruCache::new(capacity)));
+        IdCache {
+            projects: map(),
+            users: map(),
+            capacity,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn project_id(&self, name: &str) -> Option<i64> {
+        self.get(&self.projects, name)
+    }
+
+    pub fn insert_project_id(&self, name: &str, id: i64) {
+        Self::insert(&self.projects, name, id);
+    }
+
+    /// Looks up a user by the name `sql_connector::normalize_user_name` returned.
+    pub fn user_id(&self, normalized_name: &str) -> Option<i64> {
+        self.get(&self.users, normalized_name)
+    }
+
+    pub fn insert_user_id(&self, normalized_name: &str, id: i64) {
+        Self::insert(&self.users, normalized_name, id);
+    }
+
+    /// Forgets every entry. The hit and miss counts are kept.
+    pub fn invalidate(&self) {
+        for map in [&self.projects, &self.users].into_iter().flatten() {
+            map.lock().unwrap().clear();
+        }
+    }
+
+    pub fn stats(&self) -> IdCacheStats {
+        let len = |map: &Option<Mutex<LruCache<String, i64>>>| {
+            map.as_ref().map_or(0, |map| map.lock().unwrap().len())
+        };
+        IdCacheStats {
+            capacity: self.capacity,
+            projects: len(&self.projects),
+            users: len(&self.users),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    fn get(&self, map: &Option<Mutex<LruCache<String, i64>>>, key: &str) -> Option<i64> {
+        let id = map.as_ref()?.lock().unwrap().get(key).copied();
+        let counter = if id.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        id
+    }
+
+    fn insert(map: &Option<Mutex<LruCache<String, i64>>>, key: &str, id: i64) {
+        if let Some(map) = map {
+            map.lock().unwrap().put(String::from(key), id);
+        }
+    }
+}
+
+impl Default for IdCache {
+    fn default() -> Self {
+        IdCache::new(IdCache::DEFAULT_CAPACITY)
+    }
+}
@@ -0,0 +1,208 @@
+use crate::dump::DumpRow;
+use crate::models;
+use crate::retention::RetentionTable;
+use crate::sql::sql_connector::get_project_stream;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How many of the most recent changes of a table `/latest` looks back over.
+pub const RECENT_CHANGE_COUNT: usize = 100;
+
+/// The lowest id of each of the `RECENT_CHANGE_COUNT` highest change numbers of a table.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChangeWindow(BTreeMap<i32, i64>);
+
+impl ChangeWindow {
+    /// Adds a row. Adding one that is already counted changes nothing, and the result does not
+    /// depend on the order rows are added in.
+    pub fn record(&mut self, change: i32, id: i64) {
+        let first_id = self.0.entry(change).or_insert(id);
+        *first_id = (*first_id).min(id);
+        if self.0.len() > RECENT_CHANGE_COUNT {
+            self.0.pop_first();
+        }
+    }
+
+    /// Id of the first row of the oldest change in the window, or `0` without any rows.
+    pub fn last_id(&self) -> i64 {
+        self.0.first_key_value().map_or(0, |(_, id)| *id)
+    }
+
+    fn merge(&mut self, other: &ChangeWindow) {
+        for (change, id) in &other.0 {
+            self.record(*change, *id);
+        }
+    }
+}
+
+impl FromIterator<(i32, i64)> for ChangeWindow {
+    fn from_iter<I: IntoIterator<Item = (i32, i64)>>(rows: I) -> Self {
+        let mut window = ChangeWindow::default();
+        for (change, id) in rows {
+            window.record(change, id);
+        }
+        window
+    }
+}
+
+/// The windows `/latest` is answered from, for the projects of one stream or for every project.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecentChanges {
+    pub events: ChangeWindow,
+    pub comments: ChangeWindow,
+    pub builds: ChangeWindow,
+}
+
+impl RecentChanges {
+    pub fn latest(&self) -> models::LatestData {
+        models::LatestData {
+            last_event_id: self.events.last_id(),
+            last_comment_id: self.comments.last_id(),
+            last_build_id: self.builds.last_id(),
+        }
+    }
+
+    fn merge(&mut self, other: &RecentChanges) {
+        self.events.merge(&other.events);
+        self.comments.merge(&other.comments);
+        self.builds.merge(&other.builds);
+    }
+}
+
+struct Entry {
+    changes: RecentChanges,
+    /// When the last load finished, or `None` while the first is running.
+    loaded_at: Option<Instant>,
+}
+
+/// `RecentChanges` per stream, so that `/latest` does not group and sort the last changes of
+/// `UserVotes`, `Comments` and `Badges` on every call. Entries are loaded from the database on
+/// first use and kept up to date by the posts of this server; they are reloaded once `max_age`
+/// old to pick up rows written by other servers or by hand.
+pub struct LatestCache {
+    entries: Option<Mutex<HashMap<Option<String>, Entry>>>,
+    max_age: Duration,
+}
+
+impl LatestCache {
+    pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(300);
+
+    /// A `max_age` of zero disables the cache.
+    pub fn new(max_age: Duration) -> Self {
+        LatestCache {
+            entries: (!max_age.is_zero()).then(|| Mutex::new(HashMap::new())),
+            max_age,
+        }
+    }
+
+    /// The cached result of `get_last_ids(project)`, unless it has to be loaded.
+    pub fn get(&self, project: Option<&str>) -> Option<models::LatestData> {
+        let entries = self.entries.as_ref()?.lock().unwrap();
+        let entry = entries.get(&key(project))?;
+        let loaded_at = entry.loaded_at?;
+        (loaded_at.elapsed() < self.max_age).then(|| entry.changes.latest())
+    }
+
+    /// Starts collecting the posts for `project` before its `RecentChanges` are read from the
+    /// database, so that rows committed while they are read are not missed.
+    pub fn start_loading(&self, project: Option<&str>) {
+        let Some(entries) = &self.entries else {
+            return;
+        };
+        let mut entries = entries.lock().unwrap();
+        let entry = entries.entry(key(project)).or_insert(Entry {
+            changes: RecentChanges::default(),
+            loaded_at: None,
+        });
+        if entry
+            .loaded_at
+            .is_some_and(|loaded_at| loaded_at.elapsed() >= self.max_age)
+        {
+            *entry = Entry {
+                changes: RecentChanges::default(),
+                loaded_at: None,
+            };
+        }
+    }
+
+    /// Adds the `RecentChanges` read after `start_loading` to the posts collected since, and
+    /// returns the result of `get_last_ids(project)`.
+    pub fn finish_loading(
+        &self,
+        project: Option<&str>,
+        changes: RecentChanges,
+    ) -> models::LatestData {
+        let Some(entries) = &self.entries else {
+            return changes.latest();
+        };
+        let mut entries = entries.lock().unwrap();
+        let entry = entries.entry(key(project)).or_insert(Entry {
+            changes: RecentChanges::default(),
+            loaded_at: None,
+        });
+        entry.changes.merge(&changes);
+        entry.loaded_at = Some(Instant::now());
+        entry.changes.latest()
+    }
+
+    pub fn record_event(&self, project: &str, change: i32, id: i64) {
+        self.record(project, |changes| changes.events.record(change, id));
+    }
+
+    pub fn record_comment(&self, project: &str, change: i32, id: i64) {
+        self.record(project, |changes| changes.comments.record(change, id));
+    }
+
+    pub fn record_build(&self, project: &str, change: i32, id: i64) {
+        self.record(project, |changes| changes.builds.record(change, id));
+    }
+
+    /// Adds `row` once `import_row` has inserted it as `id`.
+    pub fn record_import(&self, row: &DumpRow, id: i64) {
+        match row {
+            DumpRow::Badge(build) => self.record_build(&build.project, build.change_number, id),
+            DumpRow::Comment(comment) => {
+                self.record_comment(&comment.project, comment.change_number, id)
+            }
+            DumpRow::Event { event, .. } => self.record_event(&event.project, event.change, id),
+            _ => {}
+        }
+    }
+
+    /// Forgets every entry once `prune` has deleted `rows` rows of `table` that it may count.
+    pub fn record_prune(&self, table: RetentionTable, rows: u64) {
+        if rows > 0 && table == RetentionTable::UserVotes {
+            self.invalidate();
+        }
+    }
+
+    pub fn invalidate(&self) {
+        if let Some(entries) = &self.entries {
+            entries.lock().unwrap().clear();
+        }
+    }
+
+    /// Adds a row posted for `project` to the entries of its stream and of every project.
+    fn record(&self, project: &str, add: impl Fn(&mut RecentChanges)) {
+        let Some(entries) = &self.entries else {
+            return;
+        };
+        let mut entries = entries.lock().unwrap();
+        for key in [None, key(Some(project))] {
+            if let Some(entry) = entries.get_mut(&key) {
+                add(&mut entry.changes);
+            }
+        }
+    }
+}
+
+impl Default for LatestCache {
+    fn default() -> Self {
+        LatestCache::new(LatestCache::DEFAULT_MAX_AGE)
+    }
+}
+
+fn key(project: Option<&str>) -> Option<String> {
+    project.map(get_project_stream)
+}
@@ -0,0 +1,63 @@
+use rocket::config::LogLevel;
+use rocket::figment::Figment;
+use rocket_db_pools::sqlx::{self, ConnectOptions};
+use rocket_db_pools::{Config, Error};
+use std::ops::Deref;
+use std::time::Duration;
+
+type Options<DB> = <<DB as sqlx::Database>::Connection as sqlx::Connection>::Options;
+
+/// `sqlx::Pool` that connects on first use rather than at launch, so the server starts while the
+/// database is down and answers 503 until it is up. Configured from `databases.ugsdb` the same way
+/// as the pool rocket_db_pools provides for `sqlx::Pool`.
+pub struct LazyPool<DB: sqlx::Database>(sqlx::Pool<DB>);
+
+impl<DB: sqlx::Database> Deref for LazyPool<DB> {
+    type Target = sqlx::Pool<DB>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<DB: sqlx::Database> rocket_db_pools::Pool for LazyPool<DB> {
+    type Connection = sqlx::pool::PoolConnection<DB>;
+
+    type Error = Error<sqlx::Error>;
+
+    async fn init(figment: &Figment) -> Result<Self, Self::Error> {
+        let config = figment.extract::<Config>()?;
+        let mut options = config.url.parse::<Options<DB>>().map_err(Error::Init)?;
+        // Like rocket_db_pools, waits for a locked SQLite file and creates a missing one.
+        if let Some(sqlite) = (&mut options as &mut dyn std::any::Any)
+            .downcast_mut::<sqlx::sqlite::SqliteConnectOptions>()
+        {
+            *sqlite = std::mem::take(sqlite)
+                .busy_timeout(Duration::from_secs(config.connect_timeout))
+                .create_if_missing(true);
+        }
+
+        options.disable_statement_logging();
+        if let Ok(level) = figment.extract_inner::<LogLevel>(rocket::Config::LOG_LEVEL) {
+            if !matches!(level, LogLevel::Normal | LogLevel::Off) {
+                options
+                    .log_statements(level.into())
+                    .log_slow_statements(level.into(), Duration::default());
+            }
+        }
+
+        Ok(LazyPool(
+            sqlx::pool::PoolOptions::new()
+                .max_connections(config.max_connections as u32)
+                .connect_timeout(Duration::from_secs(config.connect_timeout))
+                .idle_timeout(config.idle_timeout.map(Duration::from_secs))
+                .min_connections(config.min_connections.unwrap_or_default())
+                .connect_lazy_with(options),
+        ))
+    }
+
+    async fn get(&self) -> Result<Self::Connection, Self::Error> {
+        self.0.acquire().await.map_err(Error::Get)
+    }
+}
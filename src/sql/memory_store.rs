@@ -0,0 +1,949 @@
+use crate::crash::{self, CrashCounts, CrashReporter};
+use crate::dump::{DumpRow, DumpTable};
+use crate::models;
+use crate::retention::{RetentionPolicy, RetentionTable};
+use crate::sql::id_cache::IdCacheStats;
+use crate::sql::latest_cache::RecentChanges;
+use crate::sql::metered_store::metered;
+use crate::sql::sql_connector::{
+    get_project_stream, normalize_user_name, project_matches, sanitize_text,
+    ISSUE_SUMMARY_MAX_LENGTH,
+};
+use crate::sql::{schema, MetadataStore, Result, SharedStore};
+use rocket::fairing::AdHoc;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+type DateTime = chrono::DateTime<chrono::Utc>;
+
+/// `MetadataStore` that keeps every table in process memory, following the same semantics as the
+/// SQL backends. Nothing is persisted, so it is meant for tests and local development.
+#[derive(Default)]
+pub struct MemoryStore {
+    tables: Mutex<Tables>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    fn tables(&self) -> MutexGuard<'_, Tables> {
+        // A panic while holding the lock cannot leave a table half written, so keep going.
+        self.tables.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Fairing that manages a `SharedStore` backed by a fresh, empty `MemoryStore`.
+pub fn stage() -> AdHoc {
+    AdHoc::on_ignite("In-Memory Metadata Store", |rocket| async {
+        let store: SharedStore = Arc::new(MemoryStore::new());
+        rocket.manage(metered(store))
+    })
+}
+
+struct IssueRow {
+    project: String,
+    summary: String,
+    owner_id: Option<i64>,
+    nominated_by_id: Option<i64>,
+    created_at: DateTime,
+    acknowledged_at: Option<DateTime>,
+    fix_change: i32,
+    resolved_at: Option<DateTime>,
+}
+
+struct TelemetryRow {
+    id: i64,
+    action: String,
+    result: String,
+    user_name: String,
+    project: String,
+    timestamp: DateTime,
+    duration: f32,
+    version: String,
+    ip_address: String,
+    project_id: i64,
+}
+
+/// Rows keyed the way the SQL schema keys them. Append-only `Vec` tables use the row's index plus
+/// one as its id; tables that support deletes or pruning keep their own id counter instead.
+#[derive(Default)]
+struct Tables {
+    projects: Vec<String>,
+    users: Vec<String>,
+    badges: Vec<(i64, models::BuildData)>,
+    comments: Vec<(i64, models::CommentData)>,
+    user_votes: Vec<(i64, DateTime, models::EventData)>,
+    last_user_vote_id: i64,
+    issues: BTreeMap<i64, IssueRow>,
+    last_issue_id: i64,
+    issue_watchers: BTreeSet<(i64, i64)>,
+    issue_builds: BTreeMap<i64, (i64, models::IssueBuildData)>,
+    last_issue_build_id: i64,
+    issue_diagnostics: Vec<(i64, models::IssueDiagnosticData)>,
+    errors: Vec<(Option<i64>, models::TelemetryErrorData)>,
+    last_error_id: i64,
+    telemetry: Vec<TelemetryRow>,
+    last_telemetry_id: i64,
+}
+
+impl Tables {
+    fn project_id(&mut self, project: &str) -> i64 {
+        let index = match self.projects.iter().position(|name| name == project) {
+            Some(index) => index,
+            None => {
+                self.projects.push(String::from(project));
+                self.projects.len() - 1
+            }
+        };
+        index as i64 + 1
+    }
+
+    fn project_name(&self, project_id: i64) -> &str {
+        &self.projects[project_id as usize - 1]
+    }
+
+    /// Equivalent of `Projects.Stream = get_project_stream(project)`, or of no filter without a
+    /// project.
+    fn in_stream(&self, project_id: i64, project: Option<&str>) -> bool {
+        project.is_none_or(|project| {
+            get_project_stream(self.project_name(project_id)) == get_project_stream(project)
+        })
+    }
+
+    fn find_or_add_user_id(&mut self, name: &str) -> Option<i64> {
+        if name.is_empty() {
+            return None;
+        }
+
+        let normalized_name = normalize_user_name(name);
+        let index = match self.users.iter().position(|user| *user == normalized_name) {
+            Some(index) => index,
+            None => {
+                self.users.push(normalized_name);
+                self.users.len() - 1
+            }
+        };
+        Some(index as i64 + 1)
+    }
+
+    fn user_name(&self, user_id: Option<i64>) -> String {
+        user_id
+            .map(|id| self.users[id as usize - 1].clone())
+            .unwrap_or_default()
+    }
+
+    fn issue_data(&self, issue_id: i64, issue: &IssueRow, notify: bool) -> models::IssueData {
+        models::IssueData {
+            id: issue_id,
+            created_at: issue.created_at,
+            retrieved_at: chrono::Utc::now(),
+            project: issue.project.clone(),
+            summary: issue.summary.clone(),
+            owner: self.user_name(issue.owner_id),
+            nominated_by: self.user_name(issue.nominated_by_id),
+            acknowledged_at: issue.acknowledged_at,
+            fix_change: issue.fix_change,
+            resolved_at: issue.resolved_at,
+            notify,
+        }
+    }
+
+    fn insert_badge(&mut self, build: &models::BuildData) -> i64 {
+        let project_id = self.project_id(&build.project);
+        let mut build = build.clone();
+        build.id = self.badges.len() as i64 + 1;
+        self.badges.push((project_id, build));
+        self.badges.len() as i64
+    }
+
+    fn insert_comment(&mut self, comment: &models::CommentData) -> i64 {
+        let project_id = self.project_id(&comment.project);
+        let mut comment = comment.clone();
+        comment.id = self.comments.len() as i64 + 1;
+        self.comments.push((project_id, comment));
+        self.comments.len() as i64
+    }
+
+    fn insert_user_vote(&mut self, event: &models::EventData, created_at: DateTime) -> i64 {
+        let project_id = self.project_id(&event.project);
+        let mut event = event.clone();
+        self.last_user_vote_id += 1;
+        event.id = self.last_user_vote_id;
+        self.user_votes.push((project_id, created_at, event));
+        self.last_user_vote_id
+    }
+
+    fn insert_issue(&mut self, issue: IssueRow) -> i64 {
+        self.last_issue_id += 1;
+        self.issues.insert(self.last_issue_id, issue);
+        self.last_issue_id
+    }
+
+    fn insert_error(
+        &mut self,
+        data: &models::TelemetryErrorData,
+        version: &str,
+        ip_address: &str,
+    ) -> i64 {
+        let project_id = data
+            .project
+            .as_deref()
+            .map(|project| self.project_id(project));
+        let mut data = data.clone();
+        self.last_error_id += 1;
+        data.id = self.last_error_id;
+        data.version = String::from(version);
+        data.ip_address = String::from(ip_address);
+        self.errors.push((project_id, data));
+        self.last_error_id
+    }
+
+    fn insert_telemetry(
+        &mut self,
+        data: &models::TelemetryTimingData,
+        version: &str,
+        ip_address: &str,
+    ) -> i64 {
+        let project_id = self.project_id(&data.project);
+        self.last_telemetry_id += 1;
+        self.telemetry.push(TelemetryRow {
+            id: self.last_telemetry_id,
+            action: data.action.clone(),
+            result: data.result.clone(),
+            user_name: data.user_name.clone(),
+            project: data.project.clone(),
+            timestamp: data.timestamp,
+            duration: data.duration,
+            version: String::from(version),
+            ip_address: String::from(ip_address),
+            project_id,
+        });
+        self.last_telemetry_id
+    }
+
+    fn get_issues_internal(
+        &mut self,
+        user_name: Option<&str>,
+        include_resolved: bool,
+        num_results: Option<i32>,
+    ) -> Vec<models::IssueData> {
+        let user_id = user_name.and_then(|name| self.find_or_add_user_id(name));
+        self.issues
+            .iter()
+            .rev()
+            .filter(|(_, issue)| include_resolved || issue.resolved_at.is_none())
+            .take(num_results.map_or(usize::MAX, |n| n.max(0) as usize))
+            .map(|(id, issue)| {
+                let notify =
+                    user_id.is_some_and(|user_id| self.issue_watchers.contains(&(*id, user_id)));
+                self.issue_data(*id, issue, notify)
+            })
+            .collect()
+    }
+}
+
+/// Removes the rows at `indices`, which must be in ascending order.
+fn remove_rows<T>(rows: &mut Vec<T>, indices: &[usize]) {
+    let mut index = 0;
+    let mut indices = indices.iter().peekable();
+    rows.retain(|_| {
+        let remove = indices.next_if_eq(&&index).is_some();
+        index += 1;
+        !remove
+    });
+}
+
+/// Whether the filters of `query` match `data` of the project `project_id`, with `UserName =`
+/// being case-insensitive as in the SQL backends.
+fn error_matches(
+    tables: &Tables,
+    query: &models::ErrorQuery,
+    project_id: Option<i64>,
+    data: &models::TelemetryErrorData,
+) -> bool {
+    if let Some(project) = &query.project {
+        if !project_id.is_some_and(|id| tables.in_stream(id, Some(project))) {
+            return false;
+        }
+    }
+    if let Some(user_name) = &query.user_name {
+        if !data.user_name.eq_ignore_ascii_case(user_name) {
+            return false;
+        }
+    }
+    query
+        .version
+        .as_ref()
+        .is_none_or(|version| &data.version == version)
+        && query
+            .error_type
+            .is_none_or(|error_type| data.error_type == error_type)
+        && query.from.is_none_or(|from| data.timestamp >= from)
+        && query.to.is_none_or(|to| data.timestamp < to)
+        && query
+            .signature
+            .as_ref()
+            .is_none_or(|signature| &crash::signature(&data.text) == signature)
+        && query.before_id.is_none_or(|before_id| data.id < before_id)
+}
+
+fn timing_matches(tables: &Tables, query: &models::TimingQuery, row: &TelemetryRow) -> bool {
+    if let Some(project) = &query.project {
+        if !tables.in_stream(row.project_id, Some(project)) {
+            return false;
+        }
+    }
+    query
+        .action
+        .as_ref()
+        .is_none_or(|action| &row.action == action)
+        && query
+            .version
+            .as_ref()
+            .is_none_or(|version| &row.version == version)
+        && query.from.is_none_or(|from| row.timestamp >= from)
+        && query.to.is_none_or(|to| row.timestamp < to)
+}
+
+/// `Action`, `Project`, `Version` and `Start` of a timing sample, the latter three if grouped by.
+type TimingKey = (String, Option<String>, Option<String>, Option<i64>);
+
+fn timing_key(query: &models::TimingQuery, row: &TelemetryRow) -> TimingKey {
+    (
+        row.action.clone(),
+        query.by_project.then(|| row.project.clone()),
+        query.by_version.then(|| row.version.clone()),
+        query
+            .bucket
+            .map(|bucket| row.timestamp.timestamp().div_euclid(bucket) * bucket),
+    )
+}
+
+#[rocket::async_trait]
+impl MetadataStore for MemoryStore {
+    async fn get_builds(
+        &self,
+        project: &str,
+        last_build_id: i64,
+    ) -> Result<Vec<models::BuildData>> {
+        let tables = self.tables();
+        Ok(tables
+            .badges
+            .iter()
+            .filter(|(project_id, build)| {
+                build.id > last_build_id
+                    && project_matches(tables.project_name(*project_id), project)
+            })
+            .map(|(_, build)| build.clone())
+            .collect())
+    }
+
+    async fn post_build(&self, build: &models::BuildData) -> Result<()> {
+        self.tables().insert_badge(build);
+        Ok(())
+    }
+
+    async fn get_comments(
+        &self,
+        project: &str,
+        last_comment_id: i64,
+    ) -> Result<Vec<models::CommentData>> {
+        let tables = self.tables();
+        Ok(tables
+            .comments
+            .iter()
+            .filter(|(project_id, comment)| {
+                comment.id > last_comment_id && tables.project_name(*project_id) == project
+            })
+            .map(|(_, comment)| comment.clone())
+            .collect())
+    }
+
+    async fn post_comment(&self, comment: &models::CommentData) -> Result<()> {
+        self.tables().insert_comment(comment);
+        Ok(())
+    }
+
+    async fn get_user_votes(
+        &self,
+        project: &str,
+        last_event_id: i64,
+    ) -> Result<Vec<models::EventData>> {
+        let tables = self.tables();
+        Ok(tables
+            .user_votes
+            .iter()
+            .filter(|(project_id, _, event)| {
+                event.id > last_event_id && tables.project_name(*project_id) == project
+            })
+            .map(|(_, _, event)| event.clone())
+            .collect())
+    }
+
+    async fn post_event(&self, event: &models::EventData) -> Result<()> {
+        self.tables().insert_user_vote(event, chrono::Utc::now());
+        Ok(())
+    }
+
+    async fn post_event_batch(&self, events: &[models::EventData]) -> Result<()> {
+        let mut tables = self.tables();
+        for event in events {
+            tables.insert_user_vote(event, chrono::Utc::now());
+        }
+        Ok(())
+    }
+
+    async fn get_last_ids(&self, project: Option<&str>) -> Result<models::LatestData> {
+        let tables = self.tables();
+        let tables = &*tables;
+        Ok(RecentChanges {
+            events: tables
+                .user_votes
+                .iter()
+                .filter(|(project_id, _, _)| tables.in_stream(*project_id, project))
+                .map(|(_, _, event)| (event.change, event.id))
+                .collect(),
+            comments: tables
+                .comments
+                .iter()
+                .filter(|(project_id, _)| tables.in_stream(*project_id, project))
+                .map(|(_, comment)| (comment.change_number, comment.id))
+                .collect(),
+            builds: tables
+                .badges
+                .iter()
+                .filter(|(project_id, _)| tables.in_stream(*project_id, project))
+                .map(|(_, build)| (build.change_number, build.id))
+                .collect(),
+        }
+        .latest())
+    }
+
+    async fn add_issue(&self, issue: &models::IssueData) -> Result<i64> {
+        let mut tables = self.tables();
+        let owner_id = tables.find_or_add_user_id(&issue.owner);
+        Ok(tables.insert_issue(IssueRow {
+            project: issue.project.clone(),
+            summary: sanitize_text(&issue.summary, ISSUE_SUMMARY_MAX_LENGTH),
+            owner_id,
+            nominated_by_id: None,
+            created_at: chrono::Utc::now(),
+            acknowledged_at: None,
+            fix_change: 0,
+            resolved_at: None,
+        }))
+    }
+
+    async fn get_issue(&self, issue_id: i64) -> Result<Option<models::IssueData>> {
+        let tables = self.tables();
+        Ok(tables
+            .issues
+            .get(&issue_id)
+            .map(|issue| tables.issue_data(issue_id, issue, false)))
+    }
+
+    async fn get_issues_filtered(
+        &self,
+        include_resolved: bool,
+        num_results: Option<i32>,
+    ) -> Result<Vec<models::IssueData>> {
+        Ok(self
+            .tables()
+            .get_issues_internal(None, include_resolved, num_results))
+    }
+
+    async fn get_issues_by_user_name(&self, user_name: &str) -> Result<Vec<models::IssueData>> {
+        Ok(self
+            .tables()
+            .get_issues_internal(Some(user_name), false, None))
+    }
+
+    async fn update_issue(&self, issue_id: i64, issue: &models::IssueUpdateData) -> Result<()> {
+        let mut tables = self.tables();
+        let owner_id = tables.find_or_add_user_id(&issue.owner);
+        let nominated_by_id = tables.find_or_add_user_id(&issue.nominated_by);
+        let now = chrono::Utc::now();
+
+        if let Some(row) = tables.issues.get_mut(&issue_id) {
+            if !issue.summary.is_empty() {
+                row.summary = sanitize_text(&issue.summary, ISSUE_SUMMARY_MAX_LENGTH);
+            }
+            if !issue.owner.is_empty() {
+                row.owner_id = owner_id;
+            }
+            if !issue.nominated_by.is_empty() {
+                row.nominated_by_id = nominated_by_id;
+            }
+            if let Some(acknowledged) = issue.acknowledged {
+                row.acknowledged_at = if acknowledged { Some(now) } else { None };
+            }
+            if let Some(fix_change) = issue.fix_change {
+                row.fix_change = fix_change;
+            }
+            if let Some(resolved) = issue.resolved {
+                row.resolved_at = if resolved { Some(now) } else { None };
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete_issue(&self, issue_id: i64) -> Result<()> {
+        let mut tables = self.tables();
+        tables
+            .issue_watchers
+            .retain(|(watched_issue_id, _)| *watched_issue_id != issue_id);
+        tables
+            .issue_builds
+            .retain(|_, (build_issue_id, _)| *build_issue_id != issue_id);
+        tables.issues.remove(&issue_id);
+        Ok(())
+    }
+
+    async fn add_build(&self, issue_id: i64, build: &models::IssueBuildData) -> Result<i64> {
+        let mut tables = self.tables();
+        tables.last_issue_build_id += 1;
+        let build_id = tables.last_issue_build_id;
+        let mut build = build.clone();
+        build.id = build_id;
+        tables.issue_builds.insert(build_id, (issue_id, build));
+        Ok(build_id)
+    }
+
+    async fn get_builds_by_issue(&self, issue_id: i64) -> Result<Vec<models::IssueBuildData>> {
+        Ok(self
+            .tables()
+            .issue_builds
+            .values()
+            .filter(|(build_issue_id, _)| *build_issue_id == issue_id)
+            .map(|(_, build)| build.clone())
+            .collect())
+    }
+
+    async fn get_build(&self, build_id: i64) -> Result<Option<models::IssueBuildData>> {
+        Ok(self
+            .tables()
+            .issue_builds
+            .get(&build_id)
+            .map(|(_, build)| build.clone()))
+    }
+
+    async fn update_build(&self, build_id: i64, outcome: i32) -> Result<()> {
+        if let Some((_, build)) = self.tables().issue_builds.get_mut(&build_id) {
+            build.outcome = outcome;
+        }
+        Ok(())
+    }
+
+    async fn add_diagnostic(
+        &self,
+        issue_id: i64,
+        diagnostic: &models::IssueDiagnosticData,
+    ) -> Result<()> {
+        let mut diagnostic = diagnostic.clone();
+        diagnostic.message = sanitize_text(&diagnostic.message, 1000);
+        self.tables().issue_diagnostics.push((issue_id, diagnostic));
+        Ok(())
+    }
+
+    async fn get_diagnostics(&self, issue_id: i64) -> Result<Vec<models::IssueDiagnosticData>> {
+        Ok(self
+            .tables()
+            .issue_diagnostics
+            .iter()
+            .filter(|(diagnostic_issue_id, _)| *diagnostic_issue_id == issue_id)
+            .map(|(_, diagnostic)| diagnostic.clone())
+            .collect())
+    }
+
+    async fn add_watcher(&self, issue_id: i64, user_name: &str) -> Result<()> {
+        let mut tables = self.tables();
+        if let Some(user_id) = tables.find_or_add_user_id(user_name) {
+            tables.issue_watchers.insert((issue_id, user_id));
+        }
+        Ok(())
+    }
+
+    async fn get_watchers(&self, issue_id: i64) -> Result<Vec<String>> {
+        let tables = self.tables();
+        Ok(tables
+            .issue_watchers
+            .iter()
+            .filter(|(watched_issue_id, _)| *watched_issue_id == issue_id)
+            .map(|(_, user_id)| tables.user_name(Some(*user_id)))
+            .collect())
+    }
+
+    async fn remove_watcher(&self, issue_id: i64, user_name: &str) -> Result<()> {
+        let mut tables = self.tables();
+        if let Some(user_id) = tables.find_or_add_user_id(user_name) {
+            tables.issue_watchers.remove(&(issue_id, user_id));
+        }
+        Ok(())
+    }
+
+    async fn post_telemetry_data(
+        &self,
+        data: &models::TelemetryTimingData,
+        version: &str,
+        ip_address: &str,
+    ) -> Result<()> {
+        self.tables().insert_telemetry(data, version, ip_address);
+        Ok(())
+    }
+
+    async fn post_telemetry_batch(&self, batch: &[models::TelemetryPost]) -> Result<()> {
+        let mut tables = self.tables();
+        for post in batch {
+            tables.insert_telemetry(&post.data, &post.version, &post.ip_address);
+        }
+        Ok(())
+    }
+
+    async fn get_timing_counts(
+        &self,
+        query: &models::TimingQuery,
+    ) -> Result<Vec<models::TimingCount>> {
+        let tables = self.tables();
+        let mut counts: BTreeMap<(TimingKey, &str), i64> = BTreeMap::new();
+        for row in &tables.telemetry {
+            if timing_matches(&tables, query, row) {
+                *counts
+                    .entry((timing_key(query, row), row.result.as_str()))
+                    .or_default() += 1;
+            }
+        }
+        Ok(counts
+            .into_iter()
+            .map(
+                |(((action, project, version, start), result), count)| models::TimingCount {
+                    action,
+                    project,
+                    version,
+                    start,
+                    result: String::from(result),
+                    count,
+                },
+            )
+            .collect())
+    }
+
+    async fn get_timing_samples(
+        &self,
+        query: &models::TimingQuery,
+        every: i64,
+    ) -> Result<Vec<models::TimingSample>> {
+        let tables = self.tables();
+        Ok(tables
+            .telemetry
+            .iter()
+            .filter(|row| timing_matches(&tables, query, row))
+            .skip(every.max(1) as usize - 1)
+            .step_by(every.max(1) as usize)
+            .map(|row| {
+                let (action, project, version, start) = timing_key(query, row);
+                models::TimingSample {
+                    action,
+                    project,
+                    version,
+                    start,
+                    duration: row.duration,
+                }
+            })
+            .collect())
+    }
+
+    async fn post_error_data(
+        &self,
+        data: &models::TelemetryErrorData,
+        version: &str,
+        ip_address: &str,
+    ) -> Result<()> {
+        self.tables().insert_error(data, version, ip_address);
+        Ok(())
+    }
+
+    async fn get_error_data(
+        &self,
+        query: &models::ErrorQuery,
+    ) -> Result<Vec<models::TelemetryErrorData>> {
+        let tables = self.tables();
+        Ok(tables
+            .errors
+            .iter()
+            .rev()
+            .filter(|(project_id, data)| error_matches(&tables, query, *project_id, data))
+            .map(|(_, data)| data)
+            .take(query.records.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_top_crashes(
+        &self,
+        query: &models::ErrorQuery,
+    ) -> Result<Vec<models::CrashBucket>> {
+        let query = models::ErrorQuery {
+            before_id: None,
+            ..query.clone()
+        };
+        let tables = self.tables();
+        let mut counts: HashMap<String, CrashCounts> = HashMap::new();
+        let mut users: HashMap<String, BTreeSet<&str>> = HashMap::new();
+        let mut reporters = Vec::new();
+        for (project_id, data) in &tables.errors {
+            if !error_matches(&tables, &query, *project_id, data) {
+                continue;
+            }
+            let signature = crash::signature(&data.text);
+            let bucket = counts
+                .entry(signature.clone())
+                .or_insert_with(|| CrashCounts {
+                    signature: signature.clone(),
+                    count: 0,
+                    user_count: 0,
+                    first_seen: data.timestamp,
+                    last_seen: data.timestamp,
+                    last_error_id: data.id,
+                });
+            bucket.count += 1;
+            bucket.first_seen = bucket.first_seen.min(data.timestamp);
+            bucket.last_seen = bucket.last_seen.max(data.timestamp);
+            bucket.last_error_id = bucket.last_error_id.max(data.id);
+            let bucket_users = users.entry(signature.clone()).or_default();
+            bucket_users.insert(&data.user_name);
+            bucket.user_count = bucket_users.len() as i64;
+            reporters.push(CrashReporter {
+                signature,
+                version: data.version.clone(),
+                user_name: data.user_name.clone(),
+                project: data.project.clone(),
+            });
+        }
+
+        let mut counts: Vec<CrashCounts> = counts.into_values().collect();
+        counts.sort_by_key(|bucket| {
+            std::cmp::Reverse((bucket.user_count, bucket.count, bucket.last_error_id))
+        });
+        counts.truncate(query.records.max(0) as usize);
+        let texts = tables
+            .errors
+            .iter()
+            .filter(|(_, data)| counts.iter().any(|bucket| bucket.last_error_id == data.id))
+            .map(|(_, data)| (data.id, data.text.clone()))
+            .collect();
+        Ok(crash::buckets(counts, reporters, texts))
+    }
+
+    async fn get_error_counts(
+        &self,
+        from: DateTime,
+        to: DateTime,
+    ) -> Result<Vec<models::ErrorCount>> {
+        let mut counts: BTreeMap<(&str, Option<&str>), i64> = BTreeMap::new();
+        let tables = self.tables();
+        for (_, data) in &tables.errors {
+            if data.timestamp >= from && data.timestamp < to {
+                *counts
+                    .entry((&data.version, data.project.as_deref()))
+                    .or_default() += 1;
+            }
+        }
+        Ok(counts
+            .into_iter()
+            .map(|((version, project), count)| models::ErrorCount {
+                version: version.to_string(),
+                project: project.map(str::to_string),
+                count,
+            })
+            .collect())
+    }
+
+    async fn find_or_add_user_id(&self, name: &str) -> Result<Option<i64>> {
+        Ok(self.tables().find_or_add_user_id(name))
+    }
+
+    fn id_cache_stats(&self) -> Option<IdCacheStats> {
+        None
+    }
+
+    fn invalidate_id_cache(&self) {}
+
+    async fn ping(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn describe_schema(&self) -> Result<Option<Vec<schema::Column>>> {
+        Ok(None)
+    }
+
+    async fn backfill(&self) {}
+
+    async fn export_rows(
+        &self,
+        table: DumpTable,
+        after_id: i64,
+        limit: u32,
+    ) -> Result<Vec<DumpRow>> {
+        let tables = self.tables();
+        let limit = limit as usize;
+        let named = |names: &[String], make: fn(i64, String) -> DumpRow| {
+            names
+                .iter()
+                .enumerate()
+                .map(|(index, name)| make(index as i64 + 1, name.clone()))
+                .filter(|row| row.id() > after_id)
+                .take(limit)
+                .collect()
+        };
+        Ok(match table {
+            DumpTable::Projects => {
+                named(&tables.projects, |id, name| DumpRow::Project { id, name })
+            }
+            DumpTable::Users => named(&tables.users, |id, name| DumpRow::User { id, name }),
+            DumpTable::Badges => tables
+                .badges
+                .iter()
+                .filter(|(_, build)| build.id > after_id)
+                .take(limit)
+                .map(|(_, build)| DumpRow::Badge(build.clone()))
+                .collect(),
+            DumpTable::Comments => tables
+                .comments
+                .iter()
+                .filter(|(_, comment)| comment.id > after_id)
+                .take(limit)
+                .map(|(_, comment)| DumpRow::Comment(comment.clone()))
+                .collect(),
+            DumpTable::UserVotes => tables
+                .user_votes
+                .iter()
+                .filter(|(_, _, event)| event.id > after_id)
+                .take(limit)
+                .map(|(_, created_at, event)| DumpRow::Event {
+                    event: event.clone(),
+                    created_at: *created_at,
+                })
+                .collect(),
+            DumpTable::Issues => tables
+                .issues
+                .range(after_id + 1..)
+                .take(limit)
+                .map(|(id, issue)| DumpRow::Issue(tables.issue_data(*id, issue, false)))
+                .collect(),
+            DumpTable::Errors => tables
+                .errors
+                .iter()
+                .filter(|(_, data)| data.id > after_id)
+                .take(limit)
+                .map(|(_, data)| DumpRow::Error(data.clone()))
+                .collect(),
+            DumpTable::Telemetry => tables
+                .telemetry
+                .iter()
+                .filter(|row| row.id > after_id)
+                .take(limit)
+                .map(|row| DumpRow::Telemetry {
+                    id: row.id,
+                    data: models::TelemetryTimingData {
+                        action: row.action.clone(),
+                        result: row.result.clone(),
+                        user_name: row.user_name.clone(),
+                        project: row.project.clone(),
+                        timestamp: row.timestamp,
+                        duration: row.duration,
+                    },
+                    version: row.version.clone(),
+                    ip_address: row.ip_address.clone(),
+                })
+                .collect(),
+        })
+    }
+
+    async fn import_row(&self, row: &DumpRow) -> Result<i64> {
+        let mut tables = self.tables();
+        Ok(match row {
+            DumpRow::Project { name, .. } => tables.project_id(name),
+            DumpRow::User { name, .. } => tables.find_or_add_user_id(name).unwrap_or(0),
+            DumpRow::Badge(build) => tables.insert_badge(build),
+            DumpRow::Comment(comment) => tables.insert_comment(comment),
+            DumpRow::Event { event, created_at } => tables.insert_user_vote(event, *created_at),
+            DumpRow::Issue(issue) => {
+                let owner_id = tables.find_or_add_user_id(&issue.owner);
+                let nominated_by_id = tables.find_or_add_user_id(&issue.nominated_by);
+                tables.insert_issue(IssueRow {
+                    project: issue.project.clone(),
+                    summary: issue.summary.clone(),
+                    owner_id,
+                    nominated_by_id,
+                    created_at: issue.created_at,
+                    acknowledged_at: issue.acknowledged_at,
+                    fix_change: issue.fix_change,
+                    resolved_at: issue.resolved_at,
+                })
+            }
+            DumpRow::Error(data) => tables.insert_error(data, &data.version, &data.ip_address),
+            DumpRow::Telemetry {
+                data,
+                version,
+                ip_address,
+                ..
+            } => tables.insert_telemetry(data, version, ip_address),
+        })
+    }
+
+    async fn prune(&self, policy: &RetentionPolicy, cutoff: DateTime, limit: u32) -> Result<u64> {
+        let mut tables = self.tables();
+        let project = policy.project.as_deref();
+        let in_project = |tables: &Tables, project_id: Option<i64>| {
+            project.is_none() || project_id.is_some_and(|id| tables.in_stream(id, project))
+        };
+
+        // Rows are kept in id order, so these are the batch the SQL backends would delete.
+        let expired: Vec<usize> = match policy.table {
+            RetentionTable::Telemetry => tables
+                .telemetry
+                .iter()
+                .enumerate()
+                .filter(|(_, row)| {
+                    row.timestamp < cutoff && in_project(&tables, Some(row.project_id))
+                })
+                .map(|(index, _)| index)
+                .take(limit as usize)
+                .collect(),
+            RetentionTable::UserVotes => tables
+                .user_votes
+                .iter()
+                .enumerate()
+                .filter(|(_, (project_id, created_at, event))| {
+                    *created_at < cutoff
+                        && in_project(&tables, Some(*project_id))
+                        && policy
+                            .verdict
+                            .as_ref()
+                            .is_none_or(|verdict| *verdict == event.event_type.to_string())
+                })
+                .map(|(index, _)| index)
+                .take(limit as usize)
+                .collect(),
+            RetentionTable::Errors => tables
+                .errors
+                .iter()
+                .enumerate()
+                .filter(|(_, (project_id, data))| {
+                    data.timestamp < cutoff && in_project(&tables, *project_id)
+                })
+                .map(|(index, _)| index)
+                .take(limit as usize)
+                .collect(),
+        };
+
+        match policy.table {
+            RetentionTable::Telemetry => remove_rows(&mut tables.telemetry, &expired),
+            RetentionTable::UserVotes => remove_rows(&mut tables.user_votes, &expired),
+            RetentionTable::Errors => remove_rows(&mut tables.errors, &expired),
+        }
+        Ok(expired.len() as u64)
+    }
+}
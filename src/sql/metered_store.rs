@@ -0,0 +1,306 @@
+use crate::dump::{DumpRow, DumpTable};
+use crate::metrics::{self, METRICS};
+use crate::models;
+use crate::retention::RetentionPolicy;
+use crate::sql::id_cache::IdCacheStats;
+use crate::sql::schema::Column;
+use crate::sql::{MetadataStore, Result, SharedStore};
+use chrono::{DateTime, Utc};
+use std::future::Future;
+use std::sync::Arc;
+
+/// Wraps `store` so every call to it is timed and its failures counted in `metrics::METRICS`, by
+/// the name of the function, and so is what it stored per project.
+pub fn metered(store: SharedStore) -> SharedStore {
+    Arc::new(MeteredStore(store))
+}
+
+// Private Functions:
+
+struct MeteredStore(SharedStore);
+
+async fn observe<T>(function: &str, call: impl Future<Output = Result<T>>) -> Result<T> {
+    let timer = METRICS
+        .db_query_duration
+        .with_label_values(&[function])
+        .start_timer();
+    let result = call.await;
+    timer.observe_duration();
+    if result.is_err() {
+        METRICS.db_query_errors.with_label_values(&[function]).inc();
+    }
+    result
+}
+
+/// Counts one post of `kind` for `project` if `result` is a success.
+fn ingested<T>(result: Result<T>, kind: &str, project: &str) -> Result<T> {
+    if result.is_ok() {
+        metrics::ingested(kind, project, 1);
+    }
+    result
+}
+
+#[rocket::async_trait]
+impl MetadataStore for MeteredStore {
+    async fn get_builds(
+        &self,
+        project: &str,
+        last_build_id: i64,
+    ) -> Result<Vec<models::BuildData>> {
+        observe("get_builds", self.0.get_builds(project, last_build_id)).await
+    }
+
+    async fn post_build(&self, build: &models::BuildData) -> Result<()> {
+        let result = observe("post_build", self.0.post_build(build)).await;
+        ingested(result, "badge", &build.project)
+    }
+
+    async fn get_comments(
+        &self,
+        project: &str,
+        last_comment_id: i64,
+    ) -> Result<Vec<models::CommentData>> {
+        observe(
+            "get_comments",
+            self.0.get_comments(project, last_comment_id),
+        )
+        .await
+    }
+
+    async fn post_comment(&self, comment: &models::CommentData) -> Result<()> {
+        let result = observe("post_comment", self.0.post_comment(comment)).await;
+        ingested(result, "comment", &comment.project)
+    }
+
+    async fn get_user_votes(
+        &self,
+        project: &str,
+        last_event_id: i64,
+    ) -> Result<Vec<models::EventData>> {
+        observe(
+            "get_user_votes",
+            self.0.get_user_votes(project, last_event_id),
+        )
+        .await
+    }
+
+    async fn post_event(&self, event: &models::EventData) -> Result<()> {
+        let result = observe("post_event", self.0.post_event(event)).await;
+        ingested(result, "event", &event.project)
+    }
+
+    async fn post_event_batch(&self, events: &[models::EventData]) -> Result<()> {
+        observe("post_event_batch", self.0.post_event_batch(events)).await?;
+        for event in events {
+            metrics::ingested("event", &event.project, 1);
+        }
+        Ok(())
+    }
+
+    async fn get_last_ids(&self, project: Option<&str>) -> Result<models::LatestData> {
+        observe("get_last_ids", self.0.get_last_ids(project)).await
+    }
+
+    async fn add_issue(&self, issue: &models::IssueData) -> Result<i64> {
+        observe("add_issue", self.0.add_issue(issue)).await
+    }
+
+    async fn get_issue(&self, issue_id: i64) -> Result<Option<models::IssueData>> {
+        observe("get_issue", self.0.get_issue(issue_id)).await
+    }
+
+    async fn get_issues_filtered(
+        &self,
+        include_resolved: bool,
+        num_results: Option<i32>,
+    ) -> Result<Vec<models::IssueData>> {
+        observe(
+            "get_issues_filtered",
+            self.0.get_issues_filtered(include_resolved, num_results),
+        )
+        .await
+    }
+
+    async fn get_issues_by_user_name(&self, user_name: &str) -> Result<Vec<models::IssueData>> {
+        observe(
+            "get_issues_by_user_name",
+            self.0.get_issues_by_user_name(user_name),
+        )
+        .await
+    }
+
+    async fn update_issue(&self, issue_id: i64, issue: &models::IssueUpdateData) -> Result<()> {
+        observe("update_issue", self.0.update_issue(issue_id, issue)).await
+    }
+
+    async fn delete_issue(&self, issue_id: i64) -> Result<()> {
+        observe("delete_issue", self.0.delete_issue(issue_id)).await
+    }
+
+    async fn add_build(&self, issue_id: i64, build: &models::IssueBuildData) -> Result<i64> {
+        observe("add_build", self.0.add_build(issue_id, build)).await
+    }
+
+    async fn get_builds_by_issue(&self, issue_id: i64) -> Result<Vec<models::IssueBuildData>> {
+        observe("get_builds_by_issue", self.0.get_builds_by_issue(issue_id)).await
+    }
+
+    async fn get_build(&self, build_id: i64) -> Result<Option<models::IssueBuildData>> {
+        observe("get_build", self.0.get_build(build_id)).await
+    }
+
+    async fn update_build(&self, build_id: i64, outcome: i32) -> Result<()> {
+        observe("update_build", self.0.update_build(build_id, outcome)).await
+    }
+
+    async fn add_diagnostic(
+        &self,
+        issue_id: i64,
+        diagnostic: &models::IssueDiagnosticData,
+    ) -> Result<()> {
+        observe(
+            "add_diagnostic",
+            self.0.add_diagnostic(issue_id, diagnostic),
+        )
+        .await
+    }
+
+    async fn get_diagnostics(&self, issue_id: i64) -> Result<Vec<models::IssueDiagnosticData>> {
+        observe("get_diagnostics", self.0.get_diagnostics(issue_id)).await
+    }
+
+    async fn add_watcher(&self, issue_id: i64, user_name: &str) -> Result<()> {
+        observe("add_watcher", self.0.add_watcher(issue_id, user_name)).await
+    }
+
+    async fn get_watchers(&self, issue_id: i64) -> Result<Vec<String>> {
+        observe("get_watchers", self.0.get_watchers(issue_id)).await
+    }
+
+    async fn remove_watcher(&self, issue_id: i64, user_name: &str) -> Result<()> {
+        observe("remove_watcher", self.0.remove_watcher(issue_id, user_name)).await
+    }
+
+    async fn post_telemetry_data(
+        &self,
+        data: &models::TelemetryTimingData,
+        version: &str,
+        ip_address: &str,
+    ) -> Result<()> {
+        let result = observe(
+            "post_telemetry_data",
+            self.0.post_telemetry_data(data, version, ip_address),
+        )
+        .await;
+        ingested(result, "telemetry", &data.project)
+    }
+
+    async fn post_telemetry_batch(&self, batch: &[models::TelemetryPost]) -> Result<()> {
+        observe("post_telemetry_batch", self.0.post_telemetry_batch(batch)).await?;
+        for post in batch {
+            metrics::ingested("telemetry", &post.data.project, 1);
+        }
+        Ok(())
+    }
+
+    async fn get_timing_counts(
+        &self,
+        query: &models::TimingQuery,
+    ) -> Result<Vec<models::TimingCount>> {
+        observe("get_timing_counts", self.0.get_timing_counts(query)).await
+    }
+
+    async fn get_timing_samples(
+        &self,
+        query: &models::TimingQuery,
+        every: i64,
+    ) -> Result<Vec<models::TimingSample>> {
+        observe(
+            "get_timing_samples",
+            self.0.get_timing_samples(query, every),
+        )
+        .await
+    }
+
+    async fn post_error_data(
+        &self,
+        data: &models::TelemetryErrorData,
+        version: &str,
+        ip_address: &str,
+    ) -> Result<()> {
+        let result = observe(
+            "post_error_data",
+            self.0.post_error_data(data, version, ip_address),
+        )
+        .await;
+        ingested(result, "error", data.project.as_deref().unwrap_or_default())
+    }
+
+    async fn get_error_data(
+        &self,
+        query: &models::ErrorQuery,
+    ) -> Result<Vec<models::TelemetryErrorData>> {
+        observe("get_error_data", self.0.get_error_data(query)).await
+    }
+
+    async fn get_top_crashes(
+        &self,
+        query: &models::ErrorQuery,
+    ) -> Result<Vec<models::CrashBucket>> {
+        observe("get_top_crashes", self.0.get_top_crashes(query)).await
+    }
+
+    async fn get_error_counts(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<models::ErrorCount>> {
+        observe("get_error_counts", self.0.get_error_counts(from, to)).await
+    }
+
+    async fn find_or_add_user_id(&self, name: &str) -> Result<Option<i64>> {
+        observe("find_or_add_user_id", self.0.find_or_add_user_id(name)).await
+    }
+
+    fn id_cache_stats(&self) -> Option<IdCacheStats> {
+        self.0.id_cache_stats()
+    }
+
+    fn invalidate_id_cache(&self) {
+        self.0.invalidate_id_cache();
+    }
+
+    async fn ping(&self) -> Result<()> {
+        observe("ping", self.0.ping()).await
+    }
+
+    async fn describe_schema(&self) -> Result<Option<Vec<Column>>> {
+        observe("describe_schema", self.0.describe_schema()).await
+    }
+
+    async fn backfill(&self) {
+        self.0.backfill().await
+    }
+
+    async fn export_rows(
+        &self,
+        table: DumpTable,
+        after_id: i64,
+        limit: u32,
+    ) -> Result<Vec<DumpRow>> {
+        observe("export_rows", self.0.export_rows(table, after_id, limit)).await
+    }
+
+    async fn import_row(&self, row: &DumpRow) -> Result<i64> {
+        observe("import_row", self.0.import_row(row)).await
+    }
+
+    async fn prune(
+        &self,
+        policy: &RetentionPolicy,
+        cutoff: DateTime<Utc>,
+        limit: u32,
+    ) -> Result<u64> {
+        observe("prune", self.0.prune(policy, cutoff, limit)).await
+    }
+}
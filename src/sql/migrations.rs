@@ -0,0 +1,206 @@
+use rocket::fairing::{self, AdHoc};
+use rocket::{Build, Rocket};
+use rocket_db_pools::sqlx;
+use rocket_db_pools::Database;
+use sqlx::migrate::{Migrate, MigrateError, Migration, Migrator};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use super::availability::is_unavailable;
+use super::lazy_pool::LazyPool;
+use super::schema;
+use super::sql_connector::TableNames;
+
+pub static MYSQL: Migrator = sqlx::migrate!("migrations/mysql");
+pub static SQLITE: Migrator = sqlx::migrate!("migrations/sqlite");
+pub static POSTGRES: Migrator = sqlx::migrate!("migrations/postgres");
+
+// Public Functions:
+
+/// The MySQL migrations pointed at the configured schema and table prefix. The bookkeeping table
+/// `_sqlx_migrations` stays in the default database of the connection `url`.
+pub fn mysql(table_names: &TableNames) -> Migrator {
+    Migrator {
+        migrations: MYSQL
+            .iter()
+            .map(|m| {
+                Migration::new(
+                    m.version,
+                    m.description.clone(),
+                    m.migration_type,
+                    Cow::Owned(table_names.apply_to_migration(&m.sql)),
+                )
+            })
+            .collect(),
+        ignore_missing: MYSQL.ignore_missing,
+    }
+}
+
+/// Owned handle on one of the embedded migrators, for [`stage`].
+pub fn borrowed(migrator: &'static Migrator) -> Migrator {
+    Migrator {
+        migrations: Cow::Borrowed(migrator.migrations.as_ref()),
+        ignore_missing: migrator.ignore_missing,
+    }
+}
+
+/// Applies every migration of `migrator` that has not been applied to the database yet.
+///
+/// Fails with `MigrateError::VersionMissing` if the database has a migration applied that this
+/// build does not know about, i.e. it was migrated by a newer server.
+pub async fn run<DB>(migrator: &Migrator, pool: &sqlx::Pool<DB>) -> Result<(), MigrateError>
+where
+    DB: sqlx::Database,
+    DB::Connection: Migrate,
+{
+    migrator.run(pool).await
+}
+
+/// Returns the versions of the migrations of `migrator` that have not been applied yet, without
+/// applying them. Fails the same way as [`run`] against an unknown newer schema.
+pub async fn pending<DB>(
+    migrator: &Migrator,
+    pool: &sqlx::Pool<DB>,
+) -> Result<Vec<i64>, MigrateError>
+where
+    DB: sqlx::Database,
+    DB::Connection: Migrate,
+{
+    let mut connection = pool.acquire().await?;
+    connection.ensure_migrations_table().await?;
+
+    if let Some(version) = connection.dirty_version().await? {
+        return Err(MigrateError::Dirty(version));
+    }
+
+    let applied_versions: HashSet<i64> = connection
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|applied_migration| applied_migration.version)
+        .collect();
+    let known_versions: HashSet<i64> = migrator.iter().map(|m| m.version).collect();
+    if let Some(version) = applied_versions.difference(&known_versions).max() {
+        return Err(MigrateError::VersionMissing(*version));
+    }
+
+    Ok(migrator
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .map(|m| m.version)
+        .filter(|version| !applied_versions.contains(version))
+        .collect())
+}
+
+/// Fairing that brings the schema of database `D` up to date at startup, or only verifies it when
+/// `databases.ugsdb.auto_migrate` is set to `false`. Must be attached after `D`. If the database
+/// cannot be reached, manages a `Deferred` for the store to run once it can instead.
+pub fn stage<D, DB>(migrator: Migrator) -> AdHoc
+where
+    D: Database<Pool = LazyPool<DB>>,
+    DB: sqlx::Database,
+    DB::Connection: Migrate,
+{
+    AdHoc::try_on_ignite("Schema Migrations", move |rocket| {
+        run_at_startup::<D, DB>(migrator, rocket)
+    })
+}
+
+/// Migrations that `stage` could not run because the database was unreachable at launch.
+/// `schema::checked` runs them on the first connection instead, before it checks the schema.
+pub struct Deferred<DB: sqlx::Database> {
+    migrator: Migrator,
+    pool: sqlx::Pool<DB>,
+    auto_migrate: bool,
+}
+
+#[rocket::async_trait]
+impl<DB> schema::Prepare for Deferred<DB>
+where
+    DB: sqlx::Database,
+    DB::Connection: Migrate,
+{
+    async fn prepare(&self) -> sqlx::Result<bool> {
+        match migrate(&self.migrator, &self.pool, self.auto_migrate).await {
+            Ok(migrated) => Ok(migrated),
+            Err(MigrateError::Execute(e)) if is_unavailable(&e) => Err(e),
+            Err(e) => {
+                log::error!("Failed to migrate database schema: {}", e);
+                Ok(false)
+            }
+        }
+    }
+}
+
+// Private Functions:
+
+async fn run_at_startup<D, DB>(migrator: Migrator, rocket: Rocket<Build>) -> fairing::Result
+where
+    D: Database<Pool = LazyPool<DB>>,
+    DB: sqlx::Database,
+    DB::Connection: Migrate,
+{
+    let auto_migrate = match rocket
+        .figment()
+        .extract_inner::<bool>("databases.ugsdb.auto_migrate")
+    {
+        Ok(auto_migrate) => auto_migrate,
+        Err(e) if e.missing() => true,
+        Err(e) => {
+            log::error!("Invalid `databases.ugsdb.auto_migrate`: {}", e);
+            return Err(rocket);
+        }
+    };
+
+    let pool: &sqlx::Pool<DB> = match D::fetch(&rocket) {
+        Some(db) => db,
+        None => return Err(rocket),
+    };
+
+    match migrate(&migrator, pool, auto_migrate).await {
+        Ok(true) => Ok(rocket),
+        Ok(false) => Err(rocket),
+        Err(MigrateError::Execute(e)) if is_unavailable(&e) => {
+            log::warn!(
+                "Could not reach the database to migrate its schema, migrating on the first connection instead: {}",
+                e
+            );
+            let deferred = Deferred {
+                migrator,
+                pool: pool.clone(),
+                auto_migrate,
+            };
+            Ok(rocket.manage(Arc::new(deferred)))
+        }
+        Err(e) => {
+            log::error!("Failed to migrate database schema: {}", e);
+            Err(rocket)
+        }
+    }
+}
+
+/// Applies the pending migrations of `migrator`, or with `auto_migrate` off only checks that there
+/// are none. `Ok(false)` if there are, which is logged.
+async fn migrate<DB>(
+    migrator: &Migrator,
+    pool: &sqlx::Pool<DB>,
+    auto_migrate: bool,
+) -> Result<bool, MigrateError>
+where
+    DB: sqlx::Database,
+    DB::Connection: Migrate,
+{
+    if auto_migrate {
+        return run(migrator, pool).await.map(|_| true);
+    }
+    let pending_versions = pending(migrator, pool).await?;
+    if !pending_versions.is_empty() {
+        log::error!(
+            "Database schema is out of date, pending migrations: {:?}. Run `ugs-metadata-server migrate` or enable `auto_migrate`.",
+            pending_versions
+        );
+        return Ok(false);
+    }
+    Ok(true)
+}
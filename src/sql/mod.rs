@@ -1 +1,404 @@
-pub mod sql_connector;
\ No newline at end of file
+pub mod availability;
+pub mod id_cache;
+pub mod latest_cache;
+pub mod lazy_pool;
+pub mod memory_store;
+pub mod metered_store;
+pub mod migrations;
+pub mod mysql_store;
+pub mod postgres_connector;
+pub mod postgres_store;
+pub mod queries;
+pub mod replica;
+pub mod schema;
+pub mod sql_connector;
+pub mod sqlite_connector;
+pub mod sqlite_store;
+
+use crate::dump::{DumpRow, DumpTable};
+use crate::retention::RetentionPolicy;
+use crate::{models, PgDatabase, SqliteDatabase, UGSDatabase};
+use availability::StartupRetry;
+use chrono::{DateTime, Utc};
+use id_cache::{IdCache, IdCacheStats};
+use latest_cache::LatestCache;
+use replica::ReplicaConfig;
+use rocket::fairing::AdHoc;
+use rocket::serde::{Deserialize, Serialize};
+use rocket_db_pools::sqlx;
+use rocket_db_pools::Database;
+use sql_connector::TableNames;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub type Result<T> = std::result::Result<T, sqlx::Error>;
+
+/// Storage backend behind the web_apis handlers, which reach it through a managed `SharedStore`.
+#[rocket::async_trait]
+pub trait MetadataStore: Send + Sync {
+    // Builds
+
+    async fn get_builds(&self, project: &str, last_build_id: i64)
+        -> Result<Vec<models::BuildData>>;
+
+    async fn post_build(&self, build: &models::BuildData) -> Result<()>;
+
+    // Comments
+
+    async fn get_comments(
+        &self,
+        project: &str,
+        last_comment_id: i64,
+    ) -> Result<Vec<models::CommentData>>;
+
+    async fn post_comment(&self, comment: &models::CommentData) -> Result<()>;
+
+    // Events
+
+    async fn get_user_votes(
+        &self,
+        project: &str,
+        last_event_id: i64,
+    ) -> Result<Vec<models::EventData>>;
+
+    async fn post_event(&self, event: &models::EventData) -> Result<()>;
+
+    /// Stores every event of `events` in one transaction.
+    async fn post_event_batch(&self, events: &[models::EventData]) -> Result<()>;
+
+    async fn get_last_ids(&self, project: Option<&str>) -> Result<models::LatestData>;
+
+    // Issues
+
+    async fn add_issue(&self, issue: &models::IssueData) -> Result<i64>;
+
+    async fn get_issue(&self, issue_id: i64) -> Result<Option<models::IssueData>>;
+
+    async fn get_issues_filtered(
+        &self,
+        include_resolved: bool,
+        num_results: Option<i32>,
+    ) -> Result<Vec<models::IssueData>>;
+
+    async fn get_issues_by_user_name(&self, user_name: &str) -> Result<Vec<models::IssueData>>;
+
+    async fn update_issue(&self, issue_id: i64, issue: &models::IssueUpdateData) -> Result<()>;
+
+    async fn delete_issue(&self, issue_id: i64) -> Result<()>;
+
+    // Issue builds
+
+    async fn add_build(&self, issue_id: i64, build: &models::IssueBuildData) -> Result<i64>;
+
+    async fn get_builds_by_issue(&self, issue_id: i64) -> Result<Vec<models::IssueBuildData>>;
+
+    async fn get_build(&self, build_id: i64) -> Result<Option<models::IssueBuildData>>;
+
+    async fn update_build(&self, build_id: i64, outcome: i32) -> Result<()>;
+
+    // Issue diagnostics
+
+    async fn add_diagnostic(
+        &self,
+        issue_id: i64,
+        diagnostic: &models::IssueDiagnosticData,
+    ) -> Result<()>;
+
+    async fn get_diagnostics(&self, issue_id: i64) -> Result<Vec<models::IssueDiagnosticData>>;
+
+    // Issue watchers
+
+    async fn add_watcher(&self, issue_id: i64, user_name: &str) -> Result<()>;
+
+    async fn get_watchers(&self, issue_id: i64) -> Result<Vec<String>>;
+
+    async fn remove_watcher(&self, issue_id: i64, user_name: &str) -> Result<()>;
+
+    // Telemetry
+
+    async fn post_telemetry_data(
+        &self,
+        data: &models::TelemetryTimingData,
+        version: &str,
+        ip_address: &str,
+    ) -> Result<()>;
+
+    /// Stores every post of `batch` in one transaction, with as few statements as possible.
+    async fn post_telemetry_batch(&self, batch: &[models::TelemetryPost]) -> Result<()>;
+
+    /// How many timing samples match `query`, per group of `query` and `Result`.
+    async fn get_timing_counts(
+        &self,
+        query: &models::TimingQuery,
+    ) -> Result<Vec<models::TimingCount>>;
+
+    /// The timing samples matching `query` whose id is a multiple of `every`, in no particular
+    /// order.
+    async fn get_timing_samples(
+        &self,
+        query: &models::TimingQuery,
+        every: i64,
+    ) -> Result<Vec<models::TimingSample>>;
+
+    async fn post_error_data(
+        &self,
+        data: &models::TelemetryErrorData,
+        version: &str,
+        ip_address: &str,
+    ) -> Result<()>;
+
+    async fn get_error_data(
+        &self,
+        query: &models::ErrorQuery,
+    ) -> Result<Vec<models::TelemetryErrorData>>;
+
+    /// Up to `query.records` crash buckets of the error reports matching `query`, affecting the
+    /// most users first. `query.before_id` does not apply.
+    async fn get_top_crashes(&self, query: &models::ErrorQuery)
+        -> Result<Vec<models::CrashBucket>>;
+
+    /// Number of error reports per version and project with a `Timestamp` from `from` up to `to`.
+    async fn get_error_counts(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<models::ErrorCount>>;
+
+    // Users
+
+    async fn find_or_add_user_id(&self, name: &str) -> Result<Option<i64>>;
+
+    // Caches
+
+    /// Hit and miss counts of the project and user id cache, or `None` if the store has none.
+    fn id_cache_stats(&self) -> Option<IdCacheStats>;
+
+    /// Forgets every cached project and user id, e.g. after those tables were edited by hand.
+    fn invalidate_id_cache(&self);
+
+    // Health
+
+    /// Runs a trivial query on the database that writes go to. Fails with `IncompatibleSchema`
+    /// once that succeeds if writes are refused, and always succeeds without a database.
+    async fn ping(&self) -> Result<()>;
+
+    // Schema
+
+    /// Every column of the tables in the database, or `None` if the store has no database schema.
+    async fn describe_schema(&self) -> Result<Option<Vec<schema::Column>>>;
+
+    /// Fills in the columns that rows from before a migration lack. Run by `schema::checked` once
+    /// the schema is compatible, and logs rather than fails, as the rows are usable without them.
+    async fn backfill(&self);
+
+    // Export and import
+
+    /// Up to `limit` rows of `table` with an id above `after_id`, in id order.
+    async fn export_rows(
+        &self,
+        table: DumpTable,
+        after_id: i64,
+        limit: u32,
+    ) -> Result<Vec<DumpRow>>;
+
+    /// Adds `row` under a new id and returns that id. Projects and users are matched by name.
+    async fn import_row(&self, row: &DumpRow) -> Result<i64>;
+
+    // Retention
+
+    /// Deletes up to `limit` of the oldest rows matching `policy` that were written before
+    /// `cutoff`, returning how many were deleted.
+    async fn prune(
+        &self,
+        policy: &RetentionPolicy,
+        cutoff: DateTime<Utc>,
+        limit: u32,
+    ) -> Result<u64>;
+}
+
+pub type SharedStore = Arc<dyn MetadataStore>;
+
+/// Logs the outcome of filling in `Projects.Stream` when a SQL store starts. A failure is not
+/// fatal: the schema check that follows reports the column if it is missing.
+fn log_filled_project_streams(result: Result<u64>) {
+    match result {
+        Ok(0) => {}
+        Ok(count) => log::info!("Filled in the stream of {} projects.", count),
+        Err(e) => log::warn!("Could not fill in the stream of existing projects: {}", e),
+    }
+}
+
+/// Logs the outcome of filling in `Errors.Signature` when a SQL store starts. Like
+/// `log_filled_project_streams`, a failure is not fatal.
+fn log_filled_error_signatures(result: Result<u64>) {
+    match result {
+        Ok(0) => {}
+        Ok(count) => log::info!("Filled in the crash signature of {} error reports.", count),
+        Err(e) => log::warn!(
+            "Could not fill in the crash signature of existing error reports: {}",
+            e
+        ),
+    }
+}
+
+/// Which database `databases.ugsdb.url` points at, set with `databases.ugsdb.backend`. Managed by
+/// `stage()` for `/version`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum Backend {
+    MySql,
+    Sqlite,
+    Postgres,
+    /// Keeps everything in process memory and ignores `url`; for tests and local development.
+    Memory,
+}
+
+impl Backend {
+    /// Every backend this server supports.
+    pub const ALL: [Backend; 4] = [
+        Backend::MySql,
+        Backend::Sqlite,
+        Backend::Postgres,
+        Backend::Memory,
+    ];
+}
+
+/// Fairing that attaches the pool, migrations and `SharedStore` of the configured backend.
+pub fn stage() -> AdHoc {
+    AdHoc::try_on_ignite("Metadata Store", |rocket| async {
+        let backend = match rocket
+            .figment()
+            .extract_inner::<Backend>("databases.ugsdb.backend")
+        {
+            Ok(backend) => backend,
+            Err(e) if e.missing() => Backend::MySql,
+            Err(e) => {
+                log::error!("Invalid `databases.ugsdb.backend`: {}", e);
+                return Err(rocket);
+            }
+        };
+
+        let schema = rocket
+            .figment()
+            .extract_inner::<String>("databases.ugsdb.schema");
+        let table_prefix = rocket
+            .figment()
+            .extract_inner::<String>("databases.ugsdb.table_prefix");
+        if !matches!(backend, Backend::MySql) && (schema.is_ok() || table_prefix.is_ok()) {
+            log::error!(
+                "`databases.ugsdb.schema` and `databases.ugsdb.table_prefix` are only supported by the MySql backend."
+            );
+            return Err(rocket);
+        }
+
+        let id_cache_capacity = match rocket
+            .figment()
+            .extract_inner::<usize>("databases.ugsdb.id_cache_capacity")
+        {
+            Ok(capacity) => capacity,
+            Err(e) if e.missing() => IdCache::DEFAULT_CAPACITY,
+            Err(e) => {
+                log::error!("Invalid `databases.ugsdb.id_cache_capacity`: {}", e);
+                return Err(rocket);
+            }
+        };
+        let id_cache = IdCache::new(id_cache_capacity);
+
+        let latest_cache_max_age = match rocket
+            .figment()
+            .extract_inner::<u64>("databases.ugsdb.latest_cache_max_age")
+        {
+            Ok(seconds) => Duration::from_secs(seconds),
+            Err(e) if e.missing() => LatestCache::DEFAULT_MAX_AGE,
+            Err(e) => {
+                log::error!("Invalid `databases.ugsdb.latest_cache_max_age`: {}", e);
+                return Err(rocket);
+            }
+        };
+        let latest_cache = LatestCache::new(latest_cache_max_age);
+
+        let replica = match rocket
+            .figment()
+            .extract_inner::<ReplicaConfig>("databases.ugsdb.replica")
+        {
+            Ok(replica) => Some(replica),
+            Err(e) if e.missing() => None,
+            Err(e) => {
+                log::error!("Invalid `databases.ugsdb.replica`: {}", e);
+                return Err(rocket);
+            }
+        };
+        if matches!(backend, Backend::Memory) && replica.is_some() {
+            log::error!("`databases.ugsdb.replica` is not supported by the Memory backend.");
+            return Err(rocket);
+        }
+
+        let startup_retry = match rocket
+            .figment()
+            .extract_inner::<StartupRetry>("databases.ugsdb.startup_retry")
+        {
+            Ok(startup_retry) => startup_retry,
+            Err(e) if e.missing() => StartupRetry::default(),
+            Err(e) => {
+                log::error!("Invalid `databases.ugsdb.startup_retry`: {}", e);
+                return Err(rocket);
+            }
+        };
+        // A SQLite database is a local file, which there is no point waiting for.
+        let url = rocket
+            .figment()
+            .extract_inner::<String>("databases.ugsdb.url");
+        let waited = match (backend, &url) {
+            (Backend::MySql, Ok(url)) => startup_retry.wait_for::<sqlx::MySqlConnection>(url).await,
+            (Backend::Postgres, Ok(url)) => startup_retry.wait_for::<sqlx::PgConnection>(url).await,
+            _ => Ok(()),
+        };
+        if let Err(e) = waited {
+            // The pool connects lazily, so requests get a 503 until the database is reachable.
+            log::error!(
+                "Could not connect to the database, starting without it: {}",
+                e
+            );
+        }
+
+        log::info!("Using the {:?} metadata store.", backend);
+        let rocket = rocket.manage(backend);
+        Ok(match backend {
+            Backend::MySql => {
+                let schema = schema.unwrap_or_else(|_| String::from(TableNames::DEFAULT_SCHEMA));
+                let table_prefix = table_prefix.unwrap_or_default();
+                let table_names = match TableNames::new(&schema, &table_prefix) {
+                    Ok(table_names) => table_names,
+                    Err(e) => {
+                        log::error!("Invalid `databases.ugsdb` table names: {}", e);
+                        return Err(rocket);
+                    }
+                };
+                rocket
+                    .attach(UGSDatabase::init())
+                    .attach(migrations::stage::<UGSDatabase, _>(migrations::mysql(
+                        &table_names,
+                    )))
+                    .attach(mysql_store::stage(
+                        table_names,
+                        id_cache,
+                        latest_cache,
+                        replica,
+                    ))
+            }
+            Backend::Sqlite => rocket
+                .attach(SqliteDatabase::init())
+                .attach(migrations::stage::<SqliteDatabase, _>(
+                    migrations::borrowed(&migrations::SQLITE),
+                ))
+                .attach(sqlite_store::stage(id_cache, latest_cache, replica)),
+            Backend::Postgres => rocket
+                .attach(PgDatabase::init())
+                .attach(migrations::stage::<PgDatabase, _>(migrations::borrowed(
+                    &migrations::POSTGRES,
+                )))
+                .attach(postgres_store::stage(id_cache, latest_cache, replica)),
+            Backend::Memory => rocket.attach(memory_store::stage()),
+        })
+    })
+}
@@ -0,0 +1,819 @@
+//! Queries shared by the MySQL, SQLite and PostgreSQL connectors, built for any of them. They are
+//! written with double-quoted identifiers, which `Dialect::quote` turns into those of the database,
+//! and take their parameters with `push_bind`. The connectors run them and read the rows.
+
+use crate::crash::CrashCounts;
+use crate::dump::{DumpRow, DumpTable};
+use crate::models;
+use crate::retention::{RetentionPolicy, RetentionTable};
+use crate::sql::latest_cache::RECENT_CHANGE_COUNT;
+use crate::sql::sql_connector::{
+    get_project_stream, grouped_positions, sanitize_text, TableNames, ISSUE_SUMMARY_MAX_LENGTH,
+};
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use rocket_db_pools::sqlx::{
+    self, ColumnIndex, Database, Decode, Encode, FromRow, MySql, Postgres, QueryBuilder, Row,
+    Sqlite, Type,
+};
+use std::borrow::Cow;
+
+lazy_static! {
+    /// The tables of SQLite and PostgreSQL, which have no schema or table prefix setting.
+    pub static ref UNQUALIFIED_TABLES: TableNames = TableNames::unqualified();
+}
+
+/// What the SQL of the databases differs in, other than placeholders.
+pub trait Dialect: Database {
+    /// Whether `DELETE` takes `ORDER BY` and `LIMIT`, rather than picking its rows in a subquery.
+    const DELETE_HAS_LIMIT: bool;
+
+    /// Put before the operand of a text comparison to make it case-sensitive, as project names
+    /// are, for databases whose default collation is not.
+    const CASE_SENSITIVE: &'static str = "";
+
+    /// `sql` with its double-quoted identifiers quoted the way the database expects.
+    fn quote(sql: &str) -> Cow<'_, str> {
+        Cow::Borrowed(sql)
+    }
+
+    /// Appends the current time, in UTC.
+    fn push_now(query_builder: &mut QueryBuilder<'_, Self>);
+
+    /// Appends a condition that `text` and `column` share their first `length(column) - dropped`
+    /// characters, which none do when that is negative.
+    fn push_same_prefix<'args>(
+        query_builder: &mut QueryBuilder<'args, Self>,
+        text: &'args str,
+        column: &str,
+        dropped: usize,
+    );
+
+    /// The start of the `bucket` second interval the timestamp `column` is in, in seconds since the
+    /// Unix epoch.
+    fn time_bucket(column: &str, bucket: i64) -> String;
+}
+
+impl Dialect for MySql {
+    const DELETE_HAS_LIMIT: bool = true;
+    const CASE_SENSITIVE: &'static str = "BINARY ";
+
+    fn quote(sql: &str) -> Cow<'_, str> {
+        Cow::Owned(sql.replace('"', "`"))
+    }
+
+    fn push_now(query_builder: &mut QueryBuilder<'_, Self>) {
+        query_builder.push("UTC_TIMESTAMP()");
+    }
+
+    fn push_same_prefix<'args>(
+        query_builder: &mut QueryBuilder<'args, Self>,
+        text: &'args str,
+        column: &str,
+        dropped: usize,
+    ) {
+        let length = format!("CHAR_LENGTH({column}) - {dropped}");
+        query_builder
+            .push("LEFT(")
+            .push_bind(text)
+            .push_sql(format!(", {length}) = BINARY LEFT({column}, {length})"));
+    }
+
+    fn time_bucket(column: &str, bucket: i64) -> String {
+        format!("TIMESTAMPDIFF(SECOND, '1970-01-01 00:00:00', {column}) DIV {bucket} * {bucket}")
+    }
+}
+
+impl Dialect for Sqlite {
+    const DELETE_HAS_LIMIT: bool = false;
+
+    // Bound from Rust, as SQLite's own clock gives no time zone.
+    fn push_now(query_builder: &mut QueryBuilder<'_, Self>) {
+        query_builder.push_bind(Utc::now());
+    }
+
+    fn push_same_prefix<'args>(
+        query_builder: &mut QueryBuilder<'args, Self>,
+        text: &'args str,
+        column: &str,
+        dropped: usize,
+    ) {
+        let length = format!("length({column}) - {dropped}");
+        query_builder
+            .push("substr(")
+            .push_bind(text)
+            .push_sql(format!(", 1, {length}) = substr({column}, 1, {length})"));
+    }
+
+    fn time_bucket(column: &str, bucket: i64) -> String {
+        format!("CAST(strftime('%s', {column}) AS INTEGER) / {bucket} * {bucket}")
+    }
+}
+
+impl Dialect for Postgres {
+    const DELETE_HAS_LIMIT: bool = false;
+
+    fn push_now(query_builder: &mut QueryBuilder<'_, Self>) {
+        query_builder.push("NOW()");
+    }
+
+    fn push_same_prefix<'args>(
+        query_builder: &mut QueryBuilder<'args, Self>,
+        text: &'args str,
+        column: &str,
+        dropped: usize,
+    ) {
+        // left() counts a negative length from the end instead.
+        let length = format!("greatest(length({column}) - {dropped}, 0)");
+        query_builder
+            .push("left(")
+            .push_bind(text)
+            .push_sql(format!(", {length}) = left({column}, {length})"));
+    }
+
+    fn time_bucket(column: &str, bucket: i64) -> String {
+        format!("CAST(FLOOR(EXTRACT(EPOCH FROM {column}) / {bucket}) * {bucket} AS BIGINT)")
+    }
+}
+
+/// The queries for the `events`, `comments` and `builds` windows of `RecentChanges`, each returning
+/// a change and the first id posted for it, of the projects in `stream` if there is one.
+pub fn recent_changes<'args, DB: Dialect>(
+    tables: &TableNames,
+    stream: Option<&'args str>,
+) -> [QueryBuilder<'args, DB>; 3]
+where
+    for<'q> &'q str: Encode<'q, DB> + Type<DB>,
+{
+    [
+        (&tables.user_votes, "UserVotes", "Changelist"),
+        (&tables.comments, "Comments", "ChangeNumber"),
+        (&tables.badges, "Badges", "ChangeNumber"),
+    ]
+    .map(|(table, alias, change)| {
+        let mut query_builder = new_query(format!(
+            r#"SELECT "{alias}"."{change}", MIN("{alias}"."Id") FROM {table} AS "{alias}" INNER JOIN {projects} AS "Projects" ON "Projects"."Id" = "{alias}"."ProjectId""#,
+            projects = tables.projects,
+        ));
+        if let Some(stream) = stream {
+            query_builder
+                .push_sql(r#" WHERE "Projects"."Stream" = "#)
+                .push(DB::CASE_SENSITIVE)
+                .push_bind(stream);
+        }
+        query_builder.push_sql(format!(
+            r#" GROUP BY "{alias}"."{change}" ORDER BY "{alias}"."{change}" DESC LIMIT {RECENT_CHANGE_COUNT}"#
+        ));
+        query_builder
+    })
+}
+
+pub fn user_votes<'args, DB: Dialect>(
+    tables: &TableNames,
+    project: &'args str,
+    last_event_id: i64,
+) -> QueryBuilder<'args, DB>
+where
+    for<'q> &'q str: Encode<'q, DB> + Type<DB>,
+    for<'q> i64: Encode<'q, DB> + Type<DB>,
+{
+    let mut query_builder = new_query(format!(
+        r#"SELECT "UserVotes"."Id", "UserVotes"."Changelist" AS "Change", "UserVotes"."UserName", "UserVotes"."Verdict" AS "EventType", "UserVotes"."Project" FROM {user_votes} AS "UserVotes" INNER JOIN {projects} AS "Projects" ON "Projects"."Id" = "UserVotes"."ProjectId" WHERE "Projects"."Name" = "#,
+        user_votes = tables.user_votes,
+        projects = tables.projects,
+    ));
+    query_builder
+        .push(DB::CASE_SENSITIVE)
+        .push_bind(project)
+        .push_sql(r#" AND "UserVotes"."Id" > "#)
+        .push_bind(last_event_id)
+        .push_sql(r#" ORDER BY "UserVotes"."Id""#);
+    query_builder
+}
+
+pub fn comments<'args, DB: Dialect>(
+    tables: &TableNames,
+    project: &'args str,
+    last_comment_id: i64,
+) -> QueryBuilder<'args, DB>
+where
+    for<'q> &'q str: Encode<'q, DB> + Type<DB>,
+    for<'q> i64: Encode<'q, DB> + Type<DB>,
+{
+    let mut query_builder = new_query(format!(
+        r#"SELECT "Comments"."Id", "Comments"."ChangeNumber", "Comments"."UserName", "Comments"."Text", "Comments"."Project" FROM {comments} AS "Comments" INNER JOIN {projects} AS "Projects" ON "Projects"."Id" = "Comments"."ProjectId" WHERE "Projects"."Name" = "#,
+        comments = tables.comments,
+        projects = tables.projects,
+    ));
+    query_builder
+        .push(DB::CASE_SENSITIVE)
+        .push_bind(project)
+        .push_sql(r#" AND "Comments"."Id" > "#)
+        .push_bind(last_comment_id)
+        .push_sql(r#" ORDER BY "Comments"."Id""#);
+    query_builder
+}
+
+/// The builds shown to clients of `project`, by the same rules as `project_matches`: those of the
+/// project itself, and those of a wildcard project in its stream that the project starts with.
+pub fn builds<'args, DB: Dialect>(
+    tables: &TableNames,
+    project: &'args str,
+    last_build_id: i64,
+) -> QueryBuilder<'args, DB>
+where
+    for<'q> &'q str: Encode<'q, DB> + Type<DB>,
+    for<'q> String: Encode<'q, DB> + Type<DB>,
+    for<'q> i64: Encode<'q, DB> + Type<DB>,
+{
+    let mut query_builder = new_query(format!(
+        r#"SELECT "Badges"."Id", "Badges"."ChangeNumber", "Badges"."BuildType", "Badges"."Result", "Badges"."Url", "Projects"."Name" AS "Project", "Badges"."ArchivePath" FROM {badges} AS "Badges" INNER JOIN {projects} AS "Projects" ON "Projects"."Id" = "Badges"."ProjectId" WHERE ("Projects"."Name" = "#,
+        badges = tables.badges,
+        projects = tables.projects,
+    ));
+    query_builder
+        .push(DB::CASE_SENSITIVE)
+        .push_bind(project)
+        .push_sql(r#" OR ("Projects"."Stream" = "#)
+        .push(DB::CASE_SENSITIVE)
+        .push_bind(get_project_stream(project))
+        .push_sql(r#" AND "Projects"."Name" LIKE "#)
+        .push(DB::CASE_SENSITIVE)
+        .push("'%...' AND ");
+    DB::push_same_prefix(&mut query_builder, project, r#""Projects"."Name""#, 4);
+    query_builder
+        .push_sql(r#")) AND "Badges"."Id" > "#)
+        .push_bind(last_build_id)
+        .push_sql(r#" ORDER BY "Badges"."Id""#);
+    query_builder
+}
+
+pub fn error_data<'args, DB: Dialect>(
+    tables: &TableNames,
+    query: &'args models::ErrorQuery,
+) -> QueryBuilder<'args, DB>
+where
+    for<'q> String: Encode<'q, DB> + Type<DB>,
+    for<'q> i64: Encode<'q, DB> + Type<DB>,
+    for<'q> DateTime<Utc>: Encode<'q, DB> + Type<DB>,
+    str: Type<DB>,
+{
+    let mut query_builder = new_query(format!(
+        r#"SELECT "Id", "Type" AS "ErrorType", "Text", "UserName", "Project", "Timestamp", "Version", "IpAddress" FROM {} WHERE TRUE"#,
+        tables.errors
+    ));
+    push_error_filters(&mut query_builder, tables, query);
+    if let Some(before_id) = query.before_id {
+        query_builder
+            .push_sql(r#" AND "Id" < "#)
+            .push_bind(before_id);
+    }
+    query_builder
+        .push_sql(r#" ORDER BY "Id" DESC LIMIT "#)
+        .push_bind(i64::from(query.records));
+    query_builder
+}
+
+/// The counts of the crash buckets of the reports matching `query`, affecting the most users
+/// first. `query.before_id` does not apply.
+pub fn crash_counts<'args, DB: Dialect>(
+    tables: &TableNames,
+    query: &'args models::ErrorQuery,
+) -> QueryBuilder<'args, DB>
+where
+    for<'q> String: Encode<'q, DB> + Type<DB>,
+    for<'q> i64: Encode<'q, DB> + Type<DB>,
+    for<'q> DateTime<Utc>: Encode<'q, DB> + Type<DB>,
+    str: Type<DB>,
+{
+    let mut query_builder = new_query(format!(
+        r#"SELECT "Signature", COUNT(*) AS "Count", COUNT(DISTINCT "UserName") AS "UserCount", MIN("Timestamp") AS "FirstSeen", MAX("Timestamp") AS "LastSeen", MAX("Id") AS "LastErrorId" FROM {} WHERE "Signature" IS NOT NULL"#,
+        tables.errors
+    ));
+    push_error_filters(&mut query_builder, tables, query);
+    query_builder
+        .push_sql(r#" GROUP BY "Signature" ORDER BY "UserCount" DESC, "Count" DESC, "LastErrorId" DESC LIMIT "#)
+        .push_bind(i64::from(query.records));
+    query_builder
+}
+
+/// The distinct versions, users and projects that reported the crashes of `counts`.
+pub fn crash_reporters<'args, DB: Dialect>(
+    tables: &TableNames,
+    query: &'args models::ErrorQuery,
+    counts: &[CrashCounts],
+) -> QueryBuilder<'args, DB>
+where
+    for<'q> String: Encode<'q, DB> + Type<DB>,
+    for<'q> i64: Encode<'q, DB> + Type<DB>,
+    for<'q> DateTime<Utc>: Encode<'q, DB> + Type<DB>,
+    str: Type<DB>,
+{
+    let mut query_builder = new_query(format!(
+        r#"SELECT DISTINCT "Signature", "Version", "UserName", "Project" FROM {} WHERE "Signature" IN ("#,
+        tables.errors
+    ));
+    let mut signatures = query_builder.separated(", ");
+    for bucket in counts {
+        signatures.push_bind(bucket.signature.clone());
+    }
+    query_builder.push(")");
+    push_error_filters(&mut query_builder, tables, query);
+    query_builder
+}
+
+/// The `Id` and `Text` of the last report of each crash of `counts`.
+pub fn crash_texts<'args, DB: Dialect>(
+    tables: &TableNames,
+    counts: &[CrashCounts],
+) -> QueryBuilder<'args, DB>
+where
+    for<'q> i64: Encode<'q, DB> + Type<DB>,
+{
+    let mut query_builder = new_query(format!(
+        r#"SELECT "Id", "Text" FROM {} WHERE "Id" IN ("#,
+        tables.errors
+    ));
+    let mut ids = query_builder.separated(", ");
+    for bucket in counts {
+        ids.push_bind(bucket.last_error_id);
+    }
+    query_builder.push(")");
+    query_builder
+}
+
+pub fn error_counts<'args, DB: Dialect>(
+    tables: &TableNames,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> QueryBuilder<'args, DB>
+where
+    for<'q> DateTime<Utc>: Encode<'q, DB> + Type<DB>,
+{
+    let mut query_builder = new_query(format!(
+        r#"SELECT "Version", "Project", COUNT(*) AS "Count" FROM {} WHERE "Timestamp" >= "#,
+        tables.errors
+    ));
+    query_builder
+        .push_bind(from)
+        .push_sql(r#" AND "Timestamp" < "#)
+        .push_bind(to)
+        .push_sql(r#" GROUP BY "Version", "Project""#);
+    query_builder
+}
+
+/// How many of the timings matching `query` there are of each result, in each of its groups.
+pub fn timing_counts<'args, DB: Dialect>(
+    tables: &TableNames,
+    query: &'args models::TimingQuery,
+) -> QueryBuilder<'args, DB>
+where
+    for<'q> String: Encode<'q, DB> + Type<DB>,
+    for<'q> DateTime<Utc>: Encode<'q, DB> + Type<DB>,
+{
+    let mut query_builder = new_query("SELECT ");
+    let groups = push_timing_groups(&mut query_builder, query);
+    query_builder.push_sql(format!(
+        r#", "Result", COUNT(*) AS "Count" FROM {} WHERE TRUE"#,
+        tables.telemetry
+    ));
+    push_timing_filters(&mut query_builder, tables, query);
+    query_builder.push(format!(" GROUP BY {groups}"));
+    query_builder
+}
+
+/// The durations of the timings matching `query`, with their groups. Only every `every`-th of
+/// them by id is returned when `every` is more than 1.
+pub fn timing_samples<'args, DB: Dialect>(
+    tables: &TableNames,
+    query: &'args models::TimingQuery,
+    every: i64,
+) -> QueryBuilder<'args, DB>
+where
+    for<'q> String: Encode<'q, DB> + Type<DB>,
+    for<'q> i64: Encode<'q, DB> + Type<DB>,
+    for<'q> DateTime<Utc>: Encode<'q, DB> + Type<DB>,
+{
+    let mut query_builder = new_query(if every > 1 {
+        r#"SELECT * FROM (SELECT ROW_NUMBER() OVER (ORDER BY "Id") AS "RowNumber", "#
+    } else {
+        "SELECT "
+    });
+    push_timing_groups(&mut query_builder, query);
+    query_builder.push_sql(format!(
+        r#", "Duration" FROM {} WHERE TRUE"#,
+        tables.telemetry
+    ));
+    push_timing_filters(&mut query_builder, tables, query);
+    if every > 1 {
+        query_builder
+            .push_sql(r#") AS "Samples" WHERE "RowNumber" % "#)
+            .push_bind(every)
+            .push(" = 0");
+    }
+    query_builder
+}
+
+/// Issues, newest first: the one with `issue_id` if there is one, and otherwise all of them or the
+/// unresolved ones. `Notify` tells whether the user with `watcher_id` watches each.
+pub fn issues<'args, DB: Dialect>(
+    tables: &TableNames,
+    issue_id: Option<i64>,
+    watcher_id: Option<i64>,
+    include_resolved: bool,
+    num_results: Option<i32>,
+) -> QueryBuilder<'args, DB>
+where
+    for<'q> i64: Encode<'q, DB> + Type<DB>,
+{
+    let mut query_builder = new_query(r#"SELECT "Issues"."Id", "Issues"."CreatedAt", "#);
+    DB::push_now(&mut query_builder);
+    query_builder.push_sql(r#" AS "RetrievedAt", "Issues"."Project", "Issues"."Summary", COALESCE("OwnerUsers"."Name", '') AS "Owner", COALESCE("NominatedByUsers"."Name", '') AS "NominatedBy", "Issues"."AcknowledgedAt", "Issues"."FixChange", "Issues"."ResolvedAt""#);
+    if watcher_id.is_some() {
+        query_builder.push_sql(r#", "IssueWatchers"."UserId" IS NOT NULL AS "Notify""#);
+    } else {
+        query_builder.push_sql(r#", FALSE AS "Notify""#);
+    }
+    push_issue_joins(&mut query_builder, tables);
+    if let Some(watcher_id) = watcher_id {
+        query_builder
+            .push_sql(format!(
+                r#" LEFT JOIN {} AS "IssueWatchers" ON "IssueWatchers"."IssueId" = "Issues"."Id" AND "IssueWatchers"."UserId" = "#,
+                tables.issue_watchers
+            ))
+            .push_bind(watcher_id);
+    }
+    if let Some(issue_id) = issue_id {
+        query_builder
+            .push_sql(r#" WHERE "Issues"."Id" = "#)
+            .push_bind(issue_id);
+    } else if !include_resolved {
+        query_builder.push_sql(r#" WHERE "Issues"."ResolvedAt" IS NULL"#);
+    }
+    query_builder.push_sql(r#" ORDER BY "Issues"."Id" DESC"#);
+    if let Some(num_results) = num_results {
+        query_builder
+            .push(" LIMIT ")
+            .push_bind(i64::from(num_results));
+    }
+    query_builder
+}
+
+/// Updates the fields of the issue with `issue_id` that `issue` sets, `owner_id` and
+/// `nominated_by_id` being the ids of its users.
+pub fn update_issue<'args, DB: Dialect>(
+    tables: &TableNames,
+    issue_id: i64,
+    issue: &models::IssueUpdateData,
+    owner_id: Option<i64>,
+    nominated_by_id: Option<i64>,
+) -> QueryBuilder<'args, DB>
+where
+    for<'q> String: Encode<'q, DB> + Type<DB>,
+    for<'q> i64: Encode<'q, DB> + Type<DB>,
+    for<'q> Option<i64>: Encode<'q, DB> + Type<DB>,
+    for<'q> i32: Encode<'q, DB> + Type<DB>,
+{
+    // Start with a no-op assignment so each field below can be appended with a leading comma.
+    let mut query_builder = new_query(format!(r#"UPDATE {} SET "Id" = "Id""#, tables.issues));
+    if !issue.summary.is_empty() {
+        query_builder
+            .push_sql(r#", "Summary" = "#)
+            .push_bind(sanitize_text(&issue.summary, ISSUE_SUMMARY_MAX_LENGTH));
+    }
+    if !issue.owner.is_empty() {
+        query_builder
+            .push_sql(r#", "OwnerId" = "#)
+            .push_bind(owner_id);
+    }
+    if !issue.nominated_by.is_empty() {
+        query_builder
+            .push_sql(r#", "NominatedById" = "#)
+            .push_bind(nominated_by_id);
+    }
+    if let Some(acknowledged) = issue.acknowledged {
+        query_builder.push_sql(r#", "AcknowledgedAt" = "#);
+        push_now_if(&mut query_builder, acknowledged);
+    }
+    if let Some(fix_change) = issue.fix_change {
+        query_builder
+            .push_sql(r#", "FixChange" = "#)
+            .push_bind(fix_change);
+    }
+    if let Some(resolved) = issue.resolved {
+        query_builder.push_sql(r#", "ResolvedAt" = "#);
+        push_now_if(&mut query_builder, resolved);
+    }
+    query_builder
+        .push_sql(r#" WHERE "Id" = "#)
+        .push_bind(issue_id);
+    query_builder
+}
+
+/// Up to `limit` rows of `table` with an id above `after_id`, in the columns `dump_row` reads.
+pub fn export_rows<'args, DB: Dialect>(
+    tables: &TableNames,
+    table: DumpTable,
+    after_id: i64,
+    limit: u32,
+) -> QueryBuilder<'args, DB>
+where
+    for<'q> i64: Encode<'q, DB> + Type<DB>,
+{
+    let mut query_builder = match table {
+        DumpTable::Projects => {
+            new_query(format!(r#"SELECT "Id", "Name" FROM {}"#, tables.projects))
+        }
+        DumpTable::Users => new_query(format!(r#"SELECT "Id", "Name" FROM {}"#, tables.users)),
+        DumpTable::Badges => new_query(format!(
+            r#"SELECT "Badges"."Id", "Badges"."ChangeNumber", "Badges"."BuildType", "Badges"."Result", "Badges"."Url", "Projects"."Name" AS "Project", "Badges"."ArchivePath" FROM {badges} AS "Badges" INNER JOIN {projects} AS "Projects" ON "Projects"."Id" = "Badges"."ProjectId""#,
+            badges = tables.badges,
+            projects = tables.projects,
+        )),
+        DumpTable::Comments => new_query(format!(
+            r#"SELECT "Id", "ChangeNumber", "UserName", "Text", "Project" FROM {}"#,
+            tables.comments
+        )),
+        DumpTable::UserVotes => new_query(format!(
+            r#"SELECT "Id", "Changelist" AS "Change", "UserName", "Verdict" AS "EventType", "Project", "CreatedAt" FROM {}"#,
+            tables.user_votes
+        )),
+        DumpTable::Issues => {
+            let mut query_builder = new_query(r#"SELECT "Issues"."Id", "Issues"."CreatedAt", "#);
+            DB::push_now(&mut query_builder);
+            query_builder.push_sql(r#" AS "RetrievedAt", "Issues"."Project", "Issues"."Summary", COALESCE("OwnerUsers"."Name", '') AS "Owner", COALESCE("NominatedByUsers"."Name", '') AS "NominatedBy", "Issues"."AcknowledgedAt", "Issues"."FixChange", "Issues"."ResolvedAt", FALSE AS "Notify""#);
+            push_issue_joins(&mut query_builder, tables);
+            query_builder
+        }
+        DumpTable::Errors => new_query(format!(
+            r#"SELECT "Id", "Type" AS "ErrorType", "Text", "UserName", "Project", "Timestamp", "Version", "IpAddress" FROM {}"#,
+            tables.errors
+        )),
+        DumpTable::Telemetry => new_query(format!(
+            r#"SELECT "Id", "Action", "Result", "UserName", "Project", "Timestamp", "Duration", "Version", "IpAddress" FROM {}"#,
+            tables.telemetry
+        )),
+    };
+    // The joined queries need their id column qualified.
+    let id_column = match table {
+        DumpTable::Badges => r#""Badges"."Id""#,
+        DumpTable::Issues => r#""Issues"."Id""#,
+        _ => r#""Id""#,
+    };
+
+    query_builder
+        .push_sql(format!(" WHERE {id_column} > "))
+        .push_bind(after_id)
+        .push_sql(format!(" ORDER BY {id_column} LIMIT "))
+        .push_bind(i64::from(limit));
+    query_builder
+}
+
+/// Deletes up to `limit` of the rows `policy` applies to from before `cutoff`, oldest first.
+pub fn prune<'args, DB: Dialect>(
+    tables: &TableNames,
+    policy: &'args RetentionPolicy,
+    cutoff: DateTime<Utc>,
+    limit: u32,
+) -> QueryBuilder<'args, DB>
+where
+    for<'q> String: Encode<'q, DB> + Type<DB>,
+    for<'q> i64: Encode<'q, DB> + Type<DB>,
+    for<'q> DateTime<Utc>: Encode<'q, DB> + Type<DB>,
+{
+    let (table, timestamp_column) = match policy.table {
+        RetentionTable::Telemetry => (&tables.telemetry, "Timestamp"),
+        RetentionTable::UserVotes => (&tables.user_votes, "CreatedAt"),
+        RetentionTable::Errors => (&tables.errors, "Timestamp"),
+    };
+    let mut query_builder = if DB::DELETE_HAS_LIMIT {
+        new_query(format!(
+            r#"DELETE FROM {table} WHERE "{timestamp_column}" < "#
+        ))
+    } else {
+        new_query(format!(
+            r#"DELETE FROM {table} WHERE "Id" IN (SELECT "Id" FROM {table} WHERE "{timestamp_column}" < "#
+        ))
+    };
+    query_builder.push_bind(cutoff);
+    if let Some(project) = &policy.project {
+        query_builder
+            .push_sql(format!(
+                r#" AND "ProjectId" IN (SELECT "Id" FROM {} WHERE "Stream" = "#,
+                tables.projects
+            ))
+            .push_bind(get_project_stream(project))
+            .push(")");
+    }
+    if let Some(verdict) = &policy.verdict {
+        query_builder
+            .push_sql(r#" AND "Verdict" = "#)
+            .push_bind(verdict);
+    }
+    query_builder
+        .push_sql(r#" ORDER BY "Id" LIMIT "#)
+        .push_bind(i64::from(limit));
+    if !DB::DELETE_HAS_LIMIT {
+        query_builder.push(")");
+    }
+    query_builder
+}
+
+/// Reads a row of `export_rows`.
+pub fn dump_row<R: Row>(table: DumpTable, row: &R) -> sqlx::Result<DumpRow>
+where
+    for<'r> models::BuildData: FromRow<'r, R>,
+    for<'r> models::CommentData: FromRow<'r, R>,
+    for<'r> models::EventData: FromRow<'r, R>,
+    for<'r> models::IssueData: FromRow<'r, R>,
+    for<'r> models::TelemetryErrorData: FromRow<'r, R>,
+    for<'r> models::TelemetryTimingData: FromRow<'r, R>,
+    for<'r> i64: Decode<'r, R::Database> + Type<R::Database>,
+    for<'r> String: Decode<'r, R::Database> + Type<R::Database>,
+    for<'r> DateTime<Utc>: Decode<'r, R::Database> + Type<R::Database>,
+    for<'r> &'r str: ColumnIndex<R>,
+{
+    Ok(match table {
+        DumpTable::Projects => DumpRow::Project {
+            id: row.try_get("Id")?,
+            name: row.try_get("Name")?,
+        },
+        DumpTable::Users => DumpRow::User {
+            id: row.try_get("Id")?,
+            name: row.try_get("Name")?,
+        },
+        DumpTable::Badges => DumpRow::Badge(models::BuildData::from_row(row)?),
+        DumpTable::Comments => DumpRow::Comment(models::CommentData::from_row(row)?),
+        DumpTable::UserVotes => DumpRow::Event {
+            event: models::EventData::from_row(row)?,
+            created_at: row.try_get("CreatedAt")?,
+        },
+        DumpTable::Issues => DumpRow::Issue(models::IssueData::from_row(row)?),
+        DumpTable::Errors => DumpRow::Error(models::TelemetryErrorData::from_row(row)?),
+        DumpTable::Telemetry => DumpRow::Telemetry {
+            id: row.try_get("Id")?,
+            data: models::TelemetryTimingData::from_row(row)?,
+            version: row.try_get("Version")?,
+            ip_address: row.try_get("IpAddress")?,
+        },
+    })
+}
+
+// Private Functions:
+
+/// `QueryBuilder::push` for SQL with double-quoted identifiers.
+trait PushSql {
+    fn push_sql(&mut self, sql: impl AsRef<str>) -> &mut Self;
+}
+
+impl<'args, DB: Dialect> PushSql for QueryBuilder<'args, DB> {
+    fn push_sql(&mut self, sql: impl AsRef<str>) -> &mut Self {
+        self.push(DB::quote(sql.as_ref()))
+    }
+}
+
+fn new_query<'args, DB: Dialect>(sql: impl AsRef<str>) -> QueryBuilder<'args, DB> {
+    QueryBuilder::new(DB::quote(sql.as_ref()))
+}
+
+/// Appends the current time if `now`, and NULL otherwise.
+fn push_now_if<DB: Dialect>(query_builder: &mut QueryBuilder<'_, DB>, now: bool) {
+    if now {
+        DB::push_now(query_builder);
+    } else {
+        query_builder.push("NULL");
+    }
+}
+
+/// Appends the `Issues` table with the users it refers to, for the `IssueData` columns.
+fn push_issue_joins<DB: Dialect>(query_builder: &mut QueryBuilder<'_, DB>, tables: &TableNames) {
+    query_builder.push_sql(format!(
+        r#" FROM {issues} AS "Issues" LEFT JOIN {users} AS "OwnerUsers" ON "OwnerUsers"."Id" = "Issues"."OwnerId" LEFT JOIN {users} AS "NominatedByUsers" ON "NominatedByUsers"."Id" = "Issues"."NominatedById""#,
+        issues = tables.issues,
+        users = tables.users,
+    ));
+}
+
+/// Appends the `Action`, `Project`, `Version` and `Start` columns of the groups of `query` to a
+/// select list, with NULL for those it does not group by. Returns the positions to group by.
+fn push_timing_groups<DB: Dialect>(
+    query_builder: &mut QueryBuilder<'_, DB>,
+    query: &models::TimingQuery,
+) -> String {
+    let project = if query.by_project {
+        r#""Project""#
+    } else {
+        "NULL"
+    };
+    let version = if query.by_version {
+        r#""Version""#
+    } else {
+        "NULL"
+    };
+    // `bucket` is an integer, so it can be part of the query.
+    let start = query.bucket.map_or(String::from("NULL"), |bucket| {
+        DB::time_bucket(r#""Timestamp""#, bucket)
+    });
+    query_builder.push_sql(format!(
+        r#""Action", {project} AS "Project", {version} AS "Version", {start} AS "Start""#
+    ));
+    grouped_positions(&[project, version, &start])
+}
+
+/// Appends the filters of `query` to a query on Telemetry_v2.
+fn push_timing_filters<'args, DB: Dialect>(
+    query_builder: &mut QueryBuilder<'args, DB>,
+    tables: &TableNames,
+    query: &'args models::TimingQuery,
+) where
+    for<'q> String: Encode<'q, DB> + Type<DB>,
+    for<'q> DateTime<Utc>: Encode<'q, DB> + Type<DB>,
+{
+    if let Some(action) = &query.action {
+        query_builder
+            .push_sql(r#" AND "Action" = "#)
+            .push_bind(action);
+    }
+    if let Some(project) = &query.project {
+        push_stream_filter(query_builder, tables, project);
+    }
+    if let Some(version) = &query.version {
+        query_builder
+            .push_sql(r#" AND "Version" = "#)
+            .push_bind(version);
+    }
+    if let Some(from) = query.from {
+        query_builder
+            .push_sql(r#" AND "Timestamp" >= "#)
+            .push_bind(from);
+    }
+    if let Some(to) = query.to {
+        query_builder
+            .push_sql(r#" AND "Timestamp" < "#)
+            .push_bind(to);
+    }
+}
+
+/// Appends the filters of `query` other than `before_id` to a query on Errors.
+fn push_error_filters<'args, DB: Dialect>(
+    query_builder: &mut QueryBuilder<'args, DB>,
+    tables: &TableNames,
+    query: &'args models::ErrorQuery,
+) where
+    for<'q> String: Encode<'q, DB> + Type<DB>,
+    for<'q> DateTime<Utc>: Encode<'q, DB> + Type<DB>,
+    str: Type<DB>,
+{
+    if let Some(project) = &query.project {
+        push_stream_filter(query_builder, tables, project);
+    }
+    // Case-insensitive, as with the default collation of MySQL.
+    if let Some(user_name) = &query.user_name {
+        query_builder
+            .push_sql(r#" AND LOWER("UserName") = LOWER("#)
+            .push_bind(user_name)
+            .push(")");
+    }
+    if let Some(version) = &query.version {
+        query_builder
+            .push_sql(r#" AND "Version" = "#)
+            .push_bind(version);
+    }
+    if let Some(error_type) = query.error_type {
+        query_builder
+            .push_sql(r#" AND "Type" = "#)
+            .push_bind(error_type);
+    }
+    if let Some(from) = query.from {
+        query_builder
+            .push_sql(r#" AND "Timestamp" >= "#)
+            .push_bind(from);
+    }
+    if let Some(to) = query.to {
+        query_builder
+            .push_sql(r#" AND "Timestamp" < "#)
+            .push_bind(to);
+    }
+    if let Some(signature) = &query.signature {
+        query_builder
+            .push_sql(r#" AND "Signature" = "#)
+            .push_bind(signature);
+    }
+}
+
+/// Appends a filter on the `ProjectId` column to the projects in the stream of `project`.
+fn push_stream_filter<DB: Dialect>(
+    query_builder: &mut QueryBuilder<'_, DB>,
+    tables: &TableNames,
+    project: &str,
+) where
+    for<'q> String: Encode<'q, DB> + Type<DB>,
+{
+    query_builder
+        .push_sql(format!(
+            r#" AND "ProjectId" IN (SELECT "Id" FROM {} WHERE "Stream" = "#,
+            tables.projects
+        ))
+        .push_bind(get_project_stream(project))
+        .push(")");
+}
@@ -0,0 +1,175 @@
+use crate::sql::{postgres_connector, sql_connector, sqlite_connector, Result};
+use rocket::serde::Deserialize;
+use rocket_db_pools::sqlx::pool::{PoolConnection, PoolOptions};
+use rocket_db_pools::sqlx::{self, MySql, Pool, Postgres, Sqlite};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// `databases.ugsdb.replica`: a read replica of the database that the SQL stores send their
+/// read-only queries to.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ReplicaConfig {
+    pub url: String,
+    /// Defaults to the sqlx default of 10.
+    #[serde(default)]
+    pub max_connections: Option<u32>,
+    /// Seconds the replica may be behind before reads go back to the primary. Unlimited if unset.
+    #[serde(default)]
+    pub max_lag: Option<f64>,
+    /// Whether reads go to the primary while the replica is unavailable or behind by more than
+    /// `max_lag`. Without it they fail, or read stale data, instead.
+    #[serde(default = "ReplicaConfig::default_fallback")]
+    pub fallback: bool,
+    /// Seconds between checks of how far behind the replica is, and between attempts to use it
+    /// again after it was unavailable.
+    #[serde(default = "ReplicaConfig::default_check_interval")]
+    pub check_interval: u64,
+}
+
+impl ReplicaConfig {
+    fn default_fallback() -> bool {
+        true
+    }
+
+    fn default_check_interval() -> u64 {
+        10
+    }
+
+    /// Whether a replica `lag` seconds behind may be read from; `None` is a lag that can't be
+    /// measured.
+    pub fn accepts_lag(&self, lag: Option<f64>) -> bool {
+        match (lag, self.max_lag) {
+            (Some(lag), Some(max_lag)) => lag <= max_lag,
+            _ => true,
+        }
+    }
+}
+
+/// The query that measures how far a replica is behind, per backend.
+#[rocket::async_trait]
+pub trait ReplicationLag: sqlx::Database {
+    async fn replication_lag(connection: &mut PoolConnection<Self>) -> Result<Option<f64>>;
+}
+
+#[rocket::async_trait]
+impl ReplicationLag for MySql {
+    async fn replication_lag(connection: &mut PoolConnection<Self>) -> Result<Option<f64>> {
+        sql_connector::get_replication_lag(connection).await
+    }
+}
+
+#[rocket::async_trait]
+impl ReplicationLag for Sqlite {
+    async fn replication_lag(connection: &mut PoolConnection<Self>) -> Result<Option<f64>> {
+        sqlite_connector::get_replication_lag(connection).await
+    }
+}
+
+#[rocket::async_trait]
+impl ReplicationLag for Postgres {
+    async fn replication_lag(connection: &mut PoolConnection<Self>) -> Result<Option<f64>> {
+        postgres_connector::get_replication_lag(connection).await
+    }
+}
+
+struct State {
+    checked_at: Option<Instant>,
+    usable: bool,
+}
+
+/// Pool of a read replica, which keeps track of whether it can be read from. The stores use it for
+/// their GET queries, except `get_issues_by_user_name`, which adds the user if it is new.
+pub struct Replica<DB: sqlx::Database> {
+    pool: Pool<DB>,
+    config: ReplicaConfig,
+    state: Mutex<State>,
+}
+
+impl<DB: ReplicationLag> Replica<DB> {
+    /// Connections are made on first use, so an unavailable replica does not stop the server.
+    pub fn connect(config: ReplicaConfig) -> Result<Self> {
+        let mut options = PoolOptions::<DB>::new().connect_timeout(Duration::from_secs(5));
+        if let Some(max_connections) = config.max_connections {
+            options = options.max_connections(max_connections);
+        }
+        Ok(Replica {
+            pool: options.connect_lazy(&config.url)?,
+            config,
+            state: Mutex::new(State {
+                checked_at: None,
+                usable: true,
+            }),
+        })
+    }
+
+    /// Connection for a query that only reads: from the replica while it is usable, and from
+    /// `primary` otherwise if `fallback` is set.
+    pub async fn acquire(&self, primary: &Pool<DB>) -> Result<PoolConnection<DB>> {
+        let (check, usable) = {
+            let mut state = self.state.lock().unwrap();
+            let check = state.checked_at.is_none_or(|checked_at| {
+                checked_at.elapsed() >= Duration::from_secs(self.config.check_interval)
+            });
+            if check {
+                // Concurrent reads carry on with the current state until this check is done.
+                state.checked_at = Some(Instant::now());
+            }
+            (check, state.usable)
+        };
+        if !check && !usable && self.config.fallback {
+            return primary.acquire().await;
+        }
+
+        let mut connection = match self.pool.acquire().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                self.mark_unusable(format!("it is unavailable: {}", e));
+                return if self.config.fallback {
+                    primary.acquire().await
+                } else {
+                    Err(e)
+                };
+            }
+        };
+        if check {
+            match DB::replication_lag(&mut connection).await {
+                Ok(lag) if self.config.accepts_lag(lag) => self.mark_usable(),
+                Ok(lag) => self.mark_unusable(format!(
+                    "it is {:.0} seconds behind",
+                    lag.unwrap_or_default()
+                )),
+                Err(e) => self.mark_unusable(format!("its lag can't be read: {}", e)),
+            }
+        }
+        if self.is_usable() || !self.config.fallback {
+            Ok(connection)
+        } else {
+            primary.acquire().await
+        }
+    }
+
+    pub fn is_usable(&self) -> bool {
+        self.state.lock().unwrap().usable
+    }
+
+    fn mark_usable(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !state.usable {
+            log::info!("Reading from the replica database again.");
+        }
+        state.usable = true;
+    }
+
+    /// Also restarts the check interval, so that the replica is not tried again until it is over.
+    fn mark_unusable(&self, reason: String) {
+        let mut state = self.state.lock().unwrap();
+        if state.usable && self.config.fallback {
+            log::warn!("Reading from the primary database: {}.", reason);
+        } else if state.usable {
+            log::warn!("The replica database is not usable: {}.", reason);
+        }
+        state.usable = false;
+        state.checked_at = Some(Instant::now());
+    }
+}
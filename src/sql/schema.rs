@@ -0,0 +1,645 @@
+//! Startup check of the live database schema against the tables and columns the queries in the
+//! connectors read and write, so a database that was migrated by hand or by another tool fails
+//! loudly at launch instead of as a "Database error occurred." on some later request.
+
+use crate::dump::{DumpRow, DumpTable};
+use crate::models;
+use crate::retention::RetentionPolicy;
+use crate::sql::availability::is_unavailable;
+use crate::sql::id_cache::IdCacheStats;
+use crate::sql::{MetadataStore, Result, SharedStore};
+use chrono::{DateTime, Utc};
+use rocket::tokio::sync::OnceCell;
+use rocket_db_pools::sqlx::{self, FromRow};
+use std::fmt;
+use std::sync::Arc;
+
+/// What a column has to hold for the models to decode it, whatever the backend calls the type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Integer,
+    Float,
+    Text,
+    Timestamp,
+}
+
+impl ColumnType {
+    /// Classifies a type name as reported by `information_schema` or SQLite, e.g. `bigint`,
+    /// `character varying` or `TIMESTAMPTZ`. `None` for anything the models cannot decode.
+    pub fn of(data_type: &str) -> Option<ColumnType> {
+        let data_type = data_type.to_ascii_lowercase();
+        let has = |part: &str| data_type.contains(part);
+        if has("date") || has("time") {
+            Some(ColumnType::Timestamp)
+        } else if has("int") {
+            Some(ColumnType::Integer)
+        } else if has("char") || has("text") || has("clob") {
+            Some(ColumnType::Text)
+        } else if has("real") || has("floa") || has("doub") || has("numeric") || has("decimal") {
+            Some(ColumnType::Float)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ColumnType::Integer => "an integer",
+            ColumnType::Float => "a floating-point",
+            ColumnType::Text => "a text",
+            ColumnType::Timestamp => "a date and time",
+        })
+    }
+}
+
+/// A column of the live database. `table_name` is without the MySQL schema and table prefix.
+#[derive(Debug, Clone, PartialEq, Eq, FromRow)]
+#[sqlx(rename_all = "PascalCase")]
+pub struct Column {
+    pub table_name: String,
+    pub column_name: String,
+    pub data_type: String,
+}
+
+/// Every table and column the connectors use, as created by the migrations.
+pub const EXPECTED: &[(&str, &[(&str, ColumnType)])] = {
+    use ColumnType::*;
+    &[
+        (
+            "Projects",
+            &[("Id", Integer), ("Name", Text), ("Stream", Text)],
+        ),
+        ("Users", &[("Id", Integer), ("Name", Text)]),
+        (
+            "Badges",
+            &[
+                ("Id", Integer),
+                ("ChangeNumber", Integer),
+                ("BuildType", Text),
+                ("Result", Text),
+                ("Url", Text),
+                ("ArchivePath", Text),
+                ("ProjectId", Integer),
+            ],
+        ),
+        (
+            "Comments",
+            &[
+                ("Id", Integer),
+                ("ChangeNumber", Integer),
+                ("UserName", Text),
+                ("Text", Text),
+                ("Project", Text),
+                ("ProjectId", Integer),
+            ],
+        ),
+        (
+            "UserVotes",
+            &[
+                ("Id", Integer),
+                ("Changelist", Integer),
+                ("UserName", Text),
+                ("Verdict", Text),
+                ("Project", Text),
+                ("ProjectId", Integer),
+                ("CreatedAt", Timestamp),
+            ],
+        ),
+        (
+            "Issues",
+            &[
+                ("Id", Integer),
+                ("Project", Text),
+                ("Summary", Text),
+                ("OwnerId", Integer),
+                ("NominatedById", Integer),
+                ("CreatedAt", Timestamp),
+                ("AcknowledgedAt", Timestamp),
+                ("FixChange", Integer),
+                ("ResolvedAt", Timestamp),
+            ],
+        ),
+        (
+            "IssueWatchers",
+            &[("IssueId", Integer), ("UserId", Integer)],
+        ),
+        (
+            "IssueBuilds",
+            &[
+                ("Id", Integer),
+                ("IssueId", Integer),
+                ("Stream", Text),
+                ("Change", Integer),
+                ("JobName", Text),
+                ("JobUrl", Text),
+                ("JobStepName", Text),
+                ("JobStepUrl", Text),
+                ("ErrorUrl", Text),
+                ("Outcome", Integer),
+            ],
+        ),
+        (
+            "IssueDiagnostics",
+            &[
+                ("Id", Integer),
+                ("IssueId", Integer),
+                ("BuildId", Integer),
+                ("Message", Text),
+                ("Url", Text),
+            ],
+        ),
+        (
+            "Errors",
+            &[
+                ("Id", Integer),
+                ("Type", Text),
+                ("Text", Text),
+                ("UserName", Text),
+                ("Project", Text),
+                ("Timestamp", Timestamp),
+                ("Version", Text),
+                ("IpAddress", Text),
+                ("ProjectId", Integer),
+                ("Signature", Text),
+            ],
+        ),
+        (
+            "Telemetry_v2",
+            &[
+                ("Id", Integer),
+                ("Action", Text),
+                ("Result", Text),
+                ("UserName", Text),
+                ("Project", Text),
+                ("Timestamp", Timestamp),
+                ("Duration", Float),
+                ("Version", Text),
+                ("IpAddress", Text),
+                ("ProjectId", Integer),
+            ],
+        ),
+    ]
+};
+
+/// One way the live schema differs from `EXPECTED`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaProblem {
+    MissingTable(String),
+    MissingColumn {
+        table: String,
+        column: String,
+    },
+    WrongType {
+        table: String,
+        column: String,
+        expected: ColumnType,
+        actual: String,
+    },
+}
+
+impl fmt::Display for SchemaProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SchemaProblem::MissingTable(table) => write!(f, "missing table `{}`", table),
+            SchemaProblem::MissingColumn { table, column } => {
+                write!(f, "missing column `{}.{}`", table, column)
+            }
+            SchemaProblem::WrongType {
+                table,
+                column,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "column `{}.{}` has type `{}`, expected {} column",
+                table, column, actual, expected
+            ),
+        }
+    }
+}
+
+/// The error the writes of a store wrapped by `checked` fail with while its schema is incompatible,
+/// wrapped in `sqlx::Error::Configuration`.
+#[derive(Debug)]
+pub struct IncompatibleSchema;
+
+impl fmt::Display for IncompatibleSchema {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("the database schema is incompatible with this server, see the startup log")
+    }
+}
+
+impl std::error::Error for IncompatibleSchema {}
+
+// Public Functions:
+
+/// Compares `columns` with `EXPECTED`. Tables and columns the server does not use are ignored.
+pub fn check(columns: &[Column]) -> Vec<SchemaProblem> {
+    let mut problems = Vec::new();
+    for (table, expected_columns) in EXPECTED {
+        let table_columns: Vec<&Column> = columns
+            .iter()
+            .filter(|column| column.table_name == *table)
+            .collect();
+        if table_columns.is_empty() {
+            problems.push(SchemaProblem::MissingTable(String::from(*table)));
+            continue;
+        }
+
+        for (name, expected) in *expected_columns {
+            // MySQL column names are case-insensitive.
+            match table_columns
+                .iter()
+                .find(|column| column.column_name.eq_ignore_ascii_case(name))
+            {
+                None => problems.push(SchemaProblem::MissingColumn {
+                    table: String::from(*table),
+                    column: String::from(*name),
+                }),
+                Some(column) if ColumnType::of(&column.data_type) != Some(*expected) => problems
+                    .push(SchemaProblem::WrongType {
+                        table: String::from(*table),
+                        column: String::from(*name),
+                        expected: *expected,
+                        actual: column.data_type.clone(),
+                    }),
+                Some(_) => {}
+            }
+        }
+    }
+    problems
+}
+
+/// Work that has to be done on the database before its schema is checked, e.g. migrations that
+/// could not run at launch.
+#[rocket::async_trait]
+pub trait Prepare: Send + Sync {
+    /// `Ok(false)` if the schema must not be used, which is logged.
+    async fn prepare(&self) -> Result<bool>;
+}
+
+/// Wraps `store` so that on the first connection `prepare` runs and the schema is checked, after
+/// which reads are served as usual, and writes only if the schema is compatible. Then
+/// `MetadataStore::backfill` runs. Tries now to log what is wrong with the schema; while the
+/// database cannot be reached it tries again on every call, which fails until then.
+pub async fn checked(store: SharedStore, prepare: Option<Arc<dyn Prepare>>) -> SharedStore {
+    let store = CheckedStore {
+        store,
+        prepare,
+        compatible: OnceCell::new(),
+    };
+    if let Err(e) = store.compatible().await {
+        log::warn!(
+            "Could not read the database schema to check it, refusing writes until it can be: {}",
+            e
+        );
+    }
+    Arc::new(store)
+}
+
+/// Wraps `store` so reads are served as usual and every write fails with `IncompatibleSchema`.
+#[cfg(test)]
+pub fn read_only(store: SharedStore) -> SharedStore {
+    Arc::new(CheckedStore {
+        store,
+        prepare: None,
+        compatible: OnceCell::new_with(Some(false)),
+    })
+}
+
+// Private Functions:
+
+fn refused<T>() -> Result<T> {
+    Err(sqlx::Error::Configuration(Box::new(IncompatibleSchema)))
+}
+
+/// Runs `prepare`, then reads the schema behind `store` and logs what is wrong with it. Backfills
+/// `store` if it is compatible.
+async fn check_store(store: &dyn MetadataStore, prepare: Option<&dyn Prepare>) -> Result<bool> {
+    if let Some(prepare) = prepare {
+        if !prepare.prepare().await? {
+            log::error!("Refusing writes until the database schema is fixed.");
+            return Ok(false);
+        }
+    }
+
+    let columns = match store.describe_schema().await? {
+        Some(columns) => columns,
+        // Without a schema to read there is nothing to tell a compatible one by.
+        None => return refused(),
+    };
+
+    let problems = check(&columns);
+    if problems.is_empty() {
+        log::info!("Database schema is compatible.");
+        store.backfill().await;
+        return Ok(true);
+    }
+
+    log::error!(
+        "Database schema is incompatible with this server, refusing writes until it is fixed:"
+    );
+    for problem in &problems {
+        log::error!("  {}", problem);
+    }
+    Ok(false)
+}
+
+struct CheckedStore {
+    store: SharedStore,
+    prepare: Option<Arc<dyn Prepare>>,
+    /// Whether the schema is compatible, once it could be read.
+    compatible: OnceCell<bool>,
+}
+
+impl CheckedStore {
+    /// Whether the schema is compatible, preparing and checking it first if that has not succeeded
+    /// yet.
+    async fn compatible(&self) -> Result<bool> {
+        self.compatible
+            .get_or_try_init(|| check_store(self.store.as_ref(), self.prepare.as_deref()))
+            .await
+            .copied()
+    }
+
+    /// Fails while the database cannot be reached to prepare and check the schema, so that reads
+    /// never see it before it is migrated. Reads go ahead on any other outcome.
+    async fn readable(&self) -> Result<()> {
+        match self.compatible().await {
+            Err(e) if is_unavailable(&e) => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails with `IncompatibleSchema` unless the schema is compatible.
+    async fn writable(&self) -> Result<()> {
+        if self.compatible().await? {
+            Ok(())
+        } else {
+            refused()
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl MetadataStore for CheckedStore {
+    async fn get_builds(
+        &self,
+        project: &str,
+        last_build_id: i64,
+    ) -> Result<Vec<models::BuildData>> {
+        self.readable().await?;
+        self.store.get_builds(project, last_build_id).await
+    }
+
+    async fn post_build(&self, build: &models::BuildData) -> Result<()> {
+        self.writable().await?;
+        self.store.post_build(build).await
+    }
+
+    async fn get_comments(
+        &self,
+        project: &str,
+        last_comment_id: i64,
+    ) -> Result<Vec<models::CommentData>> {
+        self.readable().await?;
+        self.store.get_comments(project, last_comment_id).await
+    }
+
+    async fn post_comment(&self, comment: &models::CommentData) -> Result<()> {
+        self.writable().await?;
+        self.store.post_comment(comment).await
+    }
+
+    async fn get_user_votes(
+        &self,
+        project: &str,
+        last_event_id: i64,
+    ) -> Result<Vec<models::EventData>> {
+        self.readable().await?;
+        self.store.get_user_votes(project, last_event_id).await
+    }
+
+    async fn post_event(&self, event: &models::EventData) -> Result<()> {
+        self.writable().await?;
+        self.store.post_event(event).await
+    }
+
+    async fn post_event_batch(&self, events: &[models::EventData]) -> Result<()> {
+        self.writable().await?;
+        self.store.post_event_batch(events).await
+    }
+
+    async fn get_last_ids(&self, project: Option<&str>) -> Result<models::LatestData> {
+        self.readable().await?;
+        self.store.get_last_ids(project).await
+    }
+
+    async fn add_issue(&self, issue: &models::IssueData) -> Result<i64> {
+        self.writable().await?;
+        self.store.add_issue(issue).await
+    }
+
+    async fn get_issue(&self, issue_id: i64) -> Result<Option<models::IssueData>> {
+        self.readable().await?;
+        self.store.get_issue(issue_id).await
+    }
+
+    async fn get_issues_filtered(
+        &self,
+        include_resolved: bool,
+        num_results: Option<i32>,
+    ) -> Result<Vec<models::IssueData>> {
+        self.readable().await?;
+        self.store
+            .get_issues_filtered(include_resolved, num_results)
+            .await
+    }
+
+    // Adds the user if there is none of the name yet.
+    async fn get_issues_by_user_name(&self, user_name: &str) -> Result<Vec<models::IssueData>> {
+        self.writable().await?;
+        self.store.get_issues_by_user_name(user_name).await
+    }
+
+    async fn update_issue(&self, issue_id: i64, issue: &models::IssueUpdateData) -> Result<()> {
+        self.writable().await?;
+        self.store.update_issue(issue_id, issue).await
+    }
+
+    async fn delete_issue(&self, issue_id: i64) -> Result<()> {
+        self.writable().await?;
+        self.store.delete_issue(issue_id).await
+    }
+
+    async fn add_build(&self, issue_id: i64, build: &models::IssueBuildData) -> Result<i64> {
+        self.writable().await?;
+        self.store.add_build(issue_id, build).await
+    }
+
+    async fn get_builds_by_issue(&self, issue_id: i64) -> Result<Vec<models::IssueBuildData>> {
+        self.readable().await?;
+        self.store.get_builds_by_issue(issue_id).await
+    }
+
+    async fn get_build(&self, build_id: i64) -> Result<Option<models::IssueBuildData>> {
+        self.readable().await?;
+        self.store.get_build(build_id).await
+    }
+
+    async fn update_build(&self, build_id: i64, outcome: i32) -> Result<()> {
+        self.writable().await?;
+        self.store.update_build(build_id, outcome).await
+    }
+
+    async fn add_diagnostic(
+        &self,
+        issue_id: i64,
+        diagnostic: &models::IssueDiagnosticData,
+    ) -> Result<()> {
+        self.writable().await?;
+        self.store.add_diagnostic(issue_id, diagnostic).await
+    }
+
+    async fn get_diagnostics(&self, issue_id: i64) -> Result<Vec<models::IssueDiagnosticData>> {
+        self.readable().await?;
+        self.store.get_diagnostics(issue_id).await
+    }
+
+    async fn add_watcher(&self, issue_id: i64, user_name: &str) -> Result<()> {
+        self.writable().await?;
+        self.store.add_watcher(issue_id, user_name).await
+    }
+
+    async fn get_watchers(&self, issue_id: i64) -> Result<Vec<String>> {
+        self.readable().await?;
+        self.store.get_watchers(issue_id).await
+    }
+
+    async fn remove_watcher(&self, issue_id: i64, user_name: &str) -> Result<()> {
+        self.writable().await?;
+        self.store.remove_watcher(issue_id, user_name).await
+    }
+
+    async fn post_telemetry_data(
+        &self,
+        data: &models::TelemetryTimingData,
+        version: &str,
+        ip_address: &str,
+    ) -> Result<()> {
+        self.writable().await?;
+        self.store
+            .post_telemetry_data(data, version, ip_address)
+            .await
+    }
+
+    async fn post_telemetry_batch(&self, posts: &[models::TelemetryPost]) -> Result<()> {
+        self.writable().await?;
+        self.store.post_telemetry_batch(posts).await
+    }
+
+    async fn get_timing_counts(
+        &self,
+        query: &models::TimingQuery,
+    ) -> Result<Vec<models::TimingCount>> {
+        self.readable().await?;
+        self.store.get_timing_counts(query).await
+    }
+
+    async fn get_timing_samples(
+        &self,
+        query: &models::TimingQuery,
+        every: i64,
+    ) -> Result<Vec<models::TimingSample>> {
+        self.readable().await?;
+        self.store.get_timing_samples(query, every).await
+    }
+
+    async fn post_error_data(
+        &self,
+        data: &models::TelemetryErrorData,
+        version: &str,
+        ip_address: &str,
+    ) -> Result<()> {
+        self.writable().await?;
+        self.store.post_error_data(data, version, ip_address).await
+    }
+
+    async fn get_error_data(
+        &self,
+        query: &models::ErrorQuery,
+    ) -> Result<Vec<models::TelemetryErrorData>> {
+        self.readable().await?;
+        self.store.get_error_data(query).await
+    }
+
+    async fn get_top_crashes(
+        &self,
+        query: &models::ErrorQuery,
+    ) -> Result<Vec<models::CrashBucket>> {
+        self.readable().await?;
+        self.store.get_top_crashes(query).await
+    }
+
+    async fn get_error_counts(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<models::ErrorCount>> {
+        self.readable().await?;
+        self.store.get_error_counts(from, to).await
+    }
+
+    async fn find_or_add_user_id(&self, user_name: &str) -> Result<Option<i64>> {
+        self.writable().await?;
+        self.store.find_or_add_user_id(user_name).await
+    }
+
+    fn id_cache_stats(&self) -> Option<IdCacheStats> {
+        self.store.id_cache_stats()
+    }
+
+    fn invalidate_id_cache(&self) {
+        self.store.invalidate_id_cache();
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.store.ping().await?;
+        self.writable().await
+    }
+
+    async fn describe_schema(&self) -> Result<Option<Vec<Column>>> {
+        self.store.describe_schema().await
+    }
+
+    async fn backfill(&self) {
+        self.store.backfill().await
+    }
+
+    async fn export_rows(
+        &self,
+        table: DumpTable,
+        after_id: i64,
+        limit: u32,
+    ) -> Result<Vec<DumpRow>> {
+        self.readable().await?;
+        self.store.export_rows(table, after_id, limit).await
+    }
+
+    async fn import_row(&self, row: &DumpRow) -> Result<i64> {
+        self.writable().await?;
+        self.store.import_row(row).await
+    }
+
+    async fn prune(
+        &self,
+        policy: &RetentionPolicy,
+        cutoff: DateTime<Utc>,
+        limit: u32,
+    ) -> Result<u64> {
+        self.writable().await?;
+        self.store.prune(policy, cutoff, limit).await
+    }
+}
@@ -1,185 +1,330 @@
-use crate::{models, UGSDatabase};
+use crate::crash::{self, CrashCounts, CrashReporter};
+use crate::dump::{DumpRow, DumpTable};
+use crate::models;
+use crate::retention::RetentionPolicy;
+use crate::sql::id_cache::IdCache;
+use crate::sql::latest_cache::{ChangeWindow, RecentChanges};
+use crate::sql::queries;
+use crate::sql::schema;
+use chrono::{DateTime, Utc};
 use rocket_db_pools::sqlx;
+use rocket_db_pools::sqlx::pool::PoolConnection;
 use rocket_db_pools::sqlx::Acquire;
+use rocket_db_pools::sqlx::Executor;
 use rocket_db_pools::sqlx::FromRow;
-use rocket_db_pools::Connection;
+use rocket_db_pools::sqlx::MySql;
+use rocket_db_pools::sqlx::Row;
 
 type Result<T> = std::result::Result<T, sqlx::Error>;
 
-const ISSUE_SUMMARY_MAX_LENGTH: usize = 200;
+pub const ISSUE_SUMMARY_MAX_LENGTH: usize = 200;
+
+/// Rows a multi-row telemetry INSERT writes at most, to stay well below the placeholder limits.
+pub const TELEMETRY_ROWS_PER_INSERT: usize = 1000;
+
+/// Error reports `fill_error_signatures` reads and updates at a time.
+pub const ERROR_SIGNATURE_BATCH: i64 = 1000;
+
+/// Names of the tables the queries below run against: qualified with the schema from
+/// `databases.ugsdb.schema` (default `ugs_db`) and prefixed with `databases.ugsdb.table_prefix`.
+#[derive(Debug, Clone)]
+pub struct TableNames {
+    pub schema: String,
+    pub table_prefix: String,
+    pub projects: String,
+    pub users: String,
+    pub badges: String,
+    pub comments: String,
+    pub user_votes: String,
+    pub issues: String,
+    pub issue_watchers: String,
+    pub issue_builds: String,
+    pub issue_diagnostics: String,
+    pub errors: String,
+    pub telemetry: String,
+}
+
+impl TableNames {
+    pub const DEFAULT_SCHEMA: &'static str = "ugs_db";
+
+    /// Both names are spliced into the SQL text, so anything but a plain identifier is rejected.
+    pub fn new(schema: &str, table_prefix: &str) -> std::result::Result<Self, String> {
+        let is_identifier_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+        if !schema.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            || !schema.chars().all(is_identifier_char)
+        {
+            return Err(format!("`{schema}` is not a valid schema name."));
+        }
+        if !table_prefix.chars().all(is_identifier_char) {
+            return Err(format!("`{table_prefix}` is not a valid table prefix."));
+        }
+
+        let table = |name: &str| format!("{schema}.{table_prefix}{name}");
+        Ok(TableNames {
+            schema: String::from(schema),
+            table_prefix: String::from(table_prefix),
+            projects: table("Projects"),
+            users: table("Users"),
+            badges: table("Badges"),
+            comments: table("Comments"),
+            user_votes: table("UserVotes"),
+            issues: table("Issues"),
+            issue_watchers: table("IssueWatchers"),
+            issue_builds: table("IssueBuilds"),
+            issue_diagnostics: table("IssueDiagnostics"),
+            errors: table("Errors"),
+            telemetry: table("Telemetry_v2"),
+        })
+    }
+
+    /// The tables of a database without schemas or table prefixes, quoted to keep their PascalCase
+    /// names in PostgreSQL.
+    pub fn unqualified() -> Self {
+        let table = |name: &str| format!(r#""{name}""#);
+        TableNames {
+            schema: String::new(),
+            table_prefix: String::new(),
+            projects: table("Projects"),
+            users: table("Users"),
+            badges: table("Badges"),
+            comments: table("Comments"),
+            user_votes: table("UserVotes"),
+            issues: table("Issues"),
+            issue_watchers: table("IssueWatchers"),
+            issue_builds: table("IssueBuilds"),
+            issue_diagnostics: table("IssueDiagnostics"),
+            errors: table("Errors"),
+            telemetry: table("Telemetry_v2"),
+        }
+    }
+
+    /// Points the `ugs_db.` table references of a MySQL migration at these tables. The default
+    /// names leave the SQL, and so the checksum recorded for it, unchanged.
+    pub fn apply_to_migration(&self, sql: &str) -> String {
+        sql.replace(
+            "CREATE DATABASE IF NOT EXISTS ugs_db;",
+            &format!("CREATE DATABASE IF NOT EXISTS {};", self.schema),
+        )
+        .replace(
+            "ugs_db.",
+            &format!("{}.{}", self.schema, self.table_prefix),
+        )
+    }
+}
 
 // Public Functions:
 
-pub async fn get_last_ids(
-    sql_connection: &mut Connection<UGSDatabase>,
+pub async fn get_recent_changes(
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
     project: Option<&str>,
-) -> Result<models::LatestData> {
-    let project_like_string = get_project_like_string(project);
-
-    let last_event_id = sqlx::query_scalar::<_, i64>(
-        r#"WITH user_votes AS (SELECT UserVotes.Id, UserVotes.Changelist FROM ugs_db.UserVotes 
-        INNER JOIN ugs_db.Projects ON Projects.Id = UserVotes.ProjectId 
-        WHERE Projects.Name LIKE ? GROUP BY Changelist ORDER BY Changelist DESC LIMIT 100) 
-        SELECT Id FROM user_votes ORDER BY user_votes.Changelist ASC LIMIT 1"#,
-    )
-    .bind(&project_like_string)
-    .fetch_optional(&mut *(*sql_connection))
-    .await?
-    .unwrap_or(0);
-
-    let last_comment_id =
-    sqlx::query_scalar::<_, i64>(r#"WITH comments AS (SELECT Comments.Id, Comments.ChangeNumber FROM ugs_db.Comments 
-                    INNER JOIN ugs_db.Projects ON Projects.Id = Comments.ProjectId 
-                    WHERE Projects.Name LIKE ? GROUP BY ChangeNumber ORDER BY ChangeNumber DESC LIMIT 100) 
-                    SELECT Id FROM comments ORDER BY comments.ChangeNumber ASC LIMIT 1"#)
-                        .bind(&project_like_string)
-                        .fetch_optional(&mut *(*sql_connection))
-                        .await?
-                        .unwrap_or(0);
-
-    let last_build_id =
-    sqlx::query_scalar::<_, i64>(r#"WITH badges AS (SELECT Badges.Id, Badges.ChangeNumber FROM ugs_db.Badges 
-                        INNER JOIN ugs_db.Projects ON Projects.Id = Badges.ProjectId 
-                        WHERE Projects.Name LIKE ? GROUP BY ChangeNumber ORDER BY ChangeNumber DESC LIMIT 100) 
-                        SELECT Id FROM badges ORDER BY badges.ChangeNumber ASC LIMIT 1"#)
-                                .bind(&project_like_string)
-                                .fetch_optional(&mut *(*sql_connection))
-                                .await?
-                                .unwrap_or(0);
-
-    Ok(models::LatestData {
-        last_event_id,
-        last_build_id,
-        last_comment_id,
+) -> Result<RecentChanges> {
+    let stream = project.map(get_project_stream);
+    let [events, comments, builds] = queries::recent_changes(tables, stream.as_deref());
+    Ok(RecentChanges {
+        events: get_recent_change_ids(sql_connection, events).await?,
+        comments: get_recent_change_ids(sql_connection, comments).await?,
+        builds: get_recent_change_ids(sql_connection, builds).await?,
     })
 }
 
 pub async fn get_user_votes(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
     project: &str,
     last_event_id: i64,
 ) -> Result<Vec<models::EventData>> {
-    let project_like_string = get_project_like_string(Some(project));
-    let event_data_vec: Vec<models::EventData> = sqlx::query_as::<_, models::EventData>(
-        r#"SELECT UserVotes.Id, UserVotes.Changelist AS `Change`, UserVotes.UserName, UserVotes.Verdict AS `EventType`, UserVotes.Project FROM ugs_db.UserVotes INNER JOIN ugs_db.Projects ON Projects.Id = UserVotes.ProjectId WHERE UserVotes.Id > ? AND Projects.Name LIKE ? ORDER BY UserVotes.Id"#
-        )
-        .bind(last_event_id)
-        .bind(project_like_string)
-        .fetch_all(&mut *(*sql_connection)).await?
-        .into_iter()
-        .filter(|event_data| event_data.project.is_empty() || event_data.project == project)
-        .collect();
-
-    Ok(event_data_vec)
+    queries::user_votes(tables, project, last_event_id)
+        .build()
+        .fetch_all(&mut *(*sql_connection))
+        .await?
+        .iter()
+        .map(models::EventData::from_row)
+        .collect()
 }
 
 pub async fn get_comments(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
     project: &str,
     last_comment_id: i64,
 ) -> Result<Vec<models::CommentData>> {
-    let project_like_string = get_project_like_string(Some(project));
-    let comment_data_vec: Vec<models::CommentData> = sqlx::query_as::<_, models::CommentData>(
-        r#"SELECT Comments.Id, Comments.ChangeNumber, Comments.UserName, Comments.Text, Comments.Project FROM ugs_db.Comments INNER JOIN ugs_db.Projects ON Projects.Id = Comments.ProjectId WHERE Comments.Id > ? AND Projects.Name LIKE ? ORDER BY Comments.Id"#
-        )
-        .bind(last_comment_id)
-        .bind(&project_like_string)
-        .fetch_all(&mut *(*sql_connection)).await?
-        .into_iter()
-        .filter(|comment_data| comment_data.project.is_empty() || comment_data.project == project)
-        .collect();
-
-    Ok(comment_data_vec)
+    queries::comments(tables, project, last_comment_id)
+        .build()
+        .fetch_all(&mut *(*sql_connection))
+        .await?
+        .iter()
+        .map(models::CommentData::from_row)
+        .collect()
 }
 
 pub async fn get_builds(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
     project: &str,
     last_build_id: i64,
 ) -> Result<Vec<models::BuildData>> {
-    let project_like_string = get_project_like_string(Some(project));
-    let build_data_vec = sqlx::query_as::<_, models::BuildData>(
-        r#"SELECT Badges.Id, Badges.ChangeNumber, Badges.BuildType, Badges.Result, Badges.Url, Projects.Name AS `Project`, Badges.ArchivePath FROM ugs_db.Badges INNER JOIN ugs_db.Projects ON Projects.Id = Badges.ProjectId WHERE Badges.Id > ? AND Projects.Name LIKE ? ORDER BY Badges.Id"#
-        )
-        .bind(last_build_id)
-        .bind(project_like_string)
-        .fetch_all(&mut *(*sql_connection)).await?;
-    Ok(build_data_vec
-        .into_iter()
-        .filter(|build_data| {
-            build_data.project.is_empty()
-                || build_data.project == project
-                || matches_wildcard(&build_data.project, project)
-        })
-        .collect::<Vec<models::BuildData>>())
+    queries::builds(tables, project, last_build_id)
+        .build()
+        .fetch_all(&mut *(*sql_connection))
+        .await?
+        .iter()
+        .map(models::BuildData::from_row)
+        .collect()
 }
 
 pub async fn get_error_data(
-    sql_connection: &mut Connection<UGSDatabase>,
-    records: i32,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+    query: &models::ErrorQuery,
 ) -> Result<Vec<models::TelemetryErrorData>> {
-    sqlx::query_as::<_, models::TelemetryErrorData>(
-        r#"SELECT Id, Type, Text, UserName, Project, Timestamp, Version, IpAddress FROM ugs_db.Errors ORDER BY Id DESC LIMIT ?"#
-        )
-        .bind(records)
+    queries::error_data(tables, query)
+        .build()
         .fetch_all(&mut *(*sql_connection))
-        .await
+        .await?
+        .iter()
+        .map(models::TelemetryErrorData::from_row)
+        .collect()
+}
+
+/// The crash buckets of the reports matching `query`, affecting the most users first.
+/// `query.before_id` does not apply.
+pub async fn get_top_crashes(
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+    query: &models::ErrorQuery,
+) -> Result<Vec<models::CrashBucket>> {
+    let counts = queries::crash_counts(tables, query)
+        .build()
+        .fetch_all(&mut *(*sql_connection))
+        .await?
+        .iter()
+        .map(CrashCounts::from_row)
+        .collect::<Result<Vec<_>>>()?;
+    if counts.is_empty() {
+        return Ok(Vec::new());
+    }
+    let reporters = queries::crash_reporters(tables, query, &counts)
+        .build()
+        .fetch_all(&mut *(*sql_connection))
+        .await?
+        .iter()
+        .map(CrashReporter::from_row)
+        .collect::<Result<Vec<_>>>()?;
+    let texts = queries::crash_texts(tables, &counts)
+        .build()
+        .fetch_all(&mut *(*sql_connection))
+        .await?
+        .iter()
+        .map(|row| Ok((row.try_get("Id")?, row.try_get("Text")?)))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(crash::buckets(counts, reporters, texts))
+}
+
+pub async fn get_error_counts(
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<models::ErrorCount>> {
+    queries::error_counts(tables, from, to)
+        .build()
+        .fetch_all(&mut *(*sql_connection))
+        .await?
+        .iter()
+        .map(models::ErrorCount::from_row)
+        .collect()
 }
 
 pub async fn post_build(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+    cache: &IdCache,
     build: &models::BuildData,
-) -> Result<()> {
-    let project_id = try_insert_and_get_project(sql_connection, &build.project).await?;
-    sqlx::query(&r#"INSERT INTO ugs_db.Badges (ChangeNumber, BuildType, Result, URL, ArchivePath, ProjectId) VALUES (?, ?, ?, ?, ?, ?)"#)
+) -> Result<i64> {
+    let project_id = try_insert_and_get_project(sql_connection, tables, cache, &build.project).await?;
+    Ok(sqlx::query(&format!(r#"INSERT INTO {badges} (ChangeNumber, BuildType, Result, URL, ArchivePath, ProjectId) VALUES (?, ?, ?, ?, ?, ?)"#, badges = tables.badges))
         .bind(build.change_number)
         .bind(&build.build_type)
         .bind(build.result.to_string())
         .bind(&build.url)
         .bind(&build.archive_path)
         .bind(project_id)
-        .execute(&mut *(*sql_connection)).await?;
-    Ok(())
+        .execute(&mut *(*sql_connection)).await?
+        .last_insert_id() as i64)
 }
 
 pub async fn post_event(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+    cache: &IdCache,
     event: &models::EventData,
-) -> Result<()> {
-    let project_id = try_insert_and_get_project(sql_connection, &event.project).await?;
-    sqlx::query(&r#"INSERT INTO ugs_db.UserVotes (Changelist, UserName, Verdict, Project, ProjectId) VALUES (?, ?, ?, ?, ?)"#)
+) -> Result<i64> {
+    let project_id = try_insert_and_get_project(sql_connection, tables, cache, &event.project).await?;
+    Ok(sqlx::query(&format!(r#"INSERT INTO {user_votes} (Changelist, UserName, Verdict, Project, ProjectId, CreatedAt) VALUES (?, ?, ?, ?, ?, UTC_TIMESTAMP())"#, user_votes = tables.user_votes))
         .bind(event.change)
         .bind(&event.user_name)
         .bind(event.event_type.to_string())
         .bind(&event.project)
         .bind(project_id)
-        .execute(&mut *(*sql_connection)).await?;
-    Ok(())
+        .execute(&mut *(*sql_connection)).await?
+        .last_insert_id() as i64)
+}
+
+pub async fn post_event_batch(
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+    cache: &IdCache,
+    events: &[models::EventData],
+) -> Result<Vec<i64>> {
+    let statement = format!(r#"INSERT INTO {user_votes} (Changelist, UserName, Verdict, Project, ProjectId, CreatedAt) VALUES (?, ?, ?, ?, ?, UTC_TIMESTAMP())"#, user_votes = tables.user_votes);
+    let projects: Vec<&str> = events.iter().map(|event| event.project.as_str()).collect();
+    let mut transaction = sql_connection.begin().await?;
+    let (project_ids, uncached) = get_batch_project_ids(&mut transaction, tables, cache, &projects).await?;
+    let mut ids = Vec::with_capacity(events.len());
+    for (event, project_id) in events.iter().zip(project_ids) {
+        ids.push(sqlx::query(&statement)
+            .bind(event.change)
+            .bind(&event.user_name)
+            .bind(event.event_type.to_string())
+            .bind(&event.project)
+            .bind(project_id)
+            .execute(&mut transaction).await?
+            .last_insert_id() as i64);
+    }
+    transaction.commit().await?;
+    cache_project_ids(cache, uncached);
+    Ok(ids)
 }
 
 pub async fn post_comment(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+    cache: &IdCache,
     comment: &models::CommentData,
-) -> Result<()> {
-    let project_id = try_insert_and_get_project(sql_connection, &comment.project).await?;
-    sqlx::query(&r#"INSERT INTO ugs_db.Comments (ChangeNumber, UserName, Text, Project, ProjectId) VALUES (?, ?, ?, ?, ?)"#)
+) -> Result<i64> {
+    let project_id = try_insert_and_get_project(sql_connection, tables, cache, &comment.project).await?;
+    Ok(sqlx::query(&format!(r#"INSERT INTO {comments} (ChangeNumber, UserName, Text, Project, ProjectId) VALUES (?, ?, ?, ?, ?)"#, comments = tables.comments))
         .bind(comment.change_number)
         .bind(&comment.user_name)
         .bind(&comment.text)
         .bind(&comment.project)
         .bind(project_id)
-        .execute(&mut *(*sql_connection)).await?;
-    Ok(())
+        .execute(&mut *(*sql_connection)).await?
+        .last_insert_id() as i64)
 }
 
 pub async fn post_telemetry_data(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+    cache: &IdCache,
     data: &models::TelemetryTimingData,
     version: &str,
     ip_address: &str,
 ) -> Result<()> {
-    let project_id = try_insert_and_get_project(sql_connection, &data.project).await?;
-    sqlx::query(&r#"INSERT INTO ugs_db.Telemetry_v2 (Action, Result, UserName, Project, Timestamp, Duration, Version, IpAddress, ProjectId) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#)
+    let project_id = try_insert_and_get_project(sql_connection, tables, cache, &data.project).await?;
+    sqlx::query(&format!(r#"INSERT INTO {telemetry} (Action, Result, UserName, Project, Timestamp, Duration, Version, IpAddress, ProjectId) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#, telemetry = tables.telemetry))
         .bind(&data.action)
         .bind(&data.result)
         .bind(&data.user_name)
@@ -193,29 +338,94 @@ pub async fn post_telemetry_data(
     Ok(())
 }
 
+pub async fn post_telemetry_batch(
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+    cache: &IdCache,
+    batch: &[models::TelemetryPost],
+) -> Result<()> {
+    let projects: Vec<&str> = batch.iter().map(|post| post.data.project.as_str()).collect();
+    let mut transaction = sql_connection.begin().await?;
+    let (project_ids, uncached) = get_batch_project_ids(&mut transaction, tables, cache, &projects).await?;
+    for (posts, project_ids) in batch.chunks(TELEMETRY_ROWS_PER_INSERT).zip(project_ids.chunks(TELEMETRY_ROWS_PER_INSERT)) {
+        let mut query_builder: sqlx::QueryBuilder<sqlx::MySql> = sqlx::QueryBuilder::new(format!("INSERT INTO {} (Action, Result, UserName, Project, Timestamp, Duration, Version, IpAddress, ProjectId) ", tables.telemetry));
+        query_builder.push_values(posts.iter().zip(project_ids), |mut row, (post, project_id)| {
+            row.push_bind(&post.data.action)
+                .push_bind(&post.data.result)
+                .push_bind(&post.data.user_name)
+                .push_bind(&post.data.project)
+                .push_bind(post.data.timestamp)
+                .push_bind(post.data.duration)
+                .push_bind(&post.version)
+                .push_bind(&post.ip_address)
+                .push_bind(*project_id);
+        });
+        query_builder.build().execute(&mut transaction).await?;
+    }
+    transaction.commit().await?;
+    cache_project_ids(cache, uncached);
+    Ok(())
+}
+
+pub async fn get_timing_counts(
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+    query: &models::TimingQuery,
+) -> Result<Vec<models::TimingCount>> {
+    queries::timing_counts(tables, query)
+        .build()
+        .fetch_all(&mut *(*sql_connection))
+        .await?
+        .iter()
+        .map(models::TimingCount::from_row)
+        .collect()
+}
+
+pub async fn get_timing_samples(
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+    query: &models::TimingQuery,
+    every: i64,
+) -> Result<Vec<models::TimingSample>> {
+    queries::timing_samples(tables, query, every)
+        .build()
+        .fetch_all(&mut *(*sql_connection))
+        .await?
+        .iter()
+        .map(models::TimingSample::from_row)
+        .collect()
+}
+
 pub async fn post_error_data(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+    cache: &IdCache,
     data: &models::TelemetryErrorData,
     version: &str,
     ip_address: &str,
 ) -> Result<()> {
-    sqlx::query(&r#"INSERT INTO ugs_db.Telemetry_v2 (Action, Result, UserName, Project, Timestamp, Duration, Version, IpAddress, ProjectId) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#)
+    let project_id = match &data.project {
+        Some(project_name) => Some(try_insert_and_get_project(sql_connection, tables, cache, project_name).await?),
+        None => None,
+    };
+    sqlx::query(&format!(r#"INSERT INTO {errors} (Type, Text, UserName, Project, Timestamp, Version, IpAddress, ProjectId, Signature) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#, errors = tables.errors))
         .bind(&data.error_type)
         .bind(&data.text)
         .bind(&data.user_name)
-        //
         .bind(&data.project)
-        .bind(match &data.project { Some(project_name) => Some(try_insert_and_get_project(sql_connection, &project_name).await?), None => None })
-        //
         .bind(data.timestamp)
         .bind(version)
         .bind(ip_address)
+        .bind(project_id)
+        .bind(crash::signature(&data.text))
         .execute(&mut *(*sql_connection)).await?;
     Ok(())
 }
 
 pub async fn find_or_add_user_id(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+    cache: &IdCache,
     name: &str,
 ) -> Result<Option<i64>> {
     if name.is_empty() {
@@ -223,15 +433,20 @@ pub async fn find_or_add_user_id(
     }
 
     let normalized_name = normalize_user_name(name);
+    if let Some(id) = cache.user_id(&normalized_name) {
+        return Ok(Some(id));
+    }
+    let select_query = format!(r#"SELECT Id FROM {users} WHERE Name = ?"#, users = tables.users);
 
     // Try to get the id if it already exists.
     {
-        let id_opt = sqlx::query_scalar::<_, i64>(r#"SELECT Id FROM ugs_db.Users WHERE Name = ?"#)
+        let id_opt = sqlx::query_scalar::<_, i64>(&select_query)
             .bind(&normalized_name)
             .fetch_optional(&mut *(*sql_connection))
             .await?;
 
-        if id_opt.is_some() {
+        if let Some(id) = id_opt {
+            cache.insert_user_id(&normalized_name, id);
             return Ok(id_opt);
         }
     }
@@ -239,44 +454,47 @@ pub async fn find_or_add_user_id(
     // Otherwise, start a transaction and try to create the row and get the id.
     let mut transaction = sql_connection.begin().await?;
 
-    sqlx::query(r#"INSERT IGNORE INTO ugs_db.Users (Name) VALUES (?)"#)
+    sqlx::query(&format!(r#"INSERT IGNORE INTO {users} (Name) VALUES (?)"#, users = tables.users))
         .bind(&normalized_name)
         .execute(&mut transaction)
         .await?;
 
-    let id = sqlx::query_scalar::<_, i64>(r#"SELECT Id FROM ugs_db.Users WHERE Name = ?"#)
+    let id = sqlx::query_scalar::<_, i64>(&select_query)
         .bind(&normalized_name)
         .fetch_one(&mut transaction)
         .await?;
 
     transaction.commit().await?;
 
+    cache.insert_user_id(&normalized_name, id);
     Ok(Some(id))
 }
 
 pub async fn add_issue(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+    cache: &IdCache,
     issue: &models::IssueData,
 ) -> Result<i64> {
-    let id = sqlx::query_scalar::<_, i64>(r#"INSERT INTO ugs_db.Issues (Project, Summary, OwnerId, CreatedAt, FixChange) VALUES (?, ?, ?, UTC_TIMESTAMP(), 0)"#)
+    let owner_id = find_or_add_user_id(sql_connection, tables, cache, &issue.owner).await?;
+    let id = sqlx::query(&format!(r#"INSERT INTO {issues} (Project, Summary, OwnerId, CreatedAt, FixChange) VALUES (?, ?, ?, UTC_TIMESTAMP(), 0)"#, issues = tables.issues))
         .bind(&issue.project)
         .bind(sanitize_text(&issue.summary, ISSUE_SUMMARY_MAX_LENGTH))
-        //
-        .bind(find_or_add_user_id(sql_connection, &issue.owner).await?)
-        //
-        .fetch_one(&mut *(*sql_connection)).await?;
-
-    // TODO: Figure out how to get the id properly?
+        .bind(owner_id)
+        .execute(&mut *(*sql_connection)).await?
+        .last_insert_id();
 
-    Ok(id)
+    Ok(id as i64)
 }
 
 pub async fn get_issue(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+    cache: &IdCache,
     issue_id: i64,
 ) -> Result<Option<models::IssueData>> {
     let issue_data_vec =
-        get_issues_internal(sql_connection, Some(issue_id), None, true, None).await?;
+        get_issues_internal(sql_connection, tables, cache, Some(issue_id), None, true, None).await?;
     if issue_data_vec.is_empty() {
         Ok(None)
     } else {
@@ -285,117 +503,56 @@ pub async fn get_issue(
 }
 
 pub async fn get_issues_filtered(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+    cache: &IdCache,
     include_resolved: bool,
     num_results: Option<i32>,
 ) -> Result<Vec<models::IssueData>> {
-    get_issues_internal(sql_connection, None, None, include_resolved, num_results).await
+    get_issues_internal(sql_connection, tables, cache, None, None, include_resolved, num_results).await
 }
 
 pub async fn get_issues_by_user_name(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+    cache: &IdCache,
     user_name: &str,
 ) -> Result<Vec<models::IssueData>> {
-    get_issues_internal(sql_connection, None, Some(user_name), false, None).await
+    get_issues_internal(sql_connection, tables, cache, None, Some(user_name), false, None).await
 }
 
 async fn get_issues_internal(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+    cache: &IdCache,
     issue_id: Option<i64>,
     user_name: Option<&str>,
     include_resolved: bool,
     num_results: Option<i32>,
 ) -> Result<Vec<models::IssueData>> {
     let user_id = match user_name {
-        Some(s) => find_or_add_user_id(sql_connection, s).await?,
+        Some(s) => find_or_add_user_id(sql_connection, tables, cache, s).await?,
         None => None,
     };
-    let mut query_builder: sqlx::QueryBuilder<sqlx::MySql> = sqlx::QueryBuilder::new("SELECT");
-    query_builder.push(" Issues.Id, Issues.CreatedAt, UTC_TIMESTAMP(), Issues.Project, Issues.Summary, OwnerUsers.Name, NominatedByUsers.Name, Issues.AcknowledgedAt, Issues.FixChange, Issues.ResolvedAt");
-    if user_name.is_some() {
-        query_builder.push(", IssueWatchers.UserId");
-    };
-    query_builder.push(" FROM ugs_db.Issues");
-    query_builder.push(" LEFT JOIN ugs_db.Users AS OwnerUsers ON OwnerUsers.Id = Issues.OwnerId");
-    query_builder.push(
-        " LEFT JOIN ugs_db.Users AS NominatedByUsers ON NominatedByUsers.Id = Issues.NominatedById",
-    );
-    if user_name.is_some() {
-        query_builder.push(" LEFT JOIN ugs_db.IssueWatchers ON IssueWatchers.IssueId = Issues.Id AND IssueWatchers.UserId = ").push_bind(user_id.unwrap());
-    }
-    if issue_id.is_some() {
-        query_builder
-            .push(" WHERE Issues.Id = ")
-            .push_bind(issue_id.unwrap());
-    } else if !include_resolved {
-        query_builder.push(" WHERE Issues.ResolvedAt IS NULL");
-    }
-    if num_results.is_some() {
-        query_builder
-            .push(" ORDER BY Issues.Id DESC LIMIT ")
-            .push_bind(num_results.unwrap());
-    }
-
-    // TODO: The Notify field of IssueData requires some special handling, it looks like.
-
-    query_builder
+    queries::issues(tables, issue_id, user_id, include_resolved, num_results)
         .build()
-        .map(|row: sqlx::mysql::MySqlRow| models::IssueData::from_row(&row).unwrap())
         .fetch_all(&mut *(*sql_connection))
-        .await
+        .await?
+        .iter()
+        .map(models::IssueData::from_row)
+        .collect()
 }
 
 pub async fn update_issue(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+    cache: &IdCache,
     issue_id: i64,
     issue: &models::IssueUpdateData,
 ) -> Result<()> {
-    let mut query_builder: sqlx::QueryBuilder<sqlx::MySql> =
-        sqlx::QueryBuilder::new("UPDATE ugs_db.Issues SET ");
-    {
-        let mut separated_builder = query_builder.separated(",");
-        if !issue.summary.is_empty() {
-            separated_builder
-                .push("Summary=")
-                .push_bind(sanitize_text(&issue.summary, ISSUE_SUMMARY_MAX_LENGTH));
-        }
-        if !issue.owner.is_empty() {
-            separated_builder
-                .push("OwnerId=")
-                .push_bind(find_or_add_user_id(sql_connection, &issue.owner).await?);
-        }
-        if !issue.nominated_by.is_empty() {
-            separated_builder
-                .push("NominatedById=")
-                .push_bind(find_or_add_user_id(sql_connection, &issue.nominated_by).await?);
-        }
-        if issue.acknowledged.is_some() {
-            separated_builder
-                .push("AcknowledgedAt=")
-                .push(if issue.acknowledged.unwrap() {
-                    "UTC_TIMESTAMP()"
-                } else {
-                    "NULL"
-                });
-        }
-        if issue.fix_change.is_some() {
-            separated_builder
-                .push("FixChange=")
-                .push_bind(issue.fix_change.unwrap());
-        }
-        if issue.resolved.is_some() {
-            separated_builder
-                .push("ResolvedAt=")
-                .push(if issue.resolved.unwrap() {
-                    "UTC_TIMESTAMP()"
-                } else {
-                    "NULL"
-                });
-        }
-    }
-    query_builder.push(" WHERE id = ").push_bind(issue_id);
-
-    query_builder
+    let owner_id = find_or_add_user_id(sql_connection, tables, cache, &issue.owner).await?;
+    let nominated_by_id = find_or_add_user_id(sql_connection, tables, cache, &issue.nominated_by).await?;
+    queries::update_issue(tables, issue_id, issue, owner_id, nominated_by_id)
         .build()
         .execute(&mut *(*sql_connection))
         .await?;
@@ -416,22 +573,23 @@ pub fn sanitize_text(text: &str, length: usize) -> String {
 }
 
 pub async fn delete_issue(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
     issue_id: i64,
 ) -> Result<()> {
     let mut transaction = sql_connection.begin().await?;
 
-    sqlx::query(r#"DELETE FROM ugs_db.IssueWatchers WHERE IssueId = ?"#)
+    sqlx::query(&format!(r#"DELETE FROM {issue_watchers} WHERE IssueId = ?"#, issue_watchers = tables.issue_watchers))
         .bind(issue_id)
         .execute(&mut transaction)
         .await?;
 
-    sqlx::query(r#"DELETE FROM ugs_db.IssueBuilds WHERE IssueId = ?"#)
+    sqlx::query(&format!(r#"DELETE FROM {issue_builds} WHERE IssueId = ?"#, issue_builds = tables.issue_builds))
         .bind(issue_id)
         .execute(&mut transaction)
         .await?;
 
-    sqlx::query(r#"DELETE FROM ugs_db.Issues WHERE IssueId = ?"#)
+    sqlx::query(&format!(r#"DELETE FROM {issues} WHERE Id = ?"#, issues = tables.issues))
         .bind(issue_id)
         .execute(&mut transaction)
         .await?;
@@ -442,11 +600,12 @@ pub async fn delete_issue(
 }
 
 pub async fn add_diagnostic(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
     issue_id: i64,
     diagnostic: &models::IssueDiagnosticData,
 ) -> Result<()> {
-    sqlx::query(r#"INSERT INTO ugs_db.IssueDiagnostics (IssueId, BuildId, Message, Url) VALUES (?, ?, ?, ?)"#)
+    sqlx::query(&format!(r#"INSERT INTO {issue_diagnostics} (IssueId, BuildId, Message, Url) VALUES (?, ?, ?, ?)"#, issue_diagnostics = tables.issue_diagnostics))
         .bind(issue_id)
         .bind(diagnostic.build_id)
         .bind(sanitize_text(&diagnostic.message, 1000))
@@ -457,64 +616,76 @@ pub async fn add_diagnostic(
 }
 
 pub async fn get_diagnostics(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
     issue_id: i64,
 ) -> Result<Vec<models::IssueDiagnosticData>> {
-    sqlx::query_as::<_, models::IssueDiagnosticData>(
-        r#"SELECT BuildId, Message, Url FROM ugs_db.IssueDiagnostics 
-        WHERE IssueDiagnostics.IssueId = ?"#,
-    )
+    sqlx::query_as::<_, models::IssueDiagnosticData>(&format!(
+        r#"SELECT BuildId, Message, Url FROM {issue_diagnostics}
+        WHERE IssueId = ?"#,
+        issue_diagnostics = tables.issue_diagnostics,
+    ))
     .bind(issue_id)
     .fetch_all(&mut *(*sql_connection))
     .await
 }
 
 pub async fn add_watcher(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+    cache: &IdCache,
     issue_id: i64,
     user_name: &str,
 ) -> Result<()> {
-    sqlx::query(r#"INSERT IGNORE INTO ugs_db.IssueWatchers (IssueId, UserId) VALUES (?, ?)"#)
+    let user_id = find_or_add_user_id(sql_connection, tables, cache, user_name).await?;
+    sqlx::query(&format!(r#"INSERT IGNORE INTO {issue_watchers} (IssueId, UserId) VALUES (?, ?)"#, issue_watchers = tables.issue_watchers))
         .bind(issue_id)
-        .bind(find_or_add_user_id(sql_connection, user_name).await?)
+        .bind(user_id)
         .execute(&mut *(*sql_connection))
         .await?;
     Ok(())
 }
 
 pub async fn get_watchers(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
     issue_id: i64,
 ) -> Result<Vec<String>> {
-    sqlx::query_scalar(
-        r#"SELECT Users.Name FROM ugs_db.IssueWatchers 
-        LEFT JOIN ugs_db.Users ON IssueWatchers.UserId = Users.Id 
+    sqlx::query_scalar(&format!(
+        r#"SELECT Users.Name FROM {issue_watchers} AS IssueWatchers
+        INNER JOIN {users} AS Users ON IssueWatchers.UserId = Users.Id
         WHERE IssueWatchers.IssueId = ?"#,
-    )
+        issue_watchers = tables.issue_watchers,
+        users = tables.users,
+    ))
     .bind(issue_id)
     .fetch_all(&mut *(*sql_connection))
     .await
 }
 
 pub async fn remove_watcher(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+    cache: &IdCache,
     issue_id: i64,
     user_name: &str,
 ) -> Result<()> {
-    sqlx::query(r#"DELETE FROM ugs_db.IssueWatchers WHERE IssueId = ? AND UserId = ?"#)
+    let user_id = find_or_add_user_id(sql_connection, tables, cache, user_name).await?;
+    sqlx::query(&format!(r#"DELETE FROM {issue_watchers} WHERE IssueId = ? AND UserId = ?"#, issue_watchers = tables.issue_watchers))
         .bind(issue_id)
-        .bind(find_or_add_user_id(sql_connection, user_name).await?)
+        .bind(user_id)
         .execute(&mut *(*sql_connection))
         .await?;
     Ok(())
 }
 
 pub async fn add_build(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
     issue_id: i64,
     build: &models::IssueBuildData,
 ) -> Result<i64> {
-    sqlx::query_scalar::<_, i64>(r#"INSERT INTO ugs_db.IssueBuilds (IssueId, Stream, `Change`, JobName, JobUrl, JobStepName, JobStepUrl, ErrorUrl, Outcome) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#)
+    let id = sqlx::query(&format!(r#"INSERT INTO {issue_builds} (IssueId, Stream, `Change`, JobName, JobUrl, JobStepName, JobStepUrl, ErrorUrl, Outcome) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#, issue_builds = tables.issue_builds))
         .bind(issue_id)
         .bind(&build.stream)
         .bind(build.change)
@@ -524,36 +695,42 @@ pub async fn add_build(
         .bind(&build.job_step_url)
         .bind(&build.error_url)
         .bind(build.outcome)
-        .fetch_one(&mut *(*sql_connection))
-        .await
+        .execute(&mut *(*sql_connection))
+        .await?
+        .last_insert_id();
+
+    Ok(id as i64)
 }
 
 pub async fn get_builds_by_issue(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
     issue_id: i64,
 ) -> Result<Vec<models::IssueBuildData>> {
-    sqlx::query_as::<_, models::IssueBuildData>(r#"SELECT IssueBuilds.Id, IssueBuilds.Stream, IssueBuilds.Change, IssueBuilds.JobName, IssueBuilds.JobUrl, IssueBuilds.JobStepName, IssueBuilds.JobStepUrl, IssueBuilds.ErrorUrl, IssueBuilds.Outcome FROM ugs_db.IssueBuilds WHERE IssueBuilds.IssueId = ?"#)
+    sqlx::query_as::<_, models::IssueBuildData>(&format!(r#"SELECT Id, Stream, `Change`, JobName, JobUrl, JobStepName, JobStepUrl, ErrorUrl, Outcome FROM {issue_builds} WHERE IssueId = ?"#, issue_builds = tables.issue_builds))
         .bind(issue_id)
         .fetch_all(&mut *(*sql_connection))
         .await
 }
 
 pub async fn get_build(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
     build_id: i64,
 ) -> Result<Option<models::IssueBuildData>> {
-    sqlx::query_as::<_, models::IssueBuildData>(r#"SELECT IssueBuilds.Id, IssueBuilds.Stream, IssueBuilds.Change, IssueBuilds.JobName, IssueBuilds.JobUrl, IssueBuilds.JobStepName, IssueBuilds.JobStepUrl, IssueBuilds.ErrorUrl, IssueBuilds.Outcome FROM ugs_db.IssueBuilds WHERE IssueBuilds.Id = ?"#)
+    sqlx::query_as::<_, models::IssueBuildData>(&format!(r#"SELECT Id, Stream, `Change`, JobName, JobUrl, JobStepName, JobStepUrl, ErrorUrl, Outcome FROM {issue_builds} WHERE Id = ?"#, issue_builds = tables.issue_builds))
         .bind(build_id)
         .fetch_optional(&mut *(*sql_connection))
         .await
 }
 
 pub async fn update_build(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
     build_id: i64,
     outcome: i32,
 ) -> Result<()> {
-    sqlx::query(r#"UPDATE ugs_db.IssueBuilds SET (Outcome) = (?) WHERE Id = ?"#)
+    sqlx::query(&format!(r#"UPDATE {issue_builds} SET Outcome = ? WHERE Id = ?"#, issue_builds = tables.issue_builds))
         .bind(outcome)
         .bind(build_id)
         .execute(&mut *(*sql_connection))
@@ -561,41 +738,318 @@ pub async fn update_build(
     Ok(())
 }
 
-// Private Functions:
+/// Runs a query that reads no table, to check that the database answers.
+pub async fn ping(sql_connection: &mut PoolConnection<MySql>) -> Result<()> {
+    sqlx::query(r#"SELECT 1"#)
+        .execute(&mut *(*sql_connection))
+        .await
+        .map(|_| ())
+}
+
+/// Seconds the server is behind its source, `None` if it is not a replica and infinite if
+/// replication is stopped.
+pub async fn get_replication_lag(sql_connection: &mut PoolConnection<MySql>) -> Result<Option<f64>> {
+    // Run unprepared, so that the columns come back as text whatever their types.
+    let status = (&mut *(*sql_connection)).fetch_optional("SHOW SLAVE STATUS").await?;
+    Ok(match status {
+        Some(status) => Some(
+            status
+                .try_get_unchecked::<Option<i64>, _>("Seconds_Behind_Master")?
+                .map_or(f64::INFINITY, |seconds| seconds as f64),
+        ),
+        None => None,
+    })
+}
+
+/// Every column of the tables in the configured schema, with the table prefix stripped.
+pub async fn describe_schema(
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+) -> Result<Vec<schema::Column>> {
+    let columns = sqlx::query_as::<_, schema::Column>(r#"SELECT CAST(TABLE_NAME AS CHAR) AS TableName, CAST(COLUMN_NAME AS CHAR) AS ColumnName, CAST(DATA_TYPE AS CHAR) AS DataType FROM information_schema.COLUMNS WHERE TABLE_SCHEMA = ?"#)
+        .bind(&tables.schema)
+        .fetch_all(&mut *(*sql_connection))
+        .await?;
+    Ok(columns
+        .into_iter()
+        .filter_map(|column| {
+            let table_name = column.table_name.strip_prefix(&tables.table_prefix)?.to_string();
+            Some(schema::Column { table_name, ..column })
+        })
+        .collect())
+}
+
+/// Fills in `Projects.Stream` for projects added before it existed. Returns how many were updated.
+pub async fn fill_project_streams(
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+) -> Result<u64> {
+    let projects = sqlx::query_as::<_, (i64, String)>(&format!(r#"SELECT Id, Name FROM {projects} WHERE Stream IS NULL"#, projects = tables.projects))
+        .fetch_all(&mut *(*sql_connection))
+        .await?;
+    for (id, name) in &projects {
+        sqlx::query(&format!(r#"UPDATE {projects} SET Stream = ? WHERE Id = ?"#, projects = tables.projects))
+            .bind(get_project_stream(name))
+            .bind(id)
+            .execute(&mut *(*sql_connection))
+            .await?;
+    }
+    Ok(projects.len() as u64)
+}
+
+/// Fills in `Errors.Signature` for reports stored before it existed, `ERROR_SIGNATURE_BATCH` at a
+/// time. Returns how many were updated.
+pub async fn fill_error_signatures(
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+) -> Result<u64> {
+    let mut filled = 0;
+    loop {
+        let errors = sqlx::query_as::<_, (i64, String)>(&format!(r#"SELECT Id, Text FROM {errors} WHERE Signature IS NULL ORDER BY Id LIMIT ?"#, errors = tables.errors))
+            .bind(ERROR_SIGNATURE_BATCH)
+            .fetch_all(&mut *(*sql_connection))
+            .await?;
+        if errors.is_empty() {
+            return Ok(filled);
+        }
+        let mut transaction = sql_connection.begin().await?;
+        for (id, text) in &errors {
+            sqlx::query(&format!(r#"UPDATE {errors} SET Signature = ? WHERE Id = ?"#, errors = tables.errors))
+                .bind(crash::signature(text))
+                .bind(id)
+                .execute(&mut transaction)
+                .await?;
+        }
+        transaction.commit().await?;
+        filled += errors.len() as u64;
+    }
+}
+
+pub async fn export_rows(
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+    table: DumpTable,
+    after_id: i64,
+    limit: u32,
+) -> Result<Vec<DumpRow>> {
+    queries::export_rows(tables, table, after_id, limit)
+        .build()
+        .fetch_all(&mut *(*sql_connection))
+        .await?
+        .iter()
+        .map(|row| queries::dump_row(table, row))
+        .collect()
+}
 
-fn get_project_like_string(project: Option<&str>) -> String {
-    format!(
-        "%{}%",
-        match project {
-            None => String::new(),
-            Some(project_string) => get_project_stream(project_string),
+pub async fn import_row(
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+    cache: &IdCache,
+    row: &DumpRow,
+) -> Result<i64> {
+    let result = match row {
+        DumpRow::Project { name, .. } => {
+            return try_insert_and_get_project(sql_connection, tables, cache, name).await
+        }
+        DumpRow::User { name, .. } => {
+            return Ok(find_or_add_user_id(sql_connection, tables, cache, name)
+                .await?
+                .unwrap_or(0))
+        }
+        DumpRow::Badge(build) => {
+            let project_id = try_insert_and_get_project(sql_connection, tables, cache, &build.project).await?;
+            sqlx::query(&format!(r#"INSERT INTO {badges} (ChangeNumber, BuildType, Result, URL, ArchivePath, ProjectId) VALUES (?, ?, ?, ?, ?, ?)"#, badges = tables.badges))
+                .bind(build.change_number)
+                .bind(&build.build_type)
+                .bind(build.result.to_string())
+                .bind(&build.url)
+                .bind(&build.archive_path)
+                .bind(project_id)
+                .execute(&mut *(*sql_connection)).await?
+        }
+        DumpRow::Comment(comment) => {
+            let project_id = try_insert_and_get_project(sql_connection, tables, cache, &comment.project).await?;
+            sqlx::query(&format!(r#"INSERT INTO {comments} (ChangeNumber, UserName, Text, Project, ProjectId) VALUES (?, ?, ?, ?, ?)"#, comments = tables.comments))
+                .bind(comment.change_number)
+                .bind(&comment.user_name)
+                .bind(&comment.text)
+                .bind(&comment.project)
+                .bind(project_id)
+                .execute(&mut *(*sql_connection)).await?
         }
-    )
+        DumpRow::Event { event, created_at } => {
+            let project_id = try_insert_and_get_project(sql_connection, tables, cache, &event.project).await?;
+            sqlx::query(&format!(r#"INSERT INTO {user_votes} (Changelist, UserName, Verdict, Project, ProjectId, CreatedAt) VALUES (?, ?, ?, ?, ?, ?)"#, user_votes = tables.user_votes))
+                .bind(event.change)
+                .bind(&event.user_name)
+                .bind(event.event_type.to_string())
+                .bind(&event.project)
+                .bind(project_id)
+                .bind(created_at)
+                .execute(&mut *(*sql_connection)).await?
+        }
+        DumpRow::Issue(issue) => {
+            let owner_id = find_or_add_user_id(sql_connection, tables, cache, &issue.owner).await?;
+            let nominated_by_id = find_or_add_user_id(sql_connection, tables, cache, &issue.nominated_by).await?;
+            sqlx::query(&format!(r#"INSERT INTO {issues} (Project, Summary, OwnerId, NominatedById, CreatedAt, AcknowledgedAt, FixChange, ResolvedAt) VALUES (?, ?, ?, ?, ?, ?, ?, ?)"#, issues = tables.issues))
+                .bind(&issue.project)
+                .bind(&issue.summary)
+                .bind(owner_id)
+                .bind(nominated_by_id)
+                .bind(issue.created_at)
+                .bind(issue.acknowledged_at)
+                .bind(issue.fix_change)
+                .bind(issue.resolved_at)
+                .execute(&mut *(*sql_connection)).await?
+        }
+        DumpRow::Error(error) => {
+            let project_id = match &error.project {
+                Some(project) => Some(try_insert_and_get_project(sql_connection, tables, cache, project).await?),
+                None => None,
+            };
+            sqlx::query(&format!(r#"INSERT INTO {errors} (Type, Text, UserName, Project, Timestamp, Version, IpAddress, ProjectId, Signature) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#, errors = tables.errors))
+                .bind(error.error_type.to_string())
+                .bind(&error.text)
+                .bind(&error.user_name)
+                .bind(&error.project)
+                .bind(error.timestamp)
+                .bind(&error.version)
+                .bind(&error.ip_address)
+                .bind(project_id)
+                .bind(crash::signature(&error.text))
+                .execute(&mut *(*sql_connection)).await?
+        }
+        DumpRow::Telemetry { data, version, ip_address, .. } => {
+            let project_id = try_insert_and_get_project(sql_connection, tables, cache, &data.project).await?;
+            sqlx::query(&format!(r#"INSERT INTO {telemetry} (Action, Result, UserName, Project, Timestamp, Duration, Version, IpAddress, ProjectId) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#, telemetry = tables.telemetry))
+                .bind(&data.action)
+                .bind(&data.result)
+                .bind(&data.user_name)
+                .bind(&data.project)
+                .bind(data.timestamp)
+                .bind(data.duration)
+                .bind(version)
+                .bind(ip_address)
+                .bind(project_id)
+                .execute(&mut *(*sql_connection)).await?
+        }
+    };
+    Ok(result.last_insert_id() as i64)
+}
+
+pub async fn prune(
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+    policy: &RetentionPolicy,
+    cutoff: DateTime<Utc>,
+    limit: u32,
+) -> Result<u64> {
+    Ok(queries::prune(tables, policy, cutoff, limit)
+        .build()
+        .execute(&mut *(*sql_connection))
+        .await?
+        .rows_affected())
+}
+
+// Private Functions:
+
+/// Runs one of the `queries::recent_changes` queries.
+async fn get_recent_change_ids(
+    sql_connection: &mut PoolConnection<MySql>,
+    mut query_builder: sqlx::QueryBuilder<'_, MySql>,
+) -> Result<ChangeWindow> {
+    query_builder
+        .build()
+        .fetch_all(&mut *(*sql_connection))
+        .await?
+        .iter()
+        .map(|row| Ok((row.try_get(0)?, row.try_get(1)?)))
+        .collect()
 }
 
 async fn try_insert_and_get_project(
-    sql_connection: &mut Connection<UGSDatabase>,
+    sql_connection: &mut PoolConnection<MySql>,
+    tables: &TableNames,
+    cache: &IdCache,
     project: &str,
 ) -> Result<i64> {
+    if let Some(id) = cache.project_id(project) {
+        return Ok(id);
+    }
+
     let mut transaction = sql_connection.begin().await?;
 
-    sqlx::query(r#"INSERT IGNORE INTO ugs_db.Projects (Name) VALUES (?)"#)
+    let id_result = insert_and_get_project(&mut transaction, tables, project).await?;
+
+    transaction.commit().await?;
+
+    cache.insert_project_id(project, id_result);
+    Ok(id_result)
+}
+
+/// Inserts `project` on `transaction` unless it already exists, and returns its id.
+async fn insert_and_get_project(
+    transaction: &mut sqlx::Transaction<'_, MySql>,
+    tables: &TableNames,
+    project: &str,
+) -> Result<i64> {
+    sqlx::query(&format!(r#"INSERT IGNORE INTO {projects} (Name, Stream) VALUES (?, ?)"#, projects = tables.projects))
         .bind(project)
-        .execute(&mut transaction)
+        .bind(get_project_stream(project))
+        .execute(&mut *transaction)
         .await?;
 
-    let id_result =
-        sqlx::query_scalar::<_, i64>(r#"SELECT Id FROM ugs_db.Projects WHERE Name = ?"#)
+    let id =
+        sqlx::query_scalar::<_, i64>(&format!(r#"SELECT Id FROM {projects} WHERE Name = ?"#, projects = tables.projects))
             .bind(project)
-            .fetch_one(&mut transaction)
+            .fetch_one(&mut *transaction)
             .await?;
 
-    transaction.commit().await?;
+    Ok(id)
+}
 
-    Ok(id_result)
+/// Ids of the `projects` of a batch, inserting the missing ones on the batch's `transaction` so that
+/// they roll back with it. Also returns the ids that were not cached, to cache once it commits.
+async fn get_batch_project_ids<'a>(
+    transaction: &mut sqlx::Transaction<'_, MySql>,
+    tables: &TableNames,
+    cache: &IdCache,
+    projects: &[&'a str],
+) -> Result<(Vec<i64>, Vec<(&'a str, i64)>)> {
+    let mut ids = Vec::with_capacity(projects.len());
+    let mut uncached: Vec<(&str, i64)> = Vec::new();
+    for project in projects {
+        let known = cache
+            .project_id(project)
+            .or_else(|| uncached.iter().find(|(name, _)| name == project).map(|(_, id)| *id));
+        ids.push(match known {
+            Some(id) => id,
+            None => {
+                let id = insert_and_get_project(transaction, tables, project).await?;
+                uncached.push((project, id));
+                id
+            }
+        });
+    }
+    Ok((ids, uncached))
 }
 
-fn get_project_stream(project: &str) -> String {
+fn cache_project_ids(cache: &IdCache, project_ids: Vec<(&str, i64)>) {
+    for (project, id) in project_ids {
+        cache.insert_project_id(project, id);
+    }
+}
+
+/// The `GROUP BY` positions of a `push_timing_groups` select list followed by `Result`: those of
+/// `Action`, of the `[Project, Version, Start]` expressions that are not NULL, and of `Result`.
+pub fn grouped_positions(expressions: &[&str]) -> String {
+    let mut positions: Vec<usize> = vec![1];
+    positions.extend((2..).zip(expressions).filter(|(_, expression)| **expression != "NULL").map(|(position, _)| position));
+    positions.push(expressions.len() + 2);
+    positions.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+}
+
+pub fn get_project_stream(project: &str) -> String {
     use lazy_static::lazy_static;
     use regex::Regex;
 
@@ -611,10 +1065,21 @@ fn get_project_stream(project: &str) -> String {
     }
 }
 
-fn matches_wildcard(wildcard: &str, project: &str) -> bool {
-    wildcard.ends_with("...") && project.starts_with(&wildcard[0..wildcard.len() - 4])
+/// Whether builds posted for the project `name` are shown to clients of `project`: those of the
+/// project itself, and those of a wildcard such as `//UE5/Main/...` in the same stream whose path,
+/// less the `/...`, starts `project`.
+pub fn project_matches(name: &str, project: &str) -> bool {
+    if name == project {
+        return true;
+    }
+    let Some(path) = name.strip_suffix("...") else {
+        return false;
+    };
+    let mut path = path.chars();
+    path.next_back();
+    get_project_stream(name) == get_project_stream(project) && project.starts_with(path.as_str())
 }
 
-fn normalize_user_name(user_name: &str) -> String {
+pub fn normalize_user_name(user_name: &str) -> String {
     user_name.to_uppercase()
 }
@@ -0,0 +1,864 @@
+use crate::crash::{self, CrashCounts, CrashReporter};
+use crate::dump::{DumpRow, DumpTable};
+use crate::models;
+use crate::retention::RetentionPolicy;
+use crate::sql::id_cache::IdCache;
+use crate::sql::latest_cache::{ChangeWindow, RecentChanges};
+use crate::sql::queries::{self, UNQUALIFIED_TABLES};
+use crate::sql::schema;
+use crate::sql::sql_connector::{
+    get_project_stream, normalize_user_name, sanitize_text,
+    ERROR_SIGNATURE_BATCH, ISSUE_SUMMARY_MAX_LENGTH, TELEMETRY_ROWS_PER_INSERT,
+};
+use chrono::{DateTime, Utc};
+use rocket_db_pools::sqlx;
+use rocket_db_pools::sqlx::pool::PoolConnection;
+use rocket_db_pools::sqlx::Acquire;
+use rocket_db_pools::sqlx::FromRow;
+use rocket_db_pools::sqlx::Sqlite;
+use rocket_db_pools::sqlx::Row;
+
+type Result<T> = std::result::Result<T, sqlx::Error>;
+
+// SQLite versions of the statements in sql_connector that are not built by `queries`. SQLite has
+// no schemas, so tables are not prefixed with `ugs_db.`, and timestamps are bound from Rust rather
+// than using UTC_TIMESTAMP().
+
+// Public Functions:
+
+pub async fn get_recent_changes(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    project: Option<&str>,
+) -> Result<RecentChanges> {
+    let stream = project.map(get_project_stream);
+    let [events, comments, builds] = queries::recent_changes(&UNQUALIFIED_TABLES, stream.as_deref());
+    Ok(RecentChanges {
+        events: get_recent_change_ids(sql_connection, events).await?,
+        comments: get_recent_change_ids(sql_connection, comments).await?,
+        builds: get_recent_change_ids(sql_connection, builds).await?,
+    })
+}
+
+pub async fn get_user_votes(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    project: &str,
+    last_event_id: i64,
+) -> Result<Vec<models::EventData>> {
+    queries::user_votes(&UNQUALIFIED_TABLES, project, last_event_id)
+        .build()
+        .fetch_all(&mut *(*sql_connection))
+        .await?
+        .iter()
+        .map(models::EventData::from_row)
+        .collect()
+}
+
+pub async fn get_comments(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    project: &str,
+    last_comment_id: i64,
+) -> Result<Vec<models::CommentData>> {
+    queries::comments(&UNQUALIFIED_TABLES, project, last_comment_id)
+        .build()
+        .fetch_all(&mut *(*sql_connection))
+        .await?
+        .iter()
+        .map(models::CommentData::from_row)
+        .collect()
+}
+
+pub async fn get_builds(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    project: &str,
+    last_build_id: i64,
+) -> Result<Vec<models::BuildData>> {
+    queries::builds(&UNQUALIFIED_TABLES, project, last_build_id)
+        .build()
+        .fetch_all(&mut *(*sql_connection))
+        .await?
+        .iter()
+        .map(models::BuildData::from_row)
+        .collect()
+}
+
+pub async fn get_error_data(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    query: &models::ErrorQuery,
+) -> Result<Vec<models::TelemetryErrorData>> {
+    queries::error_data(&UNQUALIFIED_TABLES, query)
+        .build()
+        .fetch_all(&mut *(*sql_connection))
+        .await?
+        .iter()
+        .map(models::TelemetryErrorData::from_row)
+        .collect()
+}
+
+/// The crash buckets of the reports matching `query`, affecting the most users first.
+/// `query.before_id` does not apply.
+pub async fn get_top_crashes(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    query: &models::ErrorQuery,
+) -> Result<Vec<models::CrashBucket>> {
+    let counts = queries::crash_counts(&UNQUALIFIED_TABLES, query)
+        .build()
+        .fetch_all(&mut *(*sql_connection))
+        .await?
+        .iter()
+        .map(CrashCounts::from_row)
+        .collect::<Result<Vec<_>>>()?;
+    if counts.is_empty() {
+        return Ok(Vec::new());
+    }
+    let reporters = queries::crash_reporters(&UNQUALIFIED_TABLES, query, &counts)
+        .build()
+        .fetch_all(&mut *(*sql_connection))
+        .await?
+        .iter()
+        .map(CrashReporter::from_row)
+        .collect::<Result<Vec<_>>>()?;
+    let texts = queries::crash_texts(&UNQUALIFIED_TABLES, &counts)
+        .build()
+        .fetch_all(&mut *(*sql_connection))
+        .await?
+        .iter()
+        .map(|row| Ok((row.try_get("Id")?, row.try_get("Text")?)))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(crash::buckets(counts, reporters, texts))
+}
+
+pub async fn get_error_counts(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<models::ErrorCount>> {
+    queries::error_counts(&UNQUALIFIED_TABLES, from, to)
+        .build()
+        .fetch_all(&mut *(*sql_connection))
+        .await?
+        .iter()
+        .map(models::ErrorCount::from_row)
+        .collect()
+}
+
+pub async fn post_build(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    cache: &IdCache,
+    build: &models::BuildData,
+) -> Result<i64> {
+    let project_id = try_insert_and_get_project(sql_connection, cache, &build.project).await?;
+    Ok(sqlx::query(r#"INSERT INTO Badges (ChangeNumber, BuildType, Result, Url, ArchivePath, ProjectId) VALUES (?, ?, ?, ?, ?, ?)"#)
+        .bind(build.change_number)
+        .bind(&build.build_type)
+        .bind(build.result.to_string())
+        .bind(&build.url)
+        .bind(&build.archive_path)
+        .bind(project_id)
+        .execute(&mut *(*sql_connection)).await?
+        .last_insert_rowid())
+}
+
+pub async fn post_event(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    cache: &IdCache,
+    event: &models::EventData,
+) -> Result<i64> {
+    let project_id = try_insert_and_get_project(sql_connection, cache, &event.project).await?;
+    Ok(sqlx::query(r#"INSERT INTO UserVotes (Changelist, UserName, Verdict, Project, ProjectId, CreatedAt) VALUES (?, ?, ?, ?, ?, ?)"#)
+        .bind(event.change)
+        .bind(&event.user_name)
+        .bind(event.event_type.to_string())
+        .bind(&event.project)
+        .bind(project_id)
+        .bind(chrono::Utc::now())
+        .execute(&mut *(*sql_connection)).await?
+        .last_insert_rowid())
+}
+
+pub async fn post_event_batch(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    cache: &IdCache,
+    events: &[models::EventData],
+) -> Result<Vec<i64>> {
+    let projects: Vec<&str> = events.iter().map(|event| event.project.as_str()).collect();
+    let mut transaction = sql_connection.begin().await?;
+    let (project_ids, uncached) = get_batch_project_ids(&mut transaction, cache, &projects).await?;
+    let mut ids = Vec::with_capacity(events.len());
+    for (event, project_id) in events.iter().zip(project_ids) {
+        ids.push(sqlx::query(r#"INSERT INTO UserVotes (Changelist, UserName, Verdict, Project, ProjectId, CreatedAt) VALUES (?, ?, ?, ?, ?, ?)"#)
+            .bind(event.change)
+            .bind(&event.user_name)
+            .bind(event.event_type.to_string())
+            .bind(&event.project)
+            .bind(project_id)
+            .bind(chrono::Utc::now())
+            .execute(&mut transaction).await?
+            .last_insert_rowid());
+    }
+    transaction.commit().await?;
+    cache_project_ids(cache, uncached);
+    Ok(ids)
+}
+
+pub async fn post_comment(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    cache: &IdCache,
+    comment: &models::CommentData,
+) -> Result<i64> {
+    let project_id = try_insert_and_get_project(sql_connection, cache, &comment.project).await?;
+    Ok(sqlx::query(r#"INSERT INTO Comments (ChangeNumber, UserName, Text, Project, ProjectId) VALUES (?, ?, ?, ?, ?)"#)
+        .bind(comment.change_number)
+        .bind(&comment.user_name)
+        .bind(&comment.text)
+        .bind(&comment.project)
+        .bind(project_id)
+        .execute(&mut *(*sql_connection)).await?
+        .last_insert_rowid())
+}
+
+pub async fn post_telemetry_data(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    cache: &IdCache,
+    data: &models::TelemetryTimingData,
+    version: &str,
+    ip_address: &str,
+) -> Result<()> {
+    let project_id = try_insert_and_get_project(sql_connection, cache, &data.project).await?;
+    sqlx::query(r#"INSERT INTO Telemetry_v2 (Action, Result, UserName, Project, Timestamp, Duration, Version, IpAddress, ProjectId) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#)
+        .bind(&data.action)
+        .bind(&data.result)
+        .bind(&data.user_name)
+        .bind(&data.project)
+        .bind(data.timestamp)
+        .bind(data.duration)
+        .bind(version)
+        .bind(ip_address)
+        .bind(project_id)
+        .execute(&mut *(*sql_connection)).await?;
+    Ok(())
+}
+
+pub async fn post_telemetry_batch(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    cache: &IdCache,
+    batch: &[models::TelemetryPost],
+) -> Result<()> {
+    let projects: Vec<&str> = batch.iter().map(|post| post.data.project.as_str()).collect();
+    let mut transaction = sql_connection.begin().await?;
+    let (project_ids, uncached) = get_batch_project_ids(&mut transaction, cache, &projects).await?;
+    for (posts, project_ids) in batch.chunks(TELEMETRY_ROWS_PER_INSERT).zip(project_ids.chunks(TELEMETRY_ROWS_PER_INSERT)) {
+        let mut query_builder: sqlx::QueryBuilder<sqlx::Sqlite> = sqlx::QueryBuilder::new("INSERT INTO Telemetry_v2 (Action, Result, UserName, Project, Timestamp, Duration, Version, IpAddress, ProjectId) ");
+        query_builder.push_values(posts.iter().zip(project_ids), |mut row, (post, project_id)| {
+            row.push_bind(&post.data.action)
+                .push_bind(&post.data.result)
+                .push_bind(&post.data.user_name)
+                .push_bind(&post.data.project)
+                .push_bind(post.data.timestamp)
+                .push_bind(post.data.duration)
+                .push_bind(&post.version)
+                .push_bind(&post.ip_address)
+                .push_bind(*project_id);
+        });
+        query_builder.build().execute(&mut transaction).await?;
+    }
+    transaction.commit().await?;
+    cache_project_ids(cache, uncached);
+    Ok(())
+}
+
+pub async fn get_timing_counts(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    query: &models::TimingQuery,
+) -> Result<Vec<models::TimingCount>> {
+    queries::timing_counts(&UNQUALIFIED_TABLES, query)
+        .build()
+        .fetch_all(&mut *(*sql_connection))
+        .await?
+        .iter()
+        .map(models::TimingCount::from_row)
+        .collect()
+}
+
+pub async fn get_timing_samples(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    query: &models::TimingQuery,
+    every: i64,
+) -> Result<Vec<models::TimingSample>> {
+    queries::timing_samples(&UNQUALIFIED_TABLES, query, every)
+        .build()
+        .fetch_all(&mut *(*sql_connection))
+        .await?
+        .iter()
+        .map(models::TimingSample::from_row)
+        .collect()
+}
+
+pub async fn post_error_data(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    cache: &IdCache,
+    data: &models::TelemetryErrorData,
+    version: &str,
+    ip_address: &str,
+) -> Result<()> {
+    let project_id = match &data.project {
+        Some(project_name) => Some(try_insert_and_get_project(sql_connection, cache, project_name).await?),
+        None => None,
+    };
+    sqlx::query(r#"INSERT INTO Errors (Type, Text, UserName, Project, Timestamp, Version, IpAddress, ProjectId, Signature) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#)
+        .bind(data.error_type)
+        .bind(&data.text)
+        .bind(&data.user_name)
+        .bind(&data.project)
+        .bind(data.timestamp)
+        .bind(version)
+        .bind(ip_address)
+        .bind(project_id)
+        .bind(crash::signature(&data.text))
+        .execute(&mut *(*sql_connection)).await?;
+    Ok(())
+}
+
+pub async fn find_or_add_user_id(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    cache: &IdCache,
+    name: &str,
+) -> Result<Option<i64>> {
+    if name.is_empty() {
+        return Ok(None);
+    }
+
+    let normalized_name = normalize_user_name(name);
+    if let Some(id) = cache.user_id(&normalized_name) {
+        return Ok(Some(id));
+    }
+
+    // Try to get the id if it already exists.
+    {
+        let id_opt = sqlx::query_scalar::<_, i64>(r#"SELECT Id FROM Users WHERE Name = ?"#)
+            .bind(&normalized_name)
+            .fetch_optional(&mut *(*sql_connection))
+            .await?;
+
+        if let Some(id) = id_opt {
+            cache.insert_user_id(&normalized_name, id);
+            return Ok(id_opt);
+        }
+    }
+
+    // Otherwise, start a transaction and try to create the row and get the id.
+    let mut transaction = sql_connection.begin().await?;
+
+    sqlx::query(r#"INSERT OR IGNORE INTO Users (Name) VALUES (?)"#)
+        .bind(&normalized_name)
+        .execute(&mut transaction)
+        .await?;
+
+    let id = sqlx::query_scalar::<_, i64>(r#"SELECT Id FROM Users WHERE Name = ?"#)
+        .bind(&normalized_name)
+        .fetch_one(&mut transaction)
+        .await?;
+
+    transaction.commit().await?;
+
+    cache.insert_user_id(&normalized_name, id);
+    Ok(Some(id))
+}
+
+pub async fn add_issue(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    cache: &IdCache,
+    issue: &models::IssueData,
+) -> Result<i64> {
+    let owner_id = find_or_add_user_id(sql_connection, cache, &issue.owner).await?;
+    let id = sqlx::query(r#"INSERT INTO Issues (Project, Summary, OwnerId, CreatedAt, FixChange) VALUES (?, ?, ?, ?, 0)"#)
+        .bind(&issue.project)
+        .bind(sanitize_text(&issue.summary, ISSUE_SUMMARY_MAX_LENGTH))
+        .bind(owner_id)
+        .bind(chrono::Utc::now())
+        .execute(&mut *(*sql_connection)).await?
+        .last_insert_rowid();
+
+    Ok(id)
+}
+
+pub async fn get_issue(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    cache: &IdCache,
+    issue_id: i64,
+) -> Result<Option<models::IssueData>> {
+    let issue_data_vec =
+        get_issues_internal(sql_connection, cache, Some(issue_id), None, true, None).await?;
+    Ok(issue_data_vec.into_iter().next())
+}
+
+pub async fn get_issues_filtered(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    cache: &IdCache,
+    include_resolved: bool,
+    num_results: Option<i32>,
+) -> Result<Vec<models::IssueData>> {
+    get_issues_internal(sql_connection, cache, None, None, include_resolved, num_results).await
+}
+
+pub async fn get_issues_by_user_name(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    cache: &IdCache,
+    user_name: &str,
+) -> Result<Vec<models::IssueData>> {
+    get_issues_internal(sql_connection, cache, None, Some(user_name), false, None).await
+}
+
+async fn get_issues_internal(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    cache: &IdCache,
+    issue_id: Option<i64>,
+    user_name: Option<&str>,
+    include_resolved: bool,
+    num_results: Option<i32>,
+) -> Result<Vec<models::IssueData>> {
+    let user_id = match user_name {
+        Some(s) => find_or_add_user_id(sql_connection, cache, s).await?,
+        None => None,
+    };
+    queries::issues(&UNQUALIFIED_TABLES, issue_id, user_id, include_resolved, num_results)
+        .build()
+        .fetch_all(&mut *(*sql_connection))
+        .await?
+        .iter()
+        .map(models::IssueData::from_row)
+        .collect()
+}
+
+pub async fn update_issue(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    cache: &IdCache,
+    issue_id: i64,
+    issue: &models::IssueUpdateData,
+) -> Result<()> {
+    let owner_id = find_or_add_user_id(sql_connection, cache, &issue.owner).await?;
+    let nominated_by_id = find_or_add_user_id(sql_connection, cache, &issue.nominated_by).await?;
+    queries::update_issue(&UNQUALIFIED_TABLES, issue_id, issue, owner_id, nominated_by_id)
+        .build()
+        .execute(&mut *(*sql_connection))
+        .await?;
+    Ok(())
+}
+
+pub async fn delete_issue(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    issue_id: i64,
+) -> Result<()> {
+    let mut transaction = sql_connection.begin().await?;
+
+    sqlx::query(r#"DELETE FROM IssueWatchers WHERE IssueId = ?"#)
+        .bind(issue_id)
+        .execute(&mut transaction)
+        .await?;
+
+    sqlx::query(r#"DELETE FROM IssueBuilds WHERE IssueId = ?"#)
+        .bind(issue_id)
+        .execute(&mut transaction)
+        .await?;
+
+    sqlx::query(r#"DELETE FROM Issues WHERE Id = ?"#)
+        .bind(issue_id)
+        .execute(&mut transaction)
+        .await?;
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+pub async fn add_diagnostic(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    issue_id: i64,
+    diagnostic: &models::IssueDiagnosticData,
+) -> Result<()> {
+    sqlx::query(
+        r#"INSERT INTO IssueDiagnostics (IssueId, BuildId, Message, Url) VALUES (?, ?, ?, ?)"#,
+    )
+    .bind(issue_id)
+    .bind(diagnostic.build_id)
+    .bind(sanitize_text(&diagnostic.message, 1000))
+    .bind(&diagnostic.url)
+    .execute(&mut *(*sql_connection))
+    .await?;
+    Ok(())
+}
+
+pub async fn get_diagnostics(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    issue_id: i64,
+) -> Result<Vec<models::IssueDiagnosticData>> {
+    sqlx::query_as::<_, models::IssueDiagnosticData>(
+        r#"SELECT BuildId, Message, Url FROM IssueDiagnostics
+        WHERE IssueDiagnostics.IssueId = ?"#,
+    )
+    .bind(issue_id)
+    .fetch_all(&mut *(*sql_connection))
+    .await
+}
+
+pub async fn add_watcher(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    cache: &IdCache,
+    issue_id: i64,
+    user_name: &str,
+) -> Result<()> {
+    let user_id = find_or_add_user_id(sql_connection, cache, user_name).await?;
+    sqlx::query(r#"INSERT OR IGNORE INTO IssueWatchers (IssueId, UserId) VALUES (?, ?)"#)
+        .bind(issue_id)
+        .bind(user_id)
+        .execute(&mut *(*sql_connection))
+        .await?;
+    Ok(())
+}
+
+pub async fn get_watchers(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    issue_id: i64,
+) -> Result<Vec<String>> {
+    sqlx::query_scalar(
+        r#"SELECT Users.Name FROM IssueWatchers
+        INNER JOIN Users ON IssueWatchers.UserId = Users.Id
+        WHERE IssueWatchers.IssueId = ?"#,
+    )
+    .bind(issue_id)
+    .fetch_all(&mut *(*sql_connection))
+    .await
+}
+
+pub async fn remove_watcher(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    cache: &IdCache,
+    issue_id: i64,
+    user_name: &str,
+) -> Result<()> {
+    let user_id = find_or_add_user_id(sql_connection, cache, user_name).await?;
+    sqlx::query(r#"DELETE FROM IssueWatchers WHERE IssueId = ? AND UserId = ?"#)
+        .bind(issue_id)
+        .bind(user_id)
+        .execute(&mut *(*sql_connection))
+        .await?;
+    Ok(())
+}
+
+pub async fn add_build(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    issue_id: i64,
+    build: &models::IssueBuildData,
+) -> Result<i64> {
+    let id = sqlx::query(r#"INSERT INTO IssueBuilds (IssueId, Stream, "Change", JobName, JobUrl, JobStepName, JobStepUrl, ErrorUrl, Outcome) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#)
+        .bind(issue_id)
+        .bind(&build.stream)
+        .bind(build.change)
+        .bind(&build.job_name)
+        .bind(&build.job_url)
+        .bind(&build.job_step_name)
+        .bind(&build.job_step_url)
+        .bind(&build.error_url)
+        .bind(build.outcome)
+        .execute(&mut *(*sql_connection))
+        .await?
+        .last_insert_rowid();
+    Ok(id)
+}
+
+pub async fn get_builds_by_issue(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    issue_id: i64,
+) -> Result<Vec<models::IssueBuildData>> {
+    sqlx::query_as::<_, models::IssueBuildData>(r#"SELECT IssueBuilds.Id, IssueBuilds.Stream, IssueBuilds."Change", IssueBuilds.JobName, IssueBuilds.JobUrl, IssueBuilds.JobStepName, IssueBuilds.JobStepUrl, IssueBuilds.ErrorUrl, IssueBuilds.Outcome FROM IssueBuilds WHERE IssueBuilds.IssueId = ?"#)
+        .bind(issue_id)
+        .fetch_all(&mut *(*sql_connection))
+        .await
+}
+
+pub async fn get_build(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    build_id: i64,
+) -> Result<Option<models::IssueBuildData>> {
+    sqlx::query_as::<_, models::IssueBuildData>(r#"SELECT IssueBuilds.Id, IssueBuilds.Stream, IssueBuilds."Change", IssueBuilds.JobName, IssueBuilds.JobUrl, IssueBuilds.JobStepName, IssueBuilds.JobStepUrl, IssueBuilds.ErrorUrl, IssueBuilds.Outcome FROM IssueBuilds WHERE IssueBuilds.Id = ?"#)
+        .bind(build_id)
+        .fetch_optional(&mut *(*sql_connection))
+        .await
+}
+
+pub async fn update_build(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    build_id: i64,
+    outcome: i32,
+) -> Result<()> {
+    sqlx::query(r#"UPDATE IssueBuilds SET Outcome = ? WHERE Id = ?"#)
+        .bind(outcome)
+        .bind(build_id)
+        .execute(&mut *(*sql_connection))
+        .await?;
+    Ok(())
+}
+
+/// Runs a query that reads no table, to check that the database answers.
+pub async fn ping(sql_connection: &mut PoolConnection<Sqlite>) -> Result<()> {
+    sqlx::query(r#"SELECT 1"#)
+        .execute(&mut *(*sql_connection))
+        .await
+        .map(|_| ())
+}
+
+/// SQLite has no replication to measure, so a replica is never considered behind.
+pub async fn get_replication_lag(_sql_connection: &mut PoolConnection<Sqlite>) -> Result<Option<f64>> {
+    Ok(None)
+}
+
+pub async fn describe_schema(
+    sql_connection: &mut PoolConnection<Sqlite>,
+) -> Result<Vec<schema::Column>> {
+    // SQLite has no information_schema; its table_info pragma lists the same columns.
+    sqlx::query_as::<_, schema::Column>(r#"SELECT Tables.name AS TableName, Columns.name AS ColumnName, Columns.type AS DataType FROM sqlite_master AS Tables INNER JOIN pragma_table_info(Tables.name) AS Columns WHERE Tables.type = 'table'"#)
+        .fetch_all(&mut *(*sql_connection))
+        .await
+}
+
+/// Fills in `Projects.Stream` for projects added before it existed. Returns how many were updated.
+pub async fn fill_project_streams(sql_connection: &mut PoolConnection<Sqlite>) -> Result<u64> {
+    let projects = sqlx::query_as::<_, (i64, String)>(r#"SELECT Id, Name FROM Projects WHERE Stream IS NULL"#)
+        .fetch_all(&mut *(*sql_connection))
+        .await?;
+    for (id, name) in &projects {
+        sqlx::query(r#"UPDATE Projects SET Stream = ? WHERE Id = ?"#)
+            .bind(get_project_stream(name))
+            .bind(id)
+            .execute(&mut *(*sql_connection))
+            .await?;
+    }
+    Ok(projects.len() as u64)
+}
+
+/// Fills in `Errors.Signature` for reports stored before it existed, `ERROR_SIGNATURE_BATCH` at a
+/// time. Returns how many were updated.
+pub async fn fill_error_signatures(sql_connection: &mut PoolConnection<Sqlite>) -> Result<u64> {
+    let mut filled = 0;
+    loop {
+        let errors = sqlx::query_as::<_, (i64, String)>(r#"SELECT Id, Text FROM Errors WHERE Signature IS NULL ORDER BY Id LIMIT ?"#)
+            .bind(ERROR_SIGNATURE_BATCH)
+            .fetch_all(&mut *(*sql_connection))
+            .await?;
+        if errors.is_empty() {
+            return Ok(filled);
+        }
+        let mut transaction = sql_connection.begin().await?;
+        for (id, text) in &errors {
+            sqlx::query(r#"UPDATE Errors SET Signature = ? WHERE Id = ?"#)
+                .bind(crash::signature(text))
+                .bind(id)
+                .execute(&mut transaction)
+                .await?;
+        }
+        transaction.commit().await?;
+        filled += errors.len() as u64;
+    }
+}
+
+pub async fn export_rows(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    table: DumpTable,
+    after_id: i64,
+    limit: u32,
+) -> Result<Vec<DumpRow>> {
+    queries::export_rows(&UNQUALIFIED_TABLES, table, after_id, limit)
+        .build()
+        .fetch_all(&mut *(*sql_connection))
+        .await?
+        .iter()
+        .map(|row| queries::dump_row(table, row))
+        .collect()
+}
+
+pub async fn import_row(sql_connection: &mut PoolConnection<Sqlite>, cache: &IdCache, row: &DumpRow) -> Result<i64> {
+    let result = match row {
+        DumpRow::Project { name, .. } => {
+            return try_insert_and_get_project(sql_connection, cache, name).await
+        }
+        DumpRow::User { name, .. } => {
+            return Ok(find_or_add_user_id(sql_connection, cache, name).await?.unwrap_or(0))
+        }
+        DumpRow::Badge(build) => {
+            let project_id = try_insert_and_get_project(sql_connection, cache, &build.project).await?;
+            sqlx::query(r#"INSERT INTO Badges (ChangeNumber, BuildType, Result, Url, ArchivePath, ProjectId) VALUES (?, ?, ?, ?, ?, ?)"#)
+                .bind(build.change_number)
+                .bind(&build.build_type)
+                .bind(build.result.to_string())
+                .bind(&build.url)
+                .bind(&build.archive_path)
+                .bind(project_id)
+                .execute(&mut *(*sql_connection)).await?
+        }
+        DumpRow::Comment(comment) => {
+            let project_id = try_insert_and_get_project(sql_connection, cache, &comment.project).await?;
+            sqlx::query(r#"INSERT INTO Comments (ChangeNumber, UserName, Text, Project, ProjectId) VALUES (?, ?, ?, ?, ?)"#)
+                .bind(comment.change_number)
+                .bind(&comment.user_name)
+                .bind(&comment.text)
+                .bind(&comment.project)
+                .bind(project_id)
+                .execute(&mut *(*sql_connection)).await?
+        }
+        DumpRow::Event { event, created_at } => {
+            let project_id = try_insert_and_get_project(sql_connection, cache, &event.project).await?;
+            sqlx::query(r#"INSERT INTO UserVotes (Changelist, UserName, Verdict, Project, ProjectId, CreatedAt) VALUES (?, ?, ?, ?, ?, ?)"#)
+                .bind(event.change)
+                .bind(&event.user_name)
+                .bind(event.event_type.to_string())
+                .bind(&event.project)
+                .bind(project_id)
+                .bind(created_at)
+                .execute(&mut *(*sql_connection)).await?
+        }
+        DumpRow::Issue(issue) => {
+            let owner_id = find_or_add_user_id(sql_connection, cache, &issue.owner).await?;
+            let nominated_by_id = find_or_add_user_id(sql_connection, cache, &issue.nominated_by).await?;
+            sqlx::query(r#"INSERT INTO Issues (Project, Summary, OwnerId, NominatedById, CreatedAt, AcknowledgedAt, FixChange, ResolvedAt) VALUES (?, ?, ?, ?, ?, ?, ?, ?)"#)
+                .bind(&issue.project)
+                .bind(&issue.summary)
+                .bind(owner_id)
+                .bind(nominated_by_id)
+                .bind(issue.created_at)
+                .bind(issue.acknowledged_at)
+                .bind(issue.fix_change)
+                .bind(issue.resolved_at)
+                .execute(&mut *(*sql_connection)).await?
+        }
+        DumpRow::Error(error) => {
+            let project_id = match &error.project {
+                Some(project) => Some(try_insert_and_get_project(sql_connection, cache, project).await?),
+                None => None,
+            };
+            sqlx::query(r#"INSERT INTO Errors (Type, Text, UserName, Project, Timestamp, Version, IpAddress, ProjectId, Signature) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#)
+                .bind(error.error_type.to_string())
+                .bind(&error.text)
+                .bind(&error.user_name)
+                .bind(&error.project)
+                .bind(error.timestamp)
+                .bind(&error.version)
+                .bind(&error.ip_address)
+                .bind(project_id)
+                .bind(crash::signature(&error.text))
+                .execute(&mut *(*sql_connection)).await?
+        }
+        DumpRow::Telemetry { data, version, ip_address, .. } => {
+            let project_id = try_insert_and_get_project(sql_connection, cache, &data.project).await?;
+            sqlx::query(r#"INSERT INTO Telemetry_v2 (Action, Result, UserName, Project, Timestamp, Duration, Version, IpAddress, ProjectId) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#)
+                .bind(&data.action)
+                .bind(&data.result)
+                .bind(&data.user_name)
+                .bind(&data.project)
+                .bind(data.timestamp)
+                .bind(data.duration)
+                .bind(version)
+                .bind(ip_address)
+                .bind(project_id)
+                .execute(&mut *(*sql_connection)).await?
+        }
+    };
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn prune(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    policy: &RetentionPolicy,
+    cutoff: DateTime<Utc>,
+    limit: u32,
+) -> Result<u64> {
+    Ok(queries::prune(&UNQUALIFIED_TABLES, policy, cutoff, limit)
+        .build()
+        .execute(&mut *(*sql_connection))
+        .await?
+        .rows_affected())
+}
+
+// Private Functions:
+
+/// Runs one of the `queries::recent_changes` queries.
+async fn get_recent_change_ids(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    mut query_builder: sqlx::QueryBuilder<'_, Sqlite>,
+) -> Result<ChangeWindow> {
+    query_builder
+        .build()
+        .fetch_all(&mut *(*sql_connection))
+        .await?
+        .iter()
+        .map(|row| Ok((row.try_get(0)?, row.try_get(1)?)))
+        .collect()
+}
+
+async fn try_insert_and_get_project(
+    sql_connection: &mut PoolConnection<Sqlite>,
+    cache: &IdCache,
+    project: &str,
+) -> Result<i64> {
+    if let Some(id) = cache.project_id(project) {
+        return Ok(id);
+    }
+
+    let mut transaction = sql_connection.begin().await?;
+
+    let id_result = insert_and_get_project(&mut transaction, project).await?;
+
+    transaction.commit().await?;
+
+    cache.insert_project_id(project, id_result);
+    Ok(id_result)
+}
+
+/// Inserts `project` on `transaction` unless it already exists, and returns its id.
+async fn insert_and_get_project(
+    transaction: &mut sqlx::Transaction<'_, Sqlite>,
+    project: &str,
+) -> Result<i64> {
+    sqlx::query(r#"INSERT OR IGNORE INTO Projects (Name, Stream) VALUES (?, ?)"#)
+        .bind(project)
+        .bind(get_project_stream(project))
+        .execute(&mut *transaction)
+        .await?;
+
+    let id = sqlx::query_scalar::<_, i64>(r#"SELECT Id FROM Projects WHERE Name = ?"#)
+        .bind(project)
+        .fetch_one(&mut *transaction)
+        .await?;
+
+    Ok(id)
+}
+
+/// Ids of the `projects` of a batch, inserting the missing ones on the batch's `transaction` so that
+/// they roll back with it. Also returns the ids that were not cached, to cache once it commits.
+async fn get_batch_project_ids<'a>(
+    transaction: &mut sqlx::Transaction<'_, Sqlite>,
+    cache: &IdCache,
+    projects: &[&'a str],
+) -> Result<(Vec<i64>, Vec<(&'a str, i64)>)> {
+    let mut ids = Vec::with_capacity(projects.len());
+    let mut uncached: Vec<(&str, i64)> = Vec::new();
+    for project in projects {
+        let known = cache
+            .project_id(project)
+            .or_else(|| uncached.iter().find(|(name, _)| name == project).map(|(_, id)| *id));
+        ids.push(match known {
+            Some(id) => id,
+            None => {
+                let id = insert_and_get_project(transaction, project).await?;
+                uncached.push((project, id));
+                id
+            }
+        });
+    }
+    Ok((ids, uncached))
+}
+
+fn cache_project_ids(cache: &IdCache, project_ids: Vec<(&str, i64)>) {
+    for (project, id) in project_ids {
+        cache.insert_project_id(project, id);
+    }
+}
@@ -0,0 +1,402 @@
+use crate::dump::{DumpRow, DumpTable};
+use crate::models;
+use crate::retention::RetentionPolicy;
+use crate::sql::availability::Availability;
+use crate::sql::id_cache::{IdCache, IdCacheStats};
+use crate::sql::latest_cache::LatestCache;
+use crate::sql::metered_store::metered;
+use crate::sql::replica::{Replica, ReplicaConfig};
+use crate::sql::{
+    log_filled_error_signatures, log_filled_project_streams, migrations, schema, sqlite_connector,
+    MetadataStore, Result, SharedStore,
+};
+use crate::SqliteDatabase;
+use chrono::{DateTime, Utc};
+use rocket::fairing::AdHoc;
+use rocket_db_pools::sqlx;
+use rocket_db_pools::sqlx::pool::PoolConnection;
+use rocket_db_pools::Database;
+use std::sync::Arc;
+
+/// `MetadataStore` backed by the `SqliteDatabase` pool, with the queries in `sqlite_connector`.
+pub struct SqliteStore {
+    pool: sqlx::SqlitePool,
+    id_cache: IdCache,
+    latest_cache: LatestCache,
+    replica: Option<Replica<sqlx::Sqlite>>,
+    availability: Availability,
+}
+
+impl SqliteStore {
+    pub fn new(
+        pool: sqlx::SqlitePool,
+        id_cache: IdCache,
+        latest_cache: LatestCache,
+        replica: Option<Replica<sqlx::Sqlite>>,
+    ) -> Self {
+        SqliteStore {
+            pool,
+            id_cache,
+            latest_cache,
+            replica,
+            availability: Availability::default(),
+        }
+    }
+
+    /// Connection to the primary database, noting whether it could be reached.
+    async fn connection(&self) -> Result<PoolConnection<sqlx::Sqlite>> {
+        self.availability.record(self.pool.acquire().await)
+    }
+
+    /// Connection for a query that only reads, which goes to the replica when there is one.
+    async fn read_connection(&self) -> Result<PoolConnection<sqlx::Sqlite>> {
+        match &self.replica {
+            Some(replica) => replica.acquire(&self.pool).await,
+            None => self.connection().await,
+        }
+    }
+
+    async fn fill_project_streams(&self) -> Result<u64> {
+        let mut connection = self.connection().await?;
+        sqlite_connector::fill_project_streams(&mut connection).await
+    }
+
+    async fn fill_error_signatures(&self) -> Result<u64> {
+        let mut connection = self.connection().await?;
+        sqlite_connector::fill_error_signatures(&mut connection).await
+    }
+}
+
+/// Fairing that manages a `SharedStore` over the `SqliteDatabase` pool. Must be attached after it.
+pub fn stage(
+    id_cache: IdCache,
+    latest_cache: LatestCache,
+    replica: Option<ReplicaConfig>,
+) -> AdHoc {
+    AdHoc::try_on_ignite("SQLite Metadata Store", |rocket| async {
+        match SqliteDatabase::fetch(&rocket) {
+            Some(db) => {
+                let replica = match replica.map(Replica::connect).transpose() {
+                    Ok(replica) => replica,
+                    Err(e) => {
+                        log::error!("Invalid `databases.ugsdb.replica`: {}", e);
+                        return Err(rocket);
+                    }
+                };
+                let store = SqliteStore::new(db.0.clone(), id_cache, latest_cache, replica);
+                let store: SharedStore = Arc::new(store);
+                let migrations = rocket
+                    .state::<Arc<migrations::Deferred<sqlx::Sqlite>>>()
+                    .map(|migrations| migrations.clone() as Arc<dyn schema::Prepare>);
+                Ok(rocket.manage(metered(schema::checked(store, migrations).await)))
+            }
+            None => Err(rocket),
+        }
+    })
+}
+
+#[rocket::async_trait]
+impl MetadataStore for SqliteStore {
+    async fn get_builds(
+        &self,
+        project: &str,
+        last_build_id: i64,
+    ) -> Result<Vec<models::BuildData>> {
+        let mut connection = self.read_connection().await?;
+        sqlite_connector::get_builds(&mut connection, project, last_build_id).await
+    }
+
+    async fn post_build(&self, build: &models::BuildData) -> Result<()> {
+        let mut connection = self.connection().await?;
+        let id = sqlite_connector::post_build(&mut connection, &self.id_cache, build).await?;
+        self.latest_cache
+            .record_build(&build.project, build.change_number, id);
+        Ok(())
+    }
+
+    async fn get_comments(
+        &self,
+        project: &str,
+        last_comment_id: i64,
+    ) -> Result<Vec<models::CommentData>> {
+        let mut connection = self.read_connection().await?;
+        sqlite_connector::get_comments(&mut connection, project, last_comment_id).await
+    }
+
+    async fn post_comment(&self, comment: &models::CommentData) -> Result<()> {
+        let mut connection = self.connection().await?;
+        let id = sqlite_connector::post_comment(&mut connection, &self.id_cache, comment).await?;
+        self.latest_cache
+            .record_comment(&comment.project, comment.change_number, id);
+        Ok(())
+    }
+
+    async fn get_user_votes(
+        &self,
+        project: &str,
+        last_event_id: i64,
+    ) -> Result<Vec<models::EventData>> {
+        let mut connection = self.read_connection().await?;
+        sqlite_connector::get_user_votes(&mut connection, project, last_event_id).await
+    }
+
+    async fn post_event(&self, event: &models::EventData) -> Result<()> {
+        let mut connection = self.connection().await?;
+        let id = sqlite_connector::post_event(&mut connection, &self.id_cache, event).await?;
+        self.latest_cache
+            .record_event(&event.project, event.change, id);
+        Ok(())
+    }
+
+    async fn post_event_batch(&self, events: &[models::EventData]) -> Result<()> {
+        let mut connection = self.connection().await?;
+        let ids =
+            sqlite_connector::post_event_batch(&mut connection, &self.id_cache, events).await?;
+        for (event, id) in events.iter().zip(ids) {
+            self.latest_cache
+                .record_event(&event.project, event.change, id);
+        }
+        Ok(())
+    }
+
+    async fn get_last_ids(&self, project: Option<&str>) -> Result<models::LatestData> {
+        if let Some(latest) = self.latest_cache.get(project) {
+            return Ok(latest);
+        }
+        self.latest_cache.start_loading(project);
+        // From the primary, as the cache is then kept up to date by the writes, which a lagging
+        // replica could be missing some of.
+        let mut connection = self.connection().await?;
+        let changes = sqlite_connector::get_recent_changes(&mut connection, project).await?;
+        Ok(self.latest_cache.finish_loading(project, changes))
+    }
+
+    async fn add_issue(&self, issue: &models::IssueData) -> Result<i64> {
+        let mut connection = self.connection().await?;
+        sqlite_connector::add_issue(&mut connection, &self.id_cache, issue).await
+    }
+
+    async fn get_issue(&self, issue_id: i64) -> Result<Option<models::IssueData>> {
+        let mut connection = self.read_connection().await?;
+        sqlite_connector::get_issue(&mut connection, &self.id_cache, issue_id).await
+    }
+
+    async fn get_issues_filtered(
+        &self,
+        include_resolved: bool,
+        num_results: Option<i32>,
+    ) -> Result<Vec<models::IssueData>> {
+        let mut connection = self.read_connection().await?;
+        sqlite_connector::get_issues_filtered(
+            &mut connection,
+            &self.id_cache,
+            include_resolved,
+            num_results,
+        )
+        .await
+    }
+
+    async fn get_issues_by_user_name(&self, user_name: &str) -> Result<Vec<models::IssueData>> {
+        let mut connection = self.connection().await?;
+        sqlite_connector::get_issues_by_user_name(&mut connection, &self.id_cache, user_name).await
+    }
+
+    async fn update_issue(&self, issue_id: i64, issue: &models::IssueUpdateData) -> Result<()> {
+        let mut connection = self.connection().await?;
+        sqlite_connector::update_issue(&mut connection, &self.id_cache, issue_id, issue).await
+    }
+
+    async fn delete_issue(&self, issue_id: i64) -> Result<()> {
+        let mut connection = self.connection().await?;
+        sqlite_connector::delete_issue(&mut connection, issue_id).await
+    }
+
+    async fn add_build(&self, issue_id: i64, build: &models::IssueBuildData) -> Result<i64> {
+        let mut connection = self.connection().await?;
+        sqlite_connector::add_build(&mut connection, issue_id, build).await
+    }
+
+    async fn get_builds_by_issue(&self, issue_id: i64) -> Result<Vec<models::IssueBuildData>> {
+        let mut connection = self.read_connection().await?;
+        sqlite_connector::get_builds_by_issue(&mut connection, issue_id).await
+    }
+
+    async fn get_build(&self, build_id: i64) -> Result<Option<models::IssueBuildData>> {
+        let mut connection = self.read_connection().await?;
+        sqlite_connector::get_build(&mut connection, build_id).await
+    }
+
+    async fn update_build(&self, build_id: i64, outcome: i32) -> Result<()> {
+        let mut connection = self.connection().await?;
+        sqlite_connector::update_build(&mut connection, build_id, outcome).await
+    }
+
+    async fn add_diagnostic(
+        &self,
+        issue_id: i64,
+        diagnostic: &models::IssueDiagnosticData,
+    ) -> Result<()> {
+        let mut connection = self.connection().await?;
+        sqlite_connector::add_diagnostic(&mut connection, issue_id, diagnostic).await
+    }
+
+    async fn get_diagnostics(&self, issue_id: i64) -> Result<Vec<models::IssueDiagnosticData>> {
+        let mut connection = self.read_connection().await?;
+        sqlite_connector::get_diagnostics(&mut connection, issue_id).await
+    }
+
+    async fn add_watcher(&self, issue_id: i64, user_name: &str) -> Result<()> {
+        let mut connection = self.connection().await?;
+        sqlite_connector::add_watcher(&mut connection, &self.id_cache, issue_id, user_name).await
+    }
+
+    async fn get_watchers(&self, issue_id: i64) -> Result<Vec<String>> {
+        let mut connection = self.read_connection().await?;
+        sqlite_connector::get_watchers(&mut connection, issue_id).await
+    }
+
+    async fn remove_watcher(&self, issue_id: i64, user_name: &str) -> Result<()> {
+        let mut connection = self.connection().await?;
+        sqlite_connector::remove_watcher(&mut connection, &self.id_cache, issue_id, user_name).await
+    }
+
+    async fn post_telemetry_data(
+        &self,
+        data: &models::TelemetryTimingData,
+        version: &str,
+        ip_address: &str,
+    ) -> Result<()> {
+        let mut connection = self.connection().await?;
+        sqlite_connector::post_telemetry_data(
+            &mut connection,
+            &self.id_cache,
+            data,
+            version,
+            ip_address,
+        )
+        .await
+    }
+
+    async fn post_telemetry_batch(&self, batch: &[models::TelemetryPost]) -> Result<()> {
+        let mut connection = self.connection().await?;
+        sqlite_connector::post_telemetry_batch(&mut connection, &self.id_cache, batch).await
+    }
+
+    async fn get_timing_counts(
+        &self,
+        query: &models::TimingQuery,
+    ) -> Result<Vec<models::TimingCount>> {
+        let mut connection = self.read_connection().await?;
+        sqlite_connector::get_timing_counts(&mut connection, query).await
+    }
+
+    async fn get_timing_samples(
+        &self,
+        query: &models::TimingQuery,
+        every: i64,
+    ) -> Result<Vec<models::TimingSample>> {
+        let mut connection = self.read_connection().await?;
+        sqlite_connector::get_timing_samples(&mut connection, query, every).await
+    }
+
+    async fn post_error_data(
+        &self,
+        data: &models::TelemetryErrorData,
+        version: &str,
+        ip_address: &str,
+    ) -> Result<()> {
+        let mut connection = self.connection().await?;
+        sqlite_connector::post_error_data(
+            &mut connection,
+            &self.id_cache,
+            data,
+            version,
+            ip_address,
+        )
+        .await
+    }
+
+    async fn get_error_data(
+        &self,
+        query: &models::ErrorQuery,
+    ) -> Result<Vec<models::TelemetryErrorData>> {
+        let mut connection = self.read_connection().await?;
+        sqlite_connector::get_error_data(&mut connection, query).await
+    }
+
+    async fn get_top_crashes(
+        &self,
+        query: &models::ErrorQuery,
+    ) -> Result<Vec<models::CrashBucket>> {
+        let mut connection = self.read_connection().await?;
+        sqlite_connector::get_top_crashes(&mut connection, query).await
+    }
+
+    async fn get_error_counts(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<models::ErrorCount>> {
+        let mut connection = self.read_connection().await?;
+        sqlite_connector::get_error_counts(&mut connection, from, to).await
+    }
+
+    async fn find_or_add_user_id(&self, name: &str) -> Result<Option<i64>> {
+        let mut connection = self.connection().await?;
+        sqlite_connector::find_or_add_user_id(&mut connection, &self.id_cache, name).await
+    }
+
+    fn id_cache_stats(&self) -> Option<IdCacheStats> {
+        Some(self.id_cache.stats())
+    }
+
+    fn invalidate_id_cache(&self) {
+        self.id_cache.invalidate();
+    }
+
+    async fn ping(&self) -> Result<()> {
+        let mut connection = self.connection().await?;
+        sqlite_connector::ping(&mut connection).await
+    }
+
+    async fn describe_schema(&self) -> Result<Option<Vec<schema::Column>>> {
+        let mut connection = self.connection().await?;
+        sqlite_connector::describe_schema(&mut connection)
+            .await
+            .map(Some)
+    }
+
+    async fn backfill(&self) {
+        log_filled_project_streams(self.fill_project_streams().await);
+        log_filled_error_signatures(self.fill_error_signatures().await);
+    }
+
+    async fn export_rows(
+        &self,
+        table: DumpTable,
+        after_id: i64,
+        limit: u32,
+    ) -> Result<Vec<DumpRow>> {
+        let mut connection = self.connection().await?;
+        sqlite_connector::export_rows(&mut connection, table, after_id, limit).await
+    }
+
+    async fn import_row(&self, row: &DumpRow) -> Result<i64> {
+        let mut connection = self.connection().await?;
+        let id = sqlite_connector::import_row(&mut connection, &self.id_cache, row).await?;
+        self.latest_cache.record_import(row, id);
+        Ok(id)
+    }
+
+    async fn prune(
+        &self,
+        policy: &RetentionPolicy,
+        cutoff: DateTime<Utc>,
+        limit: u32,
+    ) -> Result<u64> {
+        let mut connection = self.connection().await?;
+        let rows = sqlite_connector::prune(&mut connection, policy, cutoff, limit).await?;
+        self.latest_cache.record_prune(policy.table, rows);
+        Ok(rows)
+    }
+}
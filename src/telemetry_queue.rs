@@ -0,0 +1,428 @@
+//! Bounded in-process queue for timing telemetry, the most frequent post. Posts wait in it until a
+//! worker writes them to the store with multi-row INSERTs, once `batch_size` of them are queued or
+//! every `flush_interval` milliseconds, and whatever is still queued is written at shutdown.
+
+use crate::models;
+use crate::spool::{SharedSpool, SpooledPost};
+use crate::sql::availability::is_unavailable;
+use crate::sql::SharedStore;
+use rocket::fairing::AdHoc;
+use rocket::figment::Figment;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio::sync::{mpsc, oneshot};
+use rocket::tokio::{self, time};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// What happens to a post that arrives while the queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum Overflow {
+    /// The post waits for room in the queue, holding up its request.
+    Block,
+    /// The post is refused with 503 and counted as dropped.
+    Drop,
+}
+
+/// The `telemetry_queue` section of the Rocket config.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct TelemetryQueueConfig {
+    /// Posts the queue holds at most. `0` disables the queue, storing every post as it arrives.
+    #[serde(default = "default_capacity")]
+    pub capacity: usize,
+    /// Posts written per batch at most; a full batch is written right away.
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    /// Milliseconds a post waits at most before its batch is written.
+    #[serde(default = "default_flush_interval")]
+    pub flush_interval: u64,
+    #[serde(default = "default_overflow")]
+    pub overflow: Overflow,
+}
+
+impl Default for TelemetryQueueConfig {
+    fn default() -> Self {
+        TelemetryQueueConfig {
+            capacity: default_capacity(),
+            batch_size: default_batch_size(),
+            flush_interval: default_flush_interval(),
+            overflow: default_overflow(),
+        }
+    }
+}
+
+impl TelemetryQueueConfig {
+    pub fn from_figment(figment: &Figment) -> Result<Self, String> {
+        let config = match figment.extract_inner::<TelemetryQueueConfig>("telemetry_queue") {
+            Ok(config) => config,
+            Err(e) if e.missing() => TelemetryQueueConfig::default(),
+            Err(e) => return Err(e.to_string()),
+        };
+
+        if config.batch_size == 0 {
+            return Err(String::from("`batch_size` must be at least 1."));
+        }
+        if config.flush_interval == 0 {
+            return Err(String::from("`flush_interval` must be at least 1."));
+        }
+        Ok(config)
+    }
+}
+
+/// Counts of what happened to the posts since the server started.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "PascalCase")]
+pub struct TelemetryQueueStats {
+    /// Posts waiting to be written right now, including the batch being written.
+    pub queued: u64,
+    pub capacity: usize,
+    pub enqueued: u64,
+    /// Posts refused because the queue was full.
+    pub dropped: u64,
+    /// Posts written to the store.
+    pub flushed: u64,
+    /// Posts handed to the spool because the database could not be reached.
+    pub spooled: u64,
+    /// Posts lost because the store rejected them, or because the database could not be reached
+    /// and they could not be spooled either.
+    pub failed: u64,
+}
+
+/// Why a post was not queued.
+#[derive(Debug)]
+pub enum EnqueueError {
+    /// The queue is full and `overflow` is `drop`.
+    Full,
+    /// There is no queue, or it has been shut down; the post should be stored directly.
+    Bypassed(models::TelemetryPost),
+}
+
+#[derive(Default)]
+struct Counters {
+    enqueued: AtomicU64,
+    dropped: AtomicU64,
+    flushed: AtomicU64,
+    spooled: AtomicU64,
+    failed: AtomicU64,
+}
+
+pub struct TelemetryQueue {
+    sender: mpsc::Sender<models::TelemetryPost>,
+    overflow: Overflow,
+    capacity: usize,
+    counters: Arc<Counters>,
+    /// Asks the worker to write what is queued and stop, with where to say it is done.
+    shutdown: Mutex<Option<oneshot::Sender<oneshot::Sender<()>>>>,
+}
+
+pub type SharedTelemetryQueue = Arc<TelemetryQueue>;
+
+/// The receiving end of a `TelemetryQueue`, which writes the posts to the store.
+pub struct Worker {
+    receiver: mpsc::Receiver<models::TelemetryPost>,
+    shutdown: oneshot::Receiver<oneshot::Sender<()>>,
+    batch_size: usize,
+    flush_interval: Duration,
+    counters: Arc<Counters>,
+}
+
+impl TelemetryQueue {
+    /// A queue for `config`, which must have a `capacity` above 0, and the worker that empties it.
+    pub fn new(config: &TelemetryQueueConfig) -> (TelemetryQueue, Worker) {
+        let (sender, receiver) = mpsc::channel(config.capacity);
+        let (shutdown_sender, shutdown_receiver) = oneshot::channel();
+        let counters = Arc::new(Counters::default());
+        let queue = TelemetryQueue {
+            sender,
+            overflow: config.overflow,
+            capacity: config.capacity,
+            counters: counters.clone(),
+            shutdown: Mutex::new(Some(shutdown_sender)),
+        };
+        let worker = Worker {
+            receiver,
+            shutdown: shutdown_receiver,
+            batch_size: config.batch_size,
+            flush_interval: Duration::from_millis(config.flush_interval),
+            counters,
+        };
+        (queue, worker)
+    }
+
+    /// Queues `post` to be written with the next batch.
+    pub async fn enqueue(&self, post: models::TelemetryPost) -> Result<(), EnqueueError> {
+        let result = match self.overflow {
+            Overflow::Block => self
+                .sender
+                .send(post)
+                .await
+                .map_err(|mpsc::error::SendError(post)| EnqueueError::Bypassed(post)),
+            Overflow::Drop => self.sender.try_send(post).map_err(|e| match e {
+                mpsc::error::TrySendError::Full(_) => {
+                    if self.counters.dropped.fetch_add(1, Ordering::Relaxed) == 0 {
+                        log::warn!("The telemetry queue is full, dropping posts.");
+                    }
+                    EnqueueError::Full
+                }
+                mpsc::error::TrySendError::Closed(post) => EnqueueError::Bypassed(post),
+            }),
+        };
+        if result.is_ok() {
+            self.counters.enqueued.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    pub fn stats(&self) -> TelemetryQueueStats {
+        let enqueued = self.counters.enqueued.load(Ordering::Relaxed);
+        let flushed = self.counters.flushed.load(Ordering::Relaxed);
+        let spooled = self.counters.spooled.load(Ordering::Relaxed);
+        let failed = self.counters.failed.load(Ordering::Relaxed);
+        TelemetryQueueStats {
+            queued: enqueued.saturating_sub(flushed + spooled + failed),
+            capacity: self.capacity,
+            enqueued,
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+            flushed,
+            spooled,
+            failed,
+        }
+    }
+
+    /// Stops taking posts, and waits for the worker to write the ones still queued. Posts that
+    /// arrive afterwards are bypassed.
+    pub async fn shutdown(&self) {
+        let shutdown = match self.shutdown.lock().unwrap().take() {
+            Some(shutdown) => shutdown,
+            None => return,
+        };
+        let (done_sender, done_receiver) = oneshot::channel();
+        // Fails if the worker never started, in which case there is nothing to wait for.
+        if shutdown.send(done_sender).is_ok() {
+            let _ = done_receiver.await;
+        }
+    }
+}
+
+impl Worker {
+    /// Writes the queued posts to `store` until the queue is shut down. Batches that can't be
+    /// written because the database is unreachable go to `spool`, or are retried every
+    /// `flush_interval` without one, in which case the queue fills up in the meantime.
+    pub async fn run(mut self, store: SharedStore, spool: Option<SharedSpool>) {
+        let mut batch = Vec::with_capacity(self.batch_size);
+        let start = time::Instant::now() + self.flush_interval;
+        let mut interval = time::interval_at(start, self.flush_interval);
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+        let done = loop {
+            tokio::select! {
+                done = &mut self.shutdown => break done.ok(),
+                post = self.receiver.recv(), if batch.len() < self.batch_size => match post {
+                    Some(post) => {
+                        batch.push(post);
+                        if batch.len() >= self.batch_size {
+                            self.flush(&store, spool.as_ref(), &mut batch).await;
+                        }
+                    }
+                    None => break None,
+                },
+                _ = interval.tick() => self.flush(&store, spool.as_ref(), &mut batch).await,
+            }
+        };
+
+        self.receiver.close();
+        while let Some(post) = self.receiver.recv().await {
+            batch.push(post);
+        }
+        if !batch.is_empty() {
+            log::info!(
+                "Writing {} queued telemetry posts before shutting down.",
+                batch.len()
+            );
+            self.flush(&store, spool.as_ref(), &mut batch).await;
+        }
+        if !batch.is_empty() {
+            log::error!(
+                "Lost {} queued telemetry posts: the database is unavailable.",
+                batch.len()
+            );
+            self.counters
+                .failed
+                .fetch_add(batch.len() as u64, Ordering::Relaxed);
+        }
+        if let Some(done) = done {
+            let _ = done.send(());
+        }
+    }
+
+    /// Writes `batch` and empties it, unless the database can't be reached and there is no spool.
+    /// While older posts are spooled, the batch is spooled after them instead.
+    async fn flush(
+        &self,
+        store: &SharedStore,
+        spool: Option<&SharedSpool>,
+        batch: &mut Vec<models::TelemetryPost>,
+    ) {
+        if batch.is_empty() {
+            return;
+        }
+        if let Some(spool) = spool.filter(|spool| spool.depth() > 0) {
+            for post in batch.drain(..) {
+                self.spool(spool, post).await;
+            }
+            return;
+        }
+        match store.post_telemetry_batch(batch).await {
+            Ok(()) => {
+                self.counters
+                    .flushed
+                    .fetch_add(batch.len() as u64, Ordering::Relaxed);
+                batch.clear();
+            }
+            // Without a spool, the store logs the outage and the batch is kept for the next flush.
+            Err(e) if is_unavailable(&e) => {
+                if let Some(spool) = spool {
+                    for post in batch.drain(..) {
+                        self.spool(spool, post).await;
+                    }
+                }
+            }
+            // One bad post fails the whole statement, so the posts are retried one at a time to
+            // only lose that one.
+            Err(e) => {
+                log::warn!(
+                    "Could not write a batch of {} telemetry posts, writing them one at a time: {}",
+                    batch.len(),
+                    e
+                );
+                for post in batch.drain(..) {
+                    match store
+                        .post_telemetry_data(&post.data, &post.version, &post.ip_address)
+                        .await
+                    {
+                        Ok(()) => {
+                            self.counters.flushed.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Err(e) => match spool {
+                            Some(spool) if is_unavailable(&e) => self.spool(spool, post).await,
+                            _ => {
+                                log::error!(
+                                    "Could not write telemetry post {:?}: {}",
+                                    post.data,
+                                    e
+                                );
+                                self.counters.failed.fetch_add(1, Ordering::Relaxed);
+                            }
+                        },
+                    }
+                }
+            }
+        }
+    }
+
+    async fn spool(&self, spool: &SharedSpool, post: models::TelemetryPost) {
+        match spool.append(SpooledPost::Telemetry(post)).await {
+            Ok(true) => {
+                self.counters.spooled.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(false) => {
+                self.counters.failed.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(e) => {
+                log::error!("Could not spool a telemetry post: {}", e);
+                self.counters.failed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Request guard for the telemetry queue, if it is enabled. An
+/// `Option<&State<SharedTelemetryQueue>>` guard would stop Rocket from launching without one.
+pub struct Enqueuer(pub Option<SharedTelemetryQueue>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Enqueuer {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(Enqueuer(
+            request.rocket().state::<SharedTelemetryQueue>().cloned(),
+        ))
+    }
+}
+
+impl Enqueuer {
+    /// `TelemetryQueue::enqueue` with the queue, or `Bypassed` without one.
+    pub async fn enqueue(&self, post: models::TelemetryPost) -> Result<(), EnqueueError> {
+        match &self.0 {
+            Some(queue) => queue.enqueue(post).await,
+            None => Err(EnqueueError::Bypassed(post)),
+        }
+    }
+}
+
+/// Fairing that creates the queue configured in `telemetry_queue`, starts its worker once the
+/// server is up, and writes what is still queued when the server shuts down. Must be attached
+/// after `sql::stage()` and `spool::stage()`.
+pub fn stage() -> AdHoc {
+    AdHoc::try_on_ignite("Telemetry Queue", |rocket| async {
+        let config = match TelemetryQueueConfig::from_figment(rocket.figment()) {
+            Ok(config) => config,
+            Err(e) => {
+                log::error!("Invalid `telemetry_queue` config: {}", e);
+                return Err(rocket);
+            }
+        };
+        if config.capacity == 0 {
+            return Ok(rocket);
+        }
+
+        let (queue, worker) = TelemetryQueue::new(&config);
+        let worker = Mutex::new(Some(worker));
+        Ok(rocket
+            .manage(Arc::new(queue) as SharedTelemetryQueue)
+            .attach(AdHoc::on_liftoff("Telemetry Queue Worker", move |rocket| {
+                let store = rocket.state::<SharedStore>().cloned();
+                let spool = rocket.state::<SharedSpool>().cloned();
+                let worker = worker.lock().unwrap().take();
+                Box::pin(async move {
+                    match (store, worker) {
+                        (Some(store), Some(worker)) => {
+                            tokio::spawn(worker.run(store, spool));
+                        }
+                        (None, _) => {
+                            log::error!("The telemetry queue is disabled: no metadata store.")
+                        }
+                        (_, None) => {}
+                    }
+                })
+            }))
+            .attach(AdHoc::on_shutdown("Telemetry Queue Flush", |rocket| {
+                Box::pin(async move {
+                    if let Some(queue) = rocket.state::<SharedTelemetryQueue>() {
+                        queue.shutdown().await;
+                    }
+                })
+            })))
+    })
+}
+
+// Private Functions:
+
+fn default_capacity() -> usize {
+    10_000
+}
+
+fn default_batch_size() -> usize {
+    500
+}
+
+fn default_flush_interval() -> u64 {
+    1000
+}
+
+fn default_overflow() -> Overflow {
+    Overflow::Drop
+}
@@ -0,0 +1,91 @@
+//! Aggregation of timing telemetry into `TimingStats`. The stores count the samples per group, and
+//! return the durations the percentiles are computed from, so that they come out the same on
+//! every backend.
+
+use crate::models;
+use chrono::{TimeZone, Utc};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// A column timing stats can be grouped by, besides the action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimingGroup {
+    Project,
+    Version,
+}
+
+impl FromStr for TimingGroup {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "project" => Ok(TimingGroup::Project),
+            "version" => Ok(TimingGroup::Version),
+            _ => Err(format!(
+                "Cannot group by `{}`; expected `project` or `version`.",
+                name
+            )),
+        }
+    }
+}
+
+/// Whether a timing sample with `result` succeeded. UGS posts `Success` for syncs and `Succeeded`
+/// for builds.
+pub fn is_success(result: &str) -> bool {
+    result.eq_ignore_ascii_case("Success") || result.eq_ignore_ascii_case("Succeeded")
+}
+
+/// The stats of each group of `counts`, with the percentiles of the `samples` of the group.
+/// Ordered by action, project, version and bucket.
+pub fn aggregate(
+    counts: Vec<models::TimingCount>,
+    samples: Vec<models::TimingSample>,
+) -> Vec<models::TimingStats> {
+    type Key = (String, Option<String>, Option<String>, Option<i64>);
+    let mut groups: BTreeMap<Key, (i64, i64, Vec<f32>)> = BTreeMap::new();
+    for count in counts {
+        let (total, successes, _) = groups
+            .entry((count.action, count.project, count.version, count.start))
+            .or_default();
+        *total += count.count;
+        if is_success(&count.result) {
+            *successes += count.count;
+        }
+    }
+    for sample in samples {
+        // Samples stored after the counts were taken may be of a group without any.
+        let key = (sample.action, sample.project, sample.version, sample.start);
+        if let Some((_, _, durations)) = groups.get_mut(&key) {
+            durations.push(sample.duration);
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(
+            |((action, project, version, start), (count, successes, mut durations))| {
+                durations.sort_by(f32::total_cmp);
+                models::TimingStats {
+                    action,
+                    project,
+                    version,
+                    start: start.and_then(|seconds| Utc.timestamp_opt(seconds, 0).single()),
+                    count,
+                    successes,
+                    success_rate: successes as f64 / count as f64,
+                    p50: percentile(&durations, 50),
+                    p90: percentile(&durations, 90),
+                    p99: percentile(&durations, 99),
+                }
+            },
+        )
+        .collect()
+}
+
+// Private Functions:
+
+/// The nearest-rank `percent` percentile of `sorted`, `None` if it is empty.
+fn percentile(sorted: &[f32], percent: usize) -> Option<f32> {
+    let rank = (sorted.len() * percent).div_ceil(100);
+    sorted.get(rank.max(1) - 1).copied()
+}
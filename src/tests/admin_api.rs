@@ -0,0 +1,103 @@
+use super::{admin_header, client, TempDir, ADMIN_TOKEN};
+use crate::spool;
+use rocket::http::{Header, Status};
+use rocket::local::blocking::Client;
+
+#[test]
+fn cache_endpoints_need_a_caching_store() {
+    // The in-memory store has nothing to cache.
+    let client = client();
+    assert_eq!(
+        client
+            .get("/api/admin/cache")
+            .header(admin_header())
+            .dispatch()
+            .status(),
+        Status::NotFound
+    );
+    assert_eq!(
+        client
+            .post("/api/admin/cache/invalidate")
+            .header(admin_header())
+            .dispatch()
+            .status(),
+        Status::NotFound
+    );
+}
+
+#[test]
+fn cache_endpoints_need_the_token() {
+    let client = client();
+    assert_eq!(
+        client.get("/api/admin/cache").dispatch().status(),
+        Status::Unauthorized
+    );
+    assert_eq!(
+        client
+            .post("/api/admin/cache/invalidate")
+            .header(Header::new("Authorization", "Bearer not-the-token"))
+            .dispatch()
+            .status(),
+        Status::Unauthorized
+    );
+}
+
+/// Client with a spool, so that the spool endpoints exist, and `admin.token` set to `token`.
+fn spool_client(dir: &TempDir, token: Option<&str>) -> Client {
+    let mut figment = rocket::Config::figment()
+        .merge(("databases.ugsdb.backend", "memory"))
+        .merge(("log_level", "off"))
+        .merge(("spool.dir", &dir.0));
+    if let Some(token) = token {
+        figment = figment.merge(("admin.token", token));
+    }
+    let rocket = rocket::custom(figment)
+        .attach(crate::sql::stage())
+        .attach(spool::stage());
+    Client::tracked(crate::mount_apis(rocket)).expect("valid rocket instance")
+}
+
+#[test]
+fn admin_endpoints_need_the_token() {
+    let dir = TempDir::new("admin-token");
+    let client = spool_client(&dir, Some(ADMIN_TOKEN));
+    let status = |authorization: Option<String>| {
+        let mut request = client.get("/api/admin/spool");
+        if let Some(authorization) = authorization {
+            request = request.header(Header::new("Authorization", authorization));
+        }
+        request.dispatch().status()
+    };
+    assert_eq!(status(None), Status::Unauthorized);
+    assert_eq!(
+        status(Some(String::from(ADMIN_TOKEN))),
+        Status::Unauthorized
+    );
+    assert_eq!(
+        status(Some(String::from("Bearer not-the-token"))),
+        Status::Unauthorized
+    );
+    assert_eq!(status(Some(format!("Bearer {}", ADMIN_TOKEN))), Status::Ok);
+    assert_eq!(
+        client.post("/api/admin/spool/drain").dispatch().status(),
+        Status::Unauthorized
+    );
+}
+
+#[test]
+fn admin_endpoints_are_hidden_without_a_token() {
+    let dir = TempDir::new("admin-no-token");
+    let client = spool_client(&dir, None);
+    assert_eq!(
+        client
+            .get("/api/admin/spool")
+            .header(Header::new("Authorization", "Bearer "))
+            .dispatch()
+            .status(),
+        Status::NotFound
+    );
+    assert_eq!(
+        client.post("/api/admin/spool/drain").dispatch().status(),
+        Status::NotFound
+    );
+}
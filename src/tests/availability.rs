@@ -0,0 +1,88 @@
+use super::{assert_database_unavailable, unavailable_client};
+use crate::sql;
+use crate::sql::availability::{is_unavailable, Availability, StartupRetry};
+use rocket::http::Status;
+use rocket::local::blocking::Client;
+use rocket::serde::json::json;
+use rocket_db_pools::sqlx;
+use std::time::Duration;
+
+#[test]
+fn unavailable_database_answers_503_with_retry_after() {
+    let client = unavailable_client();
+    assert_database_unavailable(client.get("/api/latest").dispatch());
+    assert_database_unavailable(
+        client
+            .post("/api/comment")
+            .json(&json!({
+                "ChangeNumber": 100,
+                "UserName": "Alice",
+                "Text": "Looks good",
+                "Project": "//UE5/Main/Engine",
+            }))
+            .dispatch(),
+    );
+}
+
+#[test]
+fn only_connection_errors_mean_unavailable() {
+    assert!(is_unavailable(&sqlx::Error::PoolTimedOut));
+    assert!(is_unavailable(&sqlx::Error::Io(std::io::Error::from(
+        std::io::ErrorKind::ConnectionRefused
+    ))));
+    assert!(!is_unavailable(&sqlx::Error::RowNotFound));
+
+    // Outcomes pass through whether or not they are noted as an outage.
+    let availability = Availability::default();
+    assert!(availability
+        .record::<()>(Err(sqlx::Error::PoolTimedOut))
+        .is_err());
+    assert_eq!(availability.record(Ok(1)).unwrap(), 1);
+}
+
+#[test]
+fn startup_delay_doubles_up_to_max_delay() {
+    let retry = StartupRetry {
+        attempts: 10,
+        initial_delay: 2,
+        max_delay: 30,
+    };
+    let delays: Vec<u64> = (1..=6)
+        .map(|attempt| retry.delay(attempt).as_secs())
+        .collect();
+    assert_eq!(delays, [2, 4, 8, 16, 30, 30]);
+    assert_eq!(retry.delay(u32::MAX), Duration::from_secs(30));
+}
+
+#[rocket::async_test]
+async fn startup_gives_up_after_attempts() {
+    let retry = StartupRetry {
+        attempts: 2,
+        initial_delay: 0,
+        max_delay: 0,
+    };
+    // Nothing listens on port 1, so every attempt is refused.
+    let result = retry
+        .wait_for::<sqlx::PgConnection>("postgres://localhost:1/ugs")
+        .await;
+    assert!(is_unavailable(&result.unwrap_err()));
+}
+
+#[test]
+fn unreachable_database_at_launch_answers_503() {
+    // Nothing listens on port 1, so the database never comes up.
+    let figment = rocket::Config::figment()
+        .merge(("databases.ugsdb.backend", "postgres"))
+        .merge(("databases.ugsdb.url", "postgres://localhost:1/ugs"))
+        .merge(("databases.ugsdb.connect_timeout", 1))
+        .merge(("databases.ugsdb.startup_retry.attempts", 1))
+        .merge(("log_level", "off"));
+    let rocket = crate::mount_apis(rocket::custom(figment).attach(sql::stage()));
+    let client = Client::tracked(rocket).expect("launches without the database");
+
+    assert_database_unavailable(client.get("/api/latest").dispatch());
+    assert_eq!(
+        client.get("/ready").dispatch().status(),
+        Status::ServiceUnavailable
+    );
+}
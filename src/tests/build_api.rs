@@ -0,0 +1,211 @@
+use super::{assert_database_error, client, failing_client, into_json};
+use crate::sql::sql_connector::project_matches;
+use rocket::http::{ContentType, Status};
+use rocket::serde::json::{json, Value};
+
+pub fn build(project: &str, change_number: i32, result: i32) -> Value {
+    json!({
+        "ChangeNumber": change_number,
+        "BuildType": "Editor",
+        "Result": result,
+        "Url": "https://ci/job/1",
+        "Project": project,
+        "ArchivePath": "",
+    })
+}
+
+#[test]
+fn post_then_get_returns_build() {
+    let client = client();
+    let response = client
+        .post("/api/build")
+        .json(&build("//UE5/Main/Engine", 100, 3))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let builds = into_json(
+        client
+            .get("/api/build?project=//UE5/Main/Engine&lastbuildid=0")
+            .dispatch(),
+    );
+    assert_eq!(
+        builds,
+        json!([{
+            "Id": 1,
+            "ChangeNumber": 100,
+            "BuildType": "Editor",
+            "Result": 3,
+            "Url": "https://ci/job/1",
+            "Project": "//UE5/Main/Engine",
+            "ArchivePath": "",
+        }])
+    );
+}
+
+#[test]
+fn get_skips_builds_up_to_last_build_id() {
+    let client = client();
+    for change_number in [100, 101, 102] {
+        client
+            .post("/api/build")
+            .json(&build("//UE5/Main/Engine", change_number, 1))
+            .dispatch();
+    }
+
+    let builds = into_json(
+        client
+            .get("/api/build?project=//UE5/Main/Engine&lastbuildid=2")
+            .dispatch(),
+    );
+    assert_eq!(builds.as_array().unwrap().len(), 1);
+    assert_eq!(builds[0]["ChangeNumber"], 102);
+}
+
+#[test]
+fn get_filters_other_projects() {
+    let client = client();
+    client
+        .post("/api/build")
+        .json(&build("//UE5/Main/Engine", 100, 3))
+        .dispatch();
+    client
+        .post("/api/build")
+        .json(&build("//UE5/Release/Engine", 100, 3))
+        .dispatch();
+    client
+        .post("/api/build")
+        .json(&build("//UE5/Main/Game", 100, 3))
+        .dispatch();
+
+    let builds = into_json(
+        client
+            .get("/api/build?project=//UE5/Main/Engine&lastbuildid=0")
+            .dispatch(),
+    );
+    assert_eq!(builds.as_array().unwrap().len(), 1);
+    assert_eq!(builds[0]["Project"], "//UE5/Main/Engine");
+}
+
+#[test]
+fn get_includes_wildcard_projects_of_the_stream() {
+    let client = client();
+    for project in [
+        "//UE5/Main/...",
+        "//UE5/Main/Engine/...",
+        "//UE5/Main/Game/...",
+        "//UE5/Release/...",
+        "//UE5/Main/Engine",
+        "//UE5/Main/EngineTools",
+    ] {
+        client
+            .post("/api/build")
+            .json(&build(project, 100, 3))
+            .dispatch();
+    }
+
+    let builds = into_json(
+        client
+            .get("/api/build?project=//UE5/Main/Engine&lastbuildid=0")
+            .dispatch(),
+    );
+    let projects: Vec<&str> = builds
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|build| build["Project"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        projects,
+        [
+            "//UE5/Main/...",
+            "//UE5/Main/Engine/...",
+            "//UE5/Main/Engine"
+        ]
+    );
+}
+
+#[test]
+fn project_matches_follows_wildcard_rules() {
+    assert!(project_matches("//UE5/Main/Engine", "//UE5/Main/Engine"));
+    assert!(project_matches("//UE5/Main/...", "//UE5/Main/Engine"));
+    assert!(project_matches(
+        "//UE5/Main/Engine/...",
+        "//UE5/Main/Engine"
+    ));
+    assert!(!project_matches("//UE5/Main/Game/...", "//UE5/Main/Engine"));
+    assert!(!project_matches("//UE5/Release/...", "//UE5/Main/Engine"));
+    assert!(!project_matches(
+        "//UE5/Main/Engine",
+        "//UE5/Main/Engine/..."
+    ));
+    assert!(!project_matches("//UE5/Main/engine", "//UE5/Main/Engine"));
+    // Paths are cut on characters, not bytes.
+    assert!(project_matches(
+        "//UE5/Main/Éditeur/...",
+        "//UE5/Main/Éditeur"
+    ));
+}
+
+#[test]
+fn get_requires_query_parameters() {
+    let client = client();
+    let response = client
+        .get("/api/build?project=//UE5/Main/Engine")
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = client
+        .get("/api/build?project=//UE5/Main/Engine&lastbuildid=abc")
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn post_rejects_invalid_body() {
+    let client = client();
+    let response = client
+        .post("/api/build")
+        .json(&json!({ "ChangeNumber": 100 }))
+        .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    let response = client
+        .post("/api/build")
+        .json(&build("//UE5/Main/Engine", 100, 42))
+        .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    let response = client
+        .post("/api/build")
+        .header(ContentType::JSON)
+        .body("{")
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[test]
+fn post_requires_json_content_type() {
+    let client = client();
+    let response = client
+        .post("/api/build")
+        .header(ContentType::Plain)
+        .body(build("//UE5/Main/Engine", 100, 3).to_string())
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn database_errors_return_internal_server_error() {
+    let client = failing_client();
+    assert_database_error(
+        client
+            .get("/api/build?project=//UE5/Main/Engine&lastbuildid=0")
+            .dispatch(),
+    );
+    assert_database_error(
+        client
+            .post("/api/build")
+            .json(&build("//UE5/Main/Engine", 100, 3))
+            .dispatch(),
+    );
+}
@@ -0,0 +1,113 @@
+use super::{assert_database_error, client, failing_client, into_json};
+use rocket::http::Status;
+use rocket::serde::json::{json, Value};
+
+fn comment(project: &str, change_number: i32, text: &str) -> Value {
+    json!({
+        "ChangeNumber": change_number,
+        "UserName": "Alice",
+        "Text": text,
+        "Project": project,
+    })
+}
+
+#[test]
+fn post_then_get_returns_comment() {
+    let client = client();
+    let response = client
+        .post("/api/comment")
+        .json(&comment("//UE5/Main/Engine", 100, "Looks good"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let comments = into_json(
+        client
+            .get("/api/comment?project=//UE5/Main/Engine&lastcommentid=0")
+            .dispatch(),
+    );
+    assert_eq!(
+        comments,
+        json!([{
+            "Id": 1,
+            "ChangeNumber": 100,
+            "UserName": "Alice",
+            "Text": "Looks good",
+            "Project": "//UE5/Main/Engine",
+        }])
+    );
+}
+
+#[test]
+fn get_skips_comments_up_to_last_comment_id() {
+    let client = client();
+    client
+        .post("/api/comment")
+        .json(&comment("//UE5/Main/Engine", 100, "first"))
+        .dispatch();
+    client
+        .post("/api/comment")
+        .json(&comment("//UE5/Main/Engine", 100, "second"))
+        .dispatch();
+
+    let comments = into_json(
+        client
+            .get("/api/comment?project=//UE5/Main/Engine&lastcommentid=1")
+            .dispatch(),
+    );
+    assert_eq!(comments.as_array().unwrap().len(), 1);
+    assert_eq!(comments[0]["Text"], "second");
+}
+
+#[test]
+fn get_filters_other_projects() {
+    let client = client();
+    client
+        .post("/api/comment")
+        .json(&comment("//UE5/Main/Engine", 100, "engine"))
+        .dispatch();
+    client
+        .post("/api/comment")
+        .json(&comment("//UE5/Main/Game", 100, "game"))
+        .dispatch();
+
+    let comments = into_json(
+        client
+            .get("/api/comment?project=//UE5/Main/Game&lastcommentid=0")
+            .dispatch(),
+    );
+    assert_eq!(comments.as_array().unwrap().len(), 1);
+    assert_eq!(comments[0]["Text"], "game");
+}
+
+#[test]
+fn get_requires_query_parameters() {
+    let client = client();
+    let response = client.get("/api/comment?lastcommentid=0").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn post_rejects_invalid_body() {
+    let client = client();
+    let response = client
+        .post("/api/comment")
+        .json(&json!({ "ChangeNumber": "not a number" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
+
+#[test]
+fn database_errors_return_internal_server_error() {
+    let client = failing_client();
+    assert_database_error(
+        client
+            .get("/api/comment?project=//UE5/Main/Engine&lastcommentid=0")
+            .dispatch(),
+    );
+    assert_database_error(
+        client
+            .post("/api/comment")
+            .json(&comment("//UE5/Main/Engine", 100, "Looks good"))
+            .dispatch(),
+    );
+}
@@ -0,0 +1,59 @@
+use crate::crash::{normalize, signature};
+
+const CRASH: &str = r#"System.IO.IOException: The process cannot access the file 'C:\Users\alice\AppData\Local\UnrealGameSync\Settings.cfg' because it is being used by another process.
+   at System.IO.FileStream.ValidateFileHandle(SafeFileHandle fileHandle)
+   at UnrealGameSync.UserSettings.Save() in D:\Build\++UE5\Sync\Engine\Source\Programs\UnrealGameSync\UnrealGameSync\UserSettings.cs:line 812
+   at UnrealGameSync.MainWindow.OnFormClosing(FormClosingEventArgs e) in D:\Build\++UE5\Sync\Engine\Source\Programs\UnrealGameSync\UnrealGameSync\Forms\MainWindow.cs:line 301"#;
+
+#[test]
+fn normalize_strips_messages_paths_and_line_numbers() {
+    assert_eq!(
+        normalize(CRASH),
+        [
+            "System.IO.IOException",
+            "System.IO.FileStream.ValidateFileHandle(SafeFileHandle fileHandle)",
+            "UnrealGameSync.UserSettings.Save()",
+            "UnrealGameSync.MainWindow.OnFormClosing(FormClosingEventArgs e)",
+        ]
+    );
+}
+
+#[test]
+fn normalize_strips_native_addresses() {
+    let text = "Unhandled exception at 0x00007FF6A1B2C3D4\n\
+                00007FF6A1B2C3D4 UnrealGameSync.exe!FWorkspace::Update() [/home/build/Source/Workspace.cpp:123]\n\
+                UnrealGameSync.exe!WinMain()+0x1a";
+    assert_eq!(
+        normalize(text),
+        [
+            "Unhandled exception at",
+            "UnrealGameSync.exe!FWorkspace::Update()",
+            "UnrealGameSync.exe!WinMain()",
+        ]
+    );
+}
+
+#[test]
+fn same_crash_from_another_build_has_the_same_signature() {
+    let other_build = CRASH
+        .replace("alice", "bob")
+        .replace(r"D:\Build\++UE5\Sync", r"E:\UE5-Release")
+        .replace("line 812", "line 820");
+    assert_eq!(signature(CRASH), signature(&other_build));
+    assert_eq!(signature(CRASH).len(), 16);
+
+    let other_crash = CRASH.replace("UserSettings.Save()", "UserSettings.Load()");
+    assert_ne!(signature(CRASH), signature(&other_crash));
+}
+
+#[test]
+fn only_the_top_of_the_stack_is_signed() {
+    let frames: Vec<String> = (0..20)
+        .map(|index| format!("   at UnrealGameSync.Frame{}()", index))
+        .collect();
+    let deeper = format!(
+        "{}\n   at System.Threading.ThreadHelper.ThreadStart()",
+        frames.join("\n")
+    );
+    assert_eq!(signature(&frames.join("\n")), signature(&deeper));
+}
@@ -0,0 +1,266 @@
+use crate::crash_alerts::{self, Alerter, CrashAlertConfig, CrashSpike, SpikeScope};
+use crate::models;
+use crate::sql::memory_store::MemoryStore;
+use crate::sql::SharedStore;
+use chrono::{DateTime, Duration, Utc};
+use rocket::serde::json::{self, json, Value};
+use rocket::tokio::io::{AsyncReadExt, AsyncWriteExt};
+use rocket::tokio::net::TcpListener;
+use rocket::tokio::{self, sync::mpsc};
+use std::sync::Arc;
+
+fn config(webhooks: Vec<String>) -> CrashAlertConfig {
+    CrashAlertConfig {
+        window: 3600,
+        baseline: 10 * 3600,
+        threshold: 3.0,
+        min_reports: 3,
+        webhooks,
+        ..Default::default()
+    }
+}
+
+fn count(version: &str, project: Option<&str>, count: i64) -> models::ErrorCount {
+    models::ErrorCount {
+        version: String::from(version),
+        project: project.map(String::from),
+        count,
+    }
+}
+
+async fn post_crash(store: &SharedStore, version: &str, timestamp: DateTime<Utc>) {
+    let data = models::TelemetryErrorData {
+        id: 0,
+        error_type: models::TelemetryErrorType::Crash,
+        text: String::from("System.NullReferenceException"),
+        user_name: String::from("Alice"),
+        project: Some(String::from("//UE5/Main/Engine")),
+        timestamp,
+        version: String::new(),
+        ip_address: String::new(),
+    };
+    store
+        .post_error_data(&data, version, "127.0.0.1")
+        .await
+        .unwrap();
+}
+
+async fn webhook_receiver() -> (String, mpsc::UnboundedReceiver<Value>) {
+    webhook_receiver_at("127.0.0.1:0").await
+}
+
+/// Webhook receiver on a local port of `address` that answers 200 and sends on the JSON bodies it
+/// receives.
+async fn webhook_receiver_at(address: &str) -> (String, mpsc::UnboundedReceiver<Value>) {
+    let listener = TcpListener::bind(address).await.unwrap();
+    let url = format!("http://{}/hooks/crashes", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut request = Vec::new();
+            let mut buffer = [0; 4096];
+            let body = loop {
+                let read = stream.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..read]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length: usize = head
+                        .lines()
+                        .find_map(|line| line.strip_prefix("content-length: "))
+                        .unwrap()
+                        .parse()
+                        .unwrap();
+                    if body.len() >= length {
+                        break body.to_string();
+                    }
+                }
+            };
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            sender.send(json::from_str(&body).unwrap()).unwrap();
+        }
+    });
+    (url, receiver)
+}
+
+#[test]
+fn spikes_are_rates_above_the_baseline() {
+    let recent = [
+        count("5.2", Some("//UE5/Main/Engine"), 4),
+        count("5.1", Some("//UE5/Main/Engine"), 3),
+        count("5.1", None, 1),
+    ];
+    // 5.1 and the project crashed about as often before, 5.2 is new.
+    let baseline = [count("5.1", Some("//UE5/Main/Engine"), 40)];
+
+    let spikes = crash_alerts::find_spikes(&recent, &baseline, &config(Vec::new()));
+    assert_eq!(
+        spikes,
+        [CrashSpike {
+            scope: SpikeScope::Version,
+            name: String::from("5.2"),
+            count: 4,
+            baseline_count: 0,
+            rate: 4.0,
+            baseline_rate: 0.0,
+        }]
+    );
+}
+
+#[test]
+fn few_reports_are_not_a_spike() {
+    let recent = [count("5.2", None, 2)];
+    let spikes = crash_alerts::find_spikes(&recent, &[], &config(Vec::new()));
+    assert!(spikes.is_empty());
+}
+
+#[rocket::async_test]
+async fn check_compares_the_window_with_the_baseline_before_it() {
+    let store: SharedStore = Arc::new(MemoryStore::new());
+    let now = Utc::now();
+    for minutes_ago in [5, 10, 20] {
+        post_crash(&store, "5.2", now - Duration::minutes(minutes_ago)).await;
+    }
+    for hours_ago in [2, 3, 4, 5, 6, 7] {
+        post_crash(&store, "5.1", now - Duration::hours(hours_ago)).await;
+    }
+    post_crash(&store, "5.1", now - Duration::minutes(30)).await;
+
+    let spikes = crash_alerts::check(&store, &config(Vec::new()), now)
+        .await
+        .unwrap();
+    let names: Vec<&str> = spikes.iter().map(|spike| spike.name.as_str()).collect();
+    // The project had 6 reports in the 10 hours before, so 4 in the last hour is a spike too.
+    assert_eq!(names, ["5.2", "//UE5/Main/Engine"]);
+}
+
+#[rocket::async_test]
+async fn alerts_are_posted_once_per_cooldown() {
+    let (url, mut received) = webhook_receiver().await;
+    let mut alerter = Alerter::new(config(vec![url]));
+    let spikes = [CrashSpike {
+        scope: SpikeScope::Version,
+        name: String::from("5.2"),
+        count: 12,
+        baseline_count: 0,
+        rate: 12.0,
+        baseline_rate: 0.0,
+    }];
+    let now = Utc::now();
+
+    assert_eq!(alerter.alert(&spikes, now).await, 1);
+    assert_eq!(
+        received.recv().await.unwrap(),
+        json!({
+            "text": "Crash spike in UGS version 5.2: 12 reports, 12.0 an hour against 0.0 an hour before.",
+            "Scope": "Version",
+            "Name": "5.2",
+            "Count": 12,
+            "BaselineCount": 0,
+            "Rate": 12.0,
+            "BaselineRate": 0.0,
+        })
+    );
+
+    let later = now + Duration::hours(1);
+    assert_eq!(alerter.alert(&spikes, later).await, 0);
+    let after_cooldown = now + Duration::hours(6);
+    assert_eq!(alerter.alert(&spikes, after_cooldown).await, 1);
+    assert!(received.recv().await.is_some());
+}
+
+#[rocket::async_test]
+async fn cooled_down_spikes_are_forgotten() {
+    let mut alerter = Alerter::new(config(Vec::new()));
+    let spike = |name: &str| CrashSpike {
+        scope: SpikeScope::Version,
+        name: String::from(name),
+        count: 12,
+        baseline_count: 0,
+        rate: 12.0,
+        baseline_rate: 0.0,
+    };
+    let now = Utc::now();
+    assert_eq!(alerter.alert(&[spike("5.1"), spike("5.2")], now).await, 2);
+    assert_eq!(alerter.last_alerted.len(), 2);
+
+    let after_cooldown = now + Duration::hours(6);
+    assert_eq!(alerter.alert(&[spike("5.3")], after_cooldown).await, 1);
+    let names: Vec<&str> = alerter
+        .last_alerted
+        .keys()
+        .map(|(_, name)| name.as_str())
+        .collect();
+    assert_eq!(names, ["5.3"]);
+}
+
+#[rocket::async_test]
+async fn unreachable_webhooks_do_not_stop_alerts() {
+    let (url, mut received) = webhook_receiver().await;
+    // Nothing listens on port 9 of localhost.
+    let mut alerter = Alerter::new(config(vec![String::from("http://127.0.0.1:9/"), url]));
+    let spike = CrashSpike {
+        scope: SpikeScope::Project,
+        name: String::from("//UE5/Main/Engine"),
+        count: 12,
+        baseline_count: 10,
+        rate: 12.0,
+        baseline_rate: 1.0,
+    };
+    assert_eq!(alerter.alert(&[spike], Utc::now()).await, 1);
+    assert_eq!(received.recv().await.unwrap()["Scope"], "Project");
+}
+
+#[rocket::async_test]
+async fn alerts_reach_ipv6_webhooks() {
+    let (url, mut received) = webhook_receiver_at("[::1]:0").await;
+    assert!(url.starts_with("http://[::1]:"));
+    let mut alerter = Alerter::new(config(vec![url]));
+    let spike = CrashSpike {
+        scope: SpikeScope::Version,
+        name: String::from("5.2"),
+        count: 12,
+        baseline_count: 0,
+        rate: 12.0,
+        baseline_rate: 0.0,
+    };
+    assert_eq!(alerter.alert(&[spike], Utc::now()).await, 1);
+    assert_eq!(received.recv().await.unwrap()["Name"], "5.2");
+}
+
+#[test]
+fn config_defaults_to_no_webhooks() {
+    let figment = rocket::Config::figment();
+    let config = CrashAlertConfig::from_figment(&figment).unwrap();
+    assert_eq!(config, CrashAlertConfig::default());
+    assert!(config.webhooks.is_empty());
+}
+
+#[test]
+fn config_rejects_invalid_values() {
+    for (key, value) in [
+        ("webhooks", json!(["ftp://example.com/hook"])),
+        ("webhooks", json!(["not a url"])),
+        ("threshold", json!(0.5)),
+        ("window", json!(0)),
+    ] {
+        let figment = rocket::Config::figment().merge((format!("crash_alerts.{}", key), value));
+        assert!(CrashAlertConfig::from_figment(&figment).is_err(), "{}", key);
+    }
+}
+
+#[test]
+fn config_accepts_webhooks_to_ip_addresses() {
+    let figment = rocket::Config::figment().merge((
+        "crash_alerts.webhooks",
+        [
+            "http://10.0.0.1/hook",
+            "https://10.0.0.1/hook",
+            "https://[::1]:8443/hook",
+            "https://hooks.example.com/hook",
+        ],
+    ));
+    assert!(CrashAlertConfig::from_figment(&figment).is_ok());
+}
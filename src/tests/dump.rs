@@ -0,0 +1,206 @@
+use super::issues_api::{issue, issue_build};
+use super::TempDir;
+use crate::dump::{self, DumpTable};
+use crate::models;
+use crate::sql::memory_store::MemoryStore;
+use crate::sql::SharedStore;
+use chrono::{TimeZone, Utc};
+use rocket::serde::json;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+fn comment(change_number: i32, text: &str) -> models::CommentData {
+    models::CommentData {
+        id: 0,
+        change_number,
+        user_name: String::from("Bob"),
+        text: String::from(text),
+        project: String::from("//UE5/Main/Engine"),
+    }
+}
+
+async fn add_issue(store: &SharedStore, summary: &str) -> i64 {
+    let data: models::IssueData = json::from_value(issue(summary)).unwrap();
+    store.add_issue(&data).await.unwrap()
+}
+
+async fn add_issue_build(store: &SharedStore, issue_id: i64, outcome: i32) -> i64 {
+    let data: models::IssueBuildData = json::from_value(issue_build(outcome)).unwrap();
+    store.add_build(issue_id, &data).await.unwrap()
+}
+
+async fn populate(store: &SharedStore) {
+    store.post_comment(&comment(100, "First")).await.unwrap();
+    store.post_comment(&comment(101, "Second")).await.unwrap();
+    let event: models::EventData = json::from_value(json::json!({
+        "Change": 100,
+        "UserName": "Bob",
+        "Type": 3,
+        "Project": "//UE5/Main/Engine",
+    }))
+    .unwrap();
+    store.post_event(&event).await.unwrap();
+    let build: models::BuildData = json::from_value(json::json!({
+        "ChangeNumber": 100,
+        "BuildType": "Editor",
+        "Result": 3,
+        "Url": "https://ci/1",
+        "Project": "//UE5/Main/Engine",
+        "ArchivePath": "",
+    }))
+    .unwrap();
+    store.post_build(&build).await.unwrap();
+
+    add_issue(store, "Unrelated").await;
+    let issue_id = add_issue(store, "Broken build").await;
+    add_issue_build(store, issue_id, 1).await;
+    let build_id = add_issue_build(store, issue_id, 2).await;
+    let diagnostic = models::IssueDiagnosticData {
+        build_id: Some(build_id),
+        message: String::from("error C2065"),
+        url: String::from("https://ci/2"),
+    };
+    store.add_diagnostic(issue_id, &diagnostic).await.unwrap();
+    store.add_watcher(issue_id, "Alice").await.unwrap();
+    let update: models::IssueUpdateData = json::from_value(json::json!({
+        "Summary": "",
+        "Owner": "",
+        "NominatedBy": "Carol",
+        "Resolved": true,
+    }))
+    .unwrap();
+    store.update_issue(issue_id, &update).await.unwrap();
+
+    let telemetry = models::TelemetryTimingData {
+        action: String::from("Sync"),
+        result: String::from("Succeeded"),
+        user_name: String::from("Bob"),
+        project: String::from("//UE5/Main/Engine"),
+        timestamp: Utc.timestamp_opt(1_600_000_000, 0).unwrap(),
+        duration: 2.5,
+    };
+    store
+        .post_telemetry_data(&telemetry, "1.2", "10.0.0.1")
+        .await
+        .unwrap();
+    let error = models::TelemetryErrorData {
+        id: 0,
+        error_type: models::TelemetryErrorType::Crash,
+        text: String::from("Access violation"),
+        user_name: String::from("Bob"),
+        project: Some(String::from("//UE5/Main/Engine")),
+        timestamp: Utc.timestamp_opt(1_600_000_000, 0).unwrap(),
+        version: String::new(),
+        ip_address: String::new(),
+    };
+    store
+        .post_error_data(&error, "1.2", "10.0.0.1")
+        .await
+        .unwrap();
+}
+
+#[rocket::async_test]
+async fn export_writes_every_table() {
+    let dir = TempDir::new("export");
+    let store: SharedStore = Arc::new(MemoryStore::new());
+    populate(&store).await;
+
+    let report = dump::export(&store, &dir.0).await.unwrap();
+    let rows = |table| report.iter().find(|(t, _)| *t == table).unwrap().1;
+    assert_eq!(report.len(), DumpTable::ALL.len());
+    assert_eq!(rows(DumpTable::Projects), 1);
+    assert_eq!(rows(DumpTable::Users), 2);
+    assert_eq!(rows(DumpTable::Badges), 1);
+    assert_eq!(rows(DumpTable::Comments), 2);
+    assert_eq!(rows(DumpTable::UserVotes), 1);
+    assert_eq!(rows(DumpTable::Issues), 2);
+    assert_eq!(rows(DumpTable::Errors), 1);
+    assert_eq!(rows(DumpTable::Telemetry), 1);
+    for table in DumpTable::ALL {
+        assert!(dir.0.join(table.file_name()).is_file());
+    }
+}
+
+#[rocket::async_test]
+async fn import_remaps_ids() {
+    let dir = TempDir::new("import");
+    let source: SharedStore = Arc::new(MemoryStore::new());
+    populate(&source).await;
+    dump::export(&source, &dir.0).await.unwrap();
+
+    // Rows already in the target push every imported row to a different id.
+    let target: SharedStore = Arc::new(MemoryStore::new());
+    let existing_issue_id = add_issue(&target, "Already here").await;
+    add_issue_build(&target, existing_issue_id, 1).await;
+    target.post_comment(&comment(1, "Existing")).await.unwrap();
+    dump::import(&target, &dir.0).await.unwrap();
+
+    let comments = target.get_comments("//UE5/Main/Engine", 0).await.unwrap();
+    let texts: Vec<&str> = comments.iter().map(|c| c.text.as_str()).collect();
+    assert_eq!(texts, ["Existing", "First", "Second"]);
+
+    let issue = target.get_issue(3).await.unwrap().unwrap();
+    assert_eq!(issue.summary, "Broken build");
+    assert_eq!(issue.nominated_by, "CAROL");
+    assert!(issue.resolved_at.is_some());
+
+    let builds = target.get_builds_by_issue(3).await.unwrap();
+    let build_ids: Vec<i64> = builds.iter().map(|build| build.id).collect();
+    assert_eq!(build_ids, [2, 3]);
+    let diagnostics = target.get_diagnostics(3).await.unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].build_id, Some(3));
+    assert_eq!(target.get_watchers(3).await.unwrap(), ["ALICE"]);
+
+    let errors = target
+        .get_error_data(&models::ErrorQuery {
+            records: 10,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].text, "Access violation");
+    assert_eq!(errors[0].version, "1.2");
+}
+
+#[rocket::async_test]
+async fn export_of_an_import_matches_the_original() {
+    let first = TempDir::new("roundtrip-first");
+    let second = TempDir::new("roundtrip-second");
+    let source: SharedStore = Arc::new(MemoryStore::new());
+    populate(&source).await;
+    let exported = dump::export(&source, &first.0).await.unwrap();
+
+    let target: SharedStore = Arc::new(MemoryStore::new());
+    let imported = dump::import(&target, &first.0).await.unwrap();
+    let reexported = dump::export(&target, &second.0).await.unwrap();
+    assert_eq!(exported, imported);
+    assert_eq!(exported, reexported);
+
+    for table in [
+        DumpTable::Errors,
+        DumpTable::Telemetry,
+        DumpTable::UserVotes,
+    ] {
+        let read = |dir: &PathBuf| {
+            let file = std::fs::File::open(dir.join(table.file_name())).unwrap();
+            std::io::read_to_string(flate2::read::GzDecoder::new(file)).unwrap()
+        };
+        assert_eq!(read(&first.0), read(&second.0));
+    }
+}
+
+#[rocket::async_test]
+async fn import_reports_malformed_lines() {
+    let dir = TempDir::new("malformed");
+    std::fs::create_dir_all(&dir.0).unwrap();
+    let file = std::fs::File::create(dir.0.join(DumpTable::Comments.file_name())).unwrap();
+    let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+    std::io::Write::write_all(&mut encoder, b"{\"ChangeNumber\": 1}\n").unwrap();
+    encoder.finish().unwrap();
+
+    let store: SharedStore = Arc::new(MemoryStore::new());
+    let error = dump::import(&store, &dir.0).await.err().unwrap();
+    assert!(error.to_string().contains("comments.jsonl.gz:1:"));
+}
@@ -0,0 +1,330 @@
+use super::{assert_database_error, client, failing_client, into_json};
+use rocket::http::Status;
+use rocket::local::blocking::Client;
+use rocket::serde::json::{json, Value};
+
+fn error_data(text: &str) -> Value {
+    json!({
+        "Type": 0,
+        "Text": text,
+        "UserName": "Alice",
+        "Project": "//UE5/Main/Engine",
+        "Timestamp": 1700000000,
+        "Version": "ignored",
+        "IpAddress": "ignored",
+    })
+}
+
+#[test]
+fn post_then_get_returns_newest_errors_first() {
+    let client = client();
+    for text in ["first", "second", "third"] {
+        let response = client
+            .post("/api/error?version=5.1&ipaddress=10.0.0.1")
+            .json(&error_data(text))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    let errors = into_json(client.get("/api/error?records=2").dispatch());
+    assert_eq!(
+        errors,
+        json!([
+            {
+                "Id": 3,
+                "Type": 0,
+                "Text": "third",
+                "UserName": "Alice",
+                "Project": "//UE5/Main/Engine",
+                "Timestamp": 1700000000,
+                "Version": "5.1",
+                "IpAddress": "10.0.0.1",
+            },
+            {
+                "Id": 2,
+                "Type": 0,
+                "Text": "second",
+                "UserName": "Alice",
+                "Project": "//UE5/Main/Engine",
+                "Timestamp": 1700000000,
+                "Version": "5.1",
+                "IpAddress": "10.0.0.1",
+            },
+        ])
+    );
+}
+
+#[test]
+fn get_defaults_to_ten_records() {
+    let client = client();
+    for index in 0..12 {
+        client
+            .post("/api/error?version=5.1&ipaddress=10.0.0.1")
+            .json(&error_data(&index.to_string()))
+            .dispatch();
+    }
+
+    let errors = into_json(client.get("/api/error").dispatch());
+    assert_eq!(errors.as_array().unwrap().len(), 10);
+}
+
+#[test]
+fn get_clamps_records_to_the_maximum() {
+    let client = client();
+    for index in 0..3 {
+        client
+            .post("/api/error?version=5.1&ipaddress=10.0.0.1")
+            .json(&error_data(&index.to_string()))
+            .dispatch();
+    }
+
+    let errors = into_json(client.get("/api/error?records=100000").dispatch());
+    assert_eq!(errors.as_array().unwrap().len(), 3);
+    let buckets = client.get("/api/error/top?records=100000").dispatch();
+    assert_eq!(buckets.status(), Status::Ok);
+}
+
+#[test]
+fn post_accepts_missing_project() {
+    let client = client();
+    let mut data = error_data("no project");
+    data["Project"] = Value::Null;
+    let response = client
+        .post("/api/error?version=5.1&ipaddress=10.0.0.1")
+        .json(&data)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let errors = into_json(client.get("/api/error").dispatch());
+    assert_eq!(errors[0]["Project"], Value::Null);
+}
+
+fn post_errors(client: &Client, reports: &[(&str, &str, &str, i64)]) {
+    for (user_name, project, version, timestamp) in reports {
+        let mut data = error_data(user_name);
+        data["UserName"] = json!(user_name);
+        data["Project"] = json!(project);
+        data["Timestamp"] = json!(timestamp);
+        let response = client
+            .post(format!("/api/error?version={}&ipaddress=10.0.0.1", version))
+            .json(&data)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+}
+
+fn error_ids(client: &Client, query: &str) -> Vec<i64> {
+    let errors = into_json(client.get(format!("/api/error?{}", query)).dispatch());
+    errors
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| error["Id"].as_i64().unwrap())
+        .collect()
+}
+
+#[test]
+fn get_filters_errors() {
+    let client = client();
+    post_errors(
+        &client,
+        &[
+            ("Alice", "//UE5/Main/Engine", "5.1", 1_700_000_000),
+            ("Bob", "//UE5/Main/Game", "5.1", 1_700_000_100),
+            ("alice", "//UE5/Dev/Engine", "5.2", 1_700_000_200),
+            ("Carol", "//UE5/Main/Engine", "5.2", 1_700_000_300),
+        ],
+    );
+
+    assert_eq!(error_ids(&client, "project=//UE5/Main/Engine"), [4, 2, 1]);
+    assert_eq!(error_ids(&client, "user=Alice"), [3, 1]);
+    assert_eq!(error_ids(&client, "version=5.2"), [4, 3]);
+    assert_eq!(error_ids(&client, "errortype=Crash"), [4, 3, 2, 1]);
+    assert_eq!(error_ids(&client, "from=1700000100&to=1700000300"), [3, 2]);
+    assert_eq!(
+        error_ids(&client, "project=//UE5/Main/Engine&version=5.1&user=bob"),
+        [2]
+    );
+}
+
+#[test]
+fn get_filters_errors_by_stream_rather_than_substring() {
+    let client = client();
+    post_errors(
+        &client,
+        &[
+            ("Alice", "//UE5/Main/Engine", "5.1", 1_700_000_000),
+            ("Alice", "//UE5/Main-Old/Engine", "5.1", 1_700_000_100),
+            ("Alice", "Foo", "5.1", 1_700_000_200),
+            ("Alice", "FooBar", "5.1", 1_700_000_300),
+        ],
+    );
+
+    assert_eq!(error_ids(&client, "project=//UE5/Main"), [1]);
+    assert_eq!(error_ids(&client, "project=//UE5/Main-Old/Game"), [2]);
+    assert_eq!(error_ids(&client, "project=Foo"), [3]);
+}
+
+#[test]
+fn get_pages_through_errors() {
+    let client = client();
+    for index in 0..5 {
+        client
+            .post("/api/error?version=5.1&ipaddress=10.0.0.1")
+            .json(&error_data(&index.to_string()))
+            .dispatch();
+    }
+
+    assert_eq!(error_ids(&client, "records=2"), [5, 4]);
+    assert_eq!(error_ids(&client, "records=2&beforeid=4"), [3, 2]);
+    assert_eq!(error_ids(&client, "records=2&beforeid=2"), [1]);
+    assert_eq!(
+        error_ids(&client, "records=2&beforeid=1"),
+        Vec::<i64>::new()
+    );
+}
+
+#[test]
+fn get_rejects_invalid_parameters() {
+    let client = client();
+    for query in ["records=0", "records=-1", "from=99999999999999"] {
+        let response = client.get(format!("/api/error?{}", query)).dispatch();
+        assert_eq!(response.status(), Status::BadRequest, "{}", query);
+    }
+}
+
+fn crash(method: &str, line: i32) -> String {
+    format!(
+        "System.NullReferenceException: Object reference not set to an instance of an object.\n   at UnrealGameSync.WorkspaceControl.{}() in D:\\Build\\WorkspaceControl.cs:line {}",
+        method, line
+    )
+}
+
+fn post_crash(client: &Client, text: &str, user_name: &str, version: &str, timestamp: i64) {
+    let mut data = error_data(text);
+    data["UserName"] = json!(user_name);
+    data["Timestamp"] = json!(timestamp);
+    let response = client
+        .post(format!("/api/error?version={}&ipaddress=10.0.0.1", version))
+        .json(&data)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[test]
+fn get_top_groups_crashes_by_signature() {
+    let client = client();
+    post_crash(
+        &client,
+        &crash("UpdateBuildSteps", 100),
+        "Alice",
+        "5.1",
+        1_700_000_000,
+    );
+    post_crash(&client, &crash("Sync", 200), "Alice", "5.1", 1_700_000_100);
+    post_crash(&client, &crash("Sync", 210), "Alice", "5.1", 1_700_000_200);
+    post_crash(
+        &client,
+        &crash("UpdateBuildSteps", 105),
+        "Bob",
+        "5.2",
+        1_700_000_300,
+    );
+    post_crash(
+        &client,
+        &crash("UpdateBuildSteps", 105),
+        "Carol",
+        "5.2",
+        1_700_000_400,
+    );
+
+    let buckets = into_json(client.get("/api/error/top").dispatch());
+    let buckets = buckets.as_array().unwrap();
+    assert_eq!(buckets.len(), 2);
+    let signature = buckets[0]["Signature"].as_str().unwrap();
+    assert_eq!(
+        buckets[0],
+        json!({
+            "Signature": signature,
+            "Frames": [
+                "System.NullReferenceException",
+                "UnrealGameSync.WorkspaceControl.UpdateBuildSteps()",
+            ],
+            "Count": 3,
+            "UserCount": 3,
+            "FirstSeen": 1_700_000_000,
+            "LastSeen": 1_700_000_400,
+            "LastErrorId": 5,
+            "Versions": ["5.1", "5.2"],
+            "Users": ["Alice", "Bob", "Carol"],
+            "Projects": ["//UE5/Main/Engine"],
+        })
+    );
+    assert_eq!(buckets[1]["Count"], 2);
+    assert_eq!(buckets[1]["UserCount"], 1);
+
+    // A bucket's reports are the ones with its signature.
+    let query = format!("signature={}", signature);
+    assert_eq!(error_ids(&client, &query), [5, 4, 1]);
+}
+
+#[test]
+fn get_top_applies_filters_and_records() {
+    let client = client();
+    post_crash(
+        &client,
+        &crash("UpdateBuildSteps", 100),
+        "Alice",
+        "5.1",
+        1_700_000_000,
+    );
+    post_crash(
+        &client,
+        &crash("UpdateBuildSteps", 100),
+        "Bob",
+        "5.2",
+        1_700_000_100,
+    );
+    post_crash(&client, &crash("Sync", 200), "Carol", "5.2", 1_700_000_200);
+
+    let buckets = into_json(
+        client
+            .get("/api/error/top?version=5.2&from=1700000100")
+            .dispatch(),
+    );
+    assert_eq!(buckets.as_array().unwrap().len(), 2);
+    assert_eq!(buckets[0]["Count"], 1);
+    assert_eq!(buckets[0]["Versions"], json!(["5.2"]));
+
+    let buckets = into_json(client.get("/api/error/top?records=1").dispatch());
+    assert_eq!(buckets.as_array().unwrap().len(), 1);
+    assert_eq!(buckets[0]["UserCount"], 2);
+
+    let response = client.get("/api/error/top?records=0").dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[test]
+fn post_rejects_unknown_error_type() {
+    let client = client();
+    let mut data = error_data("unknown");
+    data["Type"] = json!(7);
+    let response = client
+        .post("/api/error?version=5.1&ipaddress=10.0.0.1")
+        .json(&data)
+        .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
+
+#[test]
+fn database_errors_return_internal_server_error() {
+    let client = failing_client();
+    assert_database_error(client.get("/api/error").dispatch());
+    assert_database_error(client.get("/api/error/top").dispatch());
+    assert_database_error(
+        client
+            .post("/api/error?version=5.1&ipaddress=10.0.0.1")
+            .json(&error_data("crash"))
+            .dispatch(),
+    );
+}
@@ -0,0 +1,142 @@
+use super::{assert_database_error, client, failing_client, into_json};
+use rocket::http::Status;
+use rocket::serde::json::{json, Value};
+
+fn event(project: &str, change: i32, event_type: i32) -> Value {
+    json!({
+        "Change": change,
+        "UserName": "Bob",
+        "Type": event_type,
+        "Project": project,
+    })
+}
+
+#[test]
+fn post_then_get_returns_event() {
+    let client = client();
+    let response = client
+        .post("/api/event")
+        .json(&event("//UE5/Main/Engine", 100, 3))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let events = into_json(
+        client
+            .get("/api/event?project=//UE5/Main/Engine&lasteventid=0")
+            .dispatch(),
+    );
+    assert_eq!(
+        events,
+        json!([{
+            "Id": 1,
+            "Change": 100,
+            "UserName": "Bob",
+            "Type": 3,
+            "Project": "//UE5/Main/Engine",
+        }])
+    );
+}
+
+#[test]
+fn get_skips_events_up_to_last_event_id() {
+    let client = client();
+    for change in [100, 101] {
+        client
+            .post("/api/event")
+            .json(&event("//UE5/Main/Engine", change, 0))
+            .dispatch();
+    }
+
+    let events = into_json(
+        client
+            .get("/api/event?project=//UE5/Main/Engine&lasteventid=1")
+            .dispatch(),
+    );
+    assert_eq!(events.as_array().unwrap().len(), 1);
+    assert_eq!(events[0]["Change"], 101);
+}
+
+#[test]
+fn get_requires_query_parameters() {
+    let client = client();
+    let response = client
+        .get("/api/event?project=//UE5/Main/Engine&lasteventid=")
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn post_rejects_unknown_event_type() {
+    let client = client();
+    let response = client
+        .post("/api/event")
+        .json(&event("//UE5/Main/Engine", 100, 99))
+        .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
+
+#[test]
+fn post_batch_stores_the_events_that_parse() {
+    let client = client();
+    let result = into_json(
+        client
+            .post("/api/event/batch")
+            .json(&json!([
+                event("//UE5/Main/Engine", 100, 3),
+                event("//UE5/Main/Engine", 101, 99),
+                event("//UE5/Main/Engine", 102, 0),
+            ]))
+            .dispatch(),
+    );
+    assert_eq!(result["Stored"], 2);
+    assert_eq!(result["Rejected"], 1);
+    assert_eq!(result["Results"][0], json!({ "Status": 200 }));
+    assert_eq!(result["Results"][1]["Status"], 422);
+    assert!(result["Results"][1]["Error"].is_string());
+    assert_eq!(result["Results"][2], json!({ "Status": 200 }));
+
+    let events = into_json(
+        client
+            .get("/api/event?project=//UE5/Main/Engine&lasteventid=0")
+            .dispatch(),
+    );
+    let changes: Vec<&Value> = events
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|event| &event["Change"])
+        .collect();
+    assert_eq!(changes, [100, 102]);
+}
+
+#[test]
+fn post_batch_accepts_an_empty_batch() {
+    let client = failing_client();
+    let result = into_json(client.post("/api/event/batch").json(&json!([])).dispatch());
+    assert_eq!(
+        result,
+        json!({ "Stored": 0, "Spooled": 0, "Rejected": 0, "Results": [] })
+    );
+}
+
+#[test]
+fn database_errors_return_internal_server_error() {
+    let client = failing_client();
+    assert_database_error(
+        client
+            .get("/api/event?project=//UE5/Main/Engine&lasteventid=0")
+            .dispatch(),
+    );
+    assert_database_error(
+        client
+            .post("/api/event")
+            .json(&event("//UE5/Main/Engine", 100, 3))
+            .dispatch(),
+    );
+    assert_database_error(
+        client
+            .post("/api/event/batch")
+            .json(&json!([event("//UE5/Main/Engine", 100, 3)]))
+            .dispatch(),
+    );
+}
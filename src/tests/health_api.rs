@@ -0,0 +1,59 @@
+use super::{client, failing_client, into_json};
+use crate::sql::memory_store::MemoryStore;
+use crate::sql::{schema, SharedStore};
+use rocket::http::Status;
+use rocket::local::blocking::Client;
+use rocket::serde::json::json;
+use std::sync::Arc;
+
+#[test]
+fn health_does_not_need_the_database() {
+    let client = failing_client();
+    let response = client.get("/health").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().unwrap(), "OK");
+}
+
+#[test]
+fn ready_checks_the_database() {
+    let client = client();
+    assert_eq!(client.get("/ready").dispatch().status(), Status::Ok);
+
+    let client = failing_client();
+    let response = client.get("/ready").dispatch();
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    assert_eq!(response.into_string().unwrap(), "Database is unavailable.");
+}
+
+#[test]
+fn ready_checks_the_schema() {
+    let store = schema::read_only(Arc::new(MemoryStore::new()) as SharedStore);
+    let figment = rocket::Config::figment().merge(("log_level", "off"));
+    let rocket = crate::mount_apis(rocket::custom(figment).manage(store));
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+
+    let response = client.get("/ready").dispatch();
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    assert_eq!(
+        response.into_string().unwrap(),
+        "Database schema is incompatible."
+    );
+}
+
+#[test]
+fn version_reports_the_build_and_backend() {
+    let client = client();
+    let version = into_json(client.get("/version").dispatch());
+    assert_eq!(version["Version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(version["GitHash"], env!("GIT_HASH"));
+    assert_eq!(version["Backend"], "memory");
+    assert_eq!(
+        version["Backends"],
+        json!(["mysql", "sqlite", "postgres", "memory"])
+    );
+
+    // Without `sql::stage()`, there is no configured backend to report.
+    let client = failing_client();
+    let version = into_json(client.get("/version").dispatch());
+    assert_eq!(version["Backend"], json!(null));
+}
@@ -0,0 +1,57 @@
+use crate::sql::id_cache::{IdCache, IdCacheStats};
+
+#[test]
+fn counts_hits_and_misses() {
+    let cache = IdCache::new(10);
+    assert_eq!(cache.project_id("//UE5/Main"), None);
+    cache.insert_project_id("//UE5/Main", 1);
+    cache.insert_user_id("ALICE", 7);
+    assert_eq!(cache.project_id("//UE5/Main"), Some(1));
+    assert_eq!(cache.user_id("ALICE"), Some(7));
+    assert_eq!(cache.user_id("BOB"), None);
+
+    assert_eq!(
+        cache.stats(),
+        IdCacheStats {
+            capacity: 10,
+            projects: 1,
+            users: 1,
+            hits: 2,
+            misses: 2,
+        }
+    );
+}
+
+#[test]
+fn evicts_least_recently_used_entries() {
+    let cache = IdCache::new(2);
+    cache.insert_project_id("//UE5/Main", 1);
+    cache.insert_project_id("//UE5/Release", 2);
+    assert_eq!(cache.project_id("//UE5/Main"), Some(1));
+    cache.insert_project_id("//UE5/Dev", 3);
+
+    assert_eq!(cache.project_id("//UE5/Release"), None);
+    assert_eq!(cache.project_id("//UE5/Main"), Some(1));
+    assert_eq!(cache.project_id("//UE5/Dev"), Some(3));
+    assert_eq!(cache.stats().projects, 2);
+}
+
+#[test]
+fn invalidate_keeps_stats() {
+    let cache = IdCache::new(10);
+    cache.insert_user_id("ALICE", 7);
+    assert_eq!(cache.user_id("ALICE"), Some(7));
+    cache.invalidate();
+
+    assert_eq!(cache.user_id("ALICE"), None);
+    let stats = cache.stats();
+    assert_eq!((stats.users, stats.hits, stats.misses), (0, 1, 1));
+}
+
+#[test]
+fn zero_capacity_disables_caching() {
+    let cache = IdCache::new(0);
+    cache.insert_project_id("//UE5/Main", 1);
+    assert_eq!(cache.project_id("//UE5/Main"), None);
+    assert_eq!(cache.stats().projects, 0);
+}
@@ -0,0 +1,96 @@
+use super::issues_api::{issue, issue_build};
+use super::{assert_database_error, client, failing_client, into_json};
+use rocket::http::Status;
+use rocket::serde::json::json;
+
+#[test]
+fn get_returns_build() {
+    let client = client();
+    into_json(
+        client
+            .post("/api/issues")
+            .json(&issue("Broken build"))
+            .dispatch(),
+    );
+    let build_id = into_json(
+        client
+            .post("/api/issues/1/builds")
+            .json(&issue_build(1))
+            .dispatch(),
+    )["Id"]
+        .clone();
+
+    let build = into_json(
+        client
+            .get(format!("/api/issuebuilds/{build_id}"))
+            .dispatch(),
+    );
+    assert_eq!(build["Id"], build_id);
+    assert_eq!(build["Outcome"], 1);
+}
+
+#[test]
+fn get_unknown_build_returns_not_found() {
+    let client = client();
+    let response = client.get("/api/issuebuilds/42").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(
+        response.into_string().unwrap(),
+        "No issue build with id 42."
+    );
+}
+
+#[test]
+fn put_updates_outcome() {
+    let client = client();
+    into_json(
+        client
+            .post("/api/issues")
+            .json(&issue("Broken build"))
+            .dispatch(),
+    );
+    into_json(
+        client
+            .post("/api/issues/1/builds")
+            .json(&issue_build(1))
+            .dispatch(),
+    );
+
+    let response = client
+        .put("/api/issuebuilds/1")
+        .json(&json!({ "Outcome": 2 }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let build = into_json(client.get("/api/issuebuilds/1").dispatch());
+    assert_eq!(build["Outcome"], 2);
+}
+
+#[test]
+fn put_rejects_invalid_body() {
+    let client = client();
+    let response = client
+        .put("/api/issuebuilds/1")
+        .json(&json!({ "Outcome": "Failed" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
+
+#[test]
+fn non_numeric_id_returns_not_found() {
+    let client = client();
+    let response = client.get("/api/issuebuilds/abc").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn database_errors_return_internal_server_error() {
+    let client = failing_client();
+    assert_database_error(client.get("/api/issuebuilds/1").dispatch());
+    assert_database_error(
+        client
+            .put("/api/issuebuilds/1")
+            .json(&json!({ "Outcome": 2 }))
+            .dispatch(),
+    );
+}
@@ -0,0 +1,450 @@
+use super::{assert_database_error, client, failing_client, into_json};
+use rocket::http::Status;
+use rocket::serde::json::{json, Value};
+
+pub fn issue(summary: &str) -> Value {
+    json!({
+        "CreatedAt": 0,
+        "RetrievedAt": 0,
+        "Project": "//UE5/Main/Engine",
+        "Summary": summary,
+        "Owner": "Alice",
+        "NominatedBy": "",
+        "AcknowledgedAt": null,
+        "FixChange": 0,
+        "ResolvedAt": null,
+        "Notify": false,
+    })
+}
+
+pub fn issue_build(outcome: i32) -> Value {
+    json!({
+        "Stream": "//UE5/Main",
+        "Change": 100,
+        "JobName": "Incremental",
+        "JobUrl": "https://ci/job/1",
+        "JobStepName": "Compile Editor",
+        "JobStepUrl": "https://ci/job/1/step/2",
+        "ErrorUrl": "https://ci/job/1/step/2/log",
+        "Outcome": outcome,
+    })
+}
+
+fn issue_update(fields: Value) -> Value {
+    let mut update = json!({ "Summary": "", "Owner": "", "NominatedBy": "" });
+    update
+        .as_object_mut()
+        .unwrap()
+        .extend(fields.as_object().unwrap().clone());
+    update
+}
+
+fn issue_ids(issues: &Value) -> Vec<i64> {
+    issues
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|issue| issue["Id"].as_i64().unwrap())
+        .collect()
+}
+
+// Issues
+
+#[test]
+fn post_then_get_by_id_returns_issue() {
+    let client = client();
+    let created = into_json(
+        client
+            .post("/api/issues")
+            .json(&issue("Broken build"))
+            .dispatch(),
+    );
+    assert_eq!(created, json!({ "Id": 1 }));
+
+    let issue = into_json(client.get("/api/issues/1").dispatch());
+    assert_eq!(issue["Id"], 1);
+    assert_eq!(issue["Project"], "//UE5/Main/Engine");
+    assert_eq!(issue["Summary"], "Broken build");
+    assert_eq!(issue["Owner"], "ALICE");
+    assert_eq!(issue["NominatedBy"], "");
+    assert_eq!(issue["AcknowledgedAt"], Value::Null);
+    assert_eq!(issue["FixChange"], 0);
+    assert_eq!(issue["ResolvedAt"], Value::Null);
+    assert_eq!(issue["Notify"], false);
+    assert!(issue["CreatedAt"].as_i64().unwrap() > 0);
+}
+
+#[test]
+fn post_truncates_long_summary() {
+    let client = client();
+    into_json(
+        client
+            .post("/api/issues")
+            .json(&issue(&"x".repeat(300)))
+            .dispatch(),
+    );
+
+    let issue = into_json(client.get("/api/issues/1").dispatch());
+    let summary = issue["Summary"].as_str().unwrap();
+    assert_eq!(summary.len(), 200);
+    assert!(summary.ends_with("..."));
+}
+
+#[test]
+fn post_rejects_invalid_body() {
+    let client = client();
+    let response = client
+        .post("/api/issues")
+        .json(&json!({ "Summary": "Broken build" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
+
+#[test]
+fn get_by_id_unknown_issue_returns_not_found() {
+    let client = client();
+    let response = client.get("/api/issues/42").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn get_lists_unresolved_issues_newest_first() {
+    let client = client();
+    for summary in ["first", "second", "third"] {
+        into_json(client.post("/api/issues").json(&issue(summary)).dispatch());
+    }
+    let response = client
+        .put("/api/issues/2")
+        .json(&issue_update(json!({ "Resolved": true })))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let issues = into_json(client.get("/api/issues").dispatch());
+    assert_eq!(issue_ids(&issues), vec![3, 1]);
+
+    let issues = into_json(client.get("/api/issues?includeresolved=true").dispatch());
+    assert_eq!(issue_ids(&issues), vec![3, 2, 1]);
+
+    let issues = into_json(
+        client
+            .get("/api/issues?includeresolved=true&maxresults=2")
+            .dispatch(),
+    );
+    assert_eq!(issue_ids(&issues), vec![3, 2]);
+}
+
+#[test]
+fn get_by_user_flags_watched_issues() {
+    let client = client();
+    into_json(client.post("/api/issues").json(&issue("first")).dispatch());
+    into_json(client.post("/api/issues").json(&issue("second")).dispatch());
+    let response = client
+        .post("/api/issues/1/watchers")
+        .json(&json!({ "UserName": "Bob" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let issues = into_json(client.get("/api/issues?user=bob").dispatch());
+    assert_eq!(issue_ids(&issues), vec![2, 1]);
+    assert_eq!(issues[0]["Notify"], false);
+    assert_eq!(issues[1]["Notify"], true);
+}
+
+#[test]
+fn put_updates_only_given_fields() {
+    let client = client();
+    into_json(
+        client
+            .post("/api/issues")
+            .json(&issue("Broken build"))
+            .dispatch(),
+    );
+
+    let response = client
+        .put("/api/issues/1")
+        .json(&issue_update(json!({
+            "NominatedBy": "Carol",
+            "Acknowledged": true,
+            "FixChange": 123,
+        })))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let issue = into_json(client.get("/api/issues/1").dispatch());
+    assert_eq!(issue["Summary"], "Broken build");
+    assert_eq!(issue["Owner"], "ALICE");
+    assert_eq!(issue["NominatedBy"], "CAROL");
+    assert!(issue["AcknowledgedAt"].is_i64());
+    assert_eq!(issue["FixChange"], 123);
+    assert_eq!(issue["ResolvedAt"], Value::Null);
+
+    let response = client
+        .put("/api/issues/1")
+        .json(&issue_update(json!({
+            "Summary": "Still broken",
+            "Owner": "Dave",
+            "Acknowledged": false,
+        })))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let issue = into_json(client.get("/api/issues/1").dispatch());
+    assert_eq!(issue["Summary"], "Still broken");
+    assert_eq!(issue["Owner"], "DAVE");
+    assert_eq!(issue["NominatedBy"], "CAROL");
+    assert_eq!(issue["AcknowledgedAt"], Value::Null);
+    assert_eq!(issue["FixChange"], 123);
+}
+
+#[test]
+fn put_rejects_invalid_body() {
+    let client = client();
+    into_json(
+        client
+            .post("/api/issues")
+            .json(&issue("Broken build"))
+            .dispatch(),
+    );
+    let response = client
+        .put("/api/issues/1")
+        .json(&json!({ "Resolved": true }))
+        .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
+
+#[test]
+fn delete_removes_issue_builds_and_watchers() {
+    let client = client();
+    into_json(
+        client
+            .post("/api/issues")
+            .json(&issue("Broken build"))
+            .dispatch(),
+    );
+    into_json(
+        client
+            .post("/api/issues/1/builds")
+            .json(&issue_build(1))
+            .dispatch(),
+    );
+    client
+        .post("/api/issues/1/watchers")
+        .json(&json!({ "UserName": "Bob" }))
+        .dispatch();
+
+    let response = client.delete("/api/issues/1").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client.get("/api/issues/1").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(
+        into_json(client.get("/api/issues/1/builds").dispatch()),
+        json!([])
+    );
+    assert_eq!(
+        into_json(client.get("/api/issues/1/watchers").dispatch()),
+        json!([])
+    );
+    let response = client.get("/api/issuebuilds/1").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn non_numeric_id_returns_not_found() {
+    let client = client();
+    assert_eq!(
+        client.get("/api/issues/abc").dispatch().status(),
+        Status::NotFound
+    );
+    assert_eq!(
+        client.delete("/api/issues/abc").dispatch().status(),
+        Status::NotFound
+    );
+}
+
+// Builds
+
+#[test]
+fn post_build_then_get_builds() {
+    let client = client();
+    into_json(
+        client
+            .post("/api/issues")
+            .json(&issue("Broken build"))
+            .dispatch(),
+    );
+    assert_eq!(
+        into_json(client.get("/api/issues/1/builds").dispatch()),
+        json!([])
+    );
+
+    let created = into_json(
+        client
+            .post("/api/issues/1/builds")
+            .json(&issue_build(1))
+            .dispatch(),
+    );
+    assert_eq!(created, json!({ "Id": 1 }));
+
+    let mut expected = issue_build(1);
+    expected["Id"] = json!(1);
+    assert_eq!(
+        into_json(client.get("/api/issues/1/builds").dispatch()),
+        json!([expected])
+    );
+}
+
+#[test]
+fn post_build_rejects_invalid_body() {
+    let client = client();
+    let mut build = issue_build(1);
+    build.as_object_mut().unwrap().remove("Stream");
+    let response = client.post("/api/issues/1/builds").json(&build).dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
+
+// Diagnostics
+
+#[test]
+fn post_diagnostic_then_get_diagnostics() {
+    let client = client();
+    into_json(
+        client
+            .post("/api/issues")
+            .json(&issue("Broken build"))
+            .dispatch(),
+    );
+    assert_eq!(
+        into_json(client.get("/api/issues/1/diagnostics").dispatch()),
+        json!([])
+    );
+
+    let diagnostic = json!({
+        "BuildId": 1,
+        "Message": "error C2065: undeclared identifier",
+        "Url": "https://ci/job/1/step/2/log#L10",
+    });
+    let response = client
+        .post("/api/issues/1/diagnostics")
+        .json(&diagnostic)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let response = client
+        .post("/api/issues/1/diagnostics")
+        .json(&json!({ "BuildId": null, "Message": "warning", "Url": "" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    assert_eq!(
+        into_json(client.get("/api/issues/1/diagnostics").dispatch()),
+        json!([diagnostic, { "BuildId": null, "Message": "warning", "Url": "" }])
+    );
+}
+
+#[test]
+fn post_diagnostic_rejects_invalid_body() {
+    let client = client();
+    let response = client
+        .post("/api/issues/1/diagnostics")
+        .json(&json!({ "BuildId": 1 }))
+        .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
+
+// Watchers
+
+#[test]
+fn post_and_delete_watchers() {
+    let client = client();
+    into_json(
+        client
+            .post("/api/issues")
+            .json(&issue("Broken build"))
+            .dispatch(),
+    );
+    for user_name in ["Bob", "Carol", "bob"] {
+        let response = client
+            .post("/api/issues/1/watchers")
+            .json(&json!({ "UserName": user_name }))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+    assert_eq!(
+        into_json(client.get("/api/issues/1/watchers").dispatch()),
+        json!(["BOB", "CAROL"])
+    );
+
+    let response = client
+        .delete("/api/issues/1/watchers")
+        .json(&json!({ "UserName": "BOB" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        into_json(client.get("/api/issues/1/watchers").dispatch()),
+        json!(["CAROL"])
+    );
+}
+
+#[test]
+fn watchers_reject_invalid_body() {
+    let client = client();
+    let response = client
+        .post("/api/issues/1/watchers")
+        .json(&json!({ "Name": "Bob" }))
+        .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    let response = client.delete("/api/issues/1/watchers").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn database_errors_return_internal_server_error() {
+    let client = failing_client();
+    let watcher = json!({ "UserName": "Bob" });
+    let diagnostic = json!({ "BuildId": null, "Message": "warning", "Url": "" });
+
+    assert_database_error(client.get("/api/issues").dispatch());
+    assert_database_error(client.get("/api/issues?user=bob").dispatch());
+    assert_database_error(client.get("/api/issues/1").dispatch());
+    assert_database_error(
+        client
+            .post("/api/issues")
+            .json(&issue("Broken build"))
+            .dispatch(),
+    );
+    assert_database_error(
+        client
+            .put("/api/issues/1")
+            .json(&issue_update(json!({ "Resolved": true })))
+            .dispatch(),
+    );
+    assert_database_error(client.delete("/api/issues/1").dispatch());
+    assert_database_error(client.get("/api/issues/1/builds").dispatch());
+    assert_database_error(
+        client
+            .post("/api/issues/1/builds")
+            .json(&issue_build(1))
+            .dispatch(),
+    );
+    assert_database_error(client.get("/api/issues/1/diagnostics").dispatch());
+    assert_database_error(
+        client
+            .post("/api/issues/1/diagnostics")
+            .json(&diagnostic)
+            .dispatch(),
+    );
+    assert_database_error(client.get("/api/issues/1/watchers").dispatch());
+    assert_database_error(
+        client
+            .post("/api/issues/1/watchers")
+            .json(&watcher)
+            .dispatch(),
+    );
+    assert_database_error(
+        client
+            .delete("/api/issues/1/watchers")
+            .json(&watcher)
+            .dispatch(),
+    );
+}
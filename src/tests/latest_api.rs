@@ -0,0 +1,79 @@
+use super::{assert_database_error, client, failing_client, into_json};
+use rocket::serde::json::json;
+
+#[test]
+fn get_without_data_returns_zero_ids() {
+    let client = client();
+    let latest = into_json(client.get("/api/latest").dispatch());
+    assert_eq!(
+        latest,
+        json!({ "LastEventId": 0, "LastCommentId": 0, "LastBuildId": 0 })
+    );
+}
+
+#[test]
+fn get_returns_first_ids_of_recent_changes() {
+    let client = client();
+    for change in [100, 101] {
+        client
+            .post("/api/event")
+            .json(&json!({
+                "Change": change,
+                "UserName": "Bob",
+                "Type": 0,
+                "Project": "//UE5/Main/Engine",
+            }))
+            .dispatch();
+        client
+            .post("/api/comment")
+            .json(&json!({
+                "ChangeNumber": change,
+                "UserName": "Bob",
+                "Text": "text",
+                "Project": "//UE5/Main/Engine",
+            }))
+            .dispatch();
+    }
+    client
+        .post("/api/build")
+        .json(&json!({
+            "ChangeNumber": 101,
+            "BuildType": "Editor",
+            "Result": 3,
+            "Url": "",
+            "Project": "//UE5/Main/Engine",
+            "ArchivePath": "",
+        }))
+        .dispatch();
+
+    let latest = into_json(
+        client
+            .get("/api/latest?project=//UE5/Main/Engine")
+            .dispatch(),
+    );
+    assert_eq!(
+        latest,
+        json!({ "LastEventId": 1, "LastCommentId": 1, "LastBuildId": 1 })
+    );
+
+    let latest = into_json(
+        client
+            .get("/api/latest?project=//UE5/Other/Engine")
+            .dispatch(),
+    );
+    assert_eq!(
+        latest,
+        json!({ "LastEventId": 0, "LastCommentId": 0, "LastBuildId": 0 })
+    );
+}
+
+#[test]
+fn database_errors_return_internal_server_error() {
+    let client = failing_client();
+    assert_database_error(client.get("/api/latest").dispatch());
+    assert_database_error(
+        client
+            .get("/api/latest?project=//UE5/Main/Engine")
+            .dispatch(),
+    );
+}
@@ -0,0 +1,105 @@
+use crate::models::LatestData;
+use crate::retention::RetentionTable;
+use crate::sql::latest_cache::{ChangeWindow, LatestCache, RecentChanges, RECENT_CHANGE_COUNT};
+use std::time::Duration;
+
+fn latest(last_event_id: i64, last_comment_id: i64, last_build_id: i64) -> LatestData {
+    LatestData {
+        last_event_id,
+        last_comment_id,
+        last_build_id,
+    }
+}
+
+fn builds(rows: &[(i32, i64)]) -> RecentChanges {
+    RecentChanges {
+        builds: rows.iter().copied().collect(),
+        ..RecentChanges::default()
+    }
+}
+
+#[test]
+fn window_keeps_first_id_of_most_recent_changes() {
+    let mut window = ChangeWindow::default();
+    assert_eq!(window.last_id(), 0);
+
+    for change in 0..RECENT_CHANGE_COUNT as i32 + 10 {
+        window.record(1000 + change, 10 + change as i64);
+    }
+    // Ids of the oldest change in the window are kept at their lowest.
+    window.record(1010, 5);
+    window.record(1010, 50);
+    assert_eq!(window.last_id(), 5);
+    // Changes older than the window are dropped.
+    window.record(900, 1);
+    assert_eq!(window.last_id(), 5);
+
+    window.record(2000, 300);
+    assert_eq!(window.last_id(), 21);
+}
+
+#[test]
+fn window_does_not_depend_on_order() {
+    let rows: Vec<(i32, i64)> = (0..250).map(|id| (id as i32 % 130, id)).collect();
+    let forward: ChangeWindow = rows.iter().copied().collect();
+    let backward: ChangeWindow = rows.iter().rev().copied().collect();
+    assert_eq!(forward, backward);
+    assert_eq!(forward.last_id(), 30);
+}
+
+#[test]
+fn posts_update_loaded_entries() {
+    let cache = LatestCache::default();
+    assert_eq!(cache.get(Some("//UE5/Main/Engine")), None);
+
+    cache.start_loading(Some("//UE5/Main/Engine"));
+    // Posted while the entry is read from the database, and already in what was read.
+    cache.record_build("//UE5/Main/Engine", 101, 2);
+    // Posted while the entry is read from the database, after it was read.
+    cache.record_build("//UE5/Main/Game", 102, 3);
+    let result = cache.finish_loading(Some("//UE5/Main/Engine"), builds(&[(100, 1), (101, 2)]));
+    assert_eq!(result, latest(0, 0, 1));
+
+    cache.record_event("//UE5/Main/Editor", 103, 1);
+    cache.record_comment("//UE5/Release/Engine", 103, 1);
+    assert_eq!(cache.get(Some("//UE5/Main/Game")), Some(latest(1, 0, 1)));
+    // Every project is a separate entry, which has not been loaded.
+    assert_eq!(cache.get(None), None);
+}
+
+#[test]
+fn entries_expire() {
+    let cache = LatestCache::new(Duration::from_millis(20));
+    cache.start_loading(None);
+    cache.finish_loading(None, builds(&[(100, 1)]));
+    assert_eq!(cache.get(None), Some(latest(0, 0, 1)));
+
+    std::thread::sleep(Duration::from_millis(30));
+    assert_eq!(cache.get(None), None);
+    // Reloading starts from what is read again, so rows deleted elsewhere are forgotten.
+    cache.start_loading(None);
+    let result = cache.finish_loading(None, builds(&[(101, 2)]));
+    assert_eq!(result, latest(0, 0, 2));
+}
+
+#[test]
+fn pruning_votes_invalidates() {
+    let cache = LatestCache::default();
+    cache.start_loading(None);
+    cache.finish_loading(None, builds(&[(100, 1)]));
+
+    cache.record_prune(RetentionTable::UserVotes, 0);
+    cache.record_prune(RetentionTable::Telemetry, 10);
+    assert_eq!(cache.get(None), Some(latest(0, 0, 1)));
+    cache.record_prune(RetentionTable::UserVotes, 10);
+    assert_eq!(cache.get(None), None);
+}
+
+#[test]
+fn zero_max_age_disables_caching() {
+    let cache = LatestCache::new(Duration::ZERO);
+    cache.start_loading(None);
+    let result = cache.finish_loading(None, builds(&[(100, 1)]));
+    assert_eq!(result, latest(0, 0, 1));
+    assert_eq!(cache.get(None), None);
+}
@@ -0,0 +1,217 @@
+use super::{admin_header, failing_store, ADMIN_TOKEN};
+use crate::metrics::{LabelValues, OTHER_LABEL};
+use crate::sql::metered_store::metered;
+use crate::sql::{self, SharedStore};
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::blocking::Client;
+use rocket::serde::json::json;
+
+/// Client for the full server with the in-memory backend, which counts requests too.
+fn metered_client() -> Client {
+    let figment = rocket::Config::figment()
+        .merge(("databases.ugsdb.backend", "memory"))
+        .merge(("admin.token", ADMIN_TOKEN))
+        .merge(("log_level", "off"));
+    let rocket = rocket::custom(figment)
+        .attach(sql::stage())
+        .attach(crate::metrics::stage());
+    Client::tracked(crate::mount_apis(rocket)).expect("valid rocket instance")
+}
+
+fn scrape(client: &Client) -> String {
+    let response = client.get("/metrics").header(admin_header()).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.content_type(),
+        Some(ContentType::new("text", "plain").with_params(("version", "0.0.4")))
+    );
+    response.into_string().unwrap()
+}
+
+/// The value of `series`, e.g. `name{label="value"}`, in the scraped `text`. The metrics are
+/// shared by every test in the process, so the tests only look at series of their own.
+fn value(text: &str, series: &str) -> Option<f64> {
+    text.lines()
+        .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+        .map(|value| value.parse().unwrap())
+}
+
+#[test]
+fn requests_are_counted_and_timed_by_route() {
+    let client = metered_client();
+    for change in [1, 2] {
+        let response = client
+            .get(format!(
+                "/api/comment?project=//Metrics/Route&lastcommentid={}",
+                change
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+    client.get("/api/no-such-route/12345").dispatch();
+
+    let text = scrape(&client);
+    assert!(
+        value(
+            &text,
+            r#"ugs_http_requests_total{method="GET",route="/api/comment",status="200"}"#
+        ) >= Some(2.0)
+    );
+    assert!(
+        value(
+            &text,
+            r#"ugs_http_request_duration_seconds_count{method="GET",route="/api/comment"}"#
+        ) >= Some(2.0)
+    );
+    assert!(value(
+        &text,
+        r#"ugs_http_requests_total{method="GET",route="unmatched",status="404"}"#
+    )
+    .is_some());
+    assert!(!text.contains("no-such-route"));
+}
+
+#[test]
+fn stored_posts_are_counted_per_project() {
+    let client = metered_client();
+    for text in ["Looks good", "Still good"] {
+        let response = client
+            .post("/api/comment")
+            .json(&json!({
+                "ChangeNumber": 100,
+                "UserName": "Alice",
+                "Text": text,
+                "Project": "//Metrics/Ingested",
+            }))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    let text = scrape(&client);
+    assert_eq!(
+        value(
+            &text,
+            r#"ugs_ingested_total{kind="comment",project="//Metrics/Ingested"}"#
+        ),
+        Some(2.0)
+    );
+    assert!(value(
+        &text,
+        r#"ugs_db_query_duration_seconds_count{function="post_comment"}"#
+    )
+    .is_some());
+}
+
+#[test]
+fn client_timing_is_exported_apart_from_server_metrics() {
+    let client = metered_client();
+    let timing = |duration: f64| {
+        json!({
+            "Action": "Sync",
+            "Result": "Success",
+            "UserName": "Alice",
+            "Project": "//Metrics/Clients",
+            "Timestamp": 1700000000,
+            "Duration": duration,
+        })
+    };
+    let response = client
+        .post("/api/telemetry?version=5.1&ipaddress=10.0.0.1")
+        .json(&timing(4.0))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let response = client
+        .post("/api/telemetry/batch?version=5.1&ipaddress=10.0.0.1")
+        .json(&json!([timing(45.0), {"Action": "Sync"}]))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .get("/metrics/clients")
+        .header(admin_header())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let text = response.into_string().unwrap();
+    let labels = r#"action="Sync",project="//Metrics/Clients",result="Success",version="5.1""#;
+    let series = |name: &str, le: &str| format!("{}{{{},le=\"{}\"}}", name, labels, le);
+    let bucket = "ugs_client_duration_seconds_bucket";
+    assert_eq!(value(&text, &series(bucket, "5")), Some(1.0));
+    assert_eq!(value(&text, &series(bucket, "60")), Some(2.0));
+    assert_eq!(
+        value(
+            &text,
+            &format!("ugs_client_duration_seconds_sum{{{}}}", labels)
+        ),
+        Some(49.0)
+    );
+    assert!(!text.contains("ugs_http_requests_total"));
+    assert!(!scrape(&client).contains("ugs_client_duration_seconds"));
+}
+
+#[test]
+fn label_values_beyond_the_limit_are_folded_together() {
+    let projects = LabelValues::new(2);
+    assert_eq!(projects.get("//UE5/Main"), "//UE5/Main");
+    assert_eq!(projects.get("//UE5/Dev"), "//UE5/Dev");
+    assert_eq!(projects.get("//UE5/Release"), OTHER_LABEL);
+    assert_eq!(projects.get("//UE5/Main"), "//UE5/Main");
+}
+
+#[test]
+fn store_errors_are_counted_per_function() {
+    let store: SharedStore = metered(failing_store());
+    let figment = rocket::Config::figment()
+        .merge(("admin.token", ADMIN_TOKEN))
+        .merge(("log_level", "off"));
+    let rocket = crate::mount_apis(rocket::custom(figment).manage(store));
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+
+    let response = client.get("/api/issues/1/diagnostics").dispatch();
+    assert_eq!(response.status(), Status::InternalServerError);
+
+    let text = scrape(&client);
+    assert!(
+        value(
+            &text,
+            r#"ugs_db_query_errors_total{function="get_diagnostics"}"#
+        ) >= Some(1.0)
+    );
+    // The in-memory and failing stores have no pool, spool or telemetry queue to report.
+    assert!(!text.contains("ugs_db_pool_connections"));
+    assert!(!text.contains("ugs_spool_depth"));
+}
+
+#[test]
+fn metrics_need_a_token() {
+    let status = |token: Option<&str>, uri: &'static str, authorization: &str| {
+        let mut figment = rocket::Config::figment()
+            .merge(("databases.ugsdb.backend", "memory"))
+            .merge(("admin.token", ADMIN_TOKEN))
+            .merge(("log_level", "off"));
+        if let Some(token) = token {
+            figment = figment.merge(("metrics.token", token));
+        }
+        let rocket = crate::mount_apis(rocket::custom(figment).attach(sql::stage()));
+        let client = Client::tracked(rocket).expect("valid rocket instance");
+        let response = client
+            .get(uri)
+            .header(Header::new("Authorization", authorization.to_string()))
+            .dispatch();
+        response.status()
+    };
+    let admin = format!("Bearer {}", ADMIN_TOKEN);
+    for uri in ["/metrics", "/metrics/clients"] {
+        assert_eq!(status(None, uri, ""), Status::Unauthorized);
+        assert_eq!(status(None, uri, &admin), Status::Ok);
+        // A metrics token stands in for the admin token.
+        assert_eq!(status(Some("scraper"), uri, &admin), Status::Unauthorized);
+        assert_eq!(status(Some("scraper"), uri, "Bearer scraper"), Status::Ok);
+    }
+
+    let figment = rocket::Config::figment()
+        .merge(("databases.ugsdb.backend", "memory"))
+        .merge(("log_level", "off"));
+    let rocket = crate::mount_apis(rocket::custom(figment).attach(sql::stage()));
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+    assert_eq!(client.get("/metrics").dispatch().status(), Status::NotFound);
+}
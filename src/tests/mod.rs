@@ -0,0 +1,308 @@
+mod admin_api;
+mod availability;
+mod build_api;
+mod comment_api;
+mod crash;
+mod crash_alerts;
+mod dump;
+mod error_api;
+mod event_api;
+mod health_api;
+mod id_cache;
+mod issuebuilds_api;
+mod issues_api;
+mod latest_api;
+mod latest_cache;
+mod metrics_api;
+mod queries;
+mod replica;
+mod retention;
+mod schema;
+mod spool;
+mod sqlite;
+mod table_names;
+mod telemetry_api;
+mod telemetry_queue;
+mod telemetry_stats;
+mod user_api;
+mod wire_compatibility;
+
+use crate::dump::{DumpRow, DumpTable};
+use crate::models;
+use crate::retention::RetentionPolicy;
+use crate::sql::{self, MetadataStore, Result, SharedStore};
+use chrono::{DateTime, Utc};
+use rocket::http::{Header, Status};
+use rocket::local::blocking::{Client, LocalResponse};
+use rocket::serde::json::Value;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Fresh directory under the system temp dir, removed again when dropped.
+pub struct TempDir(pub PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("ugs-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        TempDir(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// `admin.token` of the `client()` server.
+pub const ADMIN_TOKEN: &str = "test-admin-token";
+
+/// The header the admin endpoints of the `client()` server expect.
+pub fn admin_header() -> Header<'static> {
+    Header::new("Authorization", format!("Bearer {}", ADMIN_TOKEN))
+}
+
+/// Client for the full server, configured with the in-memory backend.
+pub fn client() -> Client {
+    let figment = rocket::Config::figment()
+        .merge(("databases.ugsdb.backend", "memory"))
+        .merge(("admin.token", ADMIN_TOKEN))
+        .merge(("log_level", "off"));
+    let rocket = crate::mount_apis(rocket::custom(figment).attach(sql::stage()));
+    Client::tracked(rocket).expect("valid rocket instance")
+}
+
+/// Client whose store fails every call, to exercise the database error responses.
+pub fn failing_client() -> Client {
+    client_with(failing_store())
+}
+
+/// Client whose store fails every call as if the database could not be reached.
+pub fn unavailable_client() -> Client {
+    client_with(unavailable_store())
+}
+
+pub fn failing_store() -> SharedStore {
+    Arc::new(FailingStore { unavailable: false })
+}
+
+pub fn unavailable_store() -> SharedStore {
+    Arc::new(FailingStore { unavailable: true })
+}
+
+fn client_with(store: SharedStore) -> Client {
+    let figment = rocket::Config::figment().merge(("log_level", "off"));
+    let rocket = crate::mount_apis(rocket::custom(figment).manage(store));
+    Client::tracked(rocket).expect("valid rocket instance")
+}
+
+pub fn assert_database_error(response: LocalResponse) {
+    assert_eq!(response.status(), Status::InternalServerError);
+    assert_eq!(response.into_string().unwrap(), "Database error occurred.");
+}
+
+pub fn assert_database_unavailable(response: LocalResponse) {
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    assert_eq!(response.headers().get_one("Retry-After"), Some("5"));
+    assert_eq!(response.into_string().unwrap(), "Database is unavailable.");
+}
+
+pub fn into_json(response: LocalResponse) -> Value {
+    assert_eq!(response.status(), Status::Ok);
+    response.into_json::<Value>().expect("JSON response body")
+}
+
+struct FailingStore {
+    unavailable: bool,
+}
+
+impl FailingStore {
+    fn fail<T>(&self) -> Result<T> {
+        Err(if self.unavailable {
+            rocket_db_pools::sqlx::Error::PoolTimedOut
+        } else {
+            rocket_db_pools::sqlx::Error::Protocol(String::from("query failed"))
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl MetadataStore for FailingStore {
+    async fn get_builds(&self, _: &str, _: i64) -> Result<Vec<models::BuildData>> {
+        self.fail()
+    }
+
+    async fn post_build(&self, _: &models::BuildData) -> Result<()> {
+        self.fail()
+    }
+
+    async fn get_comments(&self, _: &str, _: i64) -> Result<Vec<models::CommentData>> {
+        self.fail()
+    }
+
+    async fn post_comment(&self, _: &models::CommentData) -> Result<()> {
+        self.fail()
+    }
+
+    async fn get_user_votes(&self, _: &str, _: i64) -> Result<Vec<models::EventData>> {
+        self.fail()
+    }
+
+    async fn post_event(&self, _: &models::EventData) -> Result<()> {
+        self.fail()
+    }
+
+    async fn post_event_batch(&self, _: &[models::EventData]) -> Result<()> {
+        self.fail()
+    }
+
+    async fn get_last_ids(&self, _: Option<&str>) -> Result<models::LatestData> {
+        self.fail()
+    }
+
+    async fn add_issue(&self, _: &models::IssueData) -> Result<i64> {
+        self.fail()
+    }
+
+    async fn get_issue(&self, _: i64) -> Result<Option<models::IssueData>> {
+        self.fail()
+    }
+
+    async fn get_issues_filtered(&self, _: bool, _: Option<i32>) -> Result<Vec<models::IssueData>> {
+        self.fail()
+    }
+
+    async fn get_issues_by_user_name(&self, _: &str) -> Result<Vec<models::IssueData>> {
+        self.fail()
+    }
+
+    async fn update_issue(&self, _: i64, _: &models::IssueUpdateData) -> Result<()> {
+        self.fail()
+    }
+
+    async fn delete_issue(&self, _: i64) -> Result<()> {
+        self.fail()
+    }
+
+    async fn add_build(&self, _: i64, _: &models::IssueBuildData) -> Result<i64> {
+        self.fail()
+    }
+
+    async fn get_builds_by_issue(&self, _: i64) -> Result<Vec<models::IssueBuildData>> {
+        self.fail()
+    }
+
+    async fn get_build(&self, _: i64) -> Result<Option<models::IssueBuildData>> {
+        self.fail()
+    }
+
+    async fn update_build(&self, _: i64, _: i32) -> Result<()> {
+        self.fail()
+    }
+
+    async fn add_diagnostic(&self, _: i64, _: &models::IssueDiagnosticData) -> Result<()> {
+        self.fail()
+    }
+
+    async fn get_diagnostics(&self, _: i64) -> Result<Vec<models::IssueDiagnosticData>> {
+        self.fail()
+    }
+
+    async fn add_watcher(&self, _: i64, _: &str) -> Result<()> {
+        self.fail()
+    }
+
+    async fn get_watchers(&self, _: i64) -> Result<Vec<String>> {
+        self.fail()
+    }
+
+    async fn remove_watcher(&self, _: i64, _: &str) -> Result<()> {
+        self.fail()
+    }
+
+    async fn post_telemetry_data(
+        &self,
+        _: &models::TelemetryTimingData,
+        _: &str,
+        _: &str,
+    ) -> Result<()> {
+        self.fail()
+    }
+
+    async fn post_telemetry_batch(&self, _: &[models::TelemetryPost]) -> Result<()> {
+        self.fail()
+    }
+
+    async fn get_timing_counts(&self, _: &models::TimingQuery) -> Result<Vec<models::TimingCount>> {
+        self.fail()
+    }
+
+    async fn get_timing_samples(
+        &self,
+        _: &models::TimingQuery,
+        _: i64,
+    ) -> Result<Vec<models::TimingSample>> {
+        self.fail()
+    }
+
+    async fn post_error_data(
+        &self,
+        _: &models::TelemetryErrorData,
+        _: &str,
+        _: &str,
+    ) -> Result<()> {
+        self.fail()
+    }
+
+    async fn get_error_data(
+        &self,
+        _: &models::ErrorQuery,
+    ) -> Result<Vec<models::TelemetryErrorData>> {
+        self.fail()
+    }
+
+    async fn get_top_crashes(&self, _: &models::ErrorQuery) -> Result<Vec<models::CrashBucket>> {
+        self.fail()
+    }
+
+    async fn get_error_counts(
+        &self,
+        _: DateTime<Utc>,
+        _: DateTime<Utc>,
+    ) -> Result<Vec<models::ErrorCount>> {
+        self.fail()
+    }
+
+    async fn find_or_add_user_id(&self, _: &str) -> Result<Option<i64>> {
+        self.fail()
+    }
+
+    async fn prune(&self, _: &RetentionPolicy, _: DateTime<Utc>, _: u32) -> Result<u64> {
+        self.fail()
+    }
+
+    fn id_cache_stats(&self) -> Option<sql::id_cache::IdCacheStats> {
+        None
+    }
+
+    fn invalidate_id_cache(&self) {}
+
+    async fn ping(&self) -> Result<()> {
+        self.fail()
+    }
+
+    async fn describe_schema(&self) -> Result<Option<Vec<sql::schema::Column>>> {
+        self.fail()
+    }
+
+    async fn backfill(&self) {}
+
+    async fn export_rows(&self, _: DumpTable, _: i64, _: u32) -> Result<Vec<DumpRow>> {
+        self.fail()
+    }
+
+    async fn import_row(&self, _: &DumpRow) -> Result<i64> {
+        self.fail()
+    }
+}
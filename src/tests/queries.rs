@@ -0,0 +1,24 @@
+use crate::sql::queries;
+use crate::sql::sql_connector::TableNames;
+use rocket_db_pools::sqlx::{Execute, MySql};
+
+#[test]
+fn mysql_compares_project_names_case_sensitively() {
+    let tables = TableNames::unqualified();
+    let mut user_votes = queries::user_votes::<MySql>(&tables, "//UE5/Main/Engine", 0);
+    assert!(user_votes
+        .build()
+        .sql()
+        .contains("WHERE `Projects`.`Name` = BINARY ?"));
+
+    let mut builds = queries::builds::<MySql>(&tables, "//UE5/Main/Engine", 0);
+    let query = builds.build();
+    for comparison in [
+        "`Projects`.`Name` = BINARY ?",
+        "`Projects`.`Stream` = BINARY ?",
+        "`Projects`.`Name` LIKE BINARY '%...'",
+        "= BINARY LEFT(`Projects`.`Name`",
+    ] {
+        assert!(query.sql().contains(comparison), "{}", query.sql());
+    }
+}
@@ -0,0 +1,83 @@
+use crate::sql::replica::{Replica, ReplicaConfig};
+use rocket_db_pools::sqlx::{self, SqlitePool};
+
+fn config(url: &str, fallback: bool) -> ReplicaConfig {
+    ReplicaConfig {
+        url: String::from(url),
+        // Every connection to `sqlite::memory:` is a separate database.
+        max_connections: Some(1),
+        max_lag: None,
+        fallback,
+        check_interval: 60,
+    }
+}
+
+/// A primary with a `PrimaryOnly` table, which a replica does not have.
+async fn primary() -> SqlitePool {
+    let pool = sqlx::pool::PoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    sqlx::query("CREATE TABLE PrimaryOnly (Id INTEGER)")
+        .execute(&pool)
+        .await
+        .unwrap();
+    pool
+}
+
+async fn is_primary(connection: &mut sqlx::SqliteConnection) -> bool {
+    sqlx::query("SELECT Id FROM PrimaryOnly")
+        .fetch_optional(connection)
+        .await
+        .is_ok()
+}
+
+#[rocket::async_test]
+async fn reads_from_the_replica() {
+    let primary = primary().await;
+    let replica = Replica::connect(config("sqlite::memory:", true)).unwrap();
+
+    let mut connection = replica.acquire(&primary).await.unwrap();
+    assert!(!is_primary(&mut connection).await);
+    assert!(replica.is_usable());
+}
+
+#[rocket::async_test]
+async fn falls_back_to_the_primary_while_unavailable() {
+    let primary = primary().await;
+    let url = "sqlite:///ugs-missing-directory/replica.db?mode=ro";
+    let replica = Replica::connect(config(url, true)).unwrap();
+
+    let mut connection = replica.acquire(&primary).await.unwrap();
+    assert!(is_primary(&mut connection).await);
+    assert!(!replica.is_usable());
+    drop(connection);
+
+    // Until the check interval is over, the replica is not tried again.
+    let mut connection = replica.acquire(&primary).await.unwrap();
+    assert!(is_primary(&mut connection).await);
+}
+
+#[rocket::async_test]
+async fn fails_while_unavailable_without_fallback() {
+    let primary = primary().await;
+    let url = "sqlite:///ugs-missing-directory/replica.db?mode=ro";
+    let replica = Replica::connect(config(url, false)).unwrap();
+
+    assert!(replica.acquire(&primary).await.is_err());
+    assert!(!replica.is_usable());
+}
+
+#[test]
+fn accepts_lag_up_to_max_lag() {
+    let mut config = config("sqlite::memory:", true);
+    assert!(config.accepts_lag(None));
+    assert!(config.accepts_lag(Some(f64::INFINITY)));
+
+    config.max_lag = Some(30.0);
+    assert!(config.accepts_lag(None));
+    assert!(config.accepts_lag(Some(30.0)));
+    assert!(!config.accepts_lag(Some(30.5)));
+    assert!(!config.accepts_lag(Some(f64::INFINITY)));
+}
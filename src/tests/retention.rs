@@ -0,0 +1,223 @@
+use crate::models;
+use crate::retention::{self, RetentionConfig, RetentionPolicy, RetentionTable};
+use crate::sql::memory_store::MemoryStore;
+use crate::sql::SharedStore;
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+
+fn policy(table: RetentionTable, days: u32) -> RetentionPolicy {
+    RetentionPolicy {
+        table,
+        days,
+        project: None,
+        verdict: None,
+    }
+}
+
+fn config(batch_size: u32, policies: Vec<RetentionPolicy>) -> RetentionConfig {
+    RetentionConfig {
+        batch_size,
+        batch_pause: 0,
+        policies,
+        ..RetentionConfig::default()
+    }
+}
+
+async fn post_telemetry(store: &SharedStore, project: &str, days_ago: i64) {
+    let data = models::TelemetryTimingData {
+        action: String::from("Sync"),
+        result: String::from("Succeeded"),
+        user_name: String::from("Bob"),
+        project: String::from(project),
+        timestamp: Utc::now() - Duration::days(days_ago),
+        duration: 1.0,
+    };
+    store
+        .post_telemetry_data(&data, "1.0", "127.0.0.1")
+        .await
+        .unwrap();
+}
+
+async fn post_event(store: &SharedStore, change: i32, event_type: models::EventType) {
+    let event = models::EventData {
+        id: 0,
+        change,
+        user_name: String::from("Bob"),
+        event_type,
+        project: String::from("//UE5/Main/Engine"),
+    };
+    store.post_event(&event).await.unwrap();
+}
+
+#[rocket::async_test]
+async fn prunes_expired_telemetry_in_batches() {
+    let store: SharedStore = Arc::new(MemoryStore::new());
+    for days_ago in [100, 95, 91, 10] {
+        post_telemetry(&store, "//UE5/Main/Engine", days_ago).await;
+    }
+
+    let policies = vec![policy(RetentionTable::Telemetry, 90)];
+    let report = retention::run(&store, &config(2, policies.clone()))
+        .await
+        .unwrap();
+    assert_eq!(report.len(), 1);
+    assert_eq!(report[0].rows, 3);
+
+    let report = retention::run(&store, &config(2, policies)).await.unwrap();
+    assert_eq!(report[0].rows, 0);
+}
+
+#[rocket::async_test]
+async fn project_policies_only_prune_matching_projects() {
+    let store: SharedStore = Arc::new(MemoryStore::new());
+    post_telemetry(&store, "//UE5/Main/Engine", 100).await;
+    post_telemetry(&store, "//UE5/Release/Engine", 100).await;
+
+    let mut main_policy = policy(RetentionTable::Telemetry, 30);
+    main_policy.project = Some(String::from("//UE5/Main/Engine"));
+    let report = retention::run(&store, &config(10, vec![main_policy]))
+        .await
+        .unwrap();
+    assert_eq!(report[0].rows, 1);
+
+    let report = retention::run(
+        &store,
+        &config(10, vec![policy(RetentionTable::Telemetry, 30)]),
+    )
+    .await
+    .unwrap();
+    assert_eq!(report[0].rows, 1);
+}
+
+#[rocket::async_test]
+async fn project_policies_spare_projects_with_overlapping_names() {
+    let store: SharedStore = Arc::new(MemoryStore::new());
+    for project in [
+        "Foo",
+        "FooBar",
+        "Main/Foo-Old",
+        "//UE5/Main/Engine",
+        "//UE5/Main-Old/Engine",
+    ] {
+        post_telemetry(&store, project, 100).await;
+    }
+
+    let prune = |project: &str| {
+        let mut policy = policy(RetentionTable::Telemetry, 30);
+        policy.project = Some(String::from(project));
+        let store = store.clone();
+        async move {
+            retention::run(&store, &config(10, vec![policy]))
+                .await
+                .unwrap()[0]
+                .rows
+        }
+    };
+    assert_eq!(prune("Foo").await, 1);
+    assert_eq!(prune("Foo").await, 0);
+    assert_eq!(prune("//UE5/Main").await, 1);
+
+    let remaining = store
+        .get_timing_samples(&models::TimingQuery::default(), 1)
+        .await
+        .unwrap();
+    assert_eq!(remaining.len(), 3);
+}
+
+#[rocket::async_test]
+async fn verdict_policies_only_prune_matching_events() {
+    let store: SharedStore = Arc::new(MemoryStore::new());
+    post_event(&store, 100, models::EventType::Syncing).await;
+    post_event(&store, 100, models::EventType::Good).await;
+    post_event(&store, 101, models::EventType::Syncing).await;
+
+    let mut syncing_policy = policy(RetentionTable::UserVotes, 30);
+    syncing_policy.verdict = Some(String::from("Syncing"));
+    let pruned = store
+        .prune(&syncing_policy, Utc::now() + Duration::days(1), 10)
+        .await
+        .unwrap();
+    assert_eq!(pruned, 2);
+
+    let events = store.get_user_votes("//UE5/Main/Engine", 0).await.unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].event_type, models::EventType::Good);
+}
+
+#[rocket::async_test]
+async fn pruning_errors_keeps_recent_ones() {
+    let store: SharedStore = Arc::new(MemoryStore::new());
+    for days_ago in [40, 5] {
+        let data = models::TelemetryErrorData {
+            id: 0,
+            error_type: models::TelemetryErrorType::Crash,
+            text: format!("{days_ago} days ago"),
+            user_name: String::from("Bob"),
+            project: None,
+            timestamp: Utc::now() - Duration::days(days_ago),
+            version: String::new(),
+            ip_address: String::new(),
+        };
+        store
+            .post_error_data(&data, "1.0", "127.0.0.1")
+            .await
+            .unwrap();
+    }
+
+    let report = retention::run(
+        &store,
+        &config(10, vec![policy(RetentionTable::Errors, 30)]),
+    )
+    .await
+    .unwrap();
+    assert_eq!(report[0].rows, 1);
+
+    let errors = store
+        .get_error_data(&models::ErrorQuery {
+            records: 10,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].text, "5 days ago");
+}
+
+#[test]
+fn config_defaults_to_no_policies() {
+    let figment = rocket::Config::figment();
+    let config = RetentionConfig::from_figment(&figment).unwrap();
+    assert_eq!(config, RetentionConfig::default());
+    assert!(config.policies.is_empty());
+}
+
+#[test]
+fn config_reads_policies() {
+    let figment = rocket::Config::figment().merge((
+        "retention",
+        rocket::serde::json::json!({
+            "interval": 600,
+            "policies": [
+                { "table": "UserVotes", "days": 30, "verdict": "Syncing" },
+                { "table": "Telemetry_v2", "days": 90, "project": "//UE5/Main" },
+            ],
+        }),
+    ));
+    let config = RetentionConfig::from_figment(&figment).unwrap();
+    assert_eq!(config.interval, 600);
+    assert_eq!(config.policies.len(), 2);
+    assert_eq!(config.policies[1].table, RetentionTable::Telemetry);
+    assert_eq!(
+        config.policies[0].to_string(),
+        "UserVotes older than 30 days with verdict Syncing"
+    );
+}
+
+#[test]
+fn config_rejects_verdict_outside_user_votes() {
+    let figment = rocket::Config::figment().merge((
+        "retention.policies",
+        rocket::serde::json::json!([{ "table": "Errors", "days": 30, "verdict": "Syncing" }]),
+    ));
+    assert!(RetentionConfig::from_figment(&figment).is_err());
+}
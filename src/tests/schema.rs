@@ -0,0 +1,153 @@
+use super::{assert_database_unavailable, unavailable_store};
+use crate::sql::memory_store::MemoryStore;
+use crate::sql::schema::{self, Column, ColumnType, SchemaProblem};
+use crate::sql::SharedStore;
+use rocket::http::Status;
+use rocket::local::blocking::Client;
+use rocket::serde::json::json;
+use rocket::tokio;
+use std::sync::Arc;
+
+/// The columns of a database created by the migrations, with `data_type` naming every type.
+fn migrated_columns(data_type: impl Fn(ColumnType) -> &'static str) -> Vec<Column> {
+    schema::EXPECTED
+        .iter()
+        .flat_map(|(table, columns)| {
+            columns.iter().map(|(name, column_type)| Column {
+                table_name: String::from(*table),
+                column_name: String::from(*name),
+                data_type: String::from(data_type(*column_type)),
+            })
+        })
+        .collect()
+}
+
+fn mysql_type(column_type: ColumnType) -> &'static str {
+    match column_type {
+        ColumnType::Integer => "bigint",
+        ColumnType::Float => "float",
+        ColumnType::Text => "varchar",
+        ColumnType::Timestamp => "datetime",
+    }
+}
+
+#[test]
+fn migrated_schemas_are_compatible() {
+    let postgres_type = |column_type| match column_type {
+        ColumnType::Integer => "integer",
+        ColumnType::Float => "real",
+        ColumnType::Text => "character varying",
+        ColumnType::Timestamp => "timestamp with time zone",
+    };
+    let sqlite_type = |column_type| match column_type {
+        ColumnType::Integer => "INTEGER",
+        ColumnType::Float => "REAL",
+        ColumnType::Text => "TEXT",
+        ColumnType::Timestamp => "DATETIME",
+    };
+    assert_eq!(schema::check(&migrated_columns(mysql_type)), []);
+    assert_eq!(schema::check(&migrated_columns(postgres_type)), []);
+    assert_eq!(schema::check(&migrated_columns(sqlite_type)), []);
+}
+
+#[test]
+fn reports_every_problem() {
+    let mut columns: Vec<Column> = migrated_columns(mysql_type)
+        .into_iter()
+        .filter(|column| column.table_name != "Errors")
+        .filter(|column| column.column_name != "FixChange")
+        .collect();
+    let duration = columns
+        .iter_mut()
+        .find(|column| column.column_name == "Duration")
+        .unwrap();
+    duration.data_type = String::from("varchar");
+    let name = columns
+        .iter_mut()
+        .find(|column| column.table_name == "Users" && column.column_name == "Name")
+        .unwrap();
+    name.column_name = String::from("NAME");
+
+    let problems = schema::check(&columns);
+    assert_eq!(
+        problems,
+        [
+            SchemaProblem::MissingColumn {
+                table: String::from("Issues"),
+                column: String::from("FixChange"),
+            },
+            SchemaProblem::MissingTable(String::from("Errors")),
+            SchemaProblem::WrongType {
+                table: String::from("Telemetry_v2"),
+                column: String::from("Duration"),
+                expected: ColumnType::Float,
+                actual: String::from("varchar"),
+            },
+        ]
+    );
+    let report: Vec<String> = problems.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        report,
+        [
+            "missing column `Issues.FixChange`",
+            "missing table `Errors`",
+            "column `Telemetry_v2.Duration` has type `varchar`, expected a floating-point column",
+        ]
+    );
+}
+
+#[test]
+fn read_only_store_refuses_writes() {
+    let store = schema::read_only(Arc::new(MemoryStore::new()) as SharedStore);
+    let figment = rocket::Config::figment().merge(("log_level", "off"));
+    let rocket = crate::mount_apis(rocket::custom(figment).manage(store));
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+
+    let response = client
+        .post("/api/comment")
+        .json(&json!({
+            "ChangeNumber": 100,
+            "UserName": "Alice",
+            "Text": "Looks good",
+            "Project": "//UE5/Main/Engine",
+        }))
+        .dispatch();
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    assert_eq!(
+        response.into_string().unwrap(),
+        "Database schema is incompatible; writes are disabled."
+    );
+
+    let response = client
+        .get("/api/comment?project=//UE5/Main/Engine&lastcommentid=0")
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    // Adds the user, so it is refused too.
+    let response = client.get("/api/issues?user=bob").dispatch();
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+}
+
+#[test]
+fn checked_store_refuses_writes_until_the_schema_can_be_read() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let store = runtime.block_on(schema::checked(unavailable_store(), None));
+    let figment = rocket::Config::figment().merge(("log_level", "off"));
+    let rocket = crate::mount_apis(rocket::custom(figment).manage(store));
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+
+    // Each write reads the schema again first, which fails with the database still unavailable.
+    let response = client
+        .post("/api/comment")
+        .json(&json!({
+            "ChangeNumber": 100,
+            "UserName": "Alice",
+            "Text": "Looks good",
+            "Project": "//UE5/Main/Engine",
+        }))
+        .dispatch();
+    assert_database_unavailable(response);
+}
@@ -0,0 +1,338 @@
+use super::build_api::build;
+use super::{
+    admin_header, client, failing_store, into_json, unavailable_store, TempDir, ADMIN_TOKEN,
+};
+use crate::models;
+use crate::spool::{self, ReplayReport, Spool, SpoolConfig, SpooledPost, SubmitError, Submitted};
+use crate::sql::memory_store::MemoryStore;
+use crate::sql::SharedStore;
+use rocket::http::Status;
+use rocket::local::blocking::Client;
+use rocket::serde::json::{self, json};
+use std::sync::Arc;
+
+fn config(dir: &TempDir) -> SpoolConfig {
+    SpoolConfig {
+        dir: dir.0.clone(),
+        replay_interval: 60,
+        max_entries: 10,
+    }
+}
+
+fn build_post(change_number: i32) -> SpooledPost {
+    SpooledPost::Build(json::from_value(build("//UE5/Main/Engine", change_number, 3)).unwrap())
+}
+
+async fn build_changes(store: &SharedStore) -> Vec<i32> {
+    let builds = store.get_builds("//UE5/Main/Engine", 0).await.unwrap();
+    builds.iter().map(|build| build.change_number).collect()
+}
+
+#[rocket::async_test]
+async fn replays_spooled_posts_in_order_once_available() {
+    let dir = TempDir::new("spool-replay");
+    let spool = Spool::open(config(&dir)).unwrap();
+    let unavailable = unavailable_store();
+    for change_number in [102, 100, 101] {
+        let submitted = spool.submit(&unavailable, build_post(change_number)).await;
+        assert_eq!(submitted.unwrap(), Submitted::Spooled);
+    }
+    assert_eq!(spool.depth(), 3);
+
+    let report = spool.replay(&unavailable).await;
+    assert_eq!(report.replayed, 0);
+    assert_eq!(report.remaining, 3);
+
+    let store: SharedStore = Arc::new(MemoryStore::new());
+    let report = spool.replay(&store).await;
+    assert_eq!(
+        report,
+        ReplayReport {
+            replayed: 3,
+            rejected: 0,
+            remaining: 0,
+        }
+    );
+    assert_eq!(build_changes(&store).await, [102, 100, 101]);
+}
+
+#[rocket::async_test]
+async fn posts_wait_behind_spooled_ones() {
+    let dir = TempDir::new("spool-order");
+    let spool = Spool::open(config(&dir)).unwrap();
+    spool
+        .submit(&unavailable_store(), build_post(100))
+        .await
+        .unwrap();
+
+    // The database is back, but the older post has not been replayed yet.
+    let store: SharedStore = Arc::new(MemoryStore::new());
+    let submitted = spool.submit(&store, build_post(101)).await.unwrap();
+    assert_eq!(submitted, Submitted::Spooled);
+    assert!(build_changes(&store).await.is_empty());
+
+    spool.replay(&store).await;
+    assert_eq!(build_changes(&store).await, [100, 101]);
+    let submitted = spool.submit(&store, build_post(102)).await.unwrap();
+    assert_eq!(submitted, Submitted::Stored);
+}
+
+#[rocket::async_test]
+async fn spooled_posts_survive_a_restart() {
+    let dir = TempDir::new("spool-restart");
+    let spool = Spool::open(config(&dir)).unwrap();
+    for change_number in [100, 101] {
+        spool
+            .submit(&unavailable_store(), build_post(change_number))
+            .await
+            .unwrap();
+    }
+    drop(spool);
+
+    let spool = Spool::open(config(&dir)).unwrap();
+    assert_eq!(spool.depth(), 2);
+    spool
+        .submit(&unavailable_store(), build_post(102))
+        .await
+        .unwrap();
+    let ids: Vec<u64> = spool
+        .entries(10)
+        .await
+        .unwrap()
+        .iter()
+        .map(|entry| entry.id)
+        .collect();
+    assert_eq!(ids, [1, 2, 3]);
+}
+
+#[rocket::async_test]
+async fn rejected_posts_are_moved_aside() {
+    let dir = TempDir::new("spool-rejected");
+    let spool = Spool::open(config(&dir)).unwrap();
+    spool
+        .submit(&unavailable_store(), build_post(100))
+        .await
+        .unwrap();
+
+    let report = spool.replay(&failing_store()).await;
+    assert_eq!(report.rejected, 1);
+    assert_eq!(spool.depth(), 0);
+    assert!(dir.0.join("rejected/00000000000000000001.json").exists());
+}
+
+#[rocket::async_test]
+async fn rejected_posts_survive_later_rejections_after_a_restart() {
+    let dir = TempDir::new("spool-rejected-restart");
+    let spool = Spool::open(config(&dir)).unwrap();
+    spool
+        .submit(&unavailable_store(), build_post(100))
+        .await
+        .unwrap();
+    assert_eq!(spool.replay(&failing_store()).await.rejected, 1);
+    drop(spool);
+
+    // Nothing is pending any more, but ids carry on after the rejected post.
+    let spool = Spool::open(config(&dir)).unwrap();
+    spool
+        .submit(&unavailable_store(), build_post(101))
+        .await
+        .unwrap();
+    assert_eq!(spool.replay(&failing_store()).await.rejected, 1);
+
+    let rejected = |name: &str| {
+        let contents = std::fs::read(dir.0.join("rejected").join(name)).unwrap();
+        let entry: spool::SpoolEntry = json::from_slice(&contents).unwrap();
+        match entry.post {
+            SpooledPost::Build(build) => build.change_number,
+            _ => panic!("{} is not a build", name),
+        }
+    };
+    assert_eq!(rejected("00000000000000000001.json"), 100);
+    assert_eq!(rejected("00000000000000000002.json"), 101);
+}
+
+#[rocket::async_test]
+async fn rejected_posts_never_replace_others() {
+    let dir = TempDir::new("spool-rejected-exists");
+    let spool = Spool::open(config(&dir)).unwrap();
+    spool
+        .submit(&unavailable_store(), build_post(100))
+        .await
+        .unwrap();
+    let existing = dir.0.join("rejected/00000000000000000001.json");
+    std::fs::write(&existing, "rejected before").unwrap();
+
+    let report = spool.replay(&failing_store()).await;
+    assert_eq!(report.rejected, 0);
+    assert_eq!(spool.depth(), 1);
+    assert_eq!(
+        std::fs::read_to_string(&existing).unwrap(),
+        "rejected before"
+    );
+}
+
+#[rocket::async_test]
+async fn batches_are_spooled_and_replayed_whole() {
+    let dir = TempDir::new("spool-batch");
+    let spool = Spool::open(config(&dir)).unwrap();
+    let events: Vec<models::EventData> = json::from_value(json!([
+        { "Change": 100, "UserName": "Alice", "Type": 0, "Project": "//UE5/Main/Engine" },
+        { "Change": 101, "UserName": "Bob", "Type": 3, "Project": "//UE5/Main/Engine" },
+    ]))
+    .unwrap();
+    let submitted = spool
+        .submit(&unavailable_store(), SpooledPost::EventBatch(events))
+        .await;
+    assert_eq!(submitted.unwrap(), Submitted::Spooled);
+    assert_eq!(spool.depth(), 1);
+
+    let store: SharedStore = Arc::new(MemoryStore::new());
+    assert_eq!(spool.replay(&store).await.replayed, 1);
+    let events = store.get_user_votes("//UE5/Main/Engine", 0).await.unwrap();
+    assert_eq!(events.len(), 2);
+}
+
+#[rocket::async_test]
+async fn full_spool_refuses_posts() {
+    let dir = TempDir::new("spool-full");
+    let spool = Spool::open(SpoolConfig {
+        max_entries: 1,
+        ..config(&dir)
+    })
+    .unwrap();
+    let unavailable = unavailable_store();
+    spool.submit(&unavailable, build_post(100)).await.unwrap();
+    assert!(matches!(
+        spool.submit(&unavailable, build_post(101)).await,
+        Err(SubmitError::SpoolFull)
+    ));
+    assert_eq!(spool.depth(), 1);
+}
+
+#[test]
+fn posts_answer_503_when_the_spool_is_full() {
+    let dir = TempDir::new("spool-api-full");
+    let figment = rocket::Config::figment()
+        .merge(("log_level", "off"))
+        .merge(("spool.dir", &dir.0))
+        .merge(("spool.max_entries", 1));
+    let rocket = rocket::custom(figment)
+        .manage(unavailable_store())
+        .attach(spool::stage());
+    let client = Client::tracked(crate::mount_apis(rocket)).expect("valid rocket instance");
+
+    let response = client
+        .post("/api/build")
+        .json(&build("//UE5/Main/Engine", 100, 3))
+        .dispatch();
+    assert_eq!(response.status(), Status::Accepted);
+    let response = client
+        .post("/api/build")
+        .json(&build("//UE5/Main/Engine", 101, 3))
+        .dispatch();
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    assert!(response.headers().get_one("Retry-After").is_some());
+    assert_eq!(
+        response.into_string().unwrap(),
+        "Database is unavailable and the spool is full."
+    );
+}
+
+#[test]
+fn posts_answer_202_while_spooled() {
+    let dir = TempDir::new("spool-api");
+    let figment = rocket::Config::figment()
+        .merge(("log_level", "off"))
+        .merge(("spool.dir", &dir.0))
+        .merge(("admin.token", ADMIN_TOKEN));
+    let rocket = rocket::custom(figment)
+        .manage(unavailable_store())
+        .attach(spool::stage());
+    let client = Client::tracked(crate::mount_apis(rocket)).expect("valid rocket instance");
+
+    let response = client
+        .post("/api/build")
+        .json(&build("//UE5/Main/Engine", 100, 3))
+        .dispatch();
+    assert_eq!(response.status(), Status::Accepted);
+    let response = client
+        .post("/api/telemetry?version=5.1&ipaddress=127.0.0.1")
+        .json(&json!({
+            "Action": "Sync",
+            "Result": "Succeeded",
+            "UserName": "Alice",
+            "Project": "//UE5/Main/Engine",
+            "Timestamp": 1_700_000_000,
+            "Duration": 1.5,
+        }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Accepted);
+
+    let spool = into_json(
+        client
+            .get("/api/admin/spool")
+            .header(admin_header())
+            .dispatch(),
+    );
+    assert_eq!(spool["Depth"], 2);
+    assert_eq!(spool["Entries"][0]["Kind"], "Build");
+    assert_eq!(spool["Entries"][0]["Data"]["ChangeNumber"], 100);
+    assert_eq!(spool["Entries"][1]["Kind"], "Telemetry");
+    assert_eq!(spool["Entries"][1]["Data"]["Version"], "5.1");
+    assert_eq!(spool["Entries"][1]["Data"].get("IpAddress"), None);
+
+    let report = into_json(
+        client
+            .post("/api/admin/spool/drain")
+            .header(admin_header())
+            .dispatch(),
+    );
+    assert_eq!(
+        report,
+        json!({ "Replayed": 0, "Rejected": 0, "Remaining": 2 })
+    );
+}
+
+#[test]
+fn spool_endpoints_need_a_spool() {
+    let client = client();
+    assert_eq!(
+        client
+            .get("/api/admin/spool")
+            .header(admin_header())
+            .dispatch()
+            .status(),
+        Status::NotFound
+    );
+    assert_eq!(
+        client
+            .post("/api/admin/spool/drain")
+            .header(admin_header())
+            .dispatch()
+            .status(),
+        Status::NotFound
+    );
+    // Without a spool, posts are stored right away.
+    let response = client
+        .post("/api/build")
+        .json(&build("//UE5/Main/Engine", 100, 3))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[test]
+fn spooled_posts_keep_their_fields() {
+    let post: models::EventData = json::from_value(json!({
+        "Change": 100,
+        "UserName": "Alice",
+        "Type": 0,
+        "Project": "//UE5/Main/Engine",
+    }))
+    .unwrap();
+    let text = json::to_string(&SpooledPost::Event(post)).unwrap();
+    let value: json::Value = json::from_str(&text).unwrap();
+    assert_eq!(value["Kind"], "Event");
+    assert_eq!(value["Data"]["Type"], 0);
+    let post: SpooledPost = json::from_str(&text).unwrap();
+    assert!(matches!(post, SpooledPost::Event(event) if event.user_name == "Alice"));
+}
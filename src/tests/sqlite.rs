@@ -0,0 +1,502 @@
+use super::build_api::build;
+use super::issues_api::{issue, issue_build};
+use super::{into_json, TempDir};
+use crate::models;
+use crate::sql::{self, sqlite_connector};
+use rocket::error::ErrorKind;
+use rocket::http::Status;
+use rocket::local::blocking::Client;
+use rocket::serde::json::{json, Value};
+use rocket::tokio;
+use rocket_db_pools::sqlx::{self, SqlitePool};
+
+/// Client for the full server on a SQLite database file in `dir`, migrated on ignition.
+fn sqlite_client(dir: &TempDir) -> Client {
+    std::fs::create_dir_all(&dir.0).unwrap();
+    let url = format!("sqlite://{}?mode=rwc", dir.0.join("ugs.db").display());
+    let figment = rocket::Config::figment()
+        .merge(("databases.ugsdb.backend", "sqlite"))
+        .merge(("databases.ugsdb.url", url))
+        .merge(("databases.ugsdb.auto_migrate", true))
+        .merge(("log_level", "off"));
+    let rocket = crate::mount_apis(rocket::custom(figment).attach(sql::stage()));
+    Client::tracked(rocket).expect("valid rocket instance")
+}
+
+/// Runs `statement` on the database file of `dir` beside the server, returning the first column of
+/// its first row if it has one.
+fn execute(dir: &TempDir, statement: &str) -> Option<i64> {
+    let url = format!("sqlite://{}", dir.0.join("ugs.db").display());
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let pool = SqlitePool::connect(&url).await.unwrap();
+        let row = sqlx::query_scalar::<_, i64>(statement)
+            .fetch_optional(&pool)
+            .await
+            .unwrap();
+        pool.close().await;
+        row
+    })
+}
+
+fn assert_posted(client: &Client, uri: &str, data: &Value) {
+    let response = client.post(uri.to_string()).json(data).dispatch();
+    assert_eq!(response.status(), Status::Ok, "POST {}", uri);
+}
+
+#[test]
+fn migrations_create_a_ready_schema() {
+    let dir = TempDir::new("sqlite-migrations");
+    let client = sqlite_client(&dir);
+    assert_eq!(client.get("/ready").dispatch().status(), Status::Ok);
+    drop(client);
+
+    // Migrating an up to date database again is a no-op, and keeps its rows.
+    let client = sqlite_client(&dir);
+    assert_eq!(client.get("/ready").dispatch().status(), Status::Ok);
+    assert_posted(&client, "/api/build", &build("//UE5/Main/Engine", 100, 3));
+    drop(client);
+
+    let client = sqlite_client(&dir);
+    let builds = into_json(
+        client
+            .get("/api/build?project=//UE5/Main/Engine&lastbuildid=0")
+            .dispatch(),
+    );
+    assert_eq!(builds.as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn incompatible_schemas_are_not_backfilled() {
+    let dir = TempDir::new("sqlite-incompatible");
+    drop(sqlite_client(&dir));
+    execute(
+        &dir,
+        "INSERT INTO Projects (Name) VALUES ('//UE5/Main/Engine')",
+    );
+    execute(&dir, "DROP TABLE Errors");
+
+    let client = sqlite_client(&dir);
+    assert_eq!(
+        client.get("/ready").dispatch().status(),
+        Status::ServiceUnavailable
+    );
+    assert_eq!(
+        execute(&dir, "SELECT COUNT(*) FROM Projects WHERE Stream IS NULL"),
+        Some(1)
+    );
+}
+
+#[test]
+fn invalid_auto_migrate_fails_ignition() {
+    let dir = TempDir::new("sqlite-auto-migrate");
+    std::fs::create_dir_all(&dir.0).unwrap();
+    let url = format!("sqlite://{}?mode=rwc", dir.0.join("ugs.db").display());
+    let figment = rocket::Config::figment()
+        .merge(("databases.ugsdb.backend", "sqlite"))
+        .merge(("databases.ugsdb.url", url))
+        .merge(("databases.ugsdb.auto_migrate", "ture"))
+        .merge(("log_level", "off"));
+    let rocket = crate::mount_apis(rocket::custom(figment).attach(sql::stage()));
+    let error = Client::tracked(rocket).expect_err("ignite fails");
+    match error.kind() {
+        ErrorKind::FailedFairings(fairings) => {
+            assert_eq!(fairings[0].name, "Schema Migrations")
+        }
+        kind => panic!("unexpected error: {kind:?}"),
+    }
+}
+
+#[test]
+fn builds_round_trip() {
+    let dir = TempDir::new("sqlite-builds");
+    let client = sqlite_client(&dir);
+    assert_posted(&client, "/api/build", &build("//UE5/Main/Engine", 100, 3));
+    assert_posted(&client, "/api/build", &build("//UE5/Main/Engine", 101, 1));
+
+    let builds = into_json(
+        client
+            .get("/api/build?project=//UE5/Main/Engine&lastbuildid=1")
+            .dispatch(),
+    );
+    assert_eq!(
+        builds,
+        json!([{
+            "Id": 2,
+            "ChangeNumber": 101,
+            "BuildType": "Editor",
+            "Result": 1,
+            "Url": "https://ci/job/1",
+            "Project": "//UE5/Main/Engine",
+            "ArchivePath": "",
+        }])
+    );
+
+    let latest = into_json(
+        client
+            .get("/api/latest?project=//UE5/Main/Engine")
+            .dispatch(),
+    );
+    assert_eq!(
+        latest,
+        json!({ "LastEventId": 0, "LastCommentId": 0, "LastBuildId": 1 })
+    );
+}
+
+#[test]
+fn project_names_are_case_sensitive() {
+    let dir = TempDir::new("sqlite-case");
+    let client = sqlite_client(&dir);
+    assert_posted(&client, "/api/build", &build("//UE5/Main/Engine", 100, 3));
+    assert_posted(&client, "/api/build", &build("//UE5/Main/...", 101, 3));
+    let comment = json!({
+        "ChangeNumber": 100,
+        "UserName": "Alice",
+        "Text": "Looks good",
+        "Project": "//UE5/Main/Engine",
+    });
+    assert_posted(&client, "/api/comment", &comment);
+    let event = json!({
+        "Change": 100,
+        "UserName": "Bob",
+        "Type": 3,
+        "Project": "//UE5/Main/Engine",
+    });
+    assert_posted(&client, "/api/event", &event);
+
+    for uri in [
+        "/api/build?project=//ue5/main/engine&lastbuildid=0",
+        "/api/build?project=//UE5/MAIN/Engine&lastbuildid=0",
+        "/api/comment?project=//ue5/main/engine&lastcommentid=0",
+        "/api/event?project=//ue5/main/engine&lasteventid=0",
+    ] {
+        assert_eq!(
+            into_json(client.get(uri).dispatch()),
+            json!([]),
+            "GET {}",
+            uri
+        );
+    }
+}
+
+#[test]
+fn latest_ids_are_loaded_from_the_primary() {
+    let dir = TempDir::new("sqlite-latest-replica");
+    drop(sqlite_client(&dir));
+    // A replica that is behind by every post that follows.
+    let replica = dir.0.join("replica.db");
+    std::fs::copy(dir.0.join("ugs.db"), &replica).unwrap();
+    let wal = dir.0.join("ugs.db-wal");
+    if wal.exists() {
+        std::fs::copy(wal, dir.0.join("replica.db-wal")).unwrap();
+    }
+
+    let url = format!("sqlite://{}", dir.0.join("ugs.db").display());
+    let figment = rocket::Config::figment()
+        .merge(("databases.ugsdb.backend", "sqlite"))
+        .merge(("databases.ugsdb.url", url))
+        .merge((
+            "databases.ugsdb.replica.url",
+            format!("sqlite://{}?mode=ro", replica.display()),
+        ))
+        .merge(("log_level", "off"));
+    let rocket = crate::mount_apis(rocket::custom(figment).attach(sql::stage()));
+    let client = Client::tracked(rocket).expect("valid rocket instance");
+    assert_posted(&client, "/api/build", &build("//UE5/Main/Engine", 100, 3));
+
+    let latest = into_json(
+        client
+            .get("/api/latest?project=//UE5/Main/Engine")
+            .dispatch(),
+    );
+    assert_eq!(latest["LastBuildId"], 1);
+    // While the build itself is read from the replica, which is yet to have it.
+    let builds = into_json(
+        client
+            .get("/api/build?project=//UE5/Main/Engine&lastbuildid=0")
+            .dispatch(),
+    );
+    assert_eq!(builds, json!([]));
+}
+
+#[test]
+fn comments_round_trip() {
+    let dir = TempDir::new("sqlite-comments");
+    let client = sqlite_client(&dir);
+    let comment = json!({
+        "ChangeNumber": 100,
+        "UserName": "Alice",
+        "Text": "Looks good",
+        "Project": "//UE5/Main/Engine",
+    });
+    assert_posted(&client, "/api/comment", &comment);
+
+    let comments = into_json(
+        client
+            .get("/api/comment?project=//UE5/Main/Engine&lastcommentid=0")
+            .dispatch(),
+    );
+    assert_eq!(
+        comments,
+        json!([{
+            "Id": 1,
+            "ChangeNumber": 100,
+            "UserName": "Alice",
+            "Text": "Looks good",
+            "Project": "//UE5/Main/Engine",
+        }])
+    );
+}
+
+#[test]
+fn events_round_trip() {
+    let dir = TempDir::new("sqlite-events");
+    let client = sqlite_client(&dir);
+    let event = |change: i32| {
+        json!({
+            "Change": change,
+            "UserName": "Bob",
+            "Type": 3,
+            "Project": "//UE5/Main/Engine",
+        })
+    };
+    assert_posted(&client, "/api/event", &event(100));
+    let result = into_json(
+        client
+            .post("/api/event/batch")
+            .json(&json!([event(101), event(102)]))
+            .dispatch(),
+    );
+    assert_eq!(result["Stored"], 2);
+
+    let events = into_json(
+        client
+            .get("/api/event?project=//UE5/Main/Engine&lasteventid=0")
+            .dispatch(),
+    );
+    let changes: Vec<&Value> = events
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|event| &event["Change"])
+        .collect();
+    assert_eq!(changes, [100, 101, 102]);
+    assert_eq!(events[0]["UserName"], "Bob");
+    assert_eq!(events[0]["Project"], "//UE5/Main/Engine");
+}
+
+#[test]
+fn issues_round_trip() {
+    let dir = TempDir::new("sqlite-issues");
+    let client = sqlite_client(&dir);
+    let created = into_json(
+        client
+            .post("/api/issues")
+            .json(&issue("Broken build"))
+            .dispatch(),
+    );
+    assert_eq!(created, json!({ "Id": 1 }));
+
+    let response = client
+        .put("/api/issues/1")
+        .json(&json!({ "Summary": "", "Owner": "", "NominatedBy": "", "FixChange": 123 }))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let build_id = into_json(
+        client
+            .post("/api/issues/1/builds")
+            .json(&issue_build(1))
+            .dispatch(),
+    )["Id"]
+        .clone();
+
+    let issue = into_json(client.get("/api/issues/1").dispatch());
+    assert_eq!(issue["Summary"], "Broken build");
+    assert_eq!(issue["Owner"], "ALICE");
+    assert_eq!(issue["FixChange"], 123);
+    let build = into_json(
+        client
+            .get(format!("/api/issuebuilds/{build_id}"))
+            .dispatch(),
+    );
+    assert_eq!(build["Outcome"], 1);
+    assert_eq!(build["Stream"], "//UE5/Main");
+}
+
+#[test]
+fn telemetry_round_trips() {
+    let dir = TempDir::new("sqlite-telemetry");
+    let client = sqlite_client(&dir);
+    let timing = |result: &str, duration: f32| {
+        json!({
+            "Action": "Sync",
+            "Result": result,
+            "UserName": "Alice",
+            "Project": "//UE5/Main/Engine",
+            "Timestamp": 1700000000,
+            "Duration": duration,
+        })
+    };
+    assert_posted(
+        &client,
+        "/api/telemetry?version=5.1&ipaddress=10.0.0.1",
+        &timing("Success", 10.0),
+    );
+    let result = into_json(
+        client
+            .post("/api/telemetry/batch?version=5.1&ipaddress=10.0.0.1")
+            .json(&json!([timing("Failed", 30.0), timing("Success", 20.0)]))
+            .dispatch(),
+    );
+    assert_eq!(result["Stored"], 2);
+
+    let stats = into_json(
+        client
+            .get("/api/telemetry/stats?project=//UE5/Main/Engine&from=1600000000&groupby=version")
+            .dispatch(),
+    );
+    assert_eq!(
+        stats,
+        json!([{
+            "Action": "Sync",
+            "Version": "5.1",
+            "Count": 3,
+            "Successes": 2,
+            "SuccessRate": 2.0 / 3.0,
+            "P50": 20.0,
+            "P90": 30.0,
+            "P99": 30.0,
+        }])
+    );
+}
+
+#[test]
+fn timing_samples_are_every_nth_matching_row() {
+    let dir = TempDir::new("sqlite-samples");
+    let client = sqlite_client(&dir);
+    let timings: Vec<Value> = (1..=8)
+        .flat_map(|duration| {
+            ["Sync", "Build"].map(|action| {
+                json!({
+                    "Action": action,
+                    "Result": "Success",
+                    "UserName": "Alice",
+                    "Project": "//UE5/Main/Engine",
+                    "Timestamp": 1700000000,
+                    "Duration": duration as f32,
+                })
+            })
+        })
+        .collect();
+    let result = into_json(
+        client
+            .post("/api/telemetry/batch?version=5.1&ipaddress=10.0.0.1")
+            .json(&timings)
+            .dispatch(),
+    );
+    assert_eq!(result["Stored"], 16);
+    drop(client);
+
+    let url = format!("sqlite://{}", dir.0.join("ugs.db").display());
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let durations: Vec<f32> = runtime.block_on(async {
+        let pool = SqlitePool::connect(&url).await.unwrap();
+        let query = models::TimingQuery {
+            action: Some(String::from("Sync")),
+            ..models::TimingQuery::default()
+        };
+        let mut connection = pool.acquire().await.unwrap();
+        let samples = sqlite_connector::get_timing_samples(&mut connection, &query, 2)
+            .await
+            .unwrap();
+        drop(connection);
+        pool.close().await;
+        samples.into_iter().map(|sample| sample.duration).collect()
+    });
+    // The Sync timings all have odd ids, which sampling by id alone would skip every one of.
+    assert_eq!(durations, [2.0, 4.0, 6.0, 8.0]);
+}
+
+#[test]
+fn errors_round_trip() {
+    let dir = TempDir::new("sqlite-errors");
+    let client = sqlite_client(&dir);
+    for text in ["first", "second"] {
+        let error = json!({
+            "Type": 0,
+            "Text": text,
+            "UserName": "Alice",
+            "Project": "//UE5/Main/Engine",
+            "Timestamp": 1700000000,
+            "Version": "ignored",
+            "IpAddress": "ignored",
+        });
+        assert_posted(&client, "/api/error?version=5.1&ipaddress=10.0.0.1", &error);
+    }
+
+    let errors = into_json(
+        client
+            .get("/api/error?project=//UE5/Main/Engine&records=1")
+            .dispatch(),
+    );
+    assert_eq!(
+        errors,
+        json!([{
+            "Id": 2,
+            "Type": 0,
+            "Text": "second",
+            "UserName": "Alice",
+            "Project": "//UE5/Main/Engine",
+            "Timestamp": 1700000000,
+            "Version": "5.1",
+            "IpAddress": "10.0.0.1",
+        }])
+    );
+}
+
+#[test]
+fn failed_batches_leave_no_projects_behind() {
+    let dir = TempDir::new("sqlite-batch-rollback");
+    let client = sqlite_client(&dir);
+    execute(
+        &dir,
+        "CREATE TRIGGER RejectNegativeChanges BEFORE INSERT ON UserVotes WHEN NEW.Changelist < 0 \
+         BEGIN SELECT RAISE(ABORT, 'rejected'); END",
+    );
+    let event = |change: i32| {
+        json!({
+            "Change": change,
+            "UserName": "Bob",
+            "Type": 3,
+            "Project": "//UE5/Main/Engine",
+        })
+    };
+    let response = client
+        .post("/api/event/batch")
+        .json(&json!([event(100), event(-1)]))
+        .dispatch();
+    assert_eq!(response.status(), Status::InternalServerError);
+    assert_eq!(execute(&dir, "SELECT COUNT(*) FROM Projects"), Some(0));
+
+    // The project id of the rolled back batch was not cached either.
+    execute(&dir, "DROP TRIGGER RejectNegativeChanges");
+    let result = into_json(
+        client
+            .post("/api/event/batch")
+            .json(&json!([event(100)]))
+            .dispatch(),
+    );
+    assert_eq!(result["Stored"], 1);
+    let events = into_json(
+        client
+            .get("/api/event?project=//UE5/Main/Engine&lasteventid=0")
+            .dispatch(),
+    );
+    assert_eq!(events.as_array().unwrap().len(), 1);
+}
@@ -0,0 +1,65 @@
+use crate::sql::migrations;
+use crate::sql::queries::Dialect;
+use crate::sql::sql_connector::TableNames;
+use rocket::error::ErrorKind;
+use rocket_db_pools::sqlx::{MySql, Postgres};
+
+#[test]
+fn default_names_match_the_original_schema() {
+    let tables = TableNames::new(TableNames::DEFAULT_SCHEMA, "").unwrap();
+    assert_eq!(tables.projects, "ugs_db.Projects");
+    assert_eq!(tables.telemetry, "ugs_db.Telemetry_v2");
+}
+
+#[test]
+fn schema_and_prefix_qualify_every_table() {
+    let tables = TableNames::new("metadata", "ugs_").unwrap();
+    assert_eq!(tables.issue_watchers, "metadata.ugs_IssueWatchers");
+    assert_eq!(tables.errors, "metadata.ugs_Errors");
+}
+
+#[test]
+fn shared_queries_quote_names_for_each_database() {
+    let tables = TableNames::unqualified();
+    assert_eq!(tables.user_votes, r#""UserVotes""#);
+    let sql = format!(r#"SELECT "Id" FROM {}"#, tables.user_votes);
+    assert_eq!(Postgres::quote(&sql), sql);
+    assert_eq!(MySql::quote(&sql), "SELECT `Id` FROM `UserVotes`");
+}
+
+#[test]
+fn rejects_names_that_are_not_identifiers() {
+    assert!(TableNames::new("", "").is_err());
+    assert!(TableNames::new("ugs_db; DROP TABLE Users", "").is_err());
+    assert!(TableNames::new("ugs_db", "ugs-").is_err());
+}
+
+#[test]
+fn default_names_keep_migration_checksums() {
+    let tables = TableNames::new(TableNames::DEFAULT_SCHEMA, "").unwrap();
+    let migrator = migrations::mysql(&tables);
+    for (rendered, embedded) in migrator.iter().zip(migrations::MYSQL.iter()) {
+        assert_eq!(rendered.checksum, embedded.checksum);
+    }
+}
+
+#[test]
+fn migrations_are_rendered_for_configured_names() {
+    let tables = TableNames::new("metadata", "ugs_").unwrap();
+    let migrator = migrations::mysql(&tables);
+    let sql = &migrator.iter().next().unwrap().sql;
+    assert!(sql.contains("CREATE DATABASE IF NOT EXISTS metadata;"));
+    assert!(sql.contains("CREATE TABLE IF NOT EXISTS metadata.ugs_Projects"));
+    assert!(!sql.contains("ugs_db."));
+}
+
+#[test]
+fn other_backends_reject_table_names() {
+    let figment = rocket::Config::figment()
+        .merge(("databases.ugsdb.backend", "memory"))
+        .merge(("databases.ugsdb.schema", "metadata"))
+        .merge(("log_level", "off"));
+    let rocket = rocket::custom(figment).attach(crate::sql::stage());
+    let error = rocket::local::blocking::Client::tracked(rocket).expect_err("ignite fails");
+    assert!(matches!(error.kind(), ErrorKind::FailedFairings(_)));
+}
@@ -0,0 +1,232 @@
+use super::{
+    assert_database_error, assert_database_unavailable, client, failing_client, into_json,
+    unavailable_client,
+};
+use rocket::http::Status;
+use rocket::local::blocking::Client;
+use rocket::serde::json::{json, Value};
+
+fn timing_data() -> Value {
+    json!({
+        "Action": "Sync",
+        "Result": "Success",
+        "UserName": "Alice",
+        "Project": "//UE5/Main/Engine",
+        "Timestamp": 1700000000,
+        "Duration": 12.5,
+    })
+}
+
+#[test]
+fn post_accepts_timing_data() {
+    let client = client();
+    let response = client
+        .post("/api/telemetry?version=5.1&ipaddress=10.0.0.1")
+        .json(&timing_data())
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[test]
+fn post_requires_version_and_ip_address() {
+    let client = client();
+    let response = client
+        .post("/api/telemetry?version=5.1")
+        .json(&timing_data())
+        .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn post_rejects_invalid_body() {
+    let client = client();
+    let mut data = timing_data();
+    data["Duration"] = json!("slow");
+    let response = client
+        .post("/api/telemetry?version=5.1&ipaddress=10.0.0.1")
+        .json(&data)
+        .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
+
+#[test]
+fn post_batch_stores_the_timing_data_that_parses() {
+    let client = client();
+    let mut invalid = timing_data();
+    invalid["Duration"] = json!("slow");
+    let result = into_json(
+        client
+            .post("/api/telemetry/batch?version=5.1&ipaddress=10.0.0.1")
+            .json(&json!([timing_data(), invalid, timing_data()]))
+            .dispatch(),
+    );
+    assert_eq!(result["Stored"], 2);
+    assert_eq!(result["Rejected"], 1);
+    let statuses: Vec<&Value> = result["Results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| &result["Status"])
+        .collect();
+    assert_eq!(statuses, [200, 422, 200]);
+}
+
+#[test]
+fn post_batch_fails_as_a_whole_while_the_database_is_unavailable() {
+    let client = unavailable_client();
+    assert_database_unavailable(
+        client
+            .post("/api/telemetry/batch?version=5.1&ipaddress=10.0.0.1")
+            .json(&json!([timing_data()]))
+            .dispatch(),
+    );
+}
+
+fn post_timing(client: &Client, version: &str, result: &str, timestamp: i64, duration: f32) {
+    let mut data = timing_data();
+    data["Result"] = json!(result);
+    data["Timestamp"] = json!(timestamp);
+    data["Duration"] = json!(duration);
+    let response = client
+        .post(format!(
+            "/api/telemetry?version={}&ipaddress=10.0.0.1",
+            version
+        ))
+        .json(&data)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[test]
+fn get_stats_aggregates_timing_data() {
+    let client = client();
+    for (version, result, timestamp, duration) in [
+        ("5.1", "Success", 1700000000, 10.0),
+        ("5.1", "Failed", 1700000100, 30.0),
+        ("5.2", "Success", 1700003600, 20.0),
+        ("5.2", "Success", 1600000000, 99.0),
+    ] {
+        post_timing(&client, version, result, timestamp, duration);
+    }
+
+    let stats = into_json(
+        client
+            .get("/api/telemetry/stats?action=Sync&from=1700000000&to=1700007200")
+            .dispatch(),
+    );
+    assert_eq!(
+        stats,
+        json!([{
+            "Action": "Sync",
+            "Count": 3,
+            "Successes": 2,
+            "SuccessRate": 2.0 / 3.0,
+            "P50": 20.0,
+            "P90": 30.0,
+            "P99": 30.0,
+        }])
+    );
+
+    let stats = into_json(
+        client
+            .get("/api/telemetry/stats?from=1700000000&groupby=version,project&bucket=3600")
+            .dispatch(),
+    );
+    let keys: Vec<(&Value, &Value, &Value, &Value)> = stats
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|stats| {
+            (
+                &stats["Project"],
+                &stats["Version"],
+                &stats["Start"],
+                &stats["Count"],
+            )
+        })
+        .collect();
+    let project = json!("//UE5/Main/Engine");
+    assert_eq!(
+        keys,
+        [
+            (&project, &json!("5.1"), &json!(1699999200), &json!(2)),
+            (&project, &json!("5.2"), &json!(1700002800), &json!(1)),
+        ]
+    );
+}
+
+#[test]
+fn get_stats_filters_by_stream_rather_than_substring() {
+    let client = client();
+    for project in [
+        "//UE5/Main/Engine",
+        "//UE5/Main-Old/Engine",
+        "//UE5/Main/Game",
+    ] {
+        let mut data = timing_data();
+        data["Project"] = json!(project);
+        let response = client
+            .post("/api/telemetry?version=5.1&ipaddress=10.0.0.1")
+            .json(&data)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    let stats = into_json(
+        client
+            .get("/api/telemetry/stats?project=//UE5/Main&from=0&groupby=project")
+            .dispatch(),
+    );
+    let projects: Vec<&Value> = stats
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|stats| &stats["Project"])
+        .collect();
+    assert_eq!(
+        projects,
+        [&json!("//UE5/Main/Engine"), &json!("//UE5/Main/Game")]
+    );
+}
+
+#[test]
+fn get_stats_defaults_to_the_last_week() {
+    let client = client();
+    post_timing(&client, "5.1", "Success", 1600000000, 10.0);
+    let stats = into_json(client.get("/api/telemetry/stats").dispatch());
+    assert_eq!(stats, json!([]));
+}
+
+#[test]
+fn get_stats_rejects_invalid_parameters() {
+    let client = client();
+    for query in [
+        "bucket=0",
+        "groupby=user",
+        "groupby=version,",
+        "from=99999999999999",
+    ] {
+        let response = client
+            .get(format!("/api/telemetry/stats?{}", query))
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest, "{}", query);
+    }
+}
+
+#[test]
+fn database_errors_return_internal_server_error() {
+    let client = failing_client();
+    assert_database_error(
+        client
+            .post("/api/telemetry?version=5.1&ipaddress=10.0.0.1")
+            .json(&timing_data())
+            .dispatch(),
+    );
+    assert_database_error(
+        client
+            .post("/api/telemetry/batch?version=5.1&ipaddress=10.0.0.1")
+            .json(&json!([timing_data()]))
+            .dispatch(),
+    );
+    assert_database_error(client.get("/api/telemetry/stats").dispatch());
+}
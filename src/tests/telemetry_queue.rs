@@ -0,0 +1,285 @@
+use super::{admin_header, into_json, unavailable_store, TempDir, ADMIN_TOKEN};
+use crate::dump::DumpTable;
+use crate::models;
+use crate::spool::{self, Spool, SpoolConfig, SpooledPost};
+use crate::sql::memory_store::MemoryStore;
+use crate::sql::SharedStore;
+use crate::telemetry_queue::{
+    self, EnqueueError, Overflow, TelemetryQueue, TelemetryQueueConfig, TelemetryQueueStats,
+};
+use chrono::Utc;
+use rocket::http::Status;
+use rocket::local::blocking::Client;
+use rocket::serde::json::{json, Value};
+use rocket::tokio::{self, time};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn spool_config(dir: &TempDir) -> SpoolConfig {
+    SpoolConfig {
+        dir: dir.0.clone(),
+        replay_interval: 60,
+        max_entries: 10,
+    }
+}
+
+fn config(capacity: usize, batch_size: usize, flush_interval: u64) -> TelemetryQueueConfig {
+    TelemetryQueueConfig {
+        capacity,
+        batch_size,
+        flush_interval,
+        overflow: Overflow::Drop,
+    }
+}
+
+fn post(user_name: &str) -> models::TelemetryPost {
+    models::TelemetryPost {
+        data: models::TelemetryTimingData {
+            action: String::from("Sync"),
+            result: String::from("Succeeded"),
+            user_name: String::from(user_name),
+            project: String::from("//UE5/Main/Engine"),
+            timestamp: Utc::now(),
+            duration: 1.5,
+        },
+        version: String::from("5.1"),
+        ip_address: String::from("127.0.0.1"),
+    }
+}
+
+fn telemetry_json(user_name: &str) -> Value {
+    json!({
+        "Action": "Sync",
+        "Result": "Succeeded",
+        "UserName": user_name,
+        "Project": "//UE5/Main/Engine",
+        "Timestamp": 1_700_000_000,
+        "Duration": 1.5,
+    })
+}
+
+async fn telemetry_rows(store: &SharedStore) -> usize {
+    let rows = store.export_rows(DumpTable::Telemetry, 0, 100).await;
+    rows.unwrap().len()
+}
+
+/// Waits up to a second for the worker to get `stats` to `until`.
+async fn wait_for(queue: &TelemetryQueue, until: impl Fn(&TelemetryQueueStats) -> bool) {
+    for _ in 0..100 {
+        if until(&queue.stats()) {
+            return;
+        }
+        time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("Telemetry queue got stuck at {:?}", queue.stats());
+}
+
+#[rocket::async_test]
+async fn full_batches_are_written_right_away() {
+    let store: SharedStore = Arc::new(MemoryStore::new());
+    let (queue, worker) = TelemetryQueue::new(&config(10, 2, 60_000));
+    tokio::spawn(worker.run(store.clone(), None));
+
+    for user_name in ["Alice", "Bob", "Carol"] {
+        queue.enqueue(post(user_name)).await.unwrap();
+    }
+    wait_for(&queue, |stats| stats.flushed == 2).await;
+    // The third waits for the next `flush_interval`.
+    assert_eq!(telemetry_rows(&store).await, 2);
+}
+
+#[rocket::async_test]
+async fn partial_batches_are_written_every_flush_interval() {
+    let store: SharedStore = Arc::new(MemoryStore::new());
+    let (queue, worker) = TelemetryQueue::new(&config(10, 100, 10));
+    tokio::spawn(worker.run(store.clone(), None));
+
+    queue.enqueue(post("Alice")).await.unwrap();
+    wait_for(&queue, |stats| stats.flushed == 1).await;
+    assert_eq!(telemetry_rows(&store).await, 1);
+}
+
+#[rocket::async_test]
+async fn full_queue_drops_and_counts_posts() {
+    // Without a running worker, nothing leaves the queue.
+    let (queue, _worker) = TelemetryQueue::new(&config(1, 10, 60_000));
+    queue.enqueue(post("Alice")).await.unwrap();
+    assert!(matches!(
+        queue.enqueue(post("Bob")).await,
+        Err(EnqueueError::Full)
+    ));
+
+    let stats = queue.stats();
+    assert_eq!(stats.queued, 1);
+    assert_eq!(stats.enqueued, 1);
+    assert_eq!(stats.dropped, 1);
+}
+
+#[rocket::async_test]
+async fn shutdown_writes_what_is_queued() {
+    let store: SharedStore = Arc::new(MemoryStore::new());
+    let (queue, worker) = TelemetryQueue::new(&config(10, 100, 60_000));
+    tokio::spawn(worker.run(store.clone(), None));
+    for user_name in ["Alice", "Bob", "Carol"] {
+        queue.enqueue(post(user_name)).await.unwrap();
+    }
+
+    queue.shutdown().await;
+    assert_eq!(queue.stats().flushed, 3);
+    assert_eq!(telemetry_rows(&store).await, 3);
+    // Later posts are stored without the queue.
+    assert!(matches!(
+        queue.enqueue(post("Dave")).await,
+        Err(EnqueueError::Bypassed(_))
+    ));
+}
+
+#[rocket::async_test]
+async fn unreachable_database_sends_batches_to_the_spool() {
+    let dir = TempDir::new("telemetry-queue-spool");
+    let spool = Arc::new(Spool::open(spool_config(&dir)).unwrap());
+    let (queue, worker) = TelemetryQueue::new(&config(10, 100, 60_000));
+    tokio::spawn(worker.run(unavailable_store(), Some(spool.clone())));
+    queue.enqueue(post("Alice")).await.unwrap();
+    queue.enqueue(post("Bob")).await.unwrap();
+
+    queue.shutdown().await;
+    assert_eq!(queue.stats().spooled, 2);
+    assert_eq!(spool.depth(), 2);
+
+    let store: SharedStore = Arc::new(MemoryStore::new());
+    assert_eq!(spool.replay(&store).await.replayed, 2);
+    assert_eq!(telemetry_rows(&store).await, 2);
+}
+
+#[rocket::async_test]
+async fn batches_are_spooled_behind_spooled_posts() {
+    let dir = TempDir::new("telemetry-queue-behind-spool");
+    let spool = Arc::new(Spool::open(spool_config(&dir)).unwrap());
+    let spooled = SpooledPost::Telemetry(post("Alice"));
+    assert!(spool.append(spooled).await.unwrap());
+    let store: SharedStore = Arc::new(MemoryStore::new());
+    let (queue, worker) = TelemetryQueue::new(&config(10, 100, 60_000));
+    tokio::spawn(worker.run(store.clone(), Some(spool.clone())));
+    queue.enqueue(post("Bob")).await.unwrap();
+
+    queue.shutdown().await;
+    assert_eq!(queue.stats().spooled, 1);
+    assert_eq!(spool.depth(), 2);
+    assert_eq!(telemetry_rows(&store).await, 0);
+}
+
+#[test]
+fn shutdown_spools_what_is_queued_when_the_database_is_down() {
+    let dir = TempDir::new("telemetry-queue-shutdown-spool");
+    let figment = rocket::Config::figment()
+        .merge(("log_level", "off"))
+        .merge(("spool.dir", &dir.0))
+        .merge(("telemetry_queue.flush_interval", 60_000));
+    let rocket = rocket::custom(figment)
+        .manage(unavailable_store())
+        .attach(spool::stage())
+        .attach(telemetry_queue::stage());
+    let client = Client::tracked(crate::mount_apis(rocket)).expect("valid rocket instance");
+
+    let response = client
+        .post("/api/telemetry?version=5.1&ipaddress=127.0.0.1")
+        .json(&telemetry_json("Alice"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    drop(response);
+
+    let _ = client.terminate();
+    assert_eq!(Spool::open(spool_config(&dir)).unwrap().depth(), 1);
+}
+
+#[test]
+fn posts_bypass_the_queue_while_posts_are_spooled() {
+    let dir = TempDir::new("telemetry-queue-bypass");
+    let spool = Spool::open(spool_config(&dir)).unwrap();
+    let spooled = SpooledPost::Telemetry(post("Alice"));
+    rocket::execute(spool.append(spooled)).unwrap();
+    drop(spool);
+
+    let figment = rocket::Config::figment()
+        .merge(("log_level", "off"))
+        .merge(("admin.token", ADMIN_TOKEN))
+        .merge(("spool.dir", &dir.0))
+        .merge(("spool.replay_interval", 60));
+    let rocket = rocket::custom(figment)
+        .manage(unavailable_store())
+        .attach(spool::stage())
+        .attach(telemetry_queue::stage());
+    let client = Client::tracked(crate::mount_apis(rocket)).expect("valid rocket instance");
+
+    let response = client
+        .post("/api/telemetry?version=5.1&ipaddress=127.0.0.1")
+        .json(&telemetry_json("Bob"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Accepted);
+
+    let stats = into_json(
+        client
+            .get("/api/admin/telemetry-queue")
+            .header(admin_header())
+            .dispatch(),
+    );
+    assert_eq!(stats["Enqueued"], 0);
+    let spool = client.rocket().state::<spool::SharedSpool>().unwrap();
+    assert_eq!(spool.depth(), 2);
+}
+
+#[test]
+fn queued_posts_answer_200() {
+    let figment = rocket::Config::figment()
+        .merge(("log_level", "off"))
+        .merge(("admin.token", ADMIN_TOKEN))
+        .merge(("telemetry_queue.flush_interval", 10));
+    let store: SharedStore = Arc::new(MemoryStore::new());
+    let rocket = rocket::custom(figment)
+        .manage(store)
+        .attach(telemetry_queue::stage());
+    let client = Client::tracked(crate::mount_apis(rocket)).expect("valid rocket instance");
+
+    let response = client
+        .post("/api/telemetry?version=5.1&ipaddress=127.0.0.1")
+        .json(&telemetry_json("Alice"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    assert_eq!(
+        client.get("/api/admin/telemetry-queue").dispatch().status(),
+        Status::Unauthorized
+    );
+    let stats = into_json(
+        client
+            .get("/api/admin/telemetry-queue")
+            .header(admin_header())
+            .dispatch(),
+    );
+    assert_eq!(stats["Capacity"], 10_000);
+    assert_eq!(stats["Enqueued"], 1);
+    assert_eq!(stats["Dropped"], 0);
+}
+
+#[test]
+fn telemetry_queue_can_be_disabled() {
+    let figment = rocket::Config::figment()
+        .merge(("log_level", "off"))
+        .merge(("admin.token", ADMIN_TOKEN))
+        .merge(("telemetry_queue.capacity", 0));
+    let store: SharedStore = Arc::new(MemoryStore::new());
+    let rocket = rocket::custom(figment)
+        .manage(store)
+        .attach(telemetry_queue::stage());
+    let client = Client::tracked(crate::mount_apis(rocket)).expect("valid rocket instance");
+
+    assert_eq!(
+        client
+            .get("/api/admin/telemetry-queue")
+            .header(admin_header())
+            .dispatch()
+            .status(),
+        Status::NotFound
+    );
+}
@@ -0,0 +1,192 @@
+use crate::models;
+use crate::sql::memory_store::MemoryStore;
+use crate::sql::MetadataStore;
+use crate::telemetry_stats::{aggregate, is_success, TimingGroup};
+use chrono::{TimeZone, Utc};
+
+fn count(action: &str, result: &str, version: Option<&str>, count: i64) -> models::TimingCount {
+    models::TimingCount {
+        action: String::from(action),
+        project: None,
+        version: version.map(String::from),
+        start: None,
+        result: String::from(result),
+        count,
+    }
+}
+
+fn sample(action: &str, version: Option<&str>, duration: f32) -> models::TimingSample {
+    models::TimingSample {
+        action: String::from(action),
+        project: None,
+        version: version.map(String::from),
+        start: None,
+        duration,
+    }
+}
+
+#[test]
+fn percentiles_are_nearest_rank() {
+    let samples = (1..=100)
+        .rev()
+        .map(|duration| sample("Sync", None, duration as f32))
+        .collect();
+    let stats = aggregate(vec![count("Sync", "Success", None, 100)], samples);
+    assert_eq!(stats.len(), 1);
+    assert_eq!(stats[0].count, 100);
+    assert_eq!(
+        (stats[0].p50, stats[0].p90, stats[0].p99),
+        (Some(50.0), Some(90.0), Some(99.0))
+    );
+
+    let stats = aggregate(
+        vec![count("Sync", "Success", None, 1)],
+        vec![sample("Sync", None, 7.0)],
+    );
+    assert_eq!(
+        (stats[0].p50, stats[0].p90, stats[0].p99),
+        (Some(7.0), Some(7.0), Some(7.0))
+    );
+}
+
+#[test]
+fn percentiles_come_from_the_samples_of_the_group() {
+    // Sampled, the counts of a group are larger than its samples, or it may have none at all.
+    let counts = vec![
+        count("Sync", "Success", None, 1000),
+        count("Build", "Success", None, 3),
+    ];
+    let samples = vec![
+        sample("Sync", None, 2.0),
+        sample("Sync", None, 4.0),
+        // Stored after the counts were taken.
+        sample("Clean", None, 1.0),
+    ];
+    let stats = aggregate(counts, samples);
+    assert_eq!(stats.len(), 2);
+    assert_eq!((stats[0].action.as_str(), stats[0].p50), ("Build", None));
+    assert_eq!(
+        (stats[1].count, stats[1].p50, stats[1].p99),
+        (1000, Some(2.0), Some(4.0))
+    );
+}
+
+#[test]
+fn success_rate_counts_both_success_results() {
+    assert!(is_success("Success") && is_success("succeeded"));
+    assert!(!is_success("Failed") && !is_success("Cancelled"));
+
+    let counts = vec![
+        count("Build", "Succeeded", None, 1),
+        count("Build", "Failed", None, 1),
+        count("Build", "Success", None, 2),
+        count("Build", "Cancelled", None, 4),
+    ];
+    let stats = aggregate(counts, Vec::new());
+    assert_eq!(
+        (stats[0].count, stats[0].successes, stats[0].success_rate),
+        (8, 3, 0.375)
+    );
+}
+
+#[test]
+fn stats_are_ordered_by_action_and_group() {
+    let mut counts = vec![
+        count("Sync", "Success", Some("5.2"), 1),
+        count("Sync", "Success", Some("5.1"), 1),
+        count("Build", "Success", Some("5.1"), 1),
+    ];
+    counts[0].start = Some(3600);
+    let keys: Vec<_> = aggregate(counts, Vec::new())
+        .into_iter()
+        .map(|stats| (stats.action, stats.version.unwrap(), stats.start))
+        .collect();
+    assert_eq!(
+        keys,
+        [
+            (String::from("Build"), String::from("5.1"), None),
+            (String::from("Sync"), String::from("5.1"), None),
+            (
+                String::from("Sync"),
+                String::from("5.2"),
+                Utc.timestamp_opt(3600, 0).single()
+            ),
+        ]
+    );
+}
+
+#[rocket::async_test]
+async fn stores_sample_every_nth_timing_sample() {
+    let store = MemoryStore::new();
+    for duration in 1..=10 {
+        let data = models::TelemetryTimingData {
+            action: String::from("Sync"),
+            result: String::from("Success"),
+            user_name: String::from("Alice"),
+            project: String::from("//UE5/Main/Engine"),
+            timestamp: Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+            duration: duration as f32,
+        };
+        store
+            .post_telemetry_data(&data, "5.1", "10.0.0.1")
+            .await
+            .unwrap();
+    }
+    let query = models::TimingQuery {
+        by_version: true,
+        ..models::TimingQuery::default()
+    };
+
+    let counts = store.get_timing_counts(&query).await.unwrap();
+    assert_eq!(counts, [count("Sync", "Success", Some("5.1"), 10)]);
+    assert_eq!(store.get_timing_samples(&query, 1).await.unwrap().len(), 10);
+    let durations: Vec<f32> = store
+        .get_timing_samples(&query, 4)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|sample| sample.duration)
+        .collect();
+    assert_eq!(durations, [4.0, 8.0]);
+}
+
+#[rocket::async_test]
+async fn samples_every_nth_matching_timing_sample() {
+    let store = MemoryStore::new();
+    for duration in 1..=8 {
+        for action in ["Sync", "Build"] {
+            let data = models::TelemetryTimingData {
+                action: String::from(action),
+                result: String::from("Success"),
+                user_name: String::from("Alice"),
+                project: String::from("//UE5/Main/Engine"),
+                timestamp: Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+                duration: duration as f32,
+            };
+            store
+                .post_telemetry_data(&data, "5.1", "10.0.0.1")
+                .await
+                .unwrap();
+        }
+    }
+    // The Sync timings all have odd ids, which sampling by id alone would skip every one of.
+    let query = models::TimingQuery {
+        action: Some(String::from("Sync")),
+        ..models::TimingQuery::default()
+    };
+    let durations: Vec<f32> = store
+        .get_timing_samples(&query, 2)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|sample| sample.duration)
+        .collect();
+    assert_eq!(durations, [2.0, 4.0, 6.0, 8.0]);
+}
+
+#[test]
+fn group_names_parse() {
+    assert_eq!("project".parse(), Ok(TimingGroup::Project));
+    assert_eq!("version".parse(), Ok(TimingGroup::Version));
+    assert!("user".parse::<TimingGroup>().is_err());
+}
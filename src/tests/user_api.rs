@@ -0,0 +1,35 @@
+use super::{assert_database_error, client, failing_client, into_json};
+use rocket::http::Status;
+use rocket::serde::json::json;
+
+#[test]
+fn get_returns_same_id_regardless_of_case() {
+    let client = client();
+    let alice = into_json(client.get("/api/user?name=Alice").dispatch());
+    let bob = into_json(client.get("/api/user?name=Bob").dispatch());
+    let alice_again = into_json(client.get("/api/user?name=ALICE").dispatch());
+
+    assert_eq!(alice, json!({ "Id": 1 }));
+    assert_eq!(bob, json!({ "Id": 2 }));
+    assert_eq!(alice_again, alice);
+}
+
+#[test]
+fn get_empty_name_returns_null_id() {
+    let client = client();
+    let user = into_json(client.get("/api/user?name=").dispatch());
+    assert_eq!(user, json!({ "Id": null }));
+}
+
+#[test]
+fn get_requires_name() {
+    let client = client();
+    let response = client.get("/api/user").dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+fn database_errors_return_internal_server_error() {
+    let client = failing_client();
+    assert_database_error(client.get("/api/user?name=Alice").dispatch());
+}
@@ -0,0 +1,442 @@
+//! Conformance suite for the JSON contract of the C# MetadataServer that shipped UnrealGameSync
+//! clients are built against. Every request and response body lives as a golden fixture under
+//! `fixtures/`; a change to `models` that alters the wire format makes these tests fail.
+
+use super::{client, into_json};
+use crate::models;
+use crate::sql::memory_store::MemoryStore;
+use crate::sql::SharedStore;
+use crate::telemetry_queue;
+use chrono::TimeZone;
+use rocket::http::Status;
+use rocket::local::blocking::Client;
+use rocket::serde::json::{self, Value};
+use rocket::serde::Serialize;
+use std::sync::Arc;
+
+macro_rules! fixture {
+    ($name:literal) => {
+        json::from_str::<Value>(include_str!(concat!("fixtures/", $name))).expect(concat!(
+            "fixtures/",
+            $name,
+            " is valid JSON"
+        ))
+    };
+}
+
+fn timestamp(seconds: i64) -> chrono::DateTime<chrono::Utc> {
+    chrono::Utc.timestamp_opt(seconds, 0).unwrap()
+}
+
+fn assert_serializes_to<T: Serialize>(value: T, expected: Value) {
+    assert_eq!(json::to_value(value).unwrap(), expected);
+}
+
+// Response shapes
+
+#[test]
+fn build_data_serializes_to_fixture() {
+    assert_serializes_to(
+        [models::BuildData {
+            id: 1,
+            change_number: 12345,
+            build_type: String::from("Editor"),
+            result: models::BuildResult::Success,
+            url: String::from("https://horde.example.com/job/1"),
+            project: String::from("//UE5/Main/Samples/Games/Lyra/Lyra.uproject"),
+            archive_path: String::from("//UE5/Main/Binaries/Editor.zip"),
+        }],
+        fixture!("builds_response.json"),
+    );
+}
+
+#[test]
+fn comment_data_serializes_to_fixture() {
+    assert_serializes_to(
+        [models::CommentData {
+            id: 1,
+            change_number: 12345,
+            user_name: String::from("Jane.Doe"),
+            text: String::from("Fixed in 12346"),
+            project: String::from("//UE5/Main/Samples/Games/Lyra/Lyra.uproject"),
+        }],
+        fixture!("comments_response.json"),
+    );
+}
+
+#[test]
+fn event_data_serializes_to_fixture() {
+    assert_serializes_to(
+        [models::EventData {
+            id: 1,
+            change: 12345,
+            user_name: String::from("Jane.Doe"),
+            event_type: models::EventType::Starred,
+            project: String::from("//UE5/Main/Samples/Games/Lyra/Lyra.uproject"),
+        }],
+        fixture!("events_response.json"),
+    );
+}
+
+#[test]
+fn latest_data_serializes_to_fixture() {
+    assert_serializes_to(
+        models::LatestData {
+            last_event_id: 1,
+            last_comment_id: 1,
+            last_build_id: 1,
+        },
+        fixture!("latest_response.json"),
+    );
+}
+
+#[test]
+fn issue_data_serializes_to_fixture() {
+    assert_serializes_to(
+        models::IssueData {
+            id: 7,
+            created_at: timestamp(1700000000),
+            retrieved_at: timestamp(1700000600),
+            project: String::from("//UE5/Main"),
+            summary: String::from("Errors in Lyra editor build"),
+            owner: String::from("JANE.DOE"),
+            nominated_by: String::from("JOHN.SMITH"),
+            acknowledged_at: Some(timestamp(1700000300)),
+            fix_change: 12346,
+            resolved_at: None,
+            notify: true,
+        },
+        fixture!("issue_response.json"),
+    );
+}
+
+#[test]
+fn issue_build_data_serializes_to_fixture() {
+    assert_serializes_to(
+        [models::IssueBuildData {
+            id: 1,
+            stream: String::from("//UE5/Main"),
+            change: 12345,
+            job_name: String::from("Incremental Editor"),
+            job_url: String::from("https://horde.example.com/job/1"),
+            job_step_name: String::from("Compile UnrealEditor Win64"),
+            job_step_url: String::from("https://horde.example.com/job/1?step=2"),
+            error_url: String::from("https://horde.example.com/log/3"),
+            outcome: 2,
+        }],
+        fixture!("issue_builds_response.json"),
+    );
+}
+
+#[test]
+fn issue_diagnostic_data_serializes_to_fixture() {
+    assert_serializes_to(
+        [models::IssueDiagnosticData {
+            build_id: Some(1),
+            message: String::from("Engine/Source/Runtime/Core/Private/Misc/Foo.cpp(12): error C2065: 'Bar': undeclared identifier"),
+            url: String::from("https://horde.example.com/log/3?line=120"),
+        }],
+        fixture!("issue_diagnostics_response.json"),
+    );
+}
+
+#[test]
+fn telemetry_error_data_serializes_to_fixture() {
+    assert_serializes_to(
+        [models::TelemetryErrorData {
+            id: 1,
+            error_type: models::TelemetryErrorType::Crash,
+            text: String::from("Unhandled exception: System.NullReferenceException"),
+            user_name: String::from("Jane.Doe"),
+            project: Some(String::from("//UE5/Main/Samples/Games/Lyra/Lyra.uproject")),
+            timestamp: timestamp(1700000000),
+            version: String::from("5.3.0"),
+            ip_address: String::from("10.0.0.1"),
+        }],
+        fixture!("errors_response.json"),
+    );
+}
+
+// Request shapes
+
+#[test]
+fn build_data_deserializes_from_fixture() {
+    let build: models::BuildData = json::from_value(fixture!("build_request.json")).unwrap();
+    assert_eq!(build.change_number, 12345);
+    assert_eq!(build.build_type, "Editor");
+    assert_eq!(build.result, models::BuildResult::Success);
+    assert_eq!(build.url, "https://horde.example.com/job/1");
+    assert_eq!(build.project, "//UE5/Main/Samples/Games/Lyra/Lyra.uproject");
+    assert_eq!(build.archive_path, "//UE5/Main/Binaries/Editor.zip");
+}
+
+#[test]
+fn comment_data_deserializes_from_fixture() {
+    let comment: models::CommentData = json::from_value(fixture!("comment_request.json")).unwrap();
+    assert_eq!(comment.change_number, 12345);
+    assert_eq!(comment.user_name, "Jane.Doe");
+    assert_eq!(comment.text, "Fixed in 12346");
+    assert_eq!(
+        comment.project,
+        "//UE5/Main/Samples/Games/Lyra/Lyra.uproject"
+    );
+}
+
+#[test]
+fn event_data_deserializes_from_fixture() {
+    let event: models::EventData = json::from_value(fixture!("event_request.json")).unwrap();
+    assert_eq!(event.change, 12345);
+    assert_eq!(event.user_name, "Jane.Doe");
+    assert_eq!(event.event_type, models::EventType::Starred);
+    assert_eq!(event.project, "//UE5/Main/Samples/Games/Lyra/Lyra.uproject");
+}
+
+#[test]
+fn issue_data_deserializes_from_fixture() {
+    let issue: models::IssueData = json::from_value(fixture!("issue_request.json")).unwrap();
+    assert_eq!(issue.created_at, timestamp(1700000000));
+    assert_eq!(issue.project, "//UE5/Main");
+    assert_eq!(issue.summary, "Errors in Lyra editor build");
+    assert_eq!(issue.owner, "Jane.Doe");
+    assert_eq!(issue.nominated_by, "");
+    assert_eq!(issue.acknowledged_at, None);
+    assert_eq!(issue.resolved_at, None);
+    assert!(!issue.notify);
+}
+
+#[test]
+fn issue_update_data_deserializes_from_fixture() {
+    let update: models::IssueUpdateData =
+        json::from_value(fixture!("issue_update_request.json")).unwrap();
+    assert_eq!(update.summary, "Errors in Lyra editor build");
+    assert_eq!(update.owner, "Jane.Doe");
+    assert_eq!(update.nominated_by, "John.Smith");
+    assert_eq!(update.acknowledged, Some(true));
+    assert_eq!(update.fix_change, Some(12346));
+    assert_eq!(update.resolved, Some(false));
+}
+
+#[test]
+fn issue_build_data_deserializes_from_fixture() {
+    let build: models::IssueBuildData =
+        json::from_value(fixture!("issue_build_request.json")).unwrap();
+    assert_eq!(build.stream, "//UE5/Main");
+    assert_eq!(build.change, 12345);
+    assert_eq!(build.job_name, "Incremental Editor");
+    assert_eq!(build.job_url, "https://horde.example.com/job/1");
+    assert_eq!(build.job_step_name, "Compile UnrealEditor Win64");
+    assert_eq!(build.job_step_url, "https://horde.example.com/job/1?step=2");
+    assert_eq!(build.error_url, "https://horde.example.com/log/3");
+    assert_eq!(build.outcome, 2);
+}
+
+#[test]
+fn issue_build_update_data_deserializes_from_fixture() {
+    let update: models::IssueBuildUpdateData =
+        json::from_value(fixture!("issue_build_update_request.json")).unwrap();
+    assert_eq!(update.outcome, 1);
+}
+
+#[test]
+fn issue_diagnostic_data_round_trips_fixture() {
+    let fixture = fixture!("issue_diagnostic_request.json");
+    let diagnostic: models::IssueDiagnosticData = json::from_value(fixture.clone()).unwrap();
+    assert_serializes_to(diagnostic, fixture);
+}
+
+#[test]
+fn issue_watcher_data_deserializes_from_fixture() {
+    let watcher: models::IssueWatcherData =
+        json::from_value(fixture!("issue_watcher_request.json")).unwrap();
+    assert_eq!(watcher.user_name, "Jane.Doe");
+}
+
+#[test]
+fn telemetry_timing_data_deserializes_from_fixture() {
+    let data: models::TelemetryTimingData =
+        json::from_value(fixture!("telemetry_request.json")).unwrap();
+    assert_eq!(data.action, "Sync");
+    assert_eq!(data.result, "Succeeded");
+    assert_eq!(data.user_name, "Jane.Doe");
+    assert_eq!(data.project, "//UE5/Main/Samples/Games/Lyra/Lyra.uproject");
+    assert_eq!(data.timestamp, timestamp(1700000000));
+    assert_eq!(data.duration, 42.5);
+}
+
+#[test]
+fn telemetry_error_data_deserializes_from_fixture() {
+    let data: models::TelemetryErrorData =
+        json::from_value(fixture!("error_request.json")).unwrap();
+    assert_eq!(data.error_type, models::TelemetryErrorType::Crash);
+    assert_eq!(
+        data.text,
+        "Unhandled exception: System.NullReferenceException"
+    );
+    assert_eq!(data.user_name, "Jane.Doe");
+    assert_eq!(
+        data.project.as_deref(),
+        Some("//UE5/Main/Samples/Games/Lyra/Lyra.uproject")
+    );
+    assert_eq!(data.timestamp, timestamp(1700000000));
+}
+
+// End to end through the routes
+
+#[test]
+fn routes_accept_request_fixtures_and_return_response_fixtures() {
+    let client = client();
+    let project = "//UE5/Main/Samples/Games/Lyra/Lyra.uproject";
+
+    for (uri, body) in [
+        ("/api/build", fixture!("build_request.json")),
+        ("/api/comment", fixture!("comment_request.json")),
+        ("/api/event", fixture!("event_request.json")),
+        (
+            "/api/error?version=5.3.0&ipaddress=10.0.0.1",
+            fixture!("error_request.json"),
+        ),
+        (
+            "/api/telemetry?version=5.3.0&ipaddress=10.0.0.1",
+            fixture!("telemetry_request.json"),
+        ),
+    ] {
+        let response = client.post(uri).json(&body).dispatch();
+        assert_eq!(response.status(), Status::Ok, "POST {uri}");
+    }
+
+    assert_eq!(
+        into_json(
+            client
+                .get(format!("/api/build?project={project}&lastbuildid=0"))
+                .dispatch()
+        ),
+        fixture!("builds_response.json")
+    );
+    assert_eq!(
+        into_json(
+            client
+                .get(format!("/api/comment?project={project}&lastcommentid=0"))
+                .dispatch()
+        ),
+        fixture!("comments_response.json")
+    );
+    assert_eq!(
+        into_json(
+            client
+                .get(format!("/api/event?project={project}&lasteventid=0"))
+                .dispatch()
+        ),
+        fixture!("events_response.json")
+    );
+    assert_eq!(
+        into_json(
+            client
+                .get(format!("/api/latest?project={project}"))
+                .dispatch()
+        ),
+        fixture!("latest_response.json")
+    );
+    assert_eq!(
+        into_json(client.get("/api/error").dispatch()),
+        fixture!("errors_response.json")
+    );
+}
+
+#[test]
+fn queued_telemetry_posts_answer_ok() {
+    let figment = rocket::Config::figment()
+        .merge(("log_level", "off"))
+        .merge(("telemetry_queue.flush_interval", 10));
+    let store: SharedStore = Arc::new(MemoryStore::new());
+    let rocket = rocket::custom(figment)
+        .manage(store)
+        .attach(telemetry_queue::stage());
+    let client = Client::tracked(crate::mount_apis(rocket)).expect("valid rocket instance");
+
+    let response = client
+        .post("/api/telemetry?version=5.3.0&ipaddress=10.0.0.1")
+        .json(&fixture!("telemetry_request.json"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[test]
+fn issue_routes_accept_request_fixtures_and_return_response_fixtures() {
+    let client = client();
+
+    assert_eq!(
+        into_json(
+            client
+                .post("/api/issues")
+                .json(&fixture!("issue_request.json"))
+                .dispatch()
+        ),
+        fixture!("id_response.json")
+    );
+    let response = client
+        .put("/api/issues/1")
+        .json(&fixture!("issue_update_request.json"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    assert_eq!(
+        into_json(
+            client
+                .post("/api/issues/1/builds")
+                .json(&fixture!("issue_build_request.json"))
+                .dispatch()
+        ),
+        fixture!("id_response.json")
+    );
+    assert_eq!(
+        into_json(client.get("/api/issues/1/builds").dispatch()),
+        fixture!("issue_builds_response.json")
+    );
+    let response = client
+        .put("/api/issuebuilds/1")
+        .json(&fixture!("issue_build_update_request.json"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .post("/api/issues/1/diagnostics")
+        .json(&fixture!("issue_diagnostic_request.json"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        into_json(client.get("/api/issues/1/diagnostics").dispatch()),
+        fixture!("issue_diagnostics_response.json")
+    );
+
+    let response = client
+        .post("/api/issues/1/watchers")
+        .json(&fixture!("issue_watcher_request.json"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        into_json(client.get("/api/issues/1/watchers").dispatch()),
+        fixture!("issue_watchers_response.json")
+    );
+
+    // Timestamps are assigned by the server, so only compare the shape of the issue.
+    let issue = into_json(client.get("/api/issues/1").dispatch());
+    let expected = fixture!("issue_response.json");
+    let keys = |value: &Value| {
+        value
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(keys(&issue), keys(&expected));
+}
+
+#[test]
+fn user_route_returns_id_fixture() {
+    let client = client();
+    assert_eq!(
+        into_json(client.get("/api/user?name=Jane.Doe").dispatch()),
+        fixture!("id_response.json")
+    );
+}
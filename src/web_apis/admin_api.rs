@@ -0,0 +1,153 @@
+use crate::spool::{ReplayReport, SpoolEntry, Spooler};
+use crate::sql::id_cache::IdCacheStats;
+use crate::sql::SharedStore;
+use crate::telemetry_queue::{Enqueuer, TelemetryQueueStats};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::json::{self, Json, Value};
+use rocket::serde::Serialize;
+use rocket::State;
+use rocket::{get, post, routes, Route};
+
+// Operational endpoints with no counterpart in the C# MetadataServer.
+
+/// Request guard for the admin endpoints. The request must carry the configured `admin.token` as
+/// `Authorization: Bearer <token>`, or it is answered 401. Without a token configured, the
+/// endpoints answer 404 as if they were not mounted.
+pub struct Admin;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize(request, "admin.token").map(|()| Admin)
+    }
+}
+
+/// Checks that `request` carries the token configured at `key` as `Authorization: Bearer <token>`,
+/// failing with 401 if it does not, and with 404 if no token is configured.
+pub fn authorize(request: &Request<'_>, key: &str) -> Outcome<(), ()> {
+    let token = match request.rocket().figment().extract_inner::<String>(key) {
+        Ok(token) if !token.is_empty() => token,
+        Ok(_) => return Outcome::Failure((Status::NotFound, ())),
+        Err(e) if e.missing() => return Outcome::Failure((Status::NotFound, ())),
+        Err(e) => {
+            log::error!("Invalid `{}` config: {}", key, e);
+            return Outcome::Failure((Status::InternalServerError, ()));
+        }
+    };
+    let given = request
+        .headers()
+        .get_one("Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match given {
+        Some(given) if constant_time_eq(given.as_bytes(), token.as_bytes()) => Outcome::Success(()),
+        _ => Outcome::Failure((Status::Unauthorized, ())),
+    }
+}
+
+/// Hit and miss counts of the project and user id cache. 404 if the store does not cache ids.
+#[get("/admin/cache")]
+pub async fn get_cache(
+    _admin: Admin,
+    store: &State<SharedStore>,
+) -> Result<Json<IdCacheStats>, Status> {
+    store.id_cache_stats().map(Json).ok_or(Status::NotFound)
+}
+
+/// Forgets every cached project and user id, and returns the stats of the emptied cache.
+#[post("/admin/cache/invalidate")]
+pub async fn invalidate_cache(
+    _admin: Admin,
+    store: &State<SharedStore>,
+) -> Result<Json<IdCacheStats>, Status> {
+    store.invalidate_id_cache();
+    store.id_cache_stats().map(Json).ok_or(Status::NotFound)
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "PascalCase")]
+pub struct SpoolData {
+    /// Number of posts waiting to be replayed.
+    pub depth: usize,
+    /// `SpoolEntry`s without the `IpAddress` of the clients that posted them.
+    pub entries: Vec<Value>,
+}
+
+/// The oldest `limit` (by default 100) spooled posts, in the order they will be replayed. 404 if
+/// there is no spool.
+#[get("/admin/spool?<limit>")]
+pub async fn get_spool(
+    _admin: Admin,
+    spooler: Spooler,
+    limit: Option<usize>,
+) -> Result<Json<SpoolData>, Status> {
+    let spool = spooler.0.ok_or(Status::NotFound)?;
+    let entries = spool.entries(limit.unwrap_or(100)).await.map_err(|e| {
+        log::warn!("Could not read the spool: {}", e);
+        Status::InternalServerError
+    })?;
+    Ok(Json(SpoolData {
+        depth: spool.depth(),
+        entries: entries.iter().map(without_ip_addresses).collect(),
+    }))
+}
+
+/// Replays the spool now instead of at the next `replay_interval`, and reports how far it got.
+#[post("/admin/spool/drain")]
+pub async fn drain_spool(
+    _admin: Admin,
+    store: &State<SharedStore>,
+    spooler: Spooler,
+) -> Result<Json<ReplayReport>, Status> {
+    let spool = spooler.0.ok_or(Status::NotFound)?;
+    Ok(Json(spool.replay(store).await))
+}
+
+/// How many telemetry posts are queued, and what happened to the ones before. 404 if the telemetry
+/// queue is disabled.
+#[get("/admin/telemetry-queue")]
+pub async fn get_telemetry_queue(
+    _admin: Admin,
+    enqueuer: Enqueuer,
+) -> Result<Json<TelemetryQueueStats>, Status> {
+    enqueuer
+        .0
+        .map(|queue| Json(queue.stats()))
+        .ok_or(Status::NotFound)
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        get_cache,
+        invalidate_cache,
+        get_spool,
+        drain_spool,
+        get_telemetry_queue
+    ]
+}
+
+// Private Functions:
+
+/// Compares in time that depends on the lengths only, so the token can't be guessed byte by byte.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// `entry` as JSON, with every `IpAddress` field of the posts in it removed.
+fn without_ip_addresses(entry: &SpoolEntry) -> Value {
+    fn strip(value: &mut Value) {
+        match value {
+            Value::Object(fields) => {
+                fields.remove("IpAddress");
+                fields.values_mut().for_each(strip);
+            }
+            Value::Array(items) => items.iter_mut().for_each(strip),
+            _ => {}
+        }
+    }
+    let mut value = json::to_value(entry).expect("spool entries serialize");
+    strip(&mut value);
+    value
+}
@@ -1,31 +1,40 @@
-use crate::sql::sql_connector;
-use crate::web_apis::sqlx_result_to_our_result;
-use crate::{models, UGSDatabase};
+use crate::models;
+use crate::spool::{SpooledPost, Spooler, Submitted};
+use crate::sql::SharedStore;
+use crate::web_apis::{
+    sqlx_result_to_our_result, submit_result_to_our_result, submitted_status, ApiError,
+};
 use log::info;
-use rocket::response::status;
+use rocket::http::Status;
 use rocket::serde::json::Json;
+use rocket::State;
 use rocket::{get, post, routes, Route};
-use rocket_db_pools::Connection;
 
-type Result<T> = std::result::Result<T, status::Custom<String>>;
+type Result<T> = std::result::Result<T, ApiError>;
 
 // From MetadataServer.Controllers.BuildController
 
 #[get("/build?<project>&<lastbuildid>")]
 pub async fn get(
-    mut db: Connection<UGSDatabase>,
+    store: &State<SharedStore>,
     project: String,
     lastbuildid: i64,
 ) -> Result<Json<Vec<models::BuildData>>> {
-    let builds_vec_result = sql_connector::get_builds(&mut db, &project, lastbuildid).await;
+    let builds_vec_result = store.get_builds(&project, lastbuildid).await;
     sqlx_result_to_our_result(builds_vec_result).map(|t| Json(t))
 }
 
 #[post("/build", format = "application/json", data = "<build>")]
-pub async fn post(mut db: Connection<UGSDatabase>, build: Json<models::BuildData>) -> Result<()> {
+pub async fn post(
+    store: &State<SharedStore>,
+    spooler: Spooler,
+    build: Json<models::BuildData>,
+) -> Result<Status> {
     let build_unwrapped = build.into_inner();
-    let result = sql_connector::post_build(&mut db, &build_unwrapped).await;
-    if result.is_ok() {
+    let result = spooler
+        .submit(store, SpooledPost::Build(build_unwrapped.clone()))
+        .await;
+    if matches!(result, Ok(Submitted::Stored)) {
         info!(
             r#"Build badge "{}" successfully updated for {}@{} to status "{}"."#,
             build_unwrapped.build_type,
@@ -34,7 +43,7 @@ pub async fn post(mut db: Connection<UGSDatabase>, build: Json<models::BuildData
             build_unwrapped.result
         );
     }
-    sqlx_result_to_our_result(result)
+    submit_result_to_our_result(result).map(submitted_status)
 }
 
 pub fn routes() -> Vec<Route> {
@@ -1,19 +1,22 @@
-use crate::sql::sql_connector;
-use crate::web_apis::sqlx_result_to_our_result;
-use crate::{models, UGSDatabase};
+use crate::models;
+use crate::spool::{SpooledPost, Spooler, Submitted};
+use crate::sql::SharedStore;
+use crate::web_apis::{
+    sqlx_result_to_our_result, submit_result_to_our_result, submitted_status, ApiError,
+};
 use log::info;
-use rocket::response::status;
+use rocket::http::Status;
 use rocket::serde::json::Json;
+use rocket::State;
 use rocket::{get, post, routes, Route};
-use rocket_db_pools::Connection;
 
-type Result<T> = std::result::Result<T, status::Custom<String>>;
+type Result<T> = std::result::Result<T, ApiError>;
 
 // From MetadataServer.Controllers.CommentController
 
 #[get("/comment?<project>&<lastcommentid>")]
 pub async fn get(
-    mut db: Connection<UGSDatabase>,
+    store: &State<SharedStore>,
     project: String,
     lastcommentid: i64,
 ) -> Result<Json<Vec<models::CommentData>>> {
@@ -21,18 +24,21 @@ pub async fn get(
         r#"Received call to get comments newer than id {} for project {}."#,
         lastcommentid, &project
     );
-    let comments_vec_result = sql_connector::get_comments(&mut db, &project, lastcommentid).await;
+    let comments_vec_result = store.get_comments(&project, lastcommentid).await;
     sqlx_result_to_our_result(comments_vec_result).map(|t| Json(t))
 }
 
 #[post("/comment", format = "application/json", data = "<comment>")]
 pub async fn post(
-    mut db: Connection<UGSDatabase>,
+    store: &State<SharedStore>,
+    spooler: Spooler,
     comment: Json<models::CommentData>,
-) -> Result<()> {
+) -> Result<Status> {
     let comment_unwrapped = comment.into_inner();
-    let result = sql_connector::post_comment(&mut db, &comment_unwrapped).await;
-    if result.is_ok() {
+    let result = spooler
+        .submit(store, SpooledPost::Comment(comment_unwrapped.clone()))
+        .await;
+    if matches!(result, Ok(Submitted::Stored)) {
         info!(
             r#"Comment by user "{}" successfully updated for {}@{} to: "{}"."#,
             comment_unwrapped.user_name,
@@ -41,7 +47,7 @@ pub async fn post(
             comment_unwrapped.text
         );
     }
-    sqlx_result_to_our_result(result)
+    submit_result_to_our_result(result).map(submitted_status)
 }
 
 pub fn routes() -> Vec<Route> {
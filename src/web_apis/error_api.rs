@@ -1,45 +1,122 @@
-use crate::sql::sql_connector;
-use crate::web_apis::sqlx_result_to_our_result;
-use crate::{models, UGSDatabase};
+use crate::models;
+use crate::spool::{SpooledPost, Spooler, Submitted};
+use crate::sql::SharedStore;
+use crate::web_apis::{
+    sqlx_result_to_our_result, submit_result_to_our_result, submitted_status, unix_time, ApiError,
+};
 use log::info;
+use rocket::http::Status;
 use rocket::response::status;
 use rocket::serde::json::Json;
+use rocket::State;
 use rocket::{get, post, routes, Route};
-use rocket_db_pools::Connection;
 
-type Result<T> = std::result::Result<T, status::Custom<String>>;
+type Result<T> = std::result::Result<T, ApiError>;
+
+/// Reports a single `GET /error`, or crash buckets a single `GET /error/top`, returns at most. A
+/// larger `records` is clamped to it, as the C# MetadataServer accepted any value.
+pub const MAX_ERROR_RECORDS: i32 = 1000;
 
 // From MetadataServer.Controllers.ErrorController
 
-#[get("/error?<records>")]
+/// The newest `records` (by default 10) error reports that match every filter given. `from` and
+/// `to` are Unix times in seconds, `errortype` is a type name such as `Crash`, and `signature` is
+/// the `Signature` of a crash bucket from `GET /error/top`. The next page is the one with a
+/// `beforeid` of the lowest `Id` of this one.
+#[allow(clippy::too_many_arguments)]
+#[get("/error?<records>&<project>&<user>&<version>&<errortype>&<from>&<to>&<signature>&<beforeid>")]
 pub async fn get(
-    mut db: Connection<UGSDatabase>,
+    store: &State<SharedStore>,
     records: Option<i32>,
+    project: Option<String>,
+    user: Option<String>,
+    version: Option<String>,
+    errortype: Option<models::TelemetryErrorType>,
+    from: Option<i64>,
+    to: Option<i64>,
+    signature: Option<String>,
+    beforeid: Option<i64>,
 ) -> Result<Json<Vec<models::TelemetryErrorData>>> {
-    let errors_vec_results = sql_connector::get_error_data(&mut db, records.unwrap_or(10)).await;
+    let query = models::ErrorQuery {
+        project,
+        user_name: user,
+        version,
+        error_type: errortype,
+        from: unix_time("from", from)?,
+        to: unix_time("to", to)?,
+        signature,
+        before_id: beforeid,
+        records: records_in_range(records)?,
+    };
+    let errors_vec_results = store.get_error_data(&query).await;
     sqlx_result_to_our_result(errors_vec_results).map(|t| Json(t))
 }
 
+// Not in the C# MetadataServer: crash reports grouped by the signature of their call stack.
+
+/// The `records` (by default 10) crash buckets affecting the most users among the error reports
+/// that match every filter given, which are the same as for `GET /error`.
+#[allow(clippy::too_many_arguments)]
+#[get("/error/top?<records>&<project>&<user>&<version>&<errortype>&<from>&<to>")]
+pub async fn get_top(
+    store: &State<SharedStore>,
+    records: Option<i32>,
+    project: Option<String>,
+    user: Option<String>,
+    version: Option<String>,
+    errortype: Option<models::TelemetryErrorType>,
+    from: Option<i64>,
+    to: Option<i64>,
+) -> Result<Json<Vec<models::CrashBucket>>> {
+    let query = models::ErrorQuery {
+        project,
+        user_name: user,
+        version,
+        error_type: errortype,
+        from: unix_time("from", from)?,
+        to: unix_time("to", to)?,
+        records: records_in_range(records)?,
+        ..Default::default()
+    };
+    sqlx_result_to_our_result(store.get_top_crashes(&query).await).map(Json)
+}
+
 #[post(
     "/error?<version>&<ipaddress>",
     format = "application/json",
     data = "<data>"
 )]
 pub async fn post(
-    mut db: Connection<UGSDatabase>,
+    store: &State<SharedStore>,
+    spooler: Spooler,
     data: Json<models::TelemetryErrorData>,
     version: String,
     ipaddress: String,
-) -> Result<()> {
+) -> Result<Status> {
     let data_unwrapped = data.into_inner();
-    let result =
-        sql_connector::post_error_data(&mut db, &data_unwrapped, &version, &ipaddress).await;
-    if result.is_ok() {
+    let post = SpooledPost::Error {
+        data: data_unwrapped.clone(),
+        version,
+        ip_address: ipaddress,
+    };
+    let result = spooler.submit(store, post).await;
+    if matches!(result, Ok(Submitted::Stored)) {
         info!(r#"Error telemetry data submitted. {:?}"#, data_unwrapped);
     }
-    sqlx_result_to_our_result(result)
+    submit_result_to_our_result(result).map(submitted_status)
+}
+
+fn records_in_range(records: Option<i32>) -> Result<i32> {
+    match records.unwrap_or(10) {
+        records if records < 1 => Err(status::Custom(
+            Status::BadRequest,
+            String::from("`records` must be at least 1."),
+        )
+        .into()),
+        records => Ok(records.min(MAX_ERROR_RECORDS)),
+    }
 }
 
 pub fn routes() -> Vec<Route> {
-    routes![get, post]
+    routes![get, get_top, post]
 }
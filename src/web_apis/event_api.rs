@@ -1,31 +1,41 @@
-use crate::sql::sql_connector;
-use crate::web_apis::sqlx_result_to_our_result;
-use crate::{models, UGSDatabase};
+use crate::models;
+use crate::spool::{SpooledPost, Spooler, Submitted};
+use crate::sql::SharedStore;
+use crate::web_apis::{
+    batch_result, parse_batch, sqlx_result_to_our_result, submit_result_to_our_result,
+    submitted_status, ApiError, BatchResult,
+};
 use log::info;
-use rocket::response::status;
-use rocket::serde::json::Json;
+use rocket::http::Status;
+use rocket::serde::json::{Json, Value};
+use rocket::State;
 use rocket::{get, post, routes, Route};
-use rocket_db_pools::Connection;
 
-type Result<T> = std::result::Result<T, status::Custom<String>>;
+type Result<T> = std::result::Result<T, ApiError>;
 
 // From MetadataServer.Controllers.EventController
 
 #[get("/event?<project>&<lasteventid>")]
 pub async fn get(
-    mut db: Connection<UGSDatabase>,
+    store: &State<SharedStore>,
     project: String,
     lasteventid: i64,
 ) -> Result<Json<Vec<models::EventData>>> {
-    let events_vec_result = sql_connector::get_user_votes(&mut db, &project, lasteventid).await;
+    let events_vec_result = store.get_user_votes(&project, lasteventid).await;
     sqlx_result_to_our_result(events_vec_result).map(|t| Json(t))
 }
 
 #[post("/event", format = "application/json", data = "<data>")]
-pub async fn post(mut db: Connection<UGSDatabase>, data: Json<models::EventData>) -> Result<()> {
+pub async fn post(
+    store: &State<SharedStore>,
+    spooler: Spooler,
+    data: Json<models::EventData>,
+) -> Result<Status> {
     let data_unwrapped = data.into_inner();
-    let result = sql_connector::post_event(&mut db, &data_unwrapped).await;
-    if result.is_ok() {
+    let result = spooler
+        .submit(store, SpooledPost::Event(data_unwrapped.clone()))
+        .await;
+    if matches!(result, Ok(Submitted::Stored)) {
         info!(
             r#"User "{}" sent event "{}" for {}@{}."#,
             data_unwrapped.user_name,
@@ -34,9 +44,33 @@ pub async fn post(mut db: Connection<UGSDatabase>, data: Json<models::EventData>
             data_unwrapped.change
         );
     }
-    sqlx_result_to_our_result(result)
+    submit_result_to_our_result(result).map(submitted_status)
+}
+
+// Not in the C# MetadataServer: for clients catching up on events they recorded while offline.
+
+/// Stores the events of `data` that parse in one transaction, in order, and reports the outcome of
+/// each. A database error fails the whole batch, so that it can be retried as a whole.
+#[post("/event/batch", format = "application/json", data = "<data>")]
+pub async fn post_batch(
+    store: &State<SharedStore>,
+    spooler: Spooler,
+    data: Json<Vec<Value>>,
+) -> Result<Json<BatchResult>> {
+    let (events, errors) = parse_batch::<models::EventData>(data.into_inner());
+    let count = events.len();
+    let submitted = match count {
+        0 => Submitted::Stored,
+        _ => submit_result_to_our_result(
+            spooler.submit(store, SpooledPost::EventBatch(events)).await,
+        )?,
+    };
+    if submitted == Submitted::Stored && count > 0 {
+        info!("{} events submitted in a batch.", count);
+    }
+    Ok(Json(batch_result(errors, submitted)))
 }
 
 pub fn routes() -> Vec<Route> {
-    routes![get, post]
+    routes![get, post, post_batch]
 }
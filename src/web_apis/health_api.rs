@@ -0,0 +1,79 @@
+use crate::sql::schema::IncompatibleSchema;
+use crate::sql::{Backend, SharedStore};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::status;
+use rocket::serde::json::Json;
+use rocket::serde::Serialize;
+use rocket::tokio::time;
+use rocket::State;
+use rocket::{get, routes, Route};
+use std::time::Duration;
+
+// Probes for load balancers and orchestrators, mounted at the root rather than under `/api`.
+
+/// How long `/ready` waits for the database before reporting it unavailable.
+pub const READY_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "PascalCase")]
+pub struct VersionData {
+    pub version: &'static str,
+    pub git_hash: &'static str,
+    /// The configured backend, `None` if the store was not set up by `sql::stage()`.
+    pub backend: Option<Backend>,
+    pub backends: [Backend; 4],
+}
+
+/// The `Backend` managed by `sql::stage()`, if any. An `Option<&State<Backend>>` guard would stop
+/// Rocket from launching without one.
+pub struct ConfiguredBackend(Option<Backend>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ConfiguredBackend {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(ConfiguredBackend(
+            request.rocket().state::<Backend>().copied(),
+        ))
+    }
+}
+
+/// 200 as long as the process serves requests, whatever the state of the database.
+#[get("/health")]
+pub fn health() -> &'static str {
+    "OK"
+}
+
+/// 200 once the database answers a query within `READY_TIMEOUT` and its schema is compatible,
+/// 503 otherwise.
+#[get("/ready")]
+pub async fn ready(store: &State<SharedStore>) -> status::Custom<String> {
+    let unavailable = |reason: String| status::Custom(Status::ServiceUnavailable, reason);
+    match time::timeout(READY_TIMEOUT, store.ping()).await {
+        Ok(Ok(())) => status::Custom(Status::Ok, String::from("Ready.")),
+        Ok(Err(sqlx::Error::Configuration(e))) if e.is::<IncompatibleSchema>() => {
+            unavailable(String::from("Database schema is incompatible."))
+        }
+        Ok(Err(e)) => {
+            log::warn!("Readiness check failed: {}", e);
+            unavailable(String::from("Database is unavailable."))
+        }
+        Err(_) => unavailable(String::from("Database did not answer in time.")),
+    }
+}
+
+#[get("/version")]
+pub fn version(backend: ConfiguredBackend) -> Json<VersionData> {
+    Json(VersionData {
+        version: env!("CARGO_PKG_VERSION"),
+        git_hash: env!("GIT_HASH"),
+        backend: backend.0,
+        backends: Backend::ALL,
+    })
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![health, ready, version]
+}
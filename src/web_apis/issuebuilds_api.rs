@@ -1,39 +1,36 @@
-use crate::sql::sql_connector;
-use crate::web_apis::sqlx_result_to_our_result;
-use crate::{models, UGSDatabase};
-use log::info;
+use crate::models;
+use crate::sql::SharedStore;
+use crate::web_apis::{sqlx_result_to_our_result, ApiError};
 use rocket::http::Status;
 use rocket::response::status;
 use rocket::serde::json::Json;
-use rocket_db_pools::Connection;
+use rocket::State;
 
-type Result<T> = std::result::Result<T, status::Custom<String>>;
+type Result<T> = std::result::Result<T, ApiError>;
 
 // From MetadataServer.Controllers.IssueBuildsController
 
 #[rocket::get("/issuebuilds/<buildid>")]
-pub async fn get(
-    mut db: Connection<UGSDatabase>,
-    buildid: i64,
-) -> Result<Json<models::IssueBuildData>> {
-    let issue_build_data_result = sql_connector::get_build(&mut db, buildid).await;
+pub async fn get(store: &State<SharedStore>, buildid: i64) -> Result<Json<models::IssueBuildData>> {
+    let issue_build_data_result = store.get_build(buildid).await;
     match sqlx_result_to_our_result(issue_build_data_result)? {
         Some(issue_build_data) => Ok(Json(issue_build_data)),
         None => Err(status::Custom(
             Status::NotFound,
             String::from(format!("No issue build with id {buildid}.")),
-        )),
+        )
+        .into()),
     }
 }
 
 #[rocket::put("/issuebuilds/<buildid>", format = "application/json", data = "<data>")]
 pub async fn put(
-    mut db: Connection<UGSDatabase>,
+    store: &State<SharedStore>,
     buildid: i64,
     data: Json<models::IssueBuildUpdateData>,
 ) -> Result<()> {
     let data_unwrapped = data.into_inner();
-    let result = sql_connector::update_build(&mut db, buildid, data_unwrapped.outcome).await;
+    let result = store.update_build(buildid, data_unwrapped.outcome).await;
     sqlx_result_to_our_result(result)
 }
 
@@ -1,70 +1,67 @@
-use crate::sql::sql_connector;
-use crate::web_apis::sqlx_result_to_our_result;
-use crate::{models, UGSDatabase};
+use crate::models;
+use crate::sql::SharedStore;
+use crate::web_apis::{sqlx_result_to_our_result, ApiError};
 use log::info;
 use rocket::http::Status;
 use rocket::response::status;
 use rocket::serde::json::{json, Json, Value};
-use rocket_db_pools::Connection;
+use rocket::State;
 
-type Result<T> = std::result::Result<T, status::Custom<String>>;
+type Result<T> = std::result::Result<T, ApiError>;
 
 // From MetadataServer.Controllers.IssuesController
 
 #[rocket::get("/issues?<includeresolved>&<maxresults>", rank = 2)]
 pub async fn get(
-    mut db: Connection<UGSDatabase>,
+    store: &State<SharedStore>,
     includeresolved: Option<bool>,
     maxresults: Option<i32>,
 ) -> Result<Json<Vec<models::IssueData>>> {
-    let issues_vec_result =
-        sql_connector::get_issues_filtered(&mut db, includeresolved.unwrap_or(false), maxresults)
-            .await;
+    let issues_vec_result = store
+        .get_issues_filtered(includeresolved.unwrap_or(false), maxresults)
+        .await;
     sqlx_result_to_our_result(issues_vec_result).map(|t| Json(t))
 }
 
 #[rocket::get("/issues?<user>", rank = 1)]
 pub async fn get_by_user(
-    mut db: Connection<UGSDatabase>,
+    store: &State<SharedStore>,
     user: String,
 ) -> Result<Json<Vec<models::IssueData>>> {
-    let issues_vec_result = sql_connector::get_issues_by_user_name(&mut db, &user).await;
+    let issues_vec_result = store.get_issues_by_user_name(&user).await;
     sqlx_result_to_our_result(issues_vec_result).map(|t| Json(t))
 }
 
 #[rocket::get("/issues/<id>")]
-pub async fn get_by_id(
-    mut db: Connection<UGSDatabase>,
-    id: i64,
-) -> Result<Json<models::IssueData>> {
-    let issue_result = sql_connector::get_issue(&mut db, id).await;
+pub async fn get_by_id(store: &State<SharedStore>, id: i64) -> Result<Json<models::IssueData>> {
+    let issue_result = store.get_issue(id).await;
     match sqlx_result_to_our_result(issue_result)? {
         Some(issue) => Ok(Json(issue)),
-        None => Err(status::Custom::<String>(Status::NotFound, String::new())),
+        None => Err(status::Custom::<String>(Status::NotFound, String::new()).into()),
     }
 }
 
 #[rocket::put("/issues/<id>", format = "application/json", data = "<issue>")]
 pub async fn put(
-    mut db: Connection<UGSDatabase>,
+    store: &State<SharedStore>,
     id: i64,
     issue: Json<models::IssueUpdateData>,
 ) -> Result<()> {
     let issue_unwrapped = issue.into_inner();
-    let result = sql_connector::update_issue(&mut db, id, &issue_unwrapped).await;
+    let result = store.update_issue(id, &issue_unwrapped).await;
     if result.is_ok() {
-        info!(r#"Issue {} successfully updated. {:?}"#, id, issue_unwrapped);
+        info!(
+            r#"Issue {} successfully updated. {:?}"#,
+            id, issue_unwrapped
+        );
     }
     sqlx_result_to_our_result(result)
 }
 
 #[rocket::post("/issues", format = "application/json", data = "<issue>")]
-pub async fn post(
-    mut db: Connection<UGSDatabase>,
-    issue: Json<models::IssueData>,
-) -> Result<Value> {
+pub async fn post(store: &State<SharedStore>, issue: Json<models::IssueData>) -> Result<Value> {
     let issue_unwrapped = issue.into_inner();
-    let issue_id_result = sql_connector::add_issue(&mut db, &issue_unwrapped).await;
+    let issue_id_result = store.add_issue(&issue_unwrapped).await;
     if issue_id_result.is_ok() {
         info!(
             r#"Issue {} successfully created. {:?}"#,
@@ -76,8 +73,8 @@ pub async fn post(
 }
 
 #[rocket::delete("/issues/<id>")]
-pub async fn delete(mut db: Connection<UGSDatabase>, id: i64) -> Result<()> {
-    let result = sql_connector::delete_issue(&mut db, id).await;
+pub async fn delete(store: &State<SharedStore>, id: i64) -> Result<()> {
+    let result = store.delete_issue(id).await;
     if result.is_ok() {
         info!(r#"Issue {} successfully deleted."#, id);
     }
@@ -86,22 +83,20 @@ pub async fn delete(mut db: Connection<UGSDatabase>, id: i64) -> Result<()> {
 
 // From MetadataServer.Controllers.IssueBuildsSubController
 pub mod builds_sub_api {
-    use crate::sql::sql_connector;
-    use crate::web_apis::sqlx_result_to_our_result;
-    use crate::{models, UGSDatabase};
-    use log::info;
-    use rocket::response::status;
+    use crate::models;
+    use crate::sql::SharedStore;
+    use crate::web_apis::{sqlx_result_to_our_result, ApiError};
     use rocket::serde::json::{json, Json, Value};
-    use rocket_db_pools::Connection;
+    use rocket::State;
 
-    type Result<T> = std::result::Result<T, status::Custom<String>>;
+    type Result<T> = std::result::Result<T, ApiError>;
 
     #[rocket::get("/issues/<issue_id>/builds")]
     pub async fn get(
-        mut db: Connection<UGSDatabase>,
+        store: &State<SharedStore>,
         issue_id: i64,
     ) -> Result<Json<Vec<models::IssueBuildData>>> {
-        let issue_build_data_result = sql_connector::get_builds_by_issue(&mut db, issue_id).await;
+        let issue_build_data_result = store.get_builds_by_issue(issue_id).await;
         sqlx_result_to_our_result(issue_build_data_result).map(|t| Json(t))
     }
 
@@ -111,11 +106,11 @@ pub mod builds_sub_api {
         data = "<data>"
     )]
     pub async fn post(
-        mut db: Connection<UGSDatabase>,
+        store: &State<SharedStore>,
         issue_id: i64,
         data: Json<models::IssueBuildData>,
     ) -> Result<Value> {
-        let build_id_result = sql_connector::add_build(&mut db, issue_id, &data.into_inner()).await;
+        let build_id_result = store.add_build(issue_id, &data.into_inner()).await;
         sqlx_result_to_our_result(build_id_result).map(|t| json!({ "Id": t }))
     }
 
@@ -126,22 +121,20 @@ pub mod builds_sub_api {
 
 // From MetadataServer.Controllers.IssueDiagnosticsSubController
 pub mod diagnostics_sub_api {
-    use crate::sql::sql_connector;
-    use crate::web_apis::sqlx_result_to_our_result;
-    use crate::{models, UGSDatabase};
-    use log::info;
-    use rocket::response::status;
+    use crate::models;
+    use crate::sql::SharedStore;
+    use crate::web_apis::{sqlx_result_to_our_result, ApiError};
     use rocket::serde::json::Json;
-    use rocket_db_pools::Connection;
+    use rocket::State;
 
-    type Result<T> = std::result::Result<T, status::Custom<String>>;
+    type Result<T> = std::result::Result<T, ApiError>;
 
     #[rocket::get("/issues/<issue_id>/diagnostics")]
     pub async fn get(
-        mut db: Connection<UGSDatabase>,
+        store: &State<SharedStore>,
         issue_id: i64,
     ) -> Result<Json<Vec<models::IssueDiagnosticData>>> {
-        let vec_result = sql_connector::get_diagnostics(&mut db, issue_id).await;
+        let vec_result = store.get_diagnostics(issue_id).await;
         Ok(Json(sqlx_result_to_our_result(vec_result)?))
     }
 
@@ -151,11 +144,11 @@ pub mod diagnostics_sub_api {
         data = "<data>"
     )]
     pub async fn post(
-        mut db: Connection<UGSDatabase>,
+        store: &State<SharedStore>,
         issue_id: i64,
         data: Json<models::IssueDiagnosticData>,
     ) -> Result<()> {
-        let result = sql_connector::add_diagnostic(&mut db, issue_id, &data.into_inner()).await;
+        let result = store.add_diagnostic(issue_id, &data.into_inner()).await;
         sqlx_result_to_our_result(result)
     }
 
@@ -166,19 +159,17 @@ pub mod diagnostics_sub_api {
 
 // From MetadataServer.Controllers.IssueWatchersController
 pub mod watchers_sub_api {
-    use crate::sql::sql_connector;
-    use crate::web_apis::sqlx_result_to_our_result;
-    use crate::{models, UGSDatabase};
-    use log::info;
-    use rocket::response::status;
+    use crate::models;
+    use crate::sql::SharedStore;
+    use crate::web_apis::{sqlx_result_to_our_result, ApiError};
     use rocket::serde::json::Json;
-    use rocket_db_pools::Connection;
+    use rocket::State;
 
-    type Result<T> = std::result::Result<T, status::Custom<String>>;
+    type Result<T> = std::result::Result<T, ApiError>;
 
     #[rocket::get("/issues/<issue_id>/watchers")]
-    pub async fn get(mut db: Connection<UGSDatabase>, issue_id: i64) -> Result<Json<Vec<String>>> {
-        let vec_result = sql_connector::get_watchers(&mut db, issue_id).await;
+    pub async fn get(store: &State<SharedStore>, issue_id: i64) -> Result<Json<Vec<String>>> {
+        let vec_result = store.get_watchers(issue_id).await;
         Ok(Json(sqlx_result_to_our_result(vec_result)?))
     }
 
@@ -188,12 +179,13 @@ pub mod watchers_sub_api {
         data = "<data>"
     )]
     pub async fn post(
-        mut db: Connection<UGSDatabase>,
+        store: &State<SharedStore>,
         issue_id: i64,
         data: Json<models::IssueWatcherData>,
     ) -> Result<()> {
-        let result =
-            sql_connector::add_watcher(&mut db, issue_id, &data.into_inner().user_name).await;
+        let result = store
+            .add_watcher(issue_id, &data.into_inner().user_name)
+            .await;
         sqlx_result_to_our_result(result)
     }
 
@@ -203,12 +195,13 @@ pub mod watchers_sub_api {
         data = "<data>"
     )]
     pub async fn delete(
-        mut db: Connection<UGSDatabase>,
+        store: &State<SharedStore>,
         issue_id: i64,
         data: Json<models::IssueWatcherData>,
     ) -> Result<()> {
-        let result =
-            sql_connector::remove_watcher(&mut db, issue_id, &data.into_inner().user_name).await;
+        let result = store
+            .remove_watcher(issue_id, &data.into_inner().user_name)
+            .await;
         sqlx_result_to_our_result(result)
     }
 
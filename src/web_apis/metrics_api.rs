@@ -0,0 +1,185 @@
+use crate::client_metrics;
+use crate::metrics;
+use crate::spool::Spooler;
+use crate::sql::Backend;
+use crate::telemetry_queue::Enqueuer;
+use crate::web_apis::admin_api::authorize;
+use crate::{PgDatabase, SqliteDatabase, UGSDatabase};
+use prometheus::{IntCounter, IntGauge, IntGaugeVec, Opts, Registry};
+use rocket::http::ContentType;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::{get, routes, Route};
+use rocket_db_pools::Database;
+
+// Not in the C# MetadataServer: mounted at the root, like the health probes, for Prometheus.
+
+/// Connections of the database pool, which `sql::stage()` opens for every backend but `Memory`.
+pub struct PoolStats {
+    /// Open connections, idle or not.
+    pub size: u32,
+    pub idle: usize,
+    pub max: u32,
+}
+
+/// Request guard for the metrics, whose labels name projects and client versions. Authorized like
+/// `Admin`, but with `metrics.token` when one is configured, so that scrapers need not hold the
+/// admin token.
+pub struct MetricsReader;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for MetricsReader {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let key = match request
+            .rocket()
+            .figment()
+            .find_value("metrics.token")
+            .is_ok()
+        {
+            true => "metrics.token",
+            false => "admin.token",
+        };
+        authorize(request, key).map(|()| MetricsReader)
+    }
+}
+
+/// The `PoolStats` of the configured backend, `None` without a pool.
+pub struct DatabasePool(Option<PoolStats>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for DatabasePool {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let rocket = request.rocket();
+        let stats = |size: u32, idle: usize| {
+            // The default of `rocket_db_pools`.
+            let workers: usize = rocket
+                .figment()
+                .extract_inner(rocket::Config::WORKERS)
+                .unwrap_or_else(|_| rocket::Config::default().workers);
+            let max = rocket
+                .figment()
+                .extract_inner::<u32>("databases.ugsdb.max_connections")
+                .unwrap_or(workers as u32 * 4);
+            PoolStats { size, idle, max }
+        };
+        let pool = match rocket.state::<Backend>() {
+            Some(Backend::MySql) => {
+                UGSDatabase::fetch(rocket).map(|db| stats(db.size(), db.num_idle()))
+            }
+            Some(Backend::Sqlite) => {
+                SqliteDatabase::fetch(rocket).map(|db| stats(db.size(), db.num_idle()))
+            }
+            Some(Backend::Postgres) => {
+                PgDatabase::fetch(rocket).map(|db| stats(db.size(), db.num_idle()))
+            }
+            Some(Backend::Memory) | None => None,
+        };
+        Outcome::Success(DatabasePool(pool))
+    }
+}
+
+/// Every server metric in the Prometheus text format. Besides those of `metrics::METRICS`, the
+/// database pool, spool and telemetry queue are read as of now.
+#[get("/metrics")]
+pub fn get(
+    _reader: MetricsReader,
+    pool: DatabasePool,
+    spooler: Spooler,
+    enqueuer: Enqueuer,
+) -> (ContentType, String) {
+    let snapshot = Registry::new();
+    let gauge = |name: &str, help: &str, value: i64| {
+        let gauge = IntGauge::new(name, help).unwrap();
+        gauge.set(value);
+        snapshot.register(Box::new(gauge)).unwrap();
+    };
+    let counter = |name: &str, help: &str, value: u64| {
+        let counter = IntCounter::new(name, help).unwrap();
+        counter.inc_by(value);
+        snapshot.register(Box::new(counter)).unwrap();
+    };
+
+    if let Some(pool) = pool.0 {
+        let connections = IntGaugeVec::new(
+            Opts::new("ugs_db_pool_connections", "Open database connections."),
+            &["state"],
+        )
+        .unwrap();
+        let idle = pool.idle as i64;
+        connections.with_label_values(&["idle"]).set(idle);
+        connections
+            .with_label_values(&["in_use"])
+            .set((pool.size as i64 - idle).max(0));
+        snapshot.register(Box::new(connections)).unwrap();
+        gauge(
+            "ugs_db_pool_max_connections",
+            "Database connections the pool opens at most.",
+            pool.max as i64,
+        );
+    }
+    if let Some(spool) = spooler.0 {
+        gauge(
+            "ugs_spool_depth",
+            "Posts waiting in the spool to be replayed.",
+            spool.depth() as i64,
+        );
+    }
+    if let Some(queue) = enqueuer.0 {
+        let stats = queue.stats();
+        gauge(
+            "ugs_telemetry_queue_queued",
+            "Telemetry posts waiting to be written.",
+            stats.queued as i64,
+        );
+        gauge(
+            "ugs_telemetry_queue_capacity",
+            "Telemetry posts the queue holds at most.",
+            stats.capacity as i64,
+        );
+        counter(
+            "ugs_telemetry_queue_enqueued_total",
+            "Telemetry posts queued.",
+            stats.enqueued,
+        );
+        counter(
+            "ugs_telemetry_queue_dropped_total",
+            "Telemetry posts refused because the queue was full.",
+            stats.dropped,
+        );
+        counter(
+            "ugs_telemetry_queue_flushed_total",
+            "Queued telemetry posts written to the database.",
+            stats.flushed,
+        );
+        counter(
+            "ugs_telemetry_queue_spooled_total",
+            "Queued telemetry posts handed to the spool.",
+            stats.spooled,
+        );
+        counter(
+            "ugs_telemetry_queue_failed_total",
+            "Queued telemetry posts lost.",
+            stats.failed,
+        );
+    }
+
+    (text_format(), metrics::render(&snapshot))
+}
+
+/// Histograms of the timing telemetry UGS clients posted since the server started, in the
+/// Prometheus text format. Kept apart from `/metrics` so that they can be scraped into another job.
+#[get("/metrics/clients")]
+pub fn get_clients(_reader: MetricsReader) -> (ContentType, String) {
+    (text_format(), client_metrics::render())
+}
+
+fn text_format() -> ContentType {
+    ContentType::new("text", "plain").with_params(("version", "0.0.4"))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![get, get_clients]
+}
@@ -1,26 +1,183 @@
+pub mod admin_api;
 pub mod build_api;
 pub mod comment_api;
 pub mod error_api;
 pub mod event_api;
+pub mod health_api;
 pub mod issuebuilds_api;
 pub mod issues_api;
 pub mod latest_api;
+pub mod metrics_api;
 pub mod telemetry_api;
 pub mod user_api;
 
+use crate::spool::{SubmitError, Submitted};
+use crate::sql::availability::is_unavailable;
+use crate::sql::schema::IncompatibleSchema;
+use chrono::{DateTime, TimeZone, Utc};
 use rocket::http::Status;
-use rocket::response::status;
+use rocket::request::Request;
+use rocket::response::{self, status, Responder};
+use rocket::serde::json::{self, Value};
+use rocket::serde::{DeserializeOwned, Serialize};
+
+/// Seconds clients are asked to wait before retrying while the database is unavailable.
+pub const RETRY_AFTER_SECONDS: u64 = 5;
+
+/// Error response of the web APIs: `status` with `message` as plain text, and a `Retry-After`
+/// header when the request may succeed later.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: Status,
+    pub message: String,
+    pub retry_after: Option<u64>,
+}
+
+impl From<status::Custom<String>> for ApiError {
+    fn from(status::Custom(status, message): status::Custom<String>) -> Self {
+        ApiError {
+            status,
+            message,
+            retry_after: None,
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = status::Custom(self.status, self.message).respond_to(request)?;
+        if let Some(seconds) = self.retry_after {
+            response.set_raw_header("Retry-After", seconds.to_string());
+        }
+        Ok(response)
+    }
+}
+
+/// 200 for a post that was stored, and 202 for one that was spooled to be stored later.
+pub fn submitted_status(submitted: Submitted) -> Status {
+    match submitted {
+        Submitted::Stored => Status::Ok,
+        Submitted::Spooled => Status::Accepted,
+    }
+}
+
+/// What became of one item of a batch post, in the order of the batch.
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "PascalCase")]
+pub struct BatchItemResult {
+    /// 200 if the item was stored, 202 if it was spooled, or 422 if it could not be parsed.
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "PascalCase")]
+pub struct BatchResult {
+    pub stored: usize,
+    pub spooled: usize,
+    pub rejected: usize,
+    pub results: Vec<BatchItemResult>,
+}
+
+/// Parses every item of a batch post on its own, so that a malformed item only rejects itself.
+/// Returns the items that parsed, and for every item the error it failed to parse with, if any.
+pub fn parse_batch<T: DeserializeOwned>(items: Vec<Value>) -> (Vec<T>, Vec<Option<String>>) {
+    let mut parsed = Vec::with_capacity(items.len());
+    let errors = items
+        .into_iter()
+        .map(|item| match json::from_value(item) {
+            Ok(item) => {
+                parsed.push(item);
+                None
+            }
+            Err(e) => Some(e.to_string()),
+        })
+        .collect();
+    (parsed, errors)
+}
+
+/// The result of a batch post whose parsed items were all `submitted` together.
+pub fn batch_result(errors: Vec<Option<String>>, submitted: Submitted) -> BatchResult {
+    let status = submitted_status(submitted);
+    let results: Vec<BatchItemResult> = errors
+        .into_iter()
+        .map(|error| BatchItemResult {
+            status: match error {
+                Some(_) => Status::UnprocessableEntity.code,
+                None => status.code,
+            },
+            error,
+        })
+        .collect();
+    let count = |status: Status| results.iter().filter(|r| r.status == status.code).count();
+    BatchResult {
+        stored: count(Status::Ok),
+        spooled: count(Status::Accepted),
+        rejected: count(Status::UnprocessableEntity),
+        results,
+    }
+}
+
+/// The time `seconds` after the Unix epoch, from the query parameter `name`.
+pub fn unix_time(name: &str, seconds: Option<i64>) -> Result<Option<DateTime<Utc>>, ApiError> {
+    match seconds.map(|seconds| Utc.timestamp_opt(seconds, 0).single()) {
+        Some(None) => {
+            Err(status::Custom(Status::BadRequest, format!("`{}` is out of range.", name)).into())
+        }
+        time => Ok(time.flatten()),
+    }
+}
+
+pub fn sqlx_error_to_api_error(sqlx_error: &sqlx::Error) -> ApiError {
+    if let sqlx::Error::Configuration(e) = sqlx_error {
+        if e.is::<IncompatibleSchema>() {
+            return ApiError::from(status::Custom(
+                Status::ServiceUnavailable,
+                String::from("Database schema is incompatible; writes are disabled."),
+            ));
+        }
+    }
+
+    // The store logs when the database becomes unavailable and when it is back.
+    if is_unavailable(sqlx_error) {
+        return ApiError {
+            status: Status::ServiceUnavailable,
+            message: String::from("Database is unavailable."),
+            retry_after: Some(RETRY_AFTER_SECONDS),
+        };
+    }
 
-pub fn sqlx_error_to_custom_status(sqlx_error: &sqlx::Error) -> status::Custom<String> {
     log::warn!("Database error: {}", sqlx_error.to_string());
-    status::Custom(
+    ApiError::from(status::Custom(
         Status::InternalServerError,
         String::from("Database error occurred."),
-    )
+    ))
+}
+
+/// The response to a post `Spooler::submit` could not store or spool.
+pub fn submit_error_to_api_error(submit_error: &SubmitError) -> ApiError {
+    match submit_error {
+        SubmitError::Database(e) => sqlx_error_to_api_error(e),
+        SubmitError::SpoolFull => ApiError {
+            status: Status::ServiceUnavailable,
+            message: String::from("Database is unavailable and the spool is full."),
+            retry_after: Some(RETRY_AFTER_SECONDS),
+        },
+        SubmitError::Spool(_) => ApiError {
+            status: Status::ServiceUnavailable,
+            message: String::from("Database is unavailable and the post could not be spooled."),
+            retry_after: Some(RETRY_AFTER_SECONDS),
+        },
+    }
+}
+
+pub fn submit_result_to_our_result(
+    submit_result: Result<Submitted, SubmitError>,
+) -> Result<Submitted, ApiError> {
+    submit_result.map_err(|e| submit_error_to_api_error(&e))
 }
 
-pub fn sqlx_result_to_our_result<T>(
-    sqlx_result: Result<T, sqlx::Error>,
-) -> Result<T, status::Custom<String>> {
-    sqlx_result.map_err(|e| sqlx_error_to_custom_status(&e))
+pub fn sqlx_result_to_our_result<T>(sqlx_result: Result<T, sqlx::Error>) -> Result<T, ApiError> {
+    sqlx_result.map_err(|e| sqlx_error_to_api_error(&e))
 }
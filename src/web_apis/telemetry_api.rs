@@ -1,13 +1,30 @@
-use crate::sql::sql_connector;
-use crate::web_apis::sqlx_result_to_our_result;
-use crate::{models, UGSDatabase};
+use crate::client_metrics;
+use crate::models;
+use crate::spool::{SpooledPost, Spooler, Submitted};
+use crate::sql::SharedStore;
+use crate::telemetry_queue::{EnqueueError, Enqueuer};
+use crate::telemetry_stats::{self, TimingGroup};
+use crate::web_apis::{
+    batch_result, parse_batch, sqlx_result_to_our_result, submit_result_to_our_result,
+    submitted_status, unix_time, ApiError, BatchResult, RETRY_AFTER_SECONDS,
+};
+use chrono::{Duration, Utc};
 use log::info;
+use rocket::http::Status;
 use rocket::response::status;
-use rocket::serde::json::Json;
-use rocket::{post, routes, Route};
-use rocket_db_pools::Connection;
+use rocket::serde::json::{Json, Value};
+use rocket::State;
+use rocket::{get, post, routes, Route};
 
-type Result<T> = std::result::Result<T, status::Custom<String>>;
+type Result<T> = std::result::Result<T, ApiError>;
+
+/// Timing samples a single `GET /telemetry/stats` reads the durations of, about. Beyond that, the
+/// percentiles are those of every `n`-th matching sample by id, while the counts still include
+/// every one.
+pub const MAX_TIMING_SAMPLES: i64 = 250_000;
+
+/// Days of timing samples `GET /telemetry/stats` aggregates without a `from`.
+pub const DEFAULT_TIMING_DAYS: i64 = 7;
 
 // From MetadataServer.Controllers.TelemetryController
 
@@ -17,20 +34,148 @@ type Result<T> = std::result::Result<T, status::Custom<String>>;
     data = "<data>"
 )]
 pub async fn post(
-    mut db: Connection<UGSDatabase>,
+    store: &State<SharedStore>,
+    spooler: Spooler,
+    enqueuer: Enqueuer,
     data: Json<models::TelemetryTimingData>,
     version: String,
     ipaddress: String,
-) -> Result<()> {
-    let data_unwrapped = data.into_inner();
-    let result =
-        sql_connector::post_telemetry_data(&mut db, &data_unwrapped, &version, &ipaddress).await;
+) -> Result<Status> {
+    let post = models::TelemetryPost {
+        data: data.into_inner(),
+        version,
+        ip_address: ipaddress,
+    };
+    let accepted = post.clone();
+    // Queued posts are written in batches, and are not logged one by one. They answer 200 like a
+    // stored post, as UGS clients expect from the C# MetadataServer. While older posts are spooled
+    // the queue is bypassed, so that the post is spooled after them instead of stored first.
+    let post = if spooler.is_spooling() {
+        post
+    } else {
+        match enqueuer.enqueue(post).await {
+            Ok(()) => {
+                client_metrics::observe(&accepted);
+                return Ok(Status::Ok);
+            }
+            Err(EnqueueError::Full) => {
+                return Err(ApiError {
+                    status: Status::ServiceUnavailable,
+                    message: String::from("Telemetry queue is full."),
+                    retry_after: Some(RETRY_AFTER_SECONDS),
+                })
+            }
+            Err(EnqueueError::Bypassed(post)) => post,
+        }
+    };
+    let result = spooler.submit(store, SpooledPost::Telemetry(post)).await;
     if result.is_ok() {
-        info!(r#"Timing telemetry data submitted. {:?}"#, data_unwrapped);
+        client_metrics::observe(&accepted);
+    }
+    if matches!(result, Ok(Submitted::Stored)) {
+        info!(r#"Timing telemetry data submitted. {:?}"#, accepted.data);
     }
-    sqlx_result_to_our_result(result)
+    submit_result_to_our_result(result).map(submitted_status)
+}
+
+// Not in the C# MetadataServer: for clients catching up on samples they recorded while offline.
+
+/// Stores the timing data of `data` that parses in one transaction, bypassing the telemetry queue,
+/// and reports the outcome of each item. A database error fails the whole batch, so that it can be
+/// retried as a whole.
+#[post(
+    "/telemetry/batch?<version>&<ipaddress>",
+    format = "application/json",
+    data = "<data>"
+)]
+pub async fn post_batch(
+    store: &State<SharedStore>,
+    spooler: Spooler,
+    data: Json<Vec<Value>>,
+    version: String,
+    ipaddress: String,
+) -> Result<Json<BatchResult>> {
+    let (timing_data, errors) = parse_batch::<models::TelemetryTimingData>(data.into_inner());
+    let batch: Vec<models::TelemetryPost> = timing_data
+        .into_iter()
+        .map(|data| models::TelemetryPost {
+            data,
+            version: version.clone(),
+            ip_address: ipaddress.clone(),
+        })
+        .collect();
+    let count = batch.len();
+    let submitted = match count {
+        0 => Submitted::Stored,
+        _ => submit_result_to_our_result(
+            spooler
+                .submit(store, SpooledPost::TelemetryBatch(batch.clone()))
+                .await,
+        )?,
+    };
+    batch.iter().for_each(client_metrics::observe);
+    if submitted == Submitted::Stored && count > 0 {
+        info!("{} timing telemetry samples submitted in a batch.", count);
+    }
+    Ok(Json(batch_result(errors, submitted)))
+}
+
+/// Count, success rate and duration percentiles of the timing samples that match every filter
+/// given, per action. `groupby` is a comma-separated list of `project` and `version` to also split
+/// the stats by, and `bucket` splits them into time buckets of that many seconds. `from` and `to`
+/// are Unix times in seconds; `from` defaults to `DEFAULT_TIMING_DAYS` before `to`.
+#[allow(clippy::too_many_arguments)]
+#[get("/telemetry/stats?<action>&<project>&<version>&<from>&<to>&<bucket>&<groupby>")]
+pub async fn get_stats(
+    store: &State<SharedStore>,
+    action: Option<String>,
+    project: Option<String>,
+    version: Option<String>,
+    from: Option<i64>,
+    to: Option<i64>,
+    bucket: Option<i64>,
+    groupby: Option<String>,
+) -> Result<Json<Vec<models::TimingStats>>> {
+    let to = unix_time("to", to)?;
+    let from = match unix_time("from", from)? {
+        Some(from) => from,
+        None => to.unwrap_or_else(Utc::now) - Duration::days(DEFAULT_TIMING_DAYS),
+    };
+    if bucket.is_some_and(|bucket| bucket < 1) {
+        return Err(bad_request(String::from(
+            "`bucket` must be at least 1 second.",
+        )));
+    }
+    let group_by = match groupby.as_deref() {
+        None | Some("") => Vec::new(),
+        Some(groupby) => groupby
+            .split(',')
+            .map(|name| name.trim().parse::<TimingGroup>())
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(bad_request)?,
+    };
+
+    let query = models::TimingQuery {
+        action,
+        project,
+        version,
+        from: Some(from),
+        to,
+        by_project: group_by.contains(&TimingGroup::Project),
+        by_version: group_by.contains(&TimingGroup::Version),
+        bucket,
+    };
+    let counts = sqlx_result_to_our_result(store.get_timing_counts(&query).await)?;
+    let total: i64 = counts.iter().map(|count| count.count).sum();
+    let every = ((total + MAX_TIMING_SAMPLES - 1) / MAX_TIMING_SAMPLES).max(1);
+    let samples = sqlx_result_to_our_result(store.get_timing_samples(&query, every).await)?;
+    Ok(Json(telemetry_stats::aggregate(counts, samples)))
+}
+
+fn bad_request(message: String) -> ApiError {
+    status::Custom(Status::BadRequest, message).into()
 }
 
 pub fn routes() -> Vec<Route> {
-    routes![post]
+    routes![post, post_batch, get_stats]
 }
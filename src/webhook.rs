@@ -0,0 +1,87 @@
+//! Outbound webhooks: a JSON body POSTed to an `http://` or `https://` URL with `reqwest`. HTTPS
+//! servers are verified against the Mozilla root certificates.
+
+use lazy_static::lazy_static;
+use reqwest::{Client, Url};
+use rocket::serde::Serialize;
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+lazy_static! {
+    /// Shared by every webhook, so that connections to the same server are reused.
+    static ref CLIENT: Client = Client::builder()
+        .use_rustls_tls()
+        .build()
+        .expect("the rustls client builds");
+}
+
+/// Why a webhook could not be delivered.
+#[derive(Debug)]
+pub enum WebhookError {
+    InvalidUrl(String),
+    Timeout,
+    Connection(String),
+    /// The server answered with something other than a 2xx status.
+    Status(u16),
+}
+
+impl fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WebhookError::InvalidUrl(e) => write!(f, "invalid URL: {}", e),
+            WebhookError::Timeout => f.write_str("timed out"),
+            WebhookError::Connection(e) => f.write_str(e),
+            WebhookError::Status(status) => write!(f, "the server answered {}", status),
+        }
+    }
+}
+
+/// Checks that `url` is one `post` can send to.
+pub fn validate(url: &str) -> Result<(), WebhookError> {
+    parse(url).map(|_| ())
+}
+
+/// POSTs `body` as JSON to `url`, giving up after `timeout`.
+pub async fn post(url: &str, body: &impl Serialize, timeout: Duration) -> Result<(), WebhookError> {
+    let response = CLIENT
+        .post(parse(url)?)
+        .json(body)
+        .timeout(timeout)
+        .send()
+        .await
+        .map_err(request_error)?;
+    match response.status().is_success() {
+        true => Ok(()),
+        false => Err(WebhookError::Status(response.status().as_u16())),
+    }
+}
+
+// Private Functions:
+
+fn parse(url: &str) -> Result<Url, WebhookError> {
+    let url = Url::parse(url).map_err(|e| WebhookError::InvalidUrl(e.to_string()))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(WebhookError::InvalidUrl(String::from(
+            "expected an http:// or https:// URL",
+        )));
+    }
+    if url.host().is_none() {
+        return Err(WebhookError::InvalidUrl(String::from("missing host")));
+    }
+    Ok(url)
+}
+
+/// The error of a failed request, with its causes, as `reqwest` only names the URL it failed on.
+fn request_error(e: reqwest::Error) -> WebhookError {
+    if e.is_timeout() {
+        return WebhookError::Timeout;
+    }
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(cause) = source {
+        message.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+    WebhookError::Connection(message)
+}